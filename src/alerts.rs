@@ -0,0 +1,282 @@
+//! Go-live alert coalescing. The first alert in a Slack channel opens a window; alerts that
+//! arrive while it's open are folded into that same message with `chat.update`, so a burst of
+//! channels going live (say, as Twitch recovers from an outage) becomes one post per window.
+//!
+//! Windows live in a table (see `store`) because every delivery is a separate invocation.
+//! Writes are conditional on a version number so concurrent deliveries don't drop each other's
+//! lines.
+//!
+//! The same table remembers the last stream each channel was announced for. That's how a late
+//! EventSub redelivery and the reconciliation pass (`treconcile`) avoid announcing a stream twice.
+
+use crate::http;
+use crate::scrub::OutputFilter;
+use crate::slack::blocks::{self, Blocks};
+use crate::slack::{self, Color, SlackAttachment, SlackMessage};
+use crate::stage::Stage;
+use crate::store::{self, Attr, Attrs, Condition, Store};
+use crate::twitch::TwitchStream;
+use crate::Error;
+use chrono::{DateTime, Utc};
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+use simple_error::bail;
+
+const DEFAULT_TABLE: &str = "tuser-alert-windows";
+/// How long a window stays open when the workspace hasn't chosen.
+pub const DEFAULT_COALESCE_MINUTES: u32 = 5;
+/// How many times to re-read a window another delivery changed underneath us.
+const WINDOW_ATTEMPTS: usize = 3;
+/// How long a channel's last alerted stream is kept. Twitch ends a stream after 48 hours, so its
+/// notifications can't arrive again after that.
+const LAST_ALERTED_SECS: i64 = 48 * 60 * 60;
+/// Appended to alerts for streams whose go-live notification never arrived.
+pub const RECOVERED_SUFFIX: &str = " _(recovered)_";
+
+/// `<link|name> is live: title (category)`, leaving out whatever the stream lookup didn't give us.
+/// The title goes through the workspace's `filter`.
+pub fn alert_line(login: &str, name: &str, stream: Option<&TwitchStream>, filter: &OutputFilter) -> String {
+    match stream {
+        Some(stream) if !stream.game_name.is_empty() => format!(
+            "<https://twitch.tv/{}|{}> is live: {} ({})",
+            login,
+            name,
+            filter.scrub(&stream.title),
+            stream.game_name
+        ),
+        Some(stream) => format!("<https://twitch.tv/{}|{}> is live: {}", login, name, filter.scrub(&stream.title)),
+        None => format!("<https://twitch.tv/{}|{}> is live", login, name),
+    }
+}
+
+/// The card `/tfollow` channels get when a stream starts: title, category and a preview image,
+/// with a button to the stream. It's posted on its own rather than coalesced, since a followed
+/// channel is one someone asked to hear about specifically.
+pub fn go_live_message(
+    login: &str,
+    name: &str,
+    stream: Option<&TwitchStream>,
+    filter: &OutputFilter,
+) -> Result<SlackMessage, Error> {
+    let url = format!("https://twitch.tv/{}", login);
+    let mut text = format!("🔴 *<{}|{}>* is live", url, name);
+    let mut layout = Blocks::new();
+    match stream {
+        Some(stream) => {
+            let title = filter.scrub(&stream.title);
+            text.push_str(&format!("\n{}", title));
+            if !stream.game_name.is_empty() {
+                text.push_str(&format!("\n_{}_", stream.game_name));
+            }
+            let preview = stream.thumbnail_url.replace("{width}", "320").replace("{height}", "180");
+            layout = layout.section_with_image(text, &preview, format!("Preview of {}'s stream", name));
+        }
+        None => layout = layout.section(text),
+    }
+    let layout = layout.actions(vec![blocks::link_button("watch_stream", "Watch", url)]);
+
+    SlackMessage::builder()
+        .text(format!("{} is live on Twitch", name))
+        .attachment(SlackAttachment {
+            fallback: format!("{} is live on Twitch", name),
+            color: Color::LIVE,
+            author_name: String::new(),
+            author_icon: String::new(),
+            blocks: layout.into_blocks(),
+        })
+        .build()
+}
+
+/// What `/tfollow` channels get when the stream ends.
+pub fn offline_message(login: &str, name: &str) -> Result<SlackMessage, Error> {
+    SlackMessage::builder()
+        .text(format!("⚫ <https://twitch.tv/{}|{}> went offline.", login, name))
+        .build()
+}
+
+/// One Slack alert message and the alert lines it holds.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AlertWindow {
+    pub ts: String,
+    /// Unix seconds.
+    pub opened_at: i64,
+    pub lines: Vec<String>,
+    #[serde(default)]
+    pub version: u64,
+}
+
+impl AlertWindow {
+    pub fn is_open(&self, now: i64, minutes: u32) -> bool {
+        now - self.opened_at < i64::from(minutes) * 60
+    }
+
+    /// The alert message for the window's lines: a single alert reads as before, several become
+    /// a list.
+    pub fn message(&self) -> Result<SlackMessage, Error> {
+        let text = match self.lines.as_slice() {
+            [line] => format!("🔴 {}", line),
+            lines => format!(
+                "🔴 {} watched channels went live:\n{}",
+                lines.len(),
+                lines.iter().map(|line| format!("• {}", line)).collect::<Vec<_>>().join("\n")
+            ),
+        };
+        SlackMessage::builder().text(text).build()
+    }
+}
+
+pub struct AlertWindowStore {
+    store: Box<dyn Store>,
+}
+
+impl AlertWindowStore {
+    pub fn new(store: Box<dyn Store>) -> AlertWindowStore {
+        AlertWindowStore { store }
+    }
+
+    pub fn from_env() -> AlertWindowStore {
+        let table = std::env::var("ALERT_WINDOW_TABLE").unwrap_or_else(|_| Stage::current().resource(DEFAULT_TABLE));
+        AlertWindowStore::new(store::open(table, "window_key"))
+    }
+
+    pub async fn get(&self, team_id: &str, channel: &str) -> Result<Option<AlertWindow>, Error> {
+        match self.get_attribute(&window_key(team_id, channel), "window").await? {
+            Some(window) => Ok(Some(serde_json::from_str(&window)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The stream id the channel's last go-live alert in this workspace was for.
+    pub async fn last_alerted(&self, team_id: &str, login: &str) -> Result<Option<String>, Error> {
+        self.get_attribute(&live_key(team_id, login), "stream_id").await
+    }
+
+    pub async fn set_last_alerted(&self, team_id: &str, login: &str, stream_id: &str) -> Result<(), Error> {
+        let mut item = Attrs::new();
+        item.insert("stream_id".to_string(), Attr::S(stream_id.to_string()));
+        item.insert("expires_at".to_string(), Attr::N(Utc::now().timestamp() + LAST_ALERTED_SECS));
+        self.store.put(&live_key(team_id, login), item).await
+    }
+
+    /// Which of a notification's sinks (see `notify::Fanout`) have taken it.
+    pub async fn sent_to(&self, team_id: &str, key: &str) -> Result<Vec<String>, Error> {
+        Ok(self
+            .get_attribute(&sent_key(team_id, key), "sinks")
+            .await?
+            .map(|sinks| sinks.split(',').map(str::to_string).collect())
+            .unwrap_or_default())
+    }
+
+    /// Records that `sinks` have taken the notification too. Kept for a day, long after any retry.
+    pub async fn add_sent(&self, team_id: &str, key: &str, sinks: &[String]) -> Result<(), Error> {
+        let mut all = self.sent_to(team_id, key).await?;
+        for sink in sinks {
+            if !all.contains(sink) {
+                all.push(sink.clone());
+            }
+        }
+        let mut item = Attrs::new();
+        item.insert("sinks".to_string(), Attr::S(all.join(",")));
+        item.insert("expires_at".to_string(), Attr::N(Utc::now().timestamp() + 24 * 60 * 60));
+        self.store.put(&sent_key(team_id, key), item).await
+    }
+
+    /// Adds an alert line to the Slack channel's open window, updating its message, or posts a
+    /// new message and opens a window with it. Coalescing is off when `minutes` is 0. The
+    /// `footer` only goes on individual alerts; a combined message has no single start time.
+    pub async fn deliver(
+        &self,
+        team_id: &str,
+        channel: &str,
+        line: String,
+        footer: &str,
+        minutes: u32,
+        token: String,
+    ) -> Result<DateTime<Utc>, Error> {
+        let now = Utc::now().timestamp();
+
+        for _ in 0..WINDOW_ATTEMPTS {
+            let existing = if minutes > 0 { self.get(team_id, channel).await? } else { None };
+            let read_version = existing.as_ref().map(|window| window.version);
+
+            match existing {
+                Some(mut window) if window.is_open(now, minutes) => {
+                    window.lines.push(line.clone());
+                    if !self.put(team_id, channel, &window, read_version).await? {
+                        continue;
+                    }
+                    info!("Coalesced a go-live alert into {} ({} channels)", window.ts, window.lines.len());
+                    let (channel, message) = (channel.to_string(), window.message()?);
+                    tokio::task::spawn_blocking(move || {
+                        let client = http::client();
+                        slack::update_message(&client, &token, &channel, &window.ts, &message)
+                    })
+                    .await??;
+                }
+                _ => {
+                    let message = SlackMessage::builder().text(format!("🔴 {}{}", line, footer)).build()?;
+                    let post_channel = channel.to_string();
+                    let ts = tokio::task::spawn_blocking(move || {
+                        slack::post(&http::client(), &token, &post_channel, &message)
+                    })
+                    .await??;
+                    if minutes > 0 {
+                        let window = AlertWindow {
+                            ts,
+                            opened_at: now,
+                            lines: vec![line],
+                            version: 0,
+                        };
+                        // Losing this race only means the next alert opens its own window.
+                        self.put(team_id, channel, &window, read_version).await?;
+                    }
+                }
+            }
+            return Ok(Utc::now());
+        }
+
+        bail!("Gave up coalescing a go-live alert into {} after {} attempts", channel, WINDOW_ATTEMPTS)
+    }
+
+    /// Saves `window` with its version bumped, unless someone else saved the channel's window
+    /// since it was read (`read_version`, `None` if there was none). Returns whether it saved.
+    pub async fn put(
+        &self,
+        team_id: &str,
+        channel: &str,
+        window: &AlertWindow,
+        read_version: Option<u64>,
+    ) -> Result<bool, Error> {
+        let window = AlertWindow {
+            version: read_version.map_or(1, |version| version + 1),
+            ..window.clone()
+        };
+        let mut item = Attrs::new();
+        item.insert("window".to_string(), Attr::S(serde_json::to_string(&window)?));
+        item.insert("version".to_string(), Attr::N(window.version as i64));
+        // Let the store clear out windows long after they've closed.
+        item.insert("expires_at".to_string(), Attr::N(window.opened_at + 24 * 60 * 60));
+
+        let condition = match read_version {
+            Some(version) => Condition::Equals("version", Attr::N(version as i64)),
+            None => Condition::Absent,
+        };
+        self.store.put_if(&window_key(team_id, channel), item, condition).await
+    }
+
+    async fn get_attribute(&self, window_key: &str, name: &str) -> Result<Option<String>, Error> {
+        Ok(self.store.get(window_key).await?.and_then(|mut item| item.remove(name)).and_then(Attr::into_string))
+    }
+}
+
+fn window_key(team_id: &str, channel: &str) -> String {
+    format!("{}:{}", team_id, channel)
+}
+
+fn sent_key(team_id: &str, key: &str) -> String {
+    format!("sent:{}:{}", team_id, key)
+}
+
+fn live_key(team_id: &str, login: &str) -> String {
+    format!("live:{}:{}", team_id, login)
+}
@@ -0,0 +1,188 @@
+//! Keeps the Twitch app access token usable. The `twitch_app_token` in Secrets Manager eventually
+//! expires, so each container validates it (Twitch asks apps to do so hourly) and gets a new one
+//! with the client-credentials grant when it has expired or Helix refuses it. With
+//! `APP_TOKEN_WRITE_BACK=true` the new token is also saved to the secret, so cold starts and other
+//! containers use it too.
+//!
+//! Like the rate-limit bucket, the token in use is kept process-wide.
+
+use crate::error::BotError;
+use crate::links::{self, auth_base, Validation};
+use crate::ratelimit::now_secs;
+use crate::secrets::{self, Secrets};
+use crate::Error;
+use log::{error, info, warn};
+use serde_derive::Deserialize;
+use std::sync::Mutex;
+
+/// How often a container re-validates the token it's using.
+const VALIDATE_INTERVAL_SECS: u64 = 60 * 60;
+/// Refresh this long before Twitch says the token expires, rather than racing the expiry.
+const EXPIRY_MARGIN_SECS: u64 = 5 * 60;
+
+#[derive(Debug, Clone)]
+struct AppToken {
+    /// The secret's token when this one was chosen. A rotated secret starts over from its value.
+    stored: String,
+    token: String,
+    checked_at: u64,
+    expires_at: u64,
+}
+
+static TOKEN: Mutex<Option<AppToken>> = Mutex::new(None);
+
+#[derive(Deserialize, Debug)]
+struct ClientCredentials {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// The token to send with app requests: the one this container last validated or refreshed, or
+/// the secret's after checking it with Twitch.
+pub fn token(client: &reqwest::blocking::Client, secrets: &Secrets) -> String {
+    let candidate = match known(secrets) {
+        Ok(token) => return token,
+        Err(candidate) => candidate,
+    };
+    let validated = links::validate(client, &candidate).map(|validation| validation.expires_in);
+    if accept(secrets, &candidate, validated) {
+        return candidate;
+    }
+    match refresh(client, secrets) {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Could not refresh the Twitch app token: {}", e);
+            candidate
+        }
+    }
+}
+
+/// [`token`] for the async client.
+pub async fn token_async(client: &reqwest::Client, secrets: &Secrets) -> String {
+    let candidate = match known(secrets) {
+        Ok(token) => return token,
+        Err(candidate) => candidate,
+    };
+    let validated = validate_async(client, &candidate).await;
+    if accept(secrets, &candidate, validated) {
+        return candidate;
+    }
+    match refresh_async(client, secrets).await {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Could not refresh the Twitch app token: {}", e);
+            candidate
+        }
+    }
+}
+
+/// Gets a new app token with the client-credentials grant and uses it from now on.
+pub fn refresh(client: &reqwest::blocking::Client, secrets: &Secrets) -> Result<String, Error> {
+    let resp = client.post(&format!("{}/token", auth_base())).form(&grant(secrets)).send()?;
+    if resp.status() != 200 {
+        return Err(BotError::twitch_api(resp.status(), "Twitch client credentials request").into());
+    }
+    let granted: ClientCredentials = resp.json()?;
+    granted_token(secrets, &granted);
+
+    if write_back() {
+        // Called from blocking lookup threads, which can still hand work to the runtime.
+        let saved = tokio::runtime::Handle::try_current()
+            .map_err(Error::from)
+            .and_then(|runtime| runtime.block_on(secrets::store_twitch_app_token(&granted.access_token)));
+        if let Err(e) = saved {
+            error!("Could not save the refreshed Twitch app token: {}", e);
+        }
+    }
+    Ok(granted.access_token)
+}
+
+/// [`refresh`] for the async client.
+pub async fn refresh_async(client: &reqwest::Client, secrets: &Secrets) -> Result<String, Error> {
+    let resp = client.post(&format!("{}/token", auth_base())).form(&grant(secrets)).send().await?;
+    if resp.status() != 200 {
+        return Err(BotError::twitch_api(resp.status(), "Twitch client credentials request").into());
+    }
+    let granted: ClientCredentials = resp.json().await?;
+    granted_token(secrets, &granted);
+
+    if write_back() {
+        if let Err(e) = secrets::store_twitch_app_token(&granted.access_token).await {
+            error!("Could not save the refreshed Twitch app token: {}", e);
+        }
+    }
+    Ok(granted.access_token)
+}
+
+/// The token to use if it was checked recently enough, or else the one that needs checking.
+fn known(secrets: &Secrets) -> Result<String, String> {
+    let now = now_secs();
+    let known = TOKEN
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .filter(|known| known.stored == secrets.twitch_app_token);
+    match known {
+        Some(known)
+            if now.saturating_sub(known.checked_at) < VALIDATE_INTERVAL_SECS
+                && known.expires_at > now + EXPIRY_MARGIN_SECS =>
+        {
+            Ok(known.token)
+        }
+        Some(known) => Err(known.token),
+        None => Err(secrets.twitch_app_token.clone()),
+    }
+}
+
+/// Remembers `candidate` if validation gave it long enough to live; otherwise it needs replacing.
+fn accept(secrets: &Secrets, candidate: &str, validated: Result<u64, Error>) -> bool {
+    match validated {
+        Ok(expires_in) if expires_in > EXPIRY_MARGIN_SECS => {
+            remember(secrets, candidate, now_secs() + expires_in);
+            true
+        }
+        Ok(_) => false,
+        Err(e) => {
+            warn!("Twitch app token failed validation: {}", e);
+            false
+        }
+    }
+}
+
+async fn validate_async(client: &reqwest::Client, token: &str) -> Result<u64, Error> {
+    let resp = client
+        .get(&format!("{}/validate", auth_base()))
+        .header("Authorization", format!("OAuth {}", token))
+        .send()
+        .await?;
+    if resp.status() != 200 {
+        return Err(BotError::twitch_api(resp.status(), "Twitch token validation").into());
+    }
+    Ok(resp.json::<Validation>().await?.expires_in)
+}
+
+fn grant(secrets: &Secrets) -> [(&str, &str); 3] {
+    [
+        ("client_id", secrets.twitch_client_id.as_str()),
+        ("client_secret", secrets.twitch_client_secret.as_str()),
+        ("grant_type", "client_credentials"),
+    ]
+}
+
+fn granted_token(secrets: &Secrets, granted: &ClientCredentials) {
+    info!("Refreshed the Twitch app token, valid for {}h", granted.expires_in / 3600);
+    remember(secrets, &granted.access_token, now_secs() + granted.expires_in);
+}
+
+fn write_back() -> bool {
+    std::env::var("APP_TOKEN_WRITE_BACK").map_or(false, |write_back| write_back == "true")
+}
+
+fn remember(secrets: &Secrets, token: &str, expires_at: u64) {
+    *TOKEN.lock().unwrap_or_else(|e| e.into_inner()) = Some(AppToken {
+        stored: secrets.twitch_app_token.clone(),
+        token: token.to_string(),
+        checked_at: now_secs(),
+        expires_at,
+    });
+}
@@ -0,0 +1,345 @@
+//! Declared argument schemas for slash commands. A command lists its subcommands, positional
+//! arguments and `--flags` once; the same declaration parses the text, produces the validation
+//! errors and renders the usage text.
+//!
+//! `--dry-run` is stripped from every command's text, schema or not: commands that make changes
+//! check it (or [`take_dry_run`] it themselves) and report what they would have done instead.
+//! Usage text only offers it for schemas that say they support it.
+
+use std::collections::HashMap;
+use std::fmt;
+
+pub const DRY_RUN_FLAG: &str = "--dry-run";
+
+/// Removes `--dry-run` from anywhere in the text, saying whether it was there.
+pub fn take_dry_run(text: &str) -> (String, bool) {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let kept: Vec<&str> = words.iter().copied().filter(|word| *word != DRY_RUN_FLAG).collect();
+    let dry_run = kept.len() != words.len();
+    (kept.join(" "), dry_run)
+}
+
+/// The reply to a dry run: what the command would have done.
+pub fn dry_run_report(action: &str) -> String {
+    format!("*Dry run:* {}. Nothing was changed.", action)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// A Twitch login; a leading `@` is dropped and it's lowercased.
+    Login,
+    /// Any single word.
+    Word,
+    /// A whole number.
+    Number,
+    /// Seconds, or a number with an `s`/`m`/`h`/`d`/`w` suffix.
+    Duration,
+    /// The rest of the text, words and all. Only valid as the last positional argument.
+    Text,
+}
+
+impl Kind {
+    fn expected(self) -> &'static str {
+        match self {
+            Kind::Login => "a Twitch login",
+            Kind::Word => "a word",
+            Kind::Number => "a number",
+            Kind::Duration => "a duration like `90`, `10m` or `2h`",
+            Kind::Text => "some text",
+        }
+    }
+
+    fn parse(self, word: &str) -> Option<Value> {
+        match self {
+            Kind::Login => {
+                let login = word.trim_start_matches('@').to_ascii_lowercase();
+                if login.is_empty()
+                    || login.len() > 25
+                    || !login.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+                {
+                    return None;
+                }
+                Some(Value::Text(login))
+            }
+            Kind::Word | Kind::Text => Some(Value::Text(word.to_string())),
+            Kind::Number => word.parse().ok().map(Value::Number),
+            Kind::Duration => parse_seconds(word).map(Value::Number),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(u64),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub required: bool,
+    pub help: &'static str,
+}
+
+impl Arg {
+    pub const fn required(name: &'static str, kind: Kind, help: &'static str) -> Arg {
+        Arg {
+            name,
+            kind,
+            required: true,
+            help,
+        }
+    }
+
+    pub const fn optional(name: &'static str, kind: Kind, help: &'static str) -> Arg {
+        Arg {
+            name,
+            kind,
+            required: false,
+            help,
+        }
+    }
+}
+
+/// A `--name` flag, with a value of the given kind (`--name value`) or none (a switch).
+#[derive(Debug, Clone, Copy)]
+pub struct Flag {
+    pub name: &'static str,
+    pub value: Option<Kind>,
+    pub help: &'static str,
+}
+
+/// One form of a command: `subcommand` is its leading keyword, or `None` for a command that
+/// takes its arguments directly.
+#[derive(Debug, Clone, Copy)]
+pub struct Schema {
+    pub subcommand: Option<&'static str>,
+    pub summary: &'static str,
+    pub args: &'static [Arg],
+    pub flags: &'static [Flag],
+}
+
+/// Every form a command accepts, in the order the usage text lists them.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandSchema {
+    pub command: &'static str,
+    pub summary: &'static str,
+    pub forms: &'static [Schema],
+    /// The command reports what it would do for `--dry-run` instead of doing it.
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgError {
+    UnknownSubcommand(String),
+    Missing(&'static str),
+    Invalid {
+        name: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+    UnknownFlag(String),
+    Unexpected(String),
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgError::UnknownSubcommand(word) => write!(f, "`{}` isn't something this command does.", word),
+            ArgError::Missing(name) => write!(f, "Missing `<{}>`.", name),
+            ArgError::Invalid { name, value, expected } => {
+                write!(f, "`{}` isn't valid for `<{}>`; expected {}.", value, name, expected)
+            }
+            ArgError::UnknownFlag(flag) => write!(f, "Unknown flag `--{}`.", flag),
+            ArgError::Unexpected(word) => write!(f, "Didn't expect `{}`.", word),
+        }
+    }
+}
+
+/// Parsed arguments. Getters return `None` for optional arguments that weren't given.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Args {
+    pub subcommand: Option<&'static str>,
+    /// `--dry-run` was given.
+    pub dry_run: bool,
+    values: HashMap<&'static str, Value>,
+    flags: HashMap<&'static str, Option<Value>>,
+}
+
+impl Args {
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.values.get(name) {
+            Some(Value::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn number(&self, name: &str) -> Option<u64> {
+        match self.values.get(name) {
+            Some(Value::Number(number)) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// Whether `--name` was given at all.
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains_key(name)
+    }
+
+    pub fn flag_value(&self, name: &str) -> Option<&Value> {
+        self.flags.get(name).and_then(Option::as_ref)
+    }
+}
+
+impl CommandSchema {
+    pub fn parse(&self, text: &str) -> Result<Args, ArgError> {
+        let (text, dry_run) = take_dry_run(text);
+        let mut args = self.parse_words(&text)?;
+        args.dry_run = dry_run;
+        Ok(args)
+    }
+
+    fn parse_words(&self, text: &str) -> Result<Args, ArgError> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let first = words.first().copied();
+
+        let keyword = self.forms.iter().find(|form| form.subcommand.is_some() && form.subcommand == first);
+        let (form, rest) = match keyword {
+            Some(form) => (form, &words[1..]),
+            None => match self.forms.iter().find(|form| form.subcommand.is_none()) {
+                Some(form) => (form, &words[..]),
+                None => {
+                    return Err(match first {
+                        Some(word) => ArgError::UnknownSubcommand(word.to_string()),
+                        None => ArgError::Missing("subcommand"),
+                    })
+                }
+            },
+        };
+        form.parse(rest)
+    }
+
+    /// Usage text listing every form, its arguments and flags.
+    pub fn usage(&self) -> String {
+        let mut lines = vec![format!("{}:", self.summary)];
+        for form in self.forms {
+            lines.push(format!("`{}` - {}", form.synopsis(self.command), form.summary));
+            for arg in form.args {
+                lines.push(format!("    `<{}>` {}", arg.name, arg.help));
+            }
+            for flag in form.flags {
+                lines.push(format!("    `--{}` {}", flag.name, flag.help));
+            }
+        }
+        if self.dry_run {
+            lines.push(format!("Add `{}` to see what a change would do without making it.", DRY_RUN_FLAG));
+        }
+        lines.push("End with `quiet` (or `private`) to show the reply only to you.".to_string());
+        lines.join("\n")
+    }
+
+    /// An error followed by the usage, for replying to text that didn't parse.
+    pub fn error_message(&self, error: &ArgError) -> String {
+        format!("{}\n{}", error, self.usage())
+    }
+}
+
+impl Schema {
+    fn synopsis(&self, command: &str) -> String {
+        let mut parts = vec![command.to_string()];
+        parts.extend(self.subcommand.map(str::to_string));
+        for arg in self.args {
+            let name = if arg.kind == Kind::Text { format!("{}…", arg.name) } else { arg.name.to_string() };
+            parts.push(if arg.required { format!("<{}>", name) } else { format!("[{}]", name) });
+        }
+        for flag in self.flags {
+            parts.push(match flag.value {
+                Some(_) => format!("[--{} <value>]", flag.name),
+                None => format!("[--{}]", flag.name),
+            });
+        }
+        parts.join(" ")
+    }
+
+    fn parse(&self, words: &[&str]) -> Result<Args, ArgError> {
+        let mut args = Args {
+            subcommand: self.subcommand,
+            ..Args::default()
+        };
+        let mut positional = vec![];
+
+        let mut words = words.iter();
+        while let Some(word) = words.next() {
+            let name = match word.strip_prefix("--") {
+                Some(name) => name,
+                None => {
+                    positional.push(*word);
+                    continue;
+                }
+            };
+            let flag = match self.flags.iter().find(|f| f.name == name) {
+                Some(flag) => flag,
+                None => return Err(ArgError::UnknownFlag(name.to_string())),
+            };
+            let value = match flag.value {
+                Some(kind) => {
+                    let word = words.next().ok_or(ArgError::Missing(flag.name))?;
+                    Some(kind.parse(word).ok_or_else(|| invalid(flag.name, word, kind))?)
+                }
+                None => None,
+            };
+            args.flags.insert(flag.name, value);
+        }
+
+        let mut positional = positional.into_iter();
+        for arg in self.args {
+            let value = if arg.kind == Kind::Text {
+                let rest: Vec<&str> = positional.by_ref().collect();
+                if rest.is_empty() {
+                    None
+                } else {
+                    Some(Value::Text(rest.join(" ")))
+                }
+            } else {
+                match positional.next() {
+                    Some(word) => Some(arg.kind.parse(word).ok_or_else(|| invalid(arg.name, word, arg.kind))?),
+                    None => None,
+                }
+            };
+            match value {
+                Some(value) => {
+                    args.values.insert(arg.name, value);
+                }
+                None if arg.required => return Err(ArgError::Missing(arg.name)),
+                None => {}
+            }
+        }
+        if let Some(extra) = positional.next() {
+            return Err(ArgError::Unexpected(extra.to_string()));
+        }
+        Ok(args)
+    }
+}
+
+fn invalid(name: &'static str, value: &str, kind: Kind) -> ArgError {
+    ArgError::Invalid {
+        name,
+        value: value.to_string(),
+        expected: kind.expected(),
+    }
+}
+
+fn parse_seconds(word: &str) -> Option<u64> {
+    let split = word.find(|c: char| !c.is_ascii_digit()).unwrap_or(word.len());
+    let (digits, unit) = word.split_at(split);
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    digits.parse::<u64>().ok().map(|amount| amount.saturating_mul(multiplier))
+}
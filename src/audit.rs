@@ -0,0 +1,126 @@
+//! Append-only record of the actions moderators and admins take through the bot, keyed by
+//! workspace and sorted by time. Entries are written before the action runs, so a failed or
+//! interrupted action still leaves a trace of who attempted it.
+//!
+//! Config changes also keep the fields' previous values, which is what `/tundo` restores, and the
+//! values they set, which `/tundo` checks are still in place first.
+
+use crate::stage::Stage;
+use crate::store::{self, Attr, Attrs, SortedStore};
+use crate::Error;
+use chrono::{SecondsFormat, Utc};
+use log::info;
+use serde_json::{Map, Value};
+
+const DEFAULT_TABLE: &str = "tuser-audit";
+
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub team_id: String,
+    /// Slack user who ran the action.
+    pub user_id: String,
+    pub action: String,
+    /// Human-readable description of what the action does.
+    pub detail: String,
+}
+
+/// An entry read back from the log.
+#[derive(Debug, Clone)]
+pub struct RecordedEntry {
+    pub recorded_at: String,
+    pub user_id: String,
+    pub action: String,
+    pub detail: String,
+    /// For config changes, the changed fields' values before the change.
+    pub previous: Option<Map<String, Value>>,
+    /// For config changes, the values the change set. Missing from entries recorded before these
+    /// were kept.
+    pub applied: Option<Map<String, Value>>,
+    /// For undos, the `recorded_at` of the entry that was undone.
+    pub undoes: Option<String>,
+}
+
+pub struct AuditLog {
+    store: Box<dyn SortedStore>,
+}
+
+impl AuditLog {
+    pub fn new(store: Box<dyn SortedStore>) -> AuditLog {
+        AuditLog { store }
+    }
+
+    pub fn from_env() -> AuditLog {
+        let table = std::env::var("AUDIT_TABLE").unwrap_or_else(|_| Stage::current().resource(DEFAULT_TABLE));
+        AuditLog::new(store::open_sorted(table, "team_id", "recorded_at"))
+    }
+
+    pub async fn record(&self, entry: &AuditEntry) -> Result<(), Error> {
+        self.put(entry, Attrs::new()).await
+    }
+
+    /// Records a config change along with the previous and new values of the fields it touches.
+    /// `undoes` marks the change as the undo of an earlier entry.
+    pub async fn record_change(
+        &self,
+        entry: &AuditEntry,
+        previous: &Map<String, Value>,
+        applied: &Map<String, Value>,
+        undoes: Option<&str>,
+    ) -> Result<(), Error> {
+        let mut extra = Attrs::new();
+        extra.insert("previous".to_string(), Attr::S(Value::Object(previous.clone()).to_string()));
+        extra.insert("applied".to_string(), Attr::S(Value::Object(applied.clone()).to_string()));
+        if let Some(undoes) = undoes {
+            extra.insert("undoes".to_string(), Attr::S(undoes.to_string()));
+        }
+        self.put(entry, extra).await
+    }
+
+    /// The workspace's entries, newest first, stopping after `limit`.
+    pub async fn recent(&self, team_id: &str, limit: usize) -> Result<Vec<RecordedEntry>, Error> {
+        let mut entries = vec![];
+        for (recorded_at, mut item) in self.store.newest(team_id, limit).await? {
+            let mut take = |name: &str| item.remove(name).and_then(Attr::into_string);
+            let previous = fields(take("previous"))?;
+            let applied = fields(take("applied"))?;
+            entries.push(RecordedEntry {
+                recorded_at,
+                user_id: take("user_id").unwrap_or_default(),
+                action: take("action").unwrap_or_default(),
+                detail: take("detail").unwrap_or_default(),
+                previous,
+                applied,
+                undoes: take("undoes"),
+            });
+        }
+        Ok(entries)
+    }
+
+    async fn put(&self, entry: &AuditEntry, extra: Attrs) -> Result<(), Error> {
+        info!(
+            target: "audit",
+            "team={} user={} action={} {}",
+            entry.team_id, entry.user_id, entry.action, entry.detail
+        );
+
+        // Microsecond timestamps keep entries from the same workspace distinct and in order.
+        let recorded_at = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+        let mut item = Attrs::new();
+        item.insert("user_id".to_string(), Attr::S(entry.user_id.clone()));
+        item.insert("action".to_string(), Attr::S(entry.action.clone()));
+        item.insert("detail".to_string(), Attr::S(entry.detail.clone()));
+        item.extend(extra);
+        self.store.put(&entry.team_id, &recorded_at, item).await
+    }
+}
+
+/// A recorded map of config fields, if the entry has one.
+fn fields(recorded: Option<String>) -> Result<Option<Map<String, Value>>, Error> {
+    match recorded {
+        Some(recorded) => match serde_json::from_str(&recorded)? {
+            Value::Object(fields) => Ok(Some(fields)),
+            _ => Ok(None),
+        },
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,136 @@
+use log::error;
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::links::{self, AccountLink, LinkStore};
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::slack::{self, SlackMessage, SlashCommand};
+use twitch_info_bot::twitch::{self, AutomodSettings, Credentials, LookupError, TimeoutConfig};
+use twitch_info_bot::{error, local, logging, Error};
+
+const USAGE: &str = "Check a channel's AutoMod:\n\
+    `/tautomod [channel] settings`\n\
+    `/tautomod [channel] check <message>`";
+
+enum AutomodCommand {
+    Settings,
+    Check(String),
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(|event| async move { automod(event).await.or_else(error::reply) }).await
+}
+
+async fn automod(event: Value) -> Result<SlackMessage, Error> {
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return slack::malformed_request();
+        }
+    };
+    let secrets = secrets::verify_slack_request(&event, &req.token).await?.secrets;
+
+    let (channel, command) = match parse_command(&req.text) {
+        Some(parsed) => parsed,
+        None => return SlackMessage::builder().text(USAGE).build(),
+    };
+    let scope = match command {
+        AutomodCommand::Settings => "moderator:read:automod_settings",
+        AutomodCommand::Check(_) => "moderation:read",
+    };
+
+    let store = LinkStore::from_env();
+    let link = links::command_link(&store, &secrets, &req.team_id, &req.user_id, channel.as_deref(), scope).await?;
+    let link = match link {
+        Ok(link) => link,
+        Err(message) => return SlackMessage::builder().text(message).build(),
+    };
+
+    let timeouts = TimeoutConfig::from_env();
+    let text = tokio::task::spawn_blocking(move || {
+        run(&command, &link, &secrets, &timeouts).unwrap_or_else(|e| e.user_message(&link.login))
+    })
+    .await?;
+
+    // Test messages can be offensive; keep the results to the invoker.
+    SlackMessage::builder().ephemeral().text(text).build()
+}
+
+/// `[channel] settings` or `[channel] check <message...>`.
+fn parse_command(text: &str) -> Option<(Option<String>, AutomodCommand)> {
+    let text = text.trim();
+    let (first, rest) = split_word(text);
+
+    let (channel, command, rest) = match first {
+        "settings" | "check" => (None, first, rest),
+        "" => return None,
+        channel => {
+            let (command, rest) = split_word(rest);
+            (Some(channel.to_ascii_lowercase()), command, rest)
+        }
+    };
+
+    match (command, rest.is_empty()) {
+        ("settings", _) => Some((channel, AutomodCommand::Settings)),
+        ("check", false) => Some((channel, AutomodCommand::Check(rest.to_string()))),
+        _ => None,
+    }
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+fn run(
+    command: &AutomodCommand,
+    link: &AccountLink,
+    secrets: &Secrets,
+    timeouts: &TimeoutConfig,
+) -> Result<String, LookupError> {
+    let client = twitch::client(timeouts)?;
+    let credentials = Credentials::user(secrets, link);
+
+    match command {
+        AutomodCommand::Settings => {
+            let settings =
+                twitch::get_automod_settings(&client, credentials, &link.twitch_user_id, &link.twitch_user_id)?;
+            Ok(render_settings(&link.login, &settings))
+        }
+        AutomodCommand::Check(message) => {
+            if twitch::check_automod(&client, credentials, &link.twitch_user_id, message)? {
+                Ok(format!("✅ AutoMod on {} would allow that message", link.login))
+            } else {
+                Ok(format!("🚫 AutoMod on {} would hold that message for review", link.login))
+            }
+        }
+    }
+}
+
+fn render_settings(login: &str, settings: &AutomodSettings) -> String {
+    let overall = match settings.overall_level {
+        Some(level) => format!("Overall level {} of 4", level),
+        None => "Custom levels".to_string(),
+    };
+    let levels = [
+        ("Discrimination (disability)", settings.disability),
+        ("Hostility (aggression)", settings.aggression),
+        ("Sexuality, sex or gender", settings.sexuality_sex_or_gender),
+        ("Misogyny", settings.misogyny),
+        ("Bullying", settings.bullying),
+        ("Swearing", settings.swearing),
+        ("Race, ethnicity or religion", settings.race_ethnicity_or_religion),
+        ("Sex-based terms", settings.sex_based_terms),
+    ];
+    let lines: Vec<String> = levels
+        .iter()
+        .map(|(name, level)| format!("• {}: {}", name, level))
+        .collect();
+
+    format!("AutoMod settings for {} — {}\n{}", login, overall, lines.join("\n"))
+}
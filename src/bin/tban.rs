@@ -0,0 +1,46 @@
+use log::error;
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::args;
+use twitch_info_bot::links::{self, LinkStore};
+use twitch_info_bot::moderation::{BanRequest, BAN_SCOPE};
+use twitch_info_bot::secrets;
+use twitch_info_bot::slack::{self, SlackMessage, SlashCommand};
+use twitch_info_bot::{error, local, logging, Error};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(|event| async move { preview_ban(event).await.or_else(error::reply) }).await
+}
+
+/// `/tban <channel> <user> [duration] [reason]`: checks the invoker's moderator link and shows
+/// a confirmation. The ban itself runs from the interactivity endpoint once they confirm.
+async fn preview_ban(event: Value) -> Result<SlackMessage, Error> {
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return slack::malformed_request();
+        }
+    };
+    let secrets = secrets::verify_slack_request(&event, &req.token).await?.secrets;
+
+    let (text, dry_run) = args::take_dry_run(&req.text);
+    let request = match BanRequest::parse(&text) {
+        Ok(request) => request,
+        Err(message) => return SlackMessage::builder().text(message).build(),
+    };
+
+    let store = LinkStore::from_env();
+    if let Err(message) = links::command_link(&store, &secrets, &req.team_id, &req.user_id, None, BAN_SCOPE).await? {
+        return SlackMessage::builder().text(message).build();
+    }
+
+    if dry_run {
+        let report = args::dry_run_report(&request.describe());
+        return SlackMessage::builder().ephemeral().text(report).build();
+    }
+    request.confirmation()
+}
@@ -0,0 +1,174 @@
+use futures::future::BoxFuture;
+use tokio;
+use twitch_info_bot::args::{self, Arg, Args, CommandSchema, Kind, Schema};
+use twitch_info_bot::command::{Command, Invocation, Router};
+use twitch_info_bot::links::AccountLink;
+use twitch_info_bot::secrets::Secrets;
+use twitch_info_bot::slack::SlackMessage;
+use twitch_info_bot::twitch::{self, BlockedUser, Credentials, LookupError, TimeoutConfig};
+use twitch_info_bot::{local, logging, Error};
+
+const TARGET: Arg = Arg::required("login", Kind::Login, "the Twitch user to block or unblock");
+
+static SCHEMA: CommandSchema = CommandSchema {
+    command: "/tblock",
+    summary: "Manage the Twitch users you've blocked",
+    forms: &[
+        Schema {
+            subcommand: Some("list"),
+            summary: "list who you've blocked",
+            args: &[],
+            flags: &[],
+        },
+        Schema {
+            subcommand: Some("add"),
+            summary: "block someone",
+            args: &[TARGET],
+            flags: &[],
+        },
+        Schema {
+            subcommand: Some("remove"),
+            summary: "unblock someone",
+            args: &[TARGET],
+            flags: &[],
+        },
+    ],
+    dry_run: true,
+};
+
+const READ_SCOPE: &str = "user:read:blocked_users";
+const MANAGE_SCOPE: &str = "user:manage:blocked_users";
+
+/// Stop reading the block list past this many users.
+const MAX_BLOCKS: usize = 1000;
+/// How many blocked users `list` names before summarising the rest.
+const MAX_LISTED: usize = 100;
+
+#[derive(Debug, PartialEq)]
+enum BlockCommand {
+    List,
+    Add(String),
+    Remove(String),
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    let router: &'static Router = Box::leak(Box::new(Router::with_defaults().command(Blocks)));
+    local::serve(move |event| router.dispatch(event)).await
+}
+
+/// `/tblock list|add|remove <login>` for the invoker's own linked account. Replies are
+/// ephemeral; who someone blocks is their business.
+struct Blocks;
+
+impl Command for Blocks {
+    fn name(&self) -> &'static str {
+        "/tblock"
+    }
+
+    fn schema(&self) -> Option<&'static CommandSchema> {
+        Some(&SCHEMA)
+    }
+
+    fn scope(&self, invocation: &Invocation) -> Option<&'static str> {
+        match parse_command(&invocation.args)? {
+            BlockCommand::List => Some(READ_SCOPE),
+            BlockCommand::Add(_) | BlockCommand::Remove(_) => Some(MANAGE_SCOPE),
+        }
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>> {
+        Box::pin(manage_blocks(invocation))
+    }
+}
+
+async fn manage_blocks(invocation: Invocation) -> Result<SlackMessage, Error> {
+    let (command, link) = match (parse_command(&invocation.args), invocation.link) {
+        (Some(command), Some(link)) => (command, link),
+        _ => return SlackMessage::builder().text(SCHEMA.usage()).build(),
+    };
+    let secrets = invocation.secrets;
+
+    if invocation.args.dry_run {
+        let action = match &command {
+            BlockCommand::List => format!("list who {} has blocked", link.login),
+            BlockCommand::Add(target) => format!("block {} on {}", target, link.login),
+            BlockCommand::Remove(target) => format!("unblock {} on {}", target, link.login),
+        };
+        return SlackMessage::builder().ephemeral().text(args::dry_run_report(&action)).build();
+    }
+
+    let timeouts = TimeoutConfig::from_env();
+    let text = tokio::task::spawn_blocking(move || {
+        run(&command, &link, &secrets, &timeouts).unwrap_or_else(|e| e.user_message(&link.login))
+    })
+    .await?;
+
+    SlackMessage::builder().ephemeral().text(text).build()
+}
+
+fn parse_command(args: &Args) -> Option<BlockCommand> {
+    let target = || args.text("login").map(str::to_string);
+
+    match args.subcommand? {
+        "list" => Some(BlockCommand::List),
+        "add" => target().map(BlockCommand::Add),
+        "remove" => target().map(BlockCommand::Remove),
+        _ => None,
+    }
+}
+
+fn run(
+    command: &BlockCommand,
+    link: &AccountLink,
+    secrets: &Secrets,
+    timeouts: &TimeoutConfig,
+) -> Result<String, LookupError> {
+    let client = twitch::client(timeouts)?;
+    let credentials = Credentials::user(secrets, link);
+
+    let (target, blocked) = match command {
+        BlockCommand::List => {
+            let blocked = twitch::get_blocked_users(&client, credentials, &link.twitch_user_id, MAX_BLOCKS)?;
+            return Ok(render_list(&link.login, &blocked));
+        }
+        BlockCommand::Add(target) => (target, true),
+        BlockCommand::Remove(target) => (target, false),
+    };
+
+    let user = match twitch::get_users_by_login(&client, &[target.clone()], secrets)?.pop() {
+        Some(user) => user,
+        None => return Ok(format!("No Twitch user named {}", target)),
+    };
+    twitch::set_blocked(&client, credentials, &user.id, blocked)?;
+
+    Ok(if blocked {
+        format!("{} is now blocked on {}.", user.display_name, link.login)
+    } else {
+        format!("{} is no longer blocked on {}.", user.display_name, link.login)
+    })
+}
+
+fn render_list(login: &str, blocked: &[BlockedUser]) -> String {
+    if blocked.is_empty() {
+        return format!("{} hasn't blocked anyone.", login);
+    }
+
+    let mut lines: Vec<String> = blocked
+        .iter()
+        .take(MAX_LISTED)
+        .map(|b| format!("• {} ({})", b.display_name, b.user_login))
+        .collect();
+    if blocked.len() > MAX_LISTED {
+        lines.push(format!("…and {} more", blocked.len() - MAX_LISTED));
+    }
+
+    let count = if blocked.len() >= MAX_BLOCKS {
+        format!("at least {}", MAX_BLOCKS)
+    } else {
+        blocked.len().to_string()
+    };
+    format!("{} has blocked {} users:\n{}", login, count, lines.join("\n"))
+}
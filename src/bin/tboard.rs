@@ -0,0 +1,121 @@
+use log::{error, info};
+use serde_json::{json, Value};
+use tokio;
+use twitch_info_bot::audit::{AuditEntry, AuditLog};
+use twitch_info_bot::board;
+use twitch_info_bot::http;
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::slack::{self, SlackMessage, SlashCommand};
+use twitch_info_bot::workspace::{BoardLocation, WorkspaceStore};
+use twitch_info_bot::{error, local, logging, Error};
+
+const USAGE: &str = "Keep a pinned status board of the watchlist in this channel:\n\
+    `/tboard` — post and pin the board here (replacing any previous board)\n\
+    `/tboard refresh` — update it now (it also refreshes every few minutes)";
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(handle_board).await
+}
+
+/// Serves `/tboard [refresh]`, and the scheduled refresh of every workspace's board.
+async fn handle_board(event: Value) -> Result<Value, Error> {
+    if event.get("source").and_then(Value::as_str) == Some("aws.events") {
+        return refresh_all().await;
+    }
+
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return Ok(serde_json::to_value(slack::malformed_request()?)?);
+        }
+    };
+    let message = match secrets::verify_slack_request(&event, &req.token).await {
+        Ok(verified) => board_command(&req, verified.secrets).await,
+        Err(e) => Err(e),
+    };
+    Ok(serde_json::to_value(message.or_else(error::reply)?)?)
+}
+
+async fn board_command(req: &SlashCommand, secrets: Secrets) -> Result<SlackMessage, Error> {
+    let refresh = match req.text.trim() {
+        "" => false,
+        "refresh" => true,
+        _ => return SlackMessage::builder().text(USAGE).build(),
+    };
+
+    let workspaces = WorkspaceStore::from_env();
+    let mut config = workspaces.load(&req.team_id).await?;
+    if config.watchlist.is_empty() {
+        return SlackMessage::builder()
+            .text("The watchlist is empty. Add channels in the bot's setup first.")
+            .build();
+    }
+    let token = workspaces
+        .bot_token(&req.team_id)
+        .await?
+        .unwrap_or_else(|| secrets.slack_bot_token.clone());
+
+    let location = match (refresh, config.board.clone()) {
+        (true, Some(location)) => location,
+        (true, None) => {
+            return SlackMessage::builder()
+                .text("There's no board yet. Run `/tboard` to post one.")
+                .build()
+        }
+        (false, _) => {
+            let board = board::message(&req.team_id, &config, &secrets).await?;
+            let channel = req.channel_id.clone();
+            let location = tokio::task::spawn_blocking(move || -> Result<BoardLocation, Error> {
+                let client = http::client();
+                let ts = slack::post(&client, &token, &channel, &board)?;
+                slack::pin(&client, &token, &channel, &ts)?;
+                Ok(BoardLocation { channel, ts })
+            })
+            .await??;
+
+            let previous = config.clone();
+            config.board = Some(location);
+            let entry = AuditEntry {
+                team_id: req.team_id.clone(),
+                user_id: req.user_id.clone(),
+                action: "board".to_string(),
+                detail: format!("post the status board in <#{}>", req.channel_id),
+            };
+            workspaces.save_change(&AuditLog::from_env(), &entry, &previous, &config).await?;
+            return SlackMessage::builder().text("Posted and pinned the board.").build();
+        }
+    };
+
+    board::update(&req.team_id, &config, &location, token, &secrets).await?;
+    SlackMessage::builder().text("Board refreshed.").build()
+}
+
+/// Updates every workspace's board; a failure in one workspace doesn't stop the rest.
+async fn refresh_all() -> Result<Value, Error> {
+    let secrets = secrets::current().await?;
+    let workspaces = WorkspaceStore::from_env();
+    let mut refreshed = 0;
+
+    for (team_id, config) in workspaces.all().await? {
+        let location = match &config.board {
+            Some(location) if !config.watchlist.is_empty() => location.clone(),
+            _ => continue,
+        };
+        let token = workspaces
+            .bot_token(&team_id)
+            .await?
+            .unwrap_or_else(|| secrets.slack_bot_token.clone());
+
+        match board::update(&team_id, &config, &location, token, &secrets).await {
+            Ok(()) => refreshed += 1,
+            Err(e) => error!("Could not refresh the board for workspace {}: {}", team_id, e),
+        }
+    }
+
+    info!("Refreshed {} status boards", refreshed);
+    Ok(json!({ "refreshed": refreshed }))
+}
@@ -0,0 +1,223 @@
+use log::{error, info};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use tokio;
+use twitch_info_bot::deferred;
+use twitch_info_bot::dm::{self, Message, TypedCommand};
+use twitch_info_bot::http;
+use twitch_info_bot::local;
+use twitch_info_bot::logging;
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::slack::blocks::Blocks;
+use twitch_info_bot::slack::{self, SlackMessage};
+use twitch_info_bot::twitch::{self, TimeoutConfig};
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::Error;
+
+/// Upper bound on logins looked up from one shared file.
+const MAX_BULK_LOGINS: usize = 1000;
+/// How many of the logins that weren't found the summary names.
+const SHOWN_MISSING: usize = 20;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(handle_event).await
+}
+
+/// Slack Events API endpoint. Answers the `url_verification` handshake, runs a bulk lookup for
+/// text/CSV files sent to the bot, and runs commands typed in DMs and mentions.
+async fn handle_event(event: Value) -> Result<Value, Error> {
+    let body: Value = serde_json::from_str(&http::body(&event)?)?;
+
+    // Slack retries events it didn't see acknowledged within 3 seconds; the first delivery is
+    // still working on it.
+    if http::header(&event, "X-Slack-Retry-Num").is_some() {
+        return Ok(json!({ "ok": true }));
+    }
+
+    let token = body.get("token").and_then(Value::as_str).unwrap_or_default();
+    let secrets = secrets::verify_slack_request(&event, token).await?.secrets;
+
+    match body.get("type").and_then(Value::as_str) {
+        Some("url_verification") => return Ok(json!({ "challenge": body["challenge"] })),
+        Some("event_callback") => {}
+        other => {
+            info!("Ignoring Slack payload of type {:?}", other);
+            return Ok(json!({ "ok": true }));
+        }
+    }
+
+    let inner = &body["event"];
+    let team_id = body.get("team_id").and_then(Value::as_str).unwrap_or_default();
+    if let Some((channel, file_ids)) = files_for_bot(inner) {
+        if deferred::enabled() && !deferred::is_deferred(&event) {
+            match deferred::invoke_self(&event).await {
+                Ok(()) => return Ok(json!({ "ok": true })),
+                Err(e) => error!("Could not defer the bulk lookup, running it now: {}", e),
+            }
+        }
+        let timeouts = TimeoutConfig::from_env();
+        let token = bot_token(team_id, &secrets).await?;
+
+        tokio::task::spawn_blocking(move || {
+            for file_id in &file_ids {
+                if let Err(e) = bulk_lookup(file_id, &channel, &token, &secrets, &timeouts) {
+                    error!("Bulk lookup of file {} failed: {}", file_id, e);
+                }
+            }
+        })
+        .await?;
+        return Ok(json!({ "ok": true }));
+    }
+
+    if let Some(message) = Message::from_event(team_id, inner) {
+        if let Err(e) = run_typed_command(&message, &secrets).await {
+            error!("Command typed in {} failed: {}", message.channel_id, e);
+        }
+        return Ok(json!({ "ok": true }));
+    }
+
+    Ok(json!({ "ok": true }))
+}
+
+/// The channel and files of an event that sends files to the bot: a file shared in a DM with it
+/// (DM channel IDs start with `D`), or files attached to a message mentioning it. Files shared
+/// anywhere else were posted for someone else, and are left alone.
+fn files_for_bot(event: &Value) -> Option<(String, Vec<String>)> {
+    let field = |name: &str| event.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+    let (channel, file_ids) = match event.get("type").and_then(Value::as_str) {
+        Some("file_shared") => (field("channel_id"), vec![field("file_id")]),
+        Some("app_mention") => {
+            let files = event.get("files").and_then(Value::as_array)?;
+            let ids = files.iter().filter_map(|file| file["id"].as_str().map(String::from)).collect();
+            (field("channel"), ids)
+        }
+        _ => return None,
+    };
+    let for_bot = event["type"] == "app_mention" || channel.starts_with('D');
+    if !for_bot || file_ids.is_empty() || file_ids.iter().any(String::is_empty) {
+        return None;
+    }
+    Some((channel, file_ids))
+}
+
+/// Runs the command in a DM or mention through its own function and posts the reply. Messages
+/// that aren't commands get a pointer to the ones that exist.
+async fn run_typed_command(message: &Message, secrets: &Secrets) -> Result<(), Error> {
+    let reply = match TypedCommand::parse(&message.text) {
+        Some(command) => {
+            info!("Running {} typed by {}", command.command, message.user_id);
+            let event = command.slash_event(message, secrets, Utc::now().timestamp())?;
+            dm::invoke(&command, &event).await?
+        }
+        None => json!({
+            "response_type": "ephemeral",
+            "text": format!(
+                "Send a command the way you'd type it after the slash, e.g. `tuser ninja`. Commands: {}",
+                dm::COMMANDS.join(", ")
+            ),
+        }),
+    };
+
+    let message = message.clone();
+    let token = bot_token(&message.team_id, secrets).await?;
+    tokio::task::spawn_blocking(move || dm::post_reply(&http::client(), &token, &message, reply)).await?
+}
+
+/// The workspace's own bot token, from installing the app there, or the default one.
+async fn bot_token(team_id: &str, secrets: &Secrets) -> Result<String, Error> {
+    let token = WorkspaceStore::from_env().bot_token(team_id).await?;
+    Ok(token.unwrap_or_else(|| secrets.slack_bot_token.clone()))
+}
+
+fn bulk_lookup(
+    file_id: &str,
+    channel: &str,
+    token: &str,
+    secrets: &Secrets,
+    timeouts: &TimeoutConfig,
+) -> Result<(), Error> {
+    let http = http::client();
+
+    let file = slack::file_info(&http, token, file_id)?;
+    if file.filetype != "text" && file.filetype != "csv" {
+        info!("Ignoring shared file {} of type {}", file.id, file.filetype);
+        return Ok(());
+    }
+
+    let mut logins = parse_login_list(&slack::download_file(&http, token, &file)?);
+    if logins.is_empty() {
+        return Ok(());
+    }
+    let truncated = logins.len() > MAX_BULK_LOGINS;
+    logins.truncate(MAX_BULK_LOGINS);
+
+    let client = twitch::client(timeouts).map_err(|e| e.user_message(&file.name))?;
+    let users = match twitch::get_users_by_login(&client, &logins, secrets) {
+        Ok(users) => users,
+        Err(e) => {
+            let message = SlackMessage::builder().text(e.user_message(&file.name)).build()?;
+            return slack::post_message(&http, token, channel, &message);
+        }
+    };
+
+    let found: HashSet<&str> = users.iter().map(|u| u.login.as_str()).collect();
+    let missing: Vec<&str> = logins.iter().map(String::as_str).filter(|l| !found.contains(l)).collect();
+
+    let counts = format!("{} logins, {} found, {} not found", logins.len(), users.len(), missing.len());
+    let mut layout = Blocks::new().section(format!("*Bulk lookup of {}*\n{}", file.name, counts));
+    if !missing.is_empty() {
+        let shown: Vec<&str> = missing.iter().take(SHOWN_MISSING).cloned().collect();
+        let more = if missing.len() > SHOWN_MISSING { ", …" } else { "" };
+        layout = layout.section(format!("*Not found:* {}{}", shown.join(", "), more));
+    }
+    let mut notes = vec!["Every login found is in the CSV below.".to_string()];
+    if truncated {
+        notes.push(format!("Only the first {} logins were looked up.", MAX_BULK_LOGINS));
+    }
+    let summary = SlackMessage::builder()
+        .text(format!("Bulk lookup of {}: {}", file.name, counts))
+        .blocks(layout.context(notes).into_blocks())
+        .build()?;
+
+    let mut csv = String::from("login,id,display_name,broadcaster_type,type\n");
+    for user in &users {
+        let fields = [&user.login, &user.id, &user.display_name, &user.broadcaster_type, &user.user_type];
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    slack::post_message(&http, token, channel, &summary)?;
+    slack::upload_file(&http, token, channel, &format!("{}-lookup.csv", file.name), &csv)
+}
+
+/// `value` as a CSV field: quoted, with quotes doubled, when it holds a comma, quote or newline.
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Reads logins from a newline/comma separated list, skipping a `login` header, anything that
+/// isn't a valid login, and duplicates.
+fn parse_login_list(content: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    content
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .map(|item| item.trim_matches('"').to_ascii_lowercase())
+        .filter(|item| {
+            !item.is_empty()
+                && item != "login"
+                && item.len() <= 25
+                && item.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+        })
+        .filter(|item| seen.insert(item.clone()))
+        .collect()
+}
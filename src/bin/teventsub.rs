@@ -0,0 +1,357 @@
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::alerts::{self, AlertWindowStore, DEFAULT_COALESCE_MINUTES};
+use twitch_info_bot::eventlog::{EventLog, LoggedEvent, Outcome};
+use twitch_info_bot::eventsub::{
+    self, Delivery, RevokedSubscription, Subscription, SubscriptionRecord, SubscriptionStore, CHANNEL_UPDATE,
+    STREAM_OFFLINE, STREAM_ONLINE, TRANSIENT_REVOCATIONS,
+};
+use twitch_info_bot::http::{self, text_response};
+use twitch_info_bot::idempotency::{Claim, IdempotencyStore};
+use twitch_info_bot::moderation::{self, UNBAN_REQUEST_EVENT};
+use twitch_info_bot::notify::{self, Fanout, Notification, SlackSink};
+use twitch_info_bot::plans::{Feature, Plan};
+use twitch_info_bot::scrub::OutputFilter;
+use twitch_info_bot::secrets::{self, Secrets, CURRENT, SECRET_ID};
+use twitch_info_bot::slack::{self, SlackMessage};
+use twitch_info_bot::titles::{TitleChange, TitleStore};
+use twitch_info_bot::twitch::{self, TimeoutConfig};
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::{local, logging, metrics, Error};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(handle_delivery).await
+}
+
+/// Twitch EventSub webhook callback. Answers verification challenges, removes or re-creates
+/// revoked subscriptions and routes notifications to the workspace that created the subscription.
+/// Every verified message and what became of it goes into the event log (`eventlog`).
+async fn handle_delivery(event: Value) -> Result<Value, Error> {
+    let mut logged = LoggedEvent::received(&event);
+    let result = process(&event, &mut logged).await;
+    match &result {
+        Ok((_, outcome)) => logged.outcome(outcome),
+        Err(e) => logged.outcome(&Outcome::Failed(e.to_string())),
+    }
+    if let Err(e) = EventLog::from_env().record(&logged).await {
+        error!("Could not log EventSub message {}: {}", logged.message_id, e);
+    }
+    result.map(|(response, _)| response)
+}
+
+async fn process(event: &Value, logged: &mut LoggedEvent) -> Result<(Value, Outcome), Error> {
+    let body = http::body(event)?;
+    let body = body.as_str();
+
+    let mut versioned = secrets::fetch(SECRET_ID, CURRENT).await?;
+    if !eventsub::verify_delivery(&versioned.secrets, event, body) {
+        // This container's copy may predate a webhook secret rotation.
+        match secrets::refetch_after_auth_failure(SECRET_ID, &versioned).await? {
+            Some(rotated) if eventsub::verify_delivery(&rotated.secrets, event, body) => versioned = rotated,
+            _ => {
+                warn!(
+                    "Rejecting EventSub delivery {} with a bad or stale signature",
+                    eventsub::header(event, eventsub::MESSAGE_ID).unwrap_or_default()
+                );
+                return Ok((text_response(403, "invalid signature"), Outcome::Rejected));
+            }
+        }
+    }
+    let secrets = versioned.secrets;
+    logged.verified = true;
+
+    let delivery: Delivery = serde_json::from_str(body)?;
+    logged.delivery(&delivery);
+    let subscription = &delivery.subscription;
+    let store = SubscriptionStore::from_env();
+
+    match eventsub::header(event, eventsub::MESSAGE_TYPE) {
+        Some("webhook_callback_verification") => {
+            info!("Verifying {} subscription {}", subscription.subscription_type, subscription.id);
+            let challenge = delivery.challenge.as_deref().unwrap_or_default();
+            return Ok((text_response(200, challenge), Outcome::Verified));
+        }
+        Some("revocation") => {
+            let outcome = handle_revocation(&store, subscription, &secrets).await?;
+            return Ok((text_response(204, ""), outcome));
+        }
+        Some("notification") => {}
+        other => {
+            info!("Ignoring EventSub message of type {:?}", other);
+            let ignored = Outcome::Ignored(format!("message type {:?}", other.unwrap_or_default()));
+            return Ok((text_response(204, ""), ignored));
+        }
+    }
+
+    // Twitch redelivers anything it isn't sure we received; only post each message once. A
+    // delivery that failed gives its claim back, so Twitch's redelivery gets another try.
+    let message_id = eventsub::header(event, eventsub::MESSAGE_ID).unwrap_or_default();
+    let idempotency = IdempotencyStore::from_env();
+    let key = match idempotency.claim("eventsub", message_id).await? {
+        Claim::New(key) => key,
+        Claim::Duplicate(_) => return Ok((text_response(204, ""), Outcome::Duplicate)),
+    };
+    match handle_notification(&store, &delivery, event, &secrets).await {
+        Ok(outcome) => {
+            idempotency.complete(&key, "posted").await?;
+            Ok((text_response(204, ""), outcome))
+        }
+        Err(e) => {
+            idempotency.release(key).await;
+            Err(e)
+        }
+    }
+}
+
+/// Posts or records a notification for the workspace whose subscription it came from.
+async fn handle_notification(
+    store: &SubscriptionStore,
+    delivery: &Delivery,
+    event: &Value,
+    secrets: &Secrets,
+) -> Result<Outcome, Error> {
+    let subscription = &delivery.subscription;
+    let record = match store.get(&subscription.id).await? {
+        Some(record) => record,
+        None => {
+            warn!("Notification for unknown subscription {}", subscription.id);
+            return Ok(Outcome::UnknownSubscription);
+        }
+    };
+
+    let outcome = match (subscription.subscription_type.as_str(), &delivery.event) {
+        (UNBAN_REQUEST_EVENT, Some(unban_request)) => post_unban_request(&record, unban_request, secrets).await?,
+        (STREAM_ONLINE, Some(online)) => {
+            let sent_at = eventsub::header(event, eventsub::MESSAGE_TIMESTAMP).and_then(parse_time);
+            post_go_live(&record, online, sent_at, secrets).await?
+        }
+        (STREAM_OFFLINE, Some(offline)) => post_offline(&record, offline, secrets).await?,
+        (CHANNEL_UPDATE, Some(update)) => {
+            let sent_at = eventsub::header(event, eventsub::MESSAGE_TIMESTAMP).unwrap_or_default();
+            let broadcaster_id = update.get("broadcaster_user_id").and_then(Value::as_str).unwrap_or_default();
+            TitleStore::from_env()
+                .record(broadcaster_id, &TitleChange::from_event(update, sent_at))
+                .await?;
+            Outcome::Handled
+        }
+        (other, _) => {
+            info!("No handler for {} notifications", other);
+            Outcome::Ignored(format!("no handler for {}", other))
+        }
+    };
+    Ok(outcome)
+}
+
+/// Removes a revoked subscription, or for a reason that may pass ([`TRANSIENT_REVOCATIONS`])
+/// creates it again. If Twitch won't have it back yet, it's kept until the user grants the app
+/// access again with `/tlink`. The operator hears about each revocation either way.
+async fn handle_revocation(
+    store: &SubscriptionStore,
+    subscription: &Subscription,
+    secrets: &Secrets,
+) -> Result<Outcome, Error> {
+    let reason = subscription.status.as_str();
+    warn!("Twitch revoked {} subscription {} ({})", subscription.subscription_type, subscription.id, reason);
+    let record = match store.get(&subscription.id).await? {
+        Some(record) => record,
+        None => return Ok(Outcome::Revoked(format!("{}, not in the EventSub table", reason))),
+    };
+    let what = format!(
+        "`{}` subscription for {} (workspace {})",
+        record.subscription_type, record.channel, record.team_id
+    );
+
+    let (detail, notice) = if !TRANSIENT_REVOCATIONS.contains(&reason) {
+        store.delete(&record).await?;
+        let notice = format!("Twitch revoked the {} (`{}`), so it's been removed.", what, reason);
+        (format!("{}, removed", reason), notice)
+    } else {
+        let revoked = RevokedSubscription::new(record, subscription);
+        match store.restore(&revoked, secrets).await? {
+            Ok(restored) => {
+                info!("Resubscribed revoked subscription {} as {}", subscription.id, restored.subscription_id);
+                let notice = format!("Twitch revoked the {} (`{}`); it's been created again.", what, reason);
+                (format!("{}, resubscribed as {}", reason, restored.subscription_id), notice)
+            }
+            Err(e) => {
+                warn!("Could not resubscribe revoked subscription {}: {:?}", subscription.id, e);
+                store.save_revoked(&revoked).await?;
+                let notice = format!(
+                    "Twitch revoked the {} (`{}`) and won't take it back yet; it'll be created again when \
+                     the channel's owner or moderator links their account with `/tlink`.",
+                    what, reason
+                );
+                (format!("{}, kept for the next /tlink ({:?})", reason, e), notice)
+            }
+        }
+    };
+    if let Err(e) = notify::operator(secrets, notice).await {
+        error!("Could not tell the operator about revoked subscription {}: {}", subscription.id, e);
+    }
+    Ok(Outcome::Revoked(detail))
+}
+
+async fn post_unban_request(
+    record: &SubscriptionRecord,
+    unban_request: &Value,
+    secrets: &Secrets,
+) -> Result<Outcome, Error> {
+    let workspaces = WorkspaceStore::from_env();
+    let channel = match workspaces.load(&record.team_id).await?.moderation_channel {
+        Some(channel) => channel,
+        None => {
+            error!("Workspace {} has no moderation channel for unban requests", record.team_id);
+            return Ok(Outcome::Skipped("no moderation channel".to_string()));
+        }
+    };
+    let token = workspaces
+        .bot_token(&record.team_id)
+        .await?
+        .unwrap_or_else(|| secrets.slack_bot_token.clone());
+
+    let message = moderation::unban_request_message(unban_request)?;
+    tokio::task::spawn_blocking(move || {
+        slack::post_message(&http::client(), &token, &channel, &message)
+    })
+    .await??;
+    Ok(Outcome::Handled)
+}
+
+/// Posts a go-live alert and records how long it took: from the stream starting (`started_at`)
+/// and from Twitch sending the notification, to the alert landing in Slack. Channels followed
+/// with `/tfollow` get a card of their own in the channel it named; the rest go to the
+/// workspace's alert channel, coalesced. The workspace's other sinks (`notify`) get it either way.
+async fn post_go_live(
+    record: &SubscriptionRecord,
+    online: &Value,
+    sent_at: Option<DateTime<Utc>>,
+    secrets: &Secrets,
+) -> Result<Outcome, Error> {
+    let workspaces = WorkspaceStore::from_env();
+    let config = workspaces.load(&record.team_id).await?;
+    if !Plan::for_workspace(&config).allows(Feature::Alerts) {
+        info!("Not alerting for {}: workspace {} is on the free plan", record.channel, record.team_id);
+        return Ok(Outcome::Skipped("free plan".to_string()));
+    }
+    let channel = record.slack_channel.clone().or_else(|| config.alert_channel.clone());
+    if channel.is_none() && config.notification_sinks.is_empty() {
+        error!("Workspace {} has no alert channel for go-live alerts", record.team_id);
+        return Ok(Outcome::Skipped("no alert channel".to_string()));
+    }
+    let token = workspaces
+        .bot_token(&record.team_id)
+        .await?
+        .unwrap_or_else(|| secrets.slack_bot_token.clone());
+
+    let field = |name: &str| online.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+    let (login, name) = (field("broadcaster_user_login"), field("broadcaster_user_name"));
+    let user_id = field("broadcaster_user_id");
+    let started_at = parse_time(&field("started_at"));
+    let stream_id = field("id");
+
+    let windows = AlertWindowStore::from_env();
+    let login_key = login.clone();
+    if windows.last_alerted(&record.team_id, &login_key).await?.as_deref() == Some(stream_id.as_str()) {
+        info!("Stream {} for {} was already announced, likely by reconciliation", stream_id, login);
+        return Ok(Outcome::Skipped(format!("stream {} already announced", stream_id)));
+    }
+    let debug_footer = config.debug_footer;
+    let filter = OutputFilter::for_workspace(&config);
+    let secrets = secrets.clone();
+
+    let stream = tokio::task::spawn_blocking(move || {
+        // Title and category are a nice-to-have; alert without them rather than late or not at all.
+        twitch::client(&TimeoutConfig::from_env())
+            .and_then(|client| twitch::get_streams(&client, &[user_id], &secrets))
+            .ok()
+            .and_then(|mut streams| streams.pop())
+    })
+    .await?;
+
+    let minutes = config.alert_coalesce_minutes.unwrap_or(DEFAULT_COALESCE_MINUTES);
+    let footer = if debug_footer {
+        let now = Utc::now();
+        let since = |time: Option<DateTime<Utc>>| match time {
+            Some(time) => format!("{:.1}s", (now - time).num_milliseconds() as f64 / 1000.0),
+            None => "?".to_string(),
+        };
+        format!(
+            "\n_Alert latency: {} after stream start, {} after Twitch sent it_",
+            since(started_at),
+            since(sent_at)
+        )
+    } else {
+        String::new()
+    };
+    let card = match record.slack_channel {
+        Some(_) => Some(alerts::go_live_message(&login, &name, stream.as_ref(), &filter)?),
+        None => None,
+    };
+    let notification = Notification {
+        team_id: record.team_id.clone(),
+        key: format!("{}:{}", login, stream_id),
+        subject: format!("{} is live on Twitch", name),
+        text: alerts::alert_line(&login, &name, stream.as_ref(), &filter),
+        url: Some(format!("https://twitch.tv/{}", login)),
+        footer,
+        card,
+    };
+    let mut fanout = Fanout::for_workspace(&config);
+    // A subscription `/tfollow` shares with the alert channel alerts both.
+    let shared_alert_channel = match (record.shared, &record.slack_channel) {
+        (true, Some(_)) => config.alert_channel.clone(),
+        _ => None,
+    };
+    for channel in channel.into_iter().chain(shared_alert_channel) {
+        fanout = fanout.sink(SlackSink {
+            team_id: record.team_id.clone(),
+            channel,
+            token: token.clone(),
+            coalesce_minutes: minutes,
+        });
+    }
+    fanout.send(&notification).await?;
+    let delivered = Utc::now();
+    windows.set_last_alerted(&record.team_id, &login_key, &stream_id).await?;
+
+    if let Some(started_at) = started_at {
+        let latency = (delivered - started_at).num_milliseconds();
+        info!("Go-live alert for {} delivered {}ms after stream start", record.channel, latency);
+        metrics::milliseconds("StreamStartToSlack", latency, &[("Event", STREAM_ONLINE)]);
+    }
+    if let Some(sent_at) = sent_at {
+        let latency = (delivered - sent_at).num_milliseconds();
+        metrics::milliseconds("NotificationToSlack", latency, &[("Event", STREAM_ONLINE)]);
+    }
+    Ok(Outcome::Handled)
+}
+
+/// Tells a `/tfollow` channel the stream ended. Offline subscriptions only exist for those.
+async fn post_offline(record: &SubscriptionRecord, offline: &Value, secrets: &Secrets) -> Result<Outcome, Error> {
+    let channel = match &record.slack_channel {
+        Some(channel) => channel.clone(),
+        None => return Ok(Outcome::Skipped("not a /tfollow subscription".to_string())),
+    };
+    let workspaces = WorkspaceStore::from_env();
+    if !Plan::for_workspace(&workspaces.load(&record.team_id).await?).allows(Feature::Alerts) {
+        return Ok(Outcome::Skipped("free plan".to_string()));
+    }
+    let token = workspaces
+        .bot_token(&record.team_id)
+        .await?
+        .unwrap_or_else(|| secrets.slack_bot_token.clone());
+
+    let field = |name: &str| offline.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+    let message = alerts::offline_message(&field("broadcaster_user_login"), &field("broadcaster_user_name"))?;
+    tokio::task::spawn_blocking(move || slack::post(&http::client(), &token, &channel, &message)).await??;
+    Ok(Outcome::Handled)
+}
+
+fn parse_time(time: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(time).ok().map(|time| time.with_timezone(&Utc))
+}
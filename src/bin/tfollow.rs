@@ -0,0 +1,257 @@
+use futures::future::BoxFuture;
+use log::{error, info};
+use serde_json::json;
+use tokio;
+use twitch_info_bot::args::{self, Arg, CommandSchema, Kind, Schema};
+use twitch_info_bot::budget;
+use twitch_info_bot::command::{Command, Invocation, Router};
+use twitch_info_bot::eventsub::{self, SubscriptionRecord, SubscriptionStore, STREAM_OFFLINE, STREAM_ONLINE};
+use twitch_info_bot::plans::Feature;
+use twitch_info_bot::secrets::Secrets;
+use twitch_info_bot::slack::SlackMessage;
+use twitch_info_bot::twitch::{self, LookupError, TimeoutConfig, TwitchUser};
+use twitch_info_bot::{local, logging, Error};
+
+/// Subscriptions created for every followed channel.
+const EVENTS: &[&str] = &[STREAM_ONLINE, STREAM_OFFLINE];
+
+const CHANNEL: Arg = Arg::required("channel", Kind::Login, "the Twitch channel's login");
+
+static SCHEMA: CommandSchema = CommandSchema {
+    command: "/tfollow",
+    summary: "Post to a Slack channel when a Twitch channel goes live or offline",
+    forms: &[
+        Schema {
+            subcommand: Some("stop"),
+            summary: "stop posting about a channel",
+            args: &[CHANNEL],
+            flags: &[],
+        },
+        Schema {
+            subcommand: None,
+            summary: "follow a channel, posting here or in the Slack channel given",
+            args: &[
+                CHANNEL,
+                Arg::optional("slack_channel", Kind::Word, "where to post, e.g. `#streams` (default: here)"),
+            ],
+            flags: &[],
+        },
+    ],
+    dry_run: true,
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    let router: &'static Router = Box::leak(Box::new(Router::with_defaults().command(Follow)));
+    local::serve(move |event| router.dispatch(event)).await
+}
+
+/// `/tfollow <login> [#channel]` subscribes to the channel's `stream.online` and
+/// `stream.offline` EventSub events and routes them to a Slack channel, where `teventsub` posts
+/// a go-live card or an offline note. `/tfollow stop <login>` removes both subscriptions.
+struct Follow;
+
+impl Command for Follow {
+    fn name(&self) -> &'static str {
+        "/tfollow"
+    }
+
+    fn schema(&self) -> Option<&'static CommandSchema> {
+        Some(&SCHEMA)
+    }
+
+    fn feature(&self, _invocation: &Invocation) -> Option<Feature> {
+        Some(Feature::Alerts)
+    }
+
+    fn audit(&self, invocation: &Invocation) -> Option<String> {
+        let login = invocation.args.text("channel").unwrap_or_default();
+        match invocation.args.subcommand {
+            Some("stop") => Some(format!("unfollow {}", login)),
+            _ => Some(format!("follow {}", login)),
+        }
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>> {
+        Box::pin(async move {
+            let login = invocation.args.text("channel").unwrap_or_default().to_ascii_lowercase();
+            if invocation.args.dry_run {
+                let action = match invocation.args.subcommand {
+                    Some("stop") => format!("would stop following {}", login),
+                    _ => format!("would follow {}", login),
+                };
+                return SlackMessage::builder().ephemeral().text(args::dry_run_report(&action)).build();
+            }
+            let text = match invocation.args.subcommand {
+                Some("stop") => unfollow(&invocation, &login).await?,
+                _ => match invocation.args.text("slack_channel").map(slack_channel_id) {
+                    Some(None) => format!(
+                        "Pick the Slack channel from the list as you type `#`, so Slack sends its ID.\n{}",
+                        SCHEMA.usage()
+                    ),
+                    Some(Some(channel)) => follow(&invocation, &login, channel).await?,
+                    None => follow(&invocation, &login, invocation.req.channel_id.clone()).await?,
+                },
+            };
+            SlackMessage::builder().ephemeral().text(text).build()
+        })
+    }
+}
+
+/// The channel ID in Slack's escaped mention, `<#C0123|streams>`. A bare `#streams` only carries
+/// the name, which would need a lookup the bot has no scope for.
+fn slack_channel_id(word: &str) -> Option<String> {
+    let id = word.strip_prefix("<#")?.trim_end_matches('>').split('|').next()?;
+    if id.is_empty() {
+        return None;
+    }
+    Some(id.to_string())
+}
+
+async fn follow(invocation: &Invocation, login: &str, slack_channel: String) -> Result<String, Error> {
+    let callback = match std::env::var("EVENTSUB_CALLBACK_URL") {
+        Ok(callback) => callback,
+        Err(_) => {
+            error!("EVENTSUB_CALLBACK_URL is not set");
+            return Ok("The bot isn't set up to receive Twitch events, so it can't follow channels.".to_string());
+        }
+    };
+
+    // Following again somewhere else just moves the alerts; Twitch's side stays as it is. Twitch
+    // allows one subscription per event and channel, so one the alert channel already has is
+    // shared rather than taken over.
+    let store = SubscriptionStore::from_env();
+    let team_id = &invocation.req.team_id;
+    let mut missing = vec![];
+    for event in EVENTS {
+        match store.find(team_id, event, login).await? {
+            Some(record) => {
+                let shared = record.shared || record.slack_channel.is_none();
+                store
+                    .save(&SubscriptionRecord {
+                        slack_channel: Some(slack_channel.clone()),
+                        shared,
+                        ..record
+                    })
+                    .await?
+            }
+            None => missing.push(*event),
+        }
+    }
+    if missing.is_empty() {
+        return Ok(format!("{} is already followed; alerts will now go to <#{}>.", login, slack_channel));
+    }
+
+    if let Some(refusal) = budget::refusal(&invocation.secrets, login, missing.len() as u64).await {
+        return Ok(refusal);
+    }
+
+    let secrets = invocation.secrets.clone();
+    let lookup_login = login.to_string();
+    let created =
+        tokio::task::spawn_blocking(move || subscribe(&lookup_login, &missing, &callback, &secrets)).await?;
+    let (user, subscriptions) = match created {
+        Ok(Some(created)) => created,
+        Ok(None) => return Ok(format!("No Twitch channel is called {}.", login)),
+        Err(e) => return Ok(e.user_message(login)),
+    };
+
+    for (event, subscription_id) in subscriptions {
+        info!("Created {} subscription {} for {}", event, subscription_id, user.login);
+        store
+            .save(&SubscriptionRecord {
+                subscription_id,
+                subscription_type: event.to_string(),
+                team_id: team_id.clone(),
+                slack_user_id: invocation.req.user_id.clone(),
+                channel: user.login.clone(),
+                broadcaster_id: user.id.clone(),
+                slack_channel: Some(slack_channel.clone()),
+                shared: false,
+            })
+            .await?;
+    }
+    budget::check(&invocation.secrets).await;
+    Ok(format!("Following {}: <#{}> will hear when they go live and when they stop.", user.display_name, slack_channel))
+}
+
+/// Looks the channel up and subscribes to each of `events`. If one fails, the ones already made
+/// are removed again so a retry starts clean.
+fn subscribe(
+    login: &str,
+    events: &[&'static str],
+    callback: &str,
+    secrets: &Secrets,
+) -> Result<Option<(TwitchUser, Vec<(&'static str, String)>)>, LookupError> {
+    let client = twitch::client(&TimeoutConfig::from_env())?;
+    let user = match twitch::get_users_by_login(&client, &[login.to_string()], secrets)?.pop() {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    let mut created: Vec<(&'static str, String)> = vec![];
+    for &event in events {
+        let condition = json!({ "broadcaster_user_id": user.id });
+        match eventsub::subscribe(&client, secrets, event, "1", condition, callback) {
+            Ok(subscription) => created.push((event, subscription.id)),
+            Err(e) => {
+                for (_, id) in &created {
+                    if let Err(e) = eventsub::unsubscribe(&client, secrets, id) {
+                        error!("Could not remove subscription {} after a failed follow: {:?}", id, e);
+                    }
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(Some((user, created)))
+}
+
+async fn unfollow(invocation: &Invocation, login: &str) -> Result<String, Error> {
+    let store = SubscriptionStore::from_env();
+    let mut records = vec![];
+    for event in EVENTS {
+        if let Some(record) = store.find(&invocation.req.team_id, event, login).await? {
+            records.push(record);
+        }
+    }
+    // A subscription for go-live alerts in the alert channel isn't `/tfollow`'s to remove.
+    records.retain(|record| record.slack_channel.is_some());
+    if records.is_empty() {
+        return Ok(format!("{} isn't followed.", login));
+    }
+
+    // A shared subscription goes back to the alert channel alone; Twitch's side stays.
+    let (shared, records): (Vec<SubscriptionRecord>, Vec<SubscriptionRecord>) =
+        records.into_iter().partition(|record| record.shared);
+    for record in shared {
+        store
+            .save(&SubscriptionRecord {
+                slack_channel: None,
+                shared: false,
+                ..record
+            })
+            .await?;
+    }
+
+    let secrets = invocation.secrets.clone();
+    let ids: Vec<String> = records.iter().map(|record| record.subscription_id.clone()).collect();
+    let removed = tokio::task::spawn_blocking(move || -> Result<(), LookupError> {
+        let client = twitch::client(&TimeoutConfig::from_env())?;
+        for id in &ids {
+            eventsub::unsubscribe(&client, &secrets, id)?;
+        }
+        Ok(())
+    })
+    .await?;
+    if let Err(e) = removed {
+        return Ok(e.user_message(login));
+    }
+
+    for record in &records {
+        store.delete(record).await?;
+    }
+    Ok(format!("Stopped following {}.", login))
+}
@@ -0,0 +1,70 @@
+use log::error;
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::follows::{self, FollowsPage};
+use twitch_info_bot::links::{self, LinkStore};
+use twitch_info_bot::secrets;
+use twitch_info_bot::slack::{self, SlackMessage, SlashCommand};
+use twitch_info_bot::twitch::{self, TimeoutConfig};
+use twitch_info_bot::{error, local, logging, Error};
+
+const USAGE: &str = "List the Twitch channels you follow: `/tfollows me [query]`\n\
+    See which of them are live, by game: `/tfollows live`";
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(|event| async move { list_follows(event).await.or_else(error::reply) }).await
+}
+
+/// `/tfollows me [query]`: the first page of the invoker's follows, optionally filtered.
+/// `/tfollows live`: which followed channels are live, grouped by game.
+async fn list_follows(event: Value) -> Result<SlackMessage, Error> {
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return slack::malformed_request();
+        }
+    };
+    let secrets = secrets::verify_slack_request(&event, &req.token).await?.secrets;
+
+    let mut words = req.text.split_whitespace();
+    let live = match words.next() {
+        Some("me") => false,
+        Some("live") => true,
+        _ => return SlackMessage::builder().text(USAGE).build(),
+    };
+    let page = FollowsPage {
+        query: words.collect::<Vec<_>>().join(" "),
+        page: 0,
+    };
+
+    let store = LinkStore::from_env();
+    let link = match links::command_link(&store, &secrets, &req.team_id, &req.user_id, None, follows::SCOPE).await? {
+        Ok(link) => link,
+        Err(message) => return SlackMessage::builder().text(message).build(),
+    };
+
+    let timeouts = TimeoutConfig::from_env();
+    if live {
+        return tokio::task::spawn_blocking(move || {
+            let found = twitch::client(&timeouts).and_then(|client| follows::fetch_live(&client, &secrets, &link));
+            let text = match found {
+                Ok(streams) => follows::live_summary(&link.login, &streams),
+                Err(e) => e.user_message(&link.login),
+            };
+            SlackMessage::builder().text(text).build()
+        })
+        .await?;
+    }
+    tokio::task::spawn_blocking(move || {
+        let found = twitch::client(&timeouts).and_then(|client| follows::fetch(&client, &secrets, &link, &page.query));
+        match found {
+            Ok(found) => follows::render(&link.login, &found, &page),
+            Err(e) => SlackMessage::builder().text(e.user_message(&link.login)).build(),
+        }
+    })
+    .await?
+}
@@ -0,0 +1,51 @@
+use log::{error, info, warn};
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::board;
+use twitch_info_bot::deeplinks::{DeepLink, LinkAction};
+use twitch_info_bot::http::html_page;
+use twitch_info_bot::secrets;
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::{local, logging, Error};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(follow_link).await
+}
+
+/// Target of the signed links in Slack messages. Nothing happens unless the link's signature
+/// checks out and it hasn't expired.
+async fn follow_link(event: Value) -> Result<Value, Error> {
+    let secrets = secrets::current().await?;
+    let params = event.get("queryStringParameters").cloned().unwrap_or(Value::Null);
+    let link = match DeepLink::verify(&secrets.deep_link_secret, &params) {
+        Ok(link) => link,
+        Err(e) => {
+            warn!("Rejected an action link: {:?}", e);
+            return Ok(html_page(400, &e.to_string()));
+        }
+    };
+    info!("Following a {} link for workspace {}", link.action.as_str(), link.team_id);
+
+    let workspaces = WorkspaceStore::from_env();
+    let config = workspaces.load(&link.team_id).await?;
+    match link.action {
+        LinkAction::RefreshBoard => {
+            let location = match config.board.clone() {
+                Some(location) if !config.watchlist.is_empty() => location,
+                _ => return Ok(html_page(404, "This workspace doesn't have a status board any more.")),
+            };
+            let token = workspaces
+                .bot_token(&link.team_id)
+                .await?
+                .unwrap_or_else(|| secrets.slack_bot_token.clone());
+            if let Err(e) = board::update(&link.team_id, &config, &location, token, &secrets).await {
+                error!("Could not refresh the board for workspace {}: {}", link.team_id, e);
+                return Ok(html_page(503, "Twitch didn't answer in time. Try again in a minute."));
+            }
+            Ok(html_page(200, "The status board is up to date. You can go back to Slack."))
+        }
+    }
+}
@@ -0,0 +1,559 @@
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use simple_error::bail;
+use std::collections::HashMap;
+use tokio;
+use twitch_info_bot::audit::{AuditEntry, AuditLog};
+use twitch_info_bot::cards::{self, WatchRequest};
+use twitch_info_bot::error::BotError;
+use twitch_info_bot::follows::{self, FollowsPage};
+use twitch_info_bot::http;
+use twitch_info_bot::links::{self, LinkStore};
+use twitch_info_bot::locale::Locale;
+use twitch_info_bot::moderation::{self, BanRequest, UnbanResolution};
+use twitch_info_bot::raids::{self, RaidRequest};
+use twitch_info_bot::scrub::OutputFilter;
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::slack::{self, blocks, Block, SlackMessage};
+use twitch_info_bot::twitch::{self, LookupError, TimeoutConfig, TwitchUser};
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::{local, logging, onboarding, Error};
+
+/// How many clips, VODs or schedule segments a drill-down shows; "More info" lists fewer upcoming
+/// streams.
+const DRILL_DOWN_COUNT: usize = 5;
+const UPCOMING_STREAMS: usize = 2;
+
+#[derive(Deserialize)]
+struct InteractionPayload {
+    #[serde(rename = "type")]
+    payload_type: String,
+    token: String,
+    #[serde(default)]
+    response_url: String,
+    #[serde(default)]
+    trigger_id: String,
+    team: Team,
+    #[serde(default)]
+    user: User,
+    #[serde(default)]
+    actions: Vec<Action>,
+    view: Option<View>,
+    /// The message the action came from; Slack leaves it out for ephemeral messages.
+    message: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct Team {
+    id: String,
+}
+
+#[derive(Deserialize, Default)]
+struct User {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct View {
+    callback_id: String,
+    state: ViewState,
+}
+
+#[derive(Deserialize)]
+struct ViewState {
+    values: Value,
+}
+
+#[derive(Deserialize)]
+struct Action {
+    action_id: String,
+    #[serde(default)]
+    block_id: String,
+    value: Option<String>,
+    selected_option: Option<SelectedOption>,
+}
+
+#[derive(Deserialize)]
+struct SelectedOption {
+    value: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(|event| async move { handle_interaction(event).await.or_else(acknowledge) }).await
+}
+
+/// Logs a failure the bot can name and answers Slack as if it went through; Slack only shows a
+/// generic warning for a failed interaction, and retrying a malformed one won't help. Any other
+/// failure is passed on.
+fn acknowledge(error: Error) -> Result<Value, Error> {
+    match BotError::find(&error) {
+        Some(bot_error) => {
+            error!("Interaction failed ({}): {}", bot_error.kind(), bot_error);
+            Ok(json!({}))
+        }
+        None => Err(error),
+    }
+}
+
+/// Slack interactivity endpoint. Slack posts a form-encoded `payload` field holding the JSON.
+async fn handle_interaction(event: Value) -> Result<Value, Error> {
+    let body = http::body(&event)?;
+    let form: HashMap<String, String> = serde_urlencoded::from_str(&body)?;
+    let payload: InteractionPayload = match form.get("payload") {
+        Some(payload) => serde_json::from_str(payload)?,
+        None => return Err(BotError::Decode("Interaction has no payload field".to_string()).into()),
+    };
+
+    let secrets = secrets::verify_slack_request(&event, &payload.token).await?.secrets;
+
+    match (payload.payload_type.as_str(), &payload.view) {
+        ("block_actions", _) => {}
+        ("view_submission", Some(view)) if view.callback_id == onboarding::CALLBACK_ID => {
+            save_onboarding(&payload.team.id, &payload.user.id, &view.state.values).await?;
+            return Ok(json!({}));
+        }
+        (other, _) => {
+            info!("Ignoring interaction of type {}", other);
+            return Ok(json!({}));
+        }
+    }
+
+    for action in payload.actions {
+        let value = match (action.action_id.as_str(), action.selected_option) {
+            ("drill_down", Some(option)) => option.value,
+            (onboarding::OPEN_ACTION, _) => {
+                open_onboarding(&payload.team.id, &payload.trigger_id).await?;
+                continue;
+            }
+            (moderation::BAN_ACTION, _) => {
+                let request = match action.value {
+                    Some(value) => serde_json::from_str(&value)?,
+                    None => return Err(BotError::Decode("Ban confirmation has no value".to_string()).into()),
+                };
+                confirm_ban(&payload.team.id, &payload.user.id, &payload.response_url, request, &secrets).await?;
+                continue;
+            }
+            (raids::CONFIRM_ACTION, _) => {
+                let request = match action.value {
+                    Some(value) => serde_json::from_str(&value)?,
+                    None => return Err(BotError::Decode("Raid confirmation has no value".to_string()).into()),
+                };
+                confirm_raid(&payload.team.id, &payload.user.id, &payload.response_url, request, &secrets).await?;
+                continue;
+            }
+            (cards::WATCH_ACTION, _) => {
+                let request = match action.value {
+                    Some(value) => serde_json::from_str(&value)?,
+                    None => return Err(BotError::Decode("Watch button has no value".to_string()).into()),
+                };
+                watch_channel(&payload.team.id, &payload.user.id, &payload.response_url, request).await?;
+                continue;
+            }
+            (id, _) if id.starts_with(follows::PAGE_ACTION) => {
+                let page = match action.value {
+                    Some(value) => serde_json::from_str(&value)?,
+                    None => return Err(BotError::Decode("Follows page button has no value".to_string()).into()),
+                };
+                turn_follows_page(&payload.team.id, &payload.user.id, &payload.response_url, page, &secrets).await?;
+                continue;
+            }
+            (id, _) if id.starts_with(cards::EXPAND_ACTION) => {
+                let value = match action.value {
+                    Some(value) => value,
+                    None => return Err(BotError::Decode("Card button has no value".to_string()).into()),
+                };
+                let (message, response_url) = (payload.message.clone(), payload.response_url.clone());
+                let (block_id, secrets) = (action.block_id, secrets.clone());
+                let config = WorkspaceStore::from_env().load(&payload.team.id).await?;
+                let (locale, filter) = (Locale::for_workspace(&config), OutputFilter::for_workspace(&config));
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = expand_card(&value, message, &block_id, &response_url, &secrets, locale, &filter) {
+                        error!("Expanding a card with {} failed: {}", value, e);
+                    }
+                })
+                .await?;
+                continue;
+            }
+            (id, _) if id.starts_with(moderation::RESOLVE_UNBAN_ACTION) => {
+                let resolution = match action.value {
+                    Some(value) => serde_json::from_str(&value)?,
+                    None => return Err(BotError::Decode("Unban resolution has no value".to_string()).into()),
+                };
+                resolve_unban(&payload.team.id, &payload.user.id, &payload.response_url, resolution, &secrets).await?;
+                continue;
+            }
+            _ => continue,
+        };
+
+        let response_url = payload.response_url.clone();
+        let secrets = secrets.clone();
+        let locale = Locale::for_team(&payload.team.id).await;
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = drill_down(&value, &response_url, &secrets, locale, &OutputFilter::default()) {
+                error!("Drill-down {} failed: {}", value, e);
+            }
+        })
+        .await?;
+    }
+
+    Ok(json!({}))
+}
+
+async fn open_onboarding(team_id: &str, trigger_id: &str) -> Result<(), Error> {
+    let store = WorkspaceStore::from_env();
+    let token = match store.bot_token(team_id).await? {
+        Some(token) => token,
+        None => bail!("No bot token stored for workspace {}", team_id),
+    };
+    let view = onboarding::wizard_view(&store.load(team_id).await?);
+
+    let trigger_id = trigger_id.to_string();
+    tokio::task::spawn_blocking(move || {
+        slack::open_view(&http::client(), &token, &trigger_id, &view)
+    })
+    .await?
+}
+
+/// Runs a ban the moderator confirmed, auditing it first and replying through `response_url`.
+async fn confirm_ban(
+    team_id: &str,
+    user_id: &str,
+    response_url: &str,
+    request: BanRequest,
+    secrets: &Secrets,
+) -> Result<(), Error> {
+    let store = LinkStore::from_env();
+    let link = links::command_link(&store, secrets, team_id, user_id, None, moderation::BAN_SCOPE).await?;
+
+    if let Ok(link) = &link {
+        let entry = AuditEntry {
+            team_id: team_id.to_string(),
+            user_id: user_id.to_string(),
+            action: "ban".to_string(),
+            detail: format!("as {}: {}", link.login, request.describe()),
+        };
+        AuditLog::from_env().record(&entry).await?;
+    }
+
+    let response_url = response_url.to_string();
+    let secrets = secrets.clone();
+    tokio::task::spawn_blocking(move || {
+        let text = match link {
+            Ok(link) => twitch::client(&TimeoutConfig::from_env())
+                .and_then(|client| moderation::ban(&client, &secrets, &link, &request))
+                .unwrap_or_else(|e| e.user_message(&request.user)),
+            Err(message) => message,
+        };
+        let message = SlackMessage::builder().ephemeral().text(text).build()?;
+        slack::respond(&http::client(), &response_url, &message)
+    })
+    .await?
+}
+
+/// Approves or denies an unban request as the moderator who clicked, posting the outcome to
+/// the moderation channel so the other moderators see it was handled.
+async fn resolve_unban(
+    team_id: &str,
+    user_id: &str,
+    response_url: &str,
+    resolution: UnbanResolution,
+    secrets: &Secrets,
+) -> Result<(), Error> {
+    let store = LinkStore::from_env();
+    let link = links::command_link(&store, secrets, team_id, user_id, None, moderation::UNBAN_SCOPE).await?;
+
+    if let Ok(link) = &link {
+        let entry = AuditEntry {
+            team_id: team_id.to_string(),
+            user_id: user_id.to_string(),
+            action: "resolve_unban".to_string(),
+            detail: format!(
+                "as {}: {} unban request {} from {} in {}",
+                link.login,
+                if resolution.approve { "approve" } else { "deny" },
+                resolution.request_id,
+                resolution.user,
+                resolution.channel
+            ),
+        };
+        AuditLog::from_env().record(&entry).await?;
+    }
+
+    let response_url = response_url.to_string();
+    let secrets = secrets.clone();
+    tokio::task::spawn_blocking(move || {
+        let message = match link {
+            Ok(link) => {
+                let text = twitch::client(&TimeoutConfig::from_env())
+                    .and_then(|client| moderation::resolve_unban(&client, &secrets, &link, &resolution))
+                    .unwrap_or_else(|e| e.user_message(&resolution.user));
+                SlackMessage::builder().in_channel().text(text).build()?
+            }
+            Err(message) => SlackMessage::builder().ephemeral().text(message).build()?,
+        };
+        slack::respond(&http::client(), &response_url, &message)
+    })
+    .await?
+}
+
+async fn confirm_raid(
+    team_id: &str,
+    user_id: &str,
+    response_url: &str,
+    request: RaidRequest,
+    secrets: &Secrets,
+) -> Result<(), Error> {
+    let store = LinkStore::from_env();
+    let link = links::command_link(&store, secrets, team_id, user_id, None, raids::SCOPE).await?;
+
+    let response_url = response_url.to_string();
+    let secrets = secrets.clone();
+    tokio::task::spawn_blocking(move || {
+        let text = match link.and_then(|link| raids::check_raider(&link, &request.from).map(|()| link)) {
+            Ok(link) => twitch::client(&TimeoutConfig::from_env())
+                .and_then(|client| raids::start(&client, &secrets, &link, &request))
+                .unwrap_or_else(|e| e.user_message(&request.to)),
+            Err(message) => message,
+        };
+        let message = SlackMessage::builder().ephemeral().text(text).build()?;
+        slack::respond(&http::client(), &response_url, &message)
+    })
+    .await?
+}
+
+/// Adds the channel on a card's watch button to the watchlist, audited as whoever clicked it,
+/// and tells them so.
+async fn watch_channel(team_id: &str, user_id: &str, response_url: &str, request: WatchRequest) -> Result<(), Error> {
+    let text = if request.is_expired() {
+        "This button has expired. Look the channel up again to add it to the watchlist.".to_string()
+    } else {
+        let store = WorkspaceStore::from_env();
+        let previous = store.load(team_id).await?;
+        if previous.watchlist.contains(&request.login) {
+            format!("{} is already on the watchlist.", request.login)
+        } else {
+            let mut config = previous.clone();
+            config.watchlist.push(request.login.clone());
+            let entry = AuditEntry {
+                team_id: team_id.to_string(),
+                user_id: user_id.to_string(),
+                action: "watchlist".to_string(),
+                detail: format!("add {} to the watchlist from a card", request.login),
+            };
+            store.save_change(&AuditLog::from_env(), &entry, &previous, &config).await?;
+            format!("Added {} to the watchlist.", request.login)
+        }
+    };
+
+    let message = SlackMessage::builder().ephemeral().text(text).build()?;
+    let response_url = response_url.to_string();
+    tokio::task::spawn_blocking(move || slack::respond(&http::client(), &response_url, &message)).await?
+}
+
+async fn turn_follows_page(
+    team_id: &str,
+    user_id: &str,
+    response_url: &str,
+    page: FollowsPage,
+    secrets: &Secrets,
+) -> Result<(), Error> {
+    let store = LinkStore::from_env();
+    let link = links::command_link(&store, secrets, team_id, user_id, None, follows::SCOPE).await?;
+
+    let response_url = response_url.to_string();
+    let secrets = secrets.clone();
+    tokio::task::spawn_blocking(move || {
+        let message = match link {
+            Ok(link) => {
+                let found = twitch::client(&TimeoutConfig::from_env())
+                    .and_then(|client| follows::fetch(&client, &secrets, &link, &page.query));
+                match found {
+                    Ok(found) => follows::render(&link.login, &found, &page)?,
+                    Err(e) => SlackMessage::builder().text(e.user_message(&link.login)).build()?,
+                }
+            }
+            Err(message) => SlackMessage::builder().text(message).build()?,
+        };
+        slack::replace(&http::client(), &response_url, &message)
+    })
+    .await?
+}
+
+async fn save_onboarding(team_id: &str, user_id: &str, values: &Value) -> Result<(), Error> {
+    let store = WorkspaceStore::from_env();
+    let previous = store.load(team_id).await?;
+    let mut config = previous.clone();
+    onboarding::apply_submission(&mut config, values);
+
+    info!("Saving onboarding settings for workspace {}", team_id);
+    let entry = AuditEntry {
+        team_id: team_id.to_string(),
+        user_id: user_id.to_string(),
+        action: "setup".to_string(),
+        detail: "save the bot's setup".to_string(),
+    };
+    store.save_change(&AuditLog::from_env(), &entry, &previous, &config).await
+}
+
+/// A drill-down from the overflow menu on older cards, posted as a message only the clicker sees.
+fn drill_down(
+    value: &str,
+    response_url: &str,
+    secrets: &Secrets,
+    locale: Locale,
+    filter: &OutputFilter,
+) -> Result<(), Error> {
+    let message = SlackMessage::builder().ephemeral().text(drill_down_text(value, secrets, locale, filter)?).build()?;
+    slack::respond(&http::client(), response_url, &message)
+}
+
+/// Shows a card button's view under the card's buttons, replacing what an earlier button showed
+/// there. The card is in a message everyone in the channel sees, so it's updated for them all. An
+/// ephemeral card, which Slack doesn't send back, gets the view as a drill-down instead.
+fn expand_card(
+    value: &str,
+    original: Option<Value>,
+    block_id: &str,
+    response_url: &str,
+    secrets: &Secrets,
+    locale: Locale,
+    filter: &OutputFilter,
+) -> Result<(), Error> {
+    let original = match original {
+        Some(original) => original,
+        None => return drill_down(value, response_url, secrets, locale, filter),
+    };
+    let text = drill_down_text(value, secrets, locale, filter)?;
+    let block = Block::Section {
+        text: blocks::mrkdwn(text),
+        accessory: None,
+    };
+    slack::update(&http::client(), response_url, &slack::with_block_after(&original, block_id, &block)?)
+}
+
+/// The text for a `<view>:<user id>` drill-down, or what went wrong asking Twitch for it.
+fn drill_down_text(value: &str, secrets: &Secrets, locale: Locale, filter: &OutputFilter) -> Result<String, Error> {
+    let mut parts = value.splitn(2, ':');
+    let (view, user_id) = match (parts.next(), parts.next()) {
+        (Some(view), Some(user_id)) => (view, user_id),
+        _ => return Err(BotError::Decode(format!("Malformed drill-down value {}", value)).into()),
+    };
+
+    let client = twitch::client(&TimeoutConfig::from_env()).map_err(|e| e.user_message(user_id))?;
+    let text = match view {
+        "clips" => twitch::get_clips(&client, user_id, DRILL_DOWN_COUNT, secrets).map(|clips| {
+            if clips.is_empty() {
+                "No clips found.".to_string()
+            } else {
+                clips
+                    .iter()
+                    .map(|c| {
+                        let views = locale.count(c.view_count);
+                        format!("• <{}|{}> by {} ({} views)", c.url, filter.scrub(&c.title), c.creator_name, views)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }),
+        "vods" => twitch::get_videos(&client, user_id, DRILL_DOWN_COUNT, secrets).map(|videos| {
+            if videos.is_empty() {
+                "No VODs found.".to_string()
+            } else {
+                videos
+                    .iter()
+                    .map(|v| {
+                        let views = locale.count(v.view_count);
+                        format!("• <{}|{}> ({}, {} views)", v.url, filter.scrub(&v.title), v.duration, views)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }),
+        "info" => more_info(&client, user_id, secrets, locale, filter),
+        "schedule" => twitch::get_schedule(&client, user_id, DRILL_DOWN_COUNT, secrets).map(|schedule| {
+            match schedule.and_then(|s| s.segments).filter(|segments| !segments.is_empty()) {
+                Some(segments) => segments
+                    .iter()
+                    .map(|s| {
+                        let start = match DateTime::parse_from_rfc3339(&s.start_time) {
+                            Ok(start) => locale.date_time(&start.with_timezone(&Utc)),
+                            Err(_) => s.start_time.clone(),
+                        };
+                        let title = filter.scrub(&s.title);
+                        match &s.category {
+                            Some(category) => format!("• {} — {} ({})", start, title, category.name),
+                            None => format!("• {} — {}", start, title),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                None => "No upcoming scheduled streams.".to_string(),
+            }
+        }),
+        _ => return Err(BotError::Decode(format!("Unknown drill-down view {}", view)).into()),
+    };
+
+    Ok(text.unwrap_or_else(|e: LookupError| e.user_message(user_id)))
+}
+
+/// "More info": what the card leaves out, i.e. the whole description, account type, exact join
+/// date and the next scheduled streams.
+fn more_info(
+    client: &reqwest::blocking::Client,
+    user_id: &str,
+    secrets: &Secrets,
+    locale: Locale,
+    filter: &OutputFilter,
+) -> Result<String, LookupError> {
+    let url = twitch::users_url(&[user_id.to_string()], &[]);
+    let user: TwitchUser = match twitch::get_users(client, &url, secrets)?.pop() {
+        Some(user) => user,
+        None => return Ok("That Twitch user no longer exists.".to_string()),
+    };
+
+    let mut lines = vec![format!("*About {}* (`{}`)", user.display_name, user.login)];
+    if !user.description.is_empty() {
+        lines.push(filter.scrub(&user.description));
+    }
+    let account = match user.broadcaster_type.as_str() {
+        "" => "Regular account".to_string(),
+        kind => format!("{}{} account", kind[..1].to_ascii_uppercase(), &kind[1..]),
+    };
+    match user.created() {
+        Some(created) => lines.push(format!("{}, made {}", account, locale.date_time(&created))),
+        None => lines.push(account),
+    }
+    // A channel without a schedule isn't worth failing the rest over.
+    let segments = twitch::get_schedule(client, user_id, UPCOMING_STREAMS, secrets)
+        .ok()
+        .flatten()
+        .and_then(|schedule| schedule.segments)
+        .unwrap_or_default();
+    for segment in segments.iter().take(UPCOMING_STREAMS) {
+        let start = match DateTime::parse_from_rfc3339(&segment.start_time) {
+            Ok(start) => locale.date_time(&start.with_timezone(&Utc)),
+            Err(_) => segment.start_time.clone(),
+        };
+        lines.push(format!("📅 Next: {} — {}", start, filter.scrub(&segment.title)));
+    }
+    // Nor is the trailer and panels, which come from outside Helix and only where configured.
+    if let Ok(Some(extras)) = twitch::get_channel_extras(client, &user.login) {
+        if let Some(trailer) = extras.trailer {
+            lines.push(format!("🎬 <{}|Channel trailer>", trailer));
+        }
+        if !extras.panels.is_empty() {
+            let panels: Vec<String> = extras
+                .panels
+                .iter()
+                .map(|panel| format!("<{}|{}>", panel.url, filter.scrub(&panel.title)))
+                .collect();
+            lines.push(format!("🔗 Panels: {}", panels.join(" · ")));
+        }
+    }
+    Ok(lines.join("\n"))
+}
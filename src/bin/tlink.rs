@@ -0,0 +1,121 @@
+use log::{error, info, warn};
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::eventsub::SubscriptionStore;
+use twitch_info_bot::http::{self, html_page};
+use twitch_info_bot::links::{self, LinkStore};
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::slack::{self, Block, Element, SlackMessage, SlashCommand, Text};
+use twitch_info_bot::{local, logging, Error};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(handle_link).await
+}
+
+/// Serves both `/tlink [unlink]` and the Twitch OAuth redirect that completes the link.
+async fn handle_link(event: Value) -> Result<Value, Error> {
+    if let Some(params) = event.get("queryStringParameters").filter(|p| p.is_object()) {
+        return finish_link(params).await;
+    }
+
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return Ok(serde_json::to_value(slack::malformed_request()?)?);
+        }
+    };
+    let secrets = secrets::verify_slack_request(&event, &req.token).await?.secrets;
+    let store = LinkStore::from_env();
+
+    let message = if req.text.trim() == "unlink" {
+        store.delete(&req.team_id, &req.user_id).await?;
+        SlackMessage::builder().text("Your Twitch account is no longer linked.").build()?
+    } else {
+        let state = store.begin(&req.team_id, &req.user_id).await?;
+        let url = links::authorize_url(&secrets.twitch_client_id, &redirect_uri(), &state)?;
+
+        SlackMessage::builder()
+            .text("Link your Twitch account")
+            .block(Block::Section {
+                text: Text::Mrkdwn {
+                    text: "Link your Twitch account so commands can act as your channel. The link expires in 10 minutes."
+                        .to_string(),
+                },
+                accessory: Some(Element::Button {
+                    action_id: "link_twitch".to_string(),
+                    text: Text::PlainText {
+                        text: "Link Twitch".to_string(),
+                    },
+                    value: None,
+                    url: Some(url),
+                    style: None,
+                    confirm: None,
+                }),
+            })
+            .build()?
+    };
+
+    Ok(serde_json::to_value(message)?)
+}
+
+async fn finish_link(params: &Value) -> Result<Value, Error> {
+    let (code, state) = match (params["code"].as_str(), params["state"].as_str()) {
+        (Some(code), Some(state)) => (code.to_string(), state.to_string()),
+        _ => return Ok(html_page(400, "Twitch didn't send back an authorization. Run /tlink again.")),
+    };
+
+    let store = LinkStore::from_env();
+    let (team_id, user_id) = match store.finish(&state).await? {
+        Some(slack_user) => slack_user,
+        None => return Ok(html_page(400, "This link has expired. Run /tlink again.")),
+    };
+
+    let secrets = secrets::current().await?;
+    let exchange_secrets = secrets.clone();
+    let link = tokio::task::spawn_blocking(move || {
+        links::exchange_code(&http::client(), &exchange_secrets, &code, &redirect_uri())
+    })
+    .await?;
+
+    match link {
+        Ok(link) => {
+            info!("Linked Slack user {}:{} to Twitch {}", team_id, user_id, link.login);
+            store.save(&team_id, &user_id, &link).await?;
+            restore_subscriptions(&link.twitch_user_id, &secrets).await;
+            Ok(html_page(200, &format!("Linked Twitch account {}. You can close this tab.", link.login)))
+        }
+        Err(e) => {
+            error!("Twitch code exchange failed: {}", e);
+            Ok(html_page(500, "Twitch didn't accept the authorization. Run /tlink again."))
+        }
+    }
+}
+
+/// Creates again the subscriptions Twitch revoked when this user took back the app's access
+/// (see `teventsub`). The link already worked, so failures are only logged.
+async fn restore_subscriptions(twitch_user_id: &str, secrets: &Secrets) {
+    let store = SubscriptionStore::from_env();
+    let revoked = match store.all_revoked().await {
+        Ok(revoked) => revoked,
+        Err(e) => {
+            error!("Could not read revoked subscriptions: {}", e);
+            return;
+        }
+    };
+    for revoked in revoked.iter().filter(|revoked| revoked.concerns(twitch_user_id)) {
+        let old_id = &revoked.record.subscription_id;
+        match store.restore(revoked, secrets).await {
+            Ok(Ok(record)) => info!("Resubscribed revoked subscription {} as {}", old_id, record.subscription_id),
+            Ok(Err(e)) => warn!("Twitch still refuses revoked subscription {}: {:?}", old_id, e),
+            Err(e) => error!("Could not resubscribe revoked subscription {}: {}", old_id, e),
+        }
+    }
+}
+
+fn redirect_uri() -> String {
+    std::env::var("TWITCH_REDIRECT_URI").unwrap_or_default()
+}
@@ -0,0 +1,78 @@
+use log::{error, info, warn};
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::http::{self, html_page};
+use twitch_info_bot::secrets;
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::{local, logging, onboarding, slack, Error};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(handle_install).await
+}
+
+/// Where "Add to Slack" points: without a `code` it starts an install and sends the browser to
+/// Slack; as Slack's redirect target it stores the workspace's bot token and DMs the installer
+/// the setup wizard.
+async fn handle_install(event: Value) -> Result<Value, Error> {
+    let params = event.get("queryStringParameters").cloned().unwrap_or(Value::Null);
+    let code = match params["code"].as_str() {
+        Some(code) => code.to_string(),
+        None => return start_install().await,
+    };
+    let state = params["state"].as_str().unwrap_or_default();
+    if state.is_empty() || !WorkspaceStore::from_env().finish_install(state).await? {
+        warn!("Install redirect with an unknown or expired state");
+        return Ok(html_page(400, "This install link has expired. Try installing the app again."));
+    }
+
+    let secrets = secrets::current().await?;
+    let access = tokio::task::spawn_blocking(move || {
+        slack::oauth_access(
+            &http::client(),
+            &secrets.slack_client_id,
+            &secrets.slack_client_secret,
+            &code,
+        )
+    })
+    .await?;
+
+    let access = match access {
+        Ok(access) => access,
+        Err(e) => {
+            error!("OAuth exchange failed: {}", e);
+            return Ok(html_page(500, "Slack didn't accept the install. Try installing the app again."));
+        }
+    };
+    info!("Installed into workspace {} by {}", access.team.id, access.authed_user.id);
+
+    WorkspaceStore::from_env()
+        .save_bot_token(&access.team.id, &access.access_token)
+        .await?;
+
+    let message = onboarding::welcome_message()?;
+    tokio::task::spawn_blocking(move || {
+        let client = http::client();
+        if let Err(e) = slack::post_message(&client, &access.access_token, &access.authed_user.id, &message) {
+            error!("Could not DM the setup wizard to {}: {}", access.authed_user.id, e);
+        }
+    })
+    .await?;
+
+    Ok(html_page(200, "The Twitch info bot is installed! Check your Slack DMs to finish setup."))
+}
+
+/// Sends the browser to Slack's authorize page with a fresh `state`, so only installs started
+/// here are completed.
+async fn start_install() -> Result<Value, Error> {
+    let install_url = std::env::var("SLACK_INSTALL_URL").unwrap_or_default();
+    if install_url.is_empty() {
+        error!("SLACK_INSTALL_URL isn't set");
+        return Ok(html_page(500, "The app can't be installed right now."));
+    }
+    let state = WorkspaceStore::from_env().begin_install().await?;
+    let separator = if install_url.contains('?') { '&' } else { '?' };
+    Ok(http::redirect(&format!("{}{}state={}", install_url, separator, state)))
+}
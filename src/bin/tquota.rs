@@ -0,0 +1,87 @@
+use chrono::Utc;
+use futures::future::BoxFuture;
+use tokio;
+use twitch_info_bot::command::{Command, Invocation, Router};
+use twitch_info_bot::locale::Locale;
+use twitch_info_bot::ratelimit;
+use twitch_info_bot::slack::SlackMessage;
+use twitch_info_bot::usage::{self, BucketSnapshot, Usage, UsageStore};
+use twitch_info_bot::{local, logging, Error};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    let router: &'static Router = Box::leak(Box::new(Router::with_defaults().command(Quota)));
+    local::serve(move |event| router.dispatch(event)).await
+}
+
+/// `/tquota`: the workspace's usage this month against its quota, how often lookups came from
+/// the cache, and how much of Twitch's rate limit is left.
+struct Quota;
+
+impl Command for Quota {
+    fn name(&self) -> &'static str {
+        "/tquota"
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>> {
+        Box::pin(async move {
+            let team_id = &invocation.req.team_id;
+            let store = UsageStore::from_env();
+            let month = usage::month_of(Utc::now());
+            let usage = store.month(team_id, &month).await?;
+            let bucket = store.last_bucket().await?;
+            let locale = Locale::for_team(team_id).await;
+
+            SlackMessage::builder()
+                .ephemeral()
+                .text(render(&month, &usage, usage::monthly_quota(), bucket, locale))
+                .build()
+        })
+    }
+}
+
+fn render(month: &str, usage: &Usage, quota: u64, bucket: Option<BucketSnapshot>, locale: Locale) -> String {
+    let percent = if quota == 0 { 100 } else { usage.invocations * 100 / quota };
+    let mut lines = vec![
+        format!("*Twitch info bot usage for {}*", month),
+        format!(
+            "• Commands: {} of {} this month ({}%)",
+            locale.count(usage.invocations),
+            locale.count(quota),
+            percent
+        ),
+    ];
+
+    lines.push(match usage.cache_hit_rate() {
+        Some(rate) => format!(
+            "• Lookup cache: {:.0}% hit rate ({} of {} lookups)",
+            rate * 100.0,
+            locale.count(usage.cache_hits),
+            locale.count(usage.cache_hits + usage.cache_misses)
+        ),
+        None => "• Lookup cache: no lookups yet this month".to_string(),
+    });
+
+    lines.push(match bucket {
+        Some(snapshot) if snapshot.bucket.reset <= ratelimit::now_secs() => format!(
+            "• Twitch rate limit: full ({} points per minute, shared by every workspace)",
+            locale.count(u64::from(snapshot.bucket.limit))
+        ),
+        Some(snapshot) => format!(
+            "• Twitch rate limit: {} of {} points left, refilling <!date^{}^{{time_secs}}|soon> \
+             (as of <!date^{}^{{time}}|recently>, shared by every workspace)",
+            locale.count(u64::from(snapshot.bucket.remaining)),
+            locale.count(u64::from(snapshot.bucket.limit)),
+            snapshot.bucket.reset,
+            snapshot.recorded_at
+        ),
+        None => "• Twitch rate limit: no requests to Twitch recorded yet".to_string(),
+    });
+
+    if usage.invocations >= quota {
+        lines.push("_This workspace is over its monthly quota._".to_string());
+    }
+    lines.join("\n")
+}
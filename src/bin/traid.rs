@@ -0,0 +1,106 @@
+use log::error;
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::args;
+use twitch_info_bot::links::{self, LinkStore};
+use twitch_info_bot::locale::Locale;
+use twitch_info_bot::raids::{self, RaidRequest};
+use twitch_info_bot::secrets;
+use twitch_info_bot::slack::{self, SlackMessage, SlashCommand};
+use twitch_info_bot::twitch::{self, TimeoutConfig};
+use twitch_info_bot::{error, local, logging, Error};
+
+const USAGE: &str = "Raid from your linked channel:\n\
+    `/traid <from> <to>` — preview the target, then confirm\n\
+    `/traid cancel` — stop a pending raid";
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(|event| async move { raid(event).await.or_else(error::reply) }).await
+}
+
+/// `/traid <from> <to>` previews the raid; the interactivity endpoint starts it once confirmed.
+/// `/traid cancel` stops a pending raid straight away.
+async fn raid(event: Value) -> Result<SlackMessage, Error> {
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return slack::malformed_request();
+        }
+    };
+    let secrets = secrets::verify_slack_request(&event, &req.token).await?.secrets;
+
+    let (text, dry_run) = args::take_dry_run(&req.text);
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('@').to_ascii_lowercase())
+        .collect();
+    let (from, to) = match words.as_slice() {
+        [cancel] if cancel == "cancel" => (None, None),
+        [from, to] => (Some(from.clone()), Some(to.clone())),
+        _ => return SlackMessage::builder().text(USAGE).build(),
+    };
+
+    let store = LinkStore::from_env();
+    let link = match links::command_link(&store, &secrets, &req.team_id, &req.user_id, None, raids::SCOPE).await? {
+        Ok(link) => link,
+        Err(message) => return SlackMessage::builder().text(message).build(),
+    };
+    let timeouts = TimeoutConfig::from_env();
+
+    let (from, to) = match (from, to) {
+        (Some(from), Some(to)) => (from, to),
+        _ if dry_run => {
+            let report = args::dry_run_report(&format!("cancel any pending raid from {}", link.login));
+            return SlackMessage::builder().text(report).build();
+        }
+        _ => {
+            let text = tokio::task::spawn_blocking(move || {
+                twitch::client(&timeouts)
+                    .and_then(|client| raids::cancel(&client, &secrets, &link))
+                    .unwrap_or_else(|e| e.user_message(&link.login))
+            })
+            .await?;
+            return SlackMessage::builder().text(text).build();
+        }
+    };
+    if let Err(message) = raids::check_raider(&link, &from) {
+        return SlackMessage::builder().text(message).build();
+    }
+    if dry_run {
+        let report = args::dry_run_report(&format!("raid {} from {}", to, from));
+        return SlackMessage::builder().text(report).build();
+    }
+
+    let locale = Locale::for_team(&req.team_id).await;
+    tokio::task::spawn_blocking(move || {
+        let lookup = twitch::client(&timeouts).and_then(|client| {
+            let target = twitch::get_users_by_login(&client, &[to.clone()], &secrets)?.pop();
+            match target {
+                Some(target) => {
+                    let stream = twitch::get_streams(&client, &[target.id.clone()], &secrets)?.pop();
+                    Ok(Some((target, stream)))
+                }
+                None => Ok(None),
+            }
+        });
+
+        match lookup {
+            Ok(Some((target, stream))) => {
+                let request = RaidRequest {
+                    from,
+                    from_id: link.twitch_user_id.clone(),
+                    to: target.login.clone(),
+                    to_id: target.id.clone(),
+                };
+                raids::confirmation(&request, &target, stream.as_ref(), locale)
+            }
+            Ok(None) => SlackMessage::builder().text(format!("No Twitch user named {}", to)).build(),
+            Err(e) => SlackMessage::builder().text(e.user_message(&to)).build(),
+        }
+    })
+    .await?
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tokio;
+use twitch_info_bot::alerts::{self, AlertWindowStore, DEFAULT_COALESCE_MINUTES, RECOVERED_SUFFIX};
+use twitch_info_bot::budget;
+use twitch_info_bot::eventsub::{SubscriptionRecord, SubscriptionStore, STREAM_ONLINE};
+use twitch_info_bot::notify::{Fanout, Notification, SlackSink};
+use twitch_info_bot::plans::{Feature, Plan};
+use twitch_info_bot::scrub::OutputFilter;
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::twitch::{self, LookupError, TimeoutConfig, TwitchStream, TwitchUser};
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::{local, logging, metrics, Error};
+
+/// A stream this far past its start without an alert means its notification went missing,
+/// rather than being on its way.
+const GRACE_MINUTES: i64 = 3;
+/// Streams older than this aren't worth a catch-up alert; it would be news to nobody.
+const MAX_RECOVERY_HOURS: i64 = 2;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(reconcile).await
+}
+
+/// Scheduled catch-up for go-live alerts. Compares which subscribed channels are actually live
+/// against the last stream announced for each; a live stream that was never announced means
+/// EventSub deliveries were missed (the callback was down, or Twitch was), so it's announced
+/// now, marked "(recovered)". It also checks the EventSub cost budget, warning the operator as
+/// it fills.
+async fn reconcile(_: Value) -> Result<Value, Error> {
+    let secrets = secrets::current().await?;
+    let mut by_team: BTreeMap<String, Vec<SubscriptionRecord>> = BTreeMap::new();
+    for record in SubscriptionStore::from_env().all_of_type(STREAM_ONLINE).await? {
+        by_team.entry(record.team_id.clone()).or_default().push(record);
+    }
+
+    let mut recovered = 0;
+    for (team_id, records) in by_team {
+        match reconcile_team(&team_id, &records, &secrets).await {
+            Ok(count) => recovered += count,
+            Err(e) => error!("Could not reconcile go-live alerts for workspace {}: {}", team_id, e),
+        }
+    }
+
+    info!("Posted {} recovered go-live alerts", recovered);
+    budget::check(&secrets).await;
+    Ok(json!({ "recovered": recovered }))
+}
+
+async fn reconcile_team(team_id: &str, records: &[SubscriptionRecord], secrets: &Secrets) -> Result<usize, Error> {
+    let workspaces = WorkspaceStore::from_env();
+    let config = workspaces.load(team_id).await?;
+    if !Plan::for_workspace(&config).allows(Feature::Alerts) {
+        return Ok(0);
+    }
+    let filter = OutputFilter::for_workspace(&config);
+    let alert_channel = config.alert_channel.clone();
+    let token = workspaces.bot_token(team_id).await?.unwrap_or_else(|| secrets.slack_bot_token.clone());
+    let minutes = config.alert_coalesce_minutes.unwrap_or(DEFAULT_COALESCE_MINUTES);
+
+    let logins: Vec<String> = records.iter().map(|record| record.channel.clone()).collect();
+    let lookup_secrets = secrets.clone();
+    let live = tokio::task::spawn_blocking(move || live_streams(&logins, &lookup_secrets)).await?;
+    let live = match live {
+        Ok(live) => live,
+        // Twitch being down is exactly when there's nothing to reconcile against yet.
+        Err(e) => {
+            warn!("Skipping reconciliation for workspace {}: {:?}", team_id, e);
+            return Ok(0);
+        }
+    };
+
+    let windows = AlertWindowStore::from_env();
+    let cutoff = Utc::now() - Duration::minutes(GRACE_MINUTES);
+    let oldest = Utc::now() - Duration::hours(MAX_RECOVERY_HOURS);
+    let mut recovered = 0;
+    for (user, stream) in live {
+        let started_at = match DateTime::parse_from_rfc3339(&stream.started_at) {
+            Ok(started_at) => started_at.with_timezone(&Utc),
+            Err(_) => continue,
+        };
+        if started_at > cutoff || started_at < oldest {
+            continue;
+        }
+        if windows.last_alerted(team_id, &user.login).await?.as_deref() == Some(stream.id.as_str()) {
+            continue;
+        }
+
+        // `/tfollow` channels get alerts one by one; the alert channel coalesces them.
+        let followed_to = records
+            .iter()
+            .find(|record| record.channel == user.login)
+            .and_then(|record| record.slack_channel.clone());
+        let mut fanout = Fanout::for_workspace(&config);
+        match (followed_to, &alert_channel) {
+            (Some(channel), _) => fanout = fanout.sink(slack_sink(team_id, channel, &token, 0)),
+            (None, Some(channel)) => fanout = fanout.sink(slack_sink(team_id, channel.clone(), &token, minutes)),
+            (None, None) => {}
+        }
+        if fanout.is_empty() {
+            continue;
+        }
+
+        warn!("Missed the go-live notification for {} (stream {}); posting it now", user.login, stream.id);
+        let line = alerts::alert_line(&user.login, &user.display_name, Some(&stream), &filter);
+        let notification = Notification {
+            team_id: team_id.to_string(),
+            key: format!("{}:{}", user.login, stream.id),
+            subject: format!("{} is live on Twitch", user.display_name),
+            text: format!("{}{}", line, RECOVERED_SUFFIX),
+            url: Some(format!("https://twitch.tv/{}", user.login)),
+            footer: String::new(),
+            card: None,
+        };
+        fanout.send(&notification).await?;
+        let delivered = Utc::now();
+        windows.set_last_alerted(team_id, &user.login, &stream.id).await?;
+        metrics::milliseconds(
+            "RecoveredAlertDelay",
+            (delivered - started_at).num_milliseconds(),
+            &[("Event", STREAM_ONLINE)],
+        );
+        recovered += 1;
+    }
+    Ok(recovered)
+}
+
+fn slack_sink(team_id: &str, channel: String, token: &str, coalesce_minutes: u32) -> SlackSink {
+    SlackSink {
+        team_id: team_id.to_string(),
+        channel,
+        token: token.to_string(),
+        coalesce_minutes,
+    }
+}
+
+/// The subscribed channels that are live right now.
+fn live_streams(logins: &[String], secrets: &Secrets) -> Result<Vec<(TwitchUser, TwitchStream)>, LookupError> {
+    let client = twitch::client(&TimeoutConfig::from_env())?;
+    let users = twitch::get_users_by_login(&client, logins, secrets)?;
+    let ids: Vec<String> = users.iter().map(|user| user.id.clone()).collect();
+    let streams = twitch::get_streams(&client, &ids, secrets)?;
+
+    Ok(streams
+        .into_iter()
+        .filter_map(|stream| {
+            let user = users.iter().find(|user| user.id == stream.user_id)?.clone();
+            Some((user, stream))
+        })
+        .collect())
+}
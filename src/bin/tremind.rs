@@ -0,0 +1,170 @@
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info};
+use serde_json::{json, Value};
+use tokio;
+use twitch_info_bot::http;
+use twitch_info_bot::idempotency::{Claim, IdempotencyStore};
+use twitch_info_bot::scrub::OutputFilter;
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::slack::{self, SlackMessage};
+use twitch_info_bot::twitch::{self, LookupError, TimeoutConfig, TwitchScheduleSegment};
+use twitch_info_bot::workspace::{WorkspaceConfig, WorkspaceStore};
+use twitch_info_bot::{local, logging, Error};
+
+/// Segments looked at per channel; only the next few can fall inside a reminder window.
+const SEGMENTS_PER_CHANNEL: usize = 5;
+
+/// A scheduled segment about to start on a watched channel.
+struct Reminder {
+    login: String,
+    display_name: String,
+    start: DateTime<Utc>,
+    segment: TwitchScheduleSegment,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(send_reminders).await
+}
+
+/// Runs on a schedule. For every workspace with reminders on, posts "going live soon" to its
+/// alert channel for watched channels whose next scheduled segment starts within the
+/// workspace's reminder window. Each segment is only announced once per workspace.
+async fn send_reminders(_event: Value) -> Result<Value, Error> {
+    let secrets = secrets::current().await?;
+    let workspaces = WorkspaceStore::from_env();
+    let idempotency = IdempotencyStore::from_env();
+    let mut sent = 0;
+
+    for (team_id, config) in workspaces.all().await? {
+        let (minutes, channel) = match (config.reminder_minutes, &config.alert_channel) {
+            (Some(minutes), Some(channel)) => (minutes, channel.clone()),
+            _ => continue,
+        };
+
+        let lookup_secrets = secrets.clone();
+        let lookup_config = config.clone();
+        let reminders = tokio::task::spawn_blocking(move || {
+            upcoming(&lookup_config, Duration::minutes(i64::from(minutes)), &lookup_secrets)
+        })
+        .await?;
+        let reminders = match reminders {
+            Ok(reminders) => reminders,
+            Err(e) => {
+                error!("Could not check schedules for workspace {}: {:?}", team_id, e);
+                continue;
+            }
+        };
+        if reminders.is_empty() {
+            continue;
+        }
+
+        let token = workspaces
+            .bot_token(&team_id)
+            .await?
+            .unwrap_or_else(|| secrets.slack_bot_token.clone());
+        let filter = OutputFilter::for_workspace(&config);
+        for reminder in reminders {
+            let message = reminder_message(&reminder, &filter)?;
+            let trigger = format!("{}:{}:{}", team_id, reminder.segment.id, reminder.segment.start_time);
+            let key = match idempotency.claim("reminder", &trigger).await? {
+                Claim::New(key) => key,
+                Claim::Duplicate(_) => continue,
+            };
+
+            let (token, channel) = (token.clone(), channel.clone());
+            let posted = tokio::task::spawn_blocking(move || {
+                slack::post_message(&http::client(), &token, &channel, &message)
+            })
+            .await
+            .map_err(Error::from)
+            .and_then(|posted| posted);
+            match posted {
+                Ok(()) => {
+                    idempotency.complete(&key, "posted").await?;
+                    sent += 1;
+                }
+                Err(e) => {
+                    // Give the segment back so the next run tries it again while it's still upcoming.
+                    idempotency.release(key).await;
+                    error!("Could not post reminder for {} to {}: {}", reminder.login, team_id, e);
+                }
+            }
+        }
+    }
+
+    info!("Sent {} going-live reminders", sent);
+    Ok(json!({ "sent": sent }))
+}
+
+/// Segments of the workspace's unmuted watched channels that start within `window` from now.
+fn upcoming(
+    config: &WorkspaceConfig,
+    window: Duration,
+    secrets: &Secrets,
+) -> Result<Vec<Reminder>, LookupError> {
+    let logins: Vec<String> = config
+        .watchlist
+        .iter()
+        .filter(|login| !config.reminder_mutes.contains(login))
+        .cloned()
+        .collect();
+    if logins.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let client = twitch::client(&TimeoutConfig::from_env())?;
+    let now = Utc::now();
+    let mut reminders = vec![];
+
+    for user in twitch::get_users_by_login(&client, &logins, secrets)? {
+        let schedule = match twitch::get_schedule(&client, &user.id, SEGMENTS_PER_CHANNEL, secrets)? {
+            Some(schedule) => schedule,
+            None => continue,
+        };
+
+        for segment in schedule.segments.unwrap_or_default() {
+            if segment.canceled_until.is_some() {
+                continue;
+            }
+            let start = match DateTime::parse_from_rfc3339(&segment.start_time) {
+                Ok(start) => start.with_timezone(&Utc),
+                Err(_) => continue,
+            };
+            if start > now && start <= now + window {
+                reminders.push(Reminder {
+                    login: user.login.clone(),
+                    display_name: user.display_name.clone(),
+                    start,
+                    segment,
+                });
+            }
+        }
+    }
+
+    Ok(reminders)
+}
+
+fn reminder_message(reminder: &Reminder, filter: &OutputFilter) -> Result<SlackMessage, Error> {
+    let minutes = (reminder.start - Utc::now()).num_minutes().max(1);
+    let category = match &reminder.segment.category {
+        Some(category) => format!(" ({})", category.name),
+        None => String::new(),
+    };
+
+    // Slack renders the date token in each reader's own timezone.
+    SlackMessage::builder()
+        .text(format!(
+            "⏰ <https://twitch.tv/{}|{}> goes live in about {} minutes, at <!date^{}^{{time}}|{}>: {}{}",
+            reminder.login,
+            reminder.display_name,
+            minutes,
+            reminder.start.timestamp(),
+            reminder.segment.start_time,
+            filter.scrub(&reminder.segment.title),
+            category
+        ))
+        .build()
+}
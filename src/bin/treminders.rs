@@ -0,0 +1,99 @@
+use log::error;
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::args;
+use twitch_info_bot::audit::{AuditEntry, AuditLog};
+use twitch_info_bot::secrets;
+use twitch_info_bot::slack::{self, SlackMessage, SlashCommand};
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::{error, local, logging, Error};
+
+const USAGE: &str = "Remind the alert channel before watched channels' scheduled streams:\n\
+    `/treminders on [minutes]` — turn reminders on (default 15 minutes ahead)\n\
+    `/treminders off`\n\
+    `/treminders mute <login>` / `/treminders unmute <login>` — per channel\n\
+    Add `--dry-run` to see what a change would do without making it.";
+
+const DEFAULT_MINUTES: u32 = 15;
+/// Twitch schedules are only read a few segments ahead, so longer windows wouldn't help much.
+const MAX_MINUTES: u32 = 24 * 60;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(|event| async move { configure_reminders(event).await.or_else(error::reply) }).await
+}
+
+async fn configure_reminders(event: Value) -> Result<SlackMessage, Error> {
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return slack::malformed_request();
+        }
+    };
+    secrets::verify_slack_request(&event, &req.token).await?;
+
+    let store = WorkspaceStore::from_env();
+    let previous = store.load(&req.team_id).await?;
+    let mut config = previous.clone();
+    let (command, dry_run) = args::take_dry_run(&req.text);
+    let words: Vec<&str> = command.split_whitespace().collect();
+
+    let text = match words.as_slice() {
+        ["on"] | ["on", _] => {
+            let minutes = match words.get(1).map(|m| m.parse::<u32>()) {
+                None => DEFAULT_MINUTES,
+                Some(Ok(minutes)) if minutes > 0 && minutes <= MAX_MINUTES => minutes,
+                Some(_) => {
+                    return SlackMessage::builder()
+                        .text(format!("Reminders can be 1 to {} minutes ahead.", MAX_MINUTES))
+                        .build()
+                }
+            };
+            config.reminder_minutes = Some(minutes);
+            match &config.alert_channel {
+                Some(channel) => format!(
+                    "Reminders will be posted in <#{}> {} minutes before watched channels go live.",
+                    channel, minutes
+                ),
+                None => format!(
+                    "Reminders are on ({} minutes ahead), but no alert channel is set up yet. \
+                     Choose one in the bot's setup.",
+                    minutes
+                ),
+            }
+        }
+        ["off"] => {
+            config.reminder_minutes = None;
+            "Going-live reminders are off.".to_string()
+        }
+        ["mute", login] => {
+            let login = login.to_ascii_lowercase();
+            if !config.reminder_mutes.contains(&login) {
+                config.reminder_mutes.push(login.clone());
+            }
+            format!("No more reminders for {}.", login)
+        }
+        ["unmute", login] => {
+            let login = login.to_ascii_lowercase();
+            config.reminder_mutes.retain(|muted| muted != &login);
+            format!("Reminders for {} are back on.", login)
+        }
+        _ => return SlackMessage::builder().text(USAGE).build(),
+    };
+    if dry_run {
+        let action = format!("`/treminders {}` would mean: {}", command, text);
+        return SlackMessage::builder().text(args::dry_run_report(action.trim_end_matches('.'))).build();
+    }
+
+    let entry = AuditEntry {
+        team_id: req.team_id.clone(),
+        user_id: req.user_id.clone(),
+        action: "reminders".to_string(),
+        detail: format!("/treminders {}", command),
+    };
+    store.save_change(&AuditLog::from_env(), &entry, &previous, &config).await?;
+    SlackMessage::builder().text(text).build()
+}
@@ -0,0 +1,119 @@
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use simple_error::bail;
+use std::process::{Command, Stdio};
+use twitch_info_bot::eventsub::{self, MESSAGE_ID, MESSAGE_SIGNATURE, MESSAGE_TIMESTAMP};
+use twitch_info_bot::secrets::{self, SLACK_SIGNATURE, SLACK_TIMESTAMP};
+use twitch_info_bot::slack::SlashCommand;
+use twitch_info_bot::{http, local, Error};
+
+const USAGE: &str = "Usage: treplay <function> <event.json> [secrets.json]
+
+Runs a saved Lambda event through <function> (tuser, teventsub, ...) in --local mode, with fake
+secrets: the fields in secrets.json, or placeholders. Slack and EventSub signatures are redone
+with the fake secrets, and an unsigned slash command's token is accepted as it is.";
+
+/// Where the fake secrets go when `secrets.json` doesn't set them.
+const FAKE_SECRET: &str = "replay";
+
+/// A dev tool, not a Lambda function: reproduces a production request offline. The event can be
+/// pasted straight from a log line, text before the JSON and all.
+fn main() -> Result<(), Error> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (function, event_path, secrets_path) = match args.as_slice() {
+        [function, event] => (function, event, None),
+        [function, event, secrets] => (function, event, Some(secrets)),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mut event = read_event(&std::fs::read_to_string(event_path)?)?;
+    let mut fake: Map<String, Value> = match secrets_path {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => Map::new(),
+    };
+    resign(&mut event, &mut fake)?;
+
+    let program = std::env::current_exe()?.with_file_name(function);
+    let mut child = Command::new(&program)
+        .arg(local::FLAG)
+        .envs(fake.iter().filter_map(|(field, value)| Some((field.to_ascii_uppercase(), value.as_str()?))))
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not start {} (build it first): {}", program.display(), e))?;
+    if let Some(stdin) = child.stdin.as_mut() {
+        serde_json::to_writer(stdin, &event)?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{} exited with {}", function, status);
+    }
+    Ok(())
+}
+
+/// The event JSON in `text`, skipping anything before it such as a log line's prefix.
+fn read_event(text: &str) -> Result<Value, Error> {
+    let start = match text.find('{') {
+        Some(start) => start,
+        None => bail!("No JSON object in the event file"),
+    };
+    let event = serde_json::Deserializer::from_str(&text[start..]).into_iter::<Value>().next();
+    match event {
+        Some(event) => Ok(event?),
+        None => bail!("No JSON object in the event file"),
+    }
+}
+
+/// Makes the event pass the function's checks against the fake secrets: Slack and EventSub
+/// signatures are redone with the fake keys and a current timestamp, and an unsigned slash
+/// command's verification token becomes the fake one.
+fn resign(event: &mut Value, fake: &mut Map<String, Value>) -> Result<(), Error> {
+    let body = http::body(event).ok();
+
+    let headers = event.get("headers").and_then(Value::as_object).cloned().unwrap_or_default();
+    let signed = |name: &str| headers.keys().any(|key| key.eq_ignore_ascii_case(name));
+    let mut replaced = vec![];
+
+    match body {
+        Some(body) if signed(SLACK_SIGNATURE) => {
+            let timestamp = Utc::now().timestamp().to_string();
+            let signature = secrets::sign_slack_request(&fake_secret(fake, "slack_signing_secret"), &timestamp, &body)?;
+            replaced.push((SLACK_TIMESTAMP, timestamp));
+            replaced.push((SLACK_SIGNATURE, signature));
+        }
+        Some(body) if signed(MESSAGE_SIGNATURE) => {
+            let id = http::header(event, MESSAGE_ID).unwrap_or_default().to_string();
+            let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+            let signature = match eventsub::sign(&fake_secret(fake, "twitch_eventsub_secret"), &id, &timestamp, &body) {
+                Some(signature) => signature,
+                None => bail!("The fake EventSub secret can't be used as an HMAC key"),
+            };
+            replaced.push((MESSAGE_TIMESTAMP, timestamp));
+            replaced.push((MESSAGE_SIGNATURE, signature));
+        }
+        _ => {
+            if let Ok(command) = SlashCommand::from_event(event) {
+                fake.entry("slack_token").or_insert(Value::String(command.token));
+            }
+        }
+    }
+
+    if let Some(Value::Object(headers)) = event.get_mut("headers") {
+        for (name, value) in replaced {
+            let recorded: Vec<String> = headers.keys().filter(|key| key.eq_ignore_ascii_case(name)).cloned().collect();
+            for key in recorded {
+                headers.remove(&key);
+            }
+            headers.insert(name.to_string(), Value::String(value));
+        }
+    }
+    Ok(())
+}
+
+/// The fake value of a secret field, settling on [`FAKE_SECRET`] if the file didn't give one.
+fn fake_secret(fake: &mut Map<String, Value>, field: &str) -> String {
+    let value = fake.entry(field).or_insert_with(|| Value::String(FAKE_SECRET.to_string()));
+    value.as_str().unwrap_or(FAKE_SECRET).to_string()
+}
@@ -0,0 +1,182 @@
+use chrono::Utc;
+use log::error;
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::deferred;
+use twitch_info_bot::http;
+use twitch_info_bot::locale::Locale;
+use twitch_info_bot::reports::{Report, ReportStore, MAX_INLINE_ROWS};
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::slack::{self, SlackMessage, SlashCommand};
+use twitch_info_bot::twitch::{self, LookupError, TimeoutConfig};
+use twitch_info_bot::{error, local, logging, Error};
+
+const USAGE: &str = "Report on your organization's extensions and drops:\n\
+    `/treport transactions <extension id>` — Bits transactions\n\
+    `/treport drops <game id>` — drops entitlements granted";
+
+/// Stop reading a report past this many rows.
+const MAX_REPORT_ROWS: usize = 10_000;
+
+#[derive(Debug, PartialEq)]
+enum ReportCommand {
+    Transactions(String),
+    Drops(String),
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(handle).await
+}
+
+/// Runs the report, answering failures the bot can name with a message (see `error`); in its
+/// deferred run (see `deferred`) also posts the reply to `response_url`.
+async fn handle(event: Value) -> Result<SlackMessage, Error> {
+    let reply = report(event.clone()).await.or_else(error::reply);
+    deferred::finish(&event, reply).await
+}
+
+/// `/treport transactions|drops <id>`. Reads with the bot's app token, so the extension or game
+/// must belong to the organization that owns the bot's Twitch app, and only that organization's
+/// admins can run it: admins of the workspace the default bot token is for. Reading up to
+/// [`MAX_REPORT_ROWS`] takes longer than Slack waits, so the report is always made in a
+/// deferred run.
+async fn report(event: Value) -> Result<SlackMessage, Error> {
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return slack::malformed_request();
+        }
+    };
+    let secrets = secrets::verify_slack_request(&event, &req.token).await?.secrets;
+
+    let command = match req.text.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["transactions", id] => ReportCommand::Transactions(id.to_string()),
+        ["drops", id] => ReportCommand::Drops(id.to_string()),
+        _ => return SlackMessage::builder().text(USAGE).build(),
+    };
+
+    if !is_operator(&secrets, &req.user_id).await? {
+        return SlackMessage::builder()
+            .ephemeral()
+            .text("Only admins of the bot's own workspace can run reports.")
+            .build();
+    }
+    if !deferred::is_deferred(&event) && !req.response_url.is_empty() {
+        if let Some(placeholder) = deferred::hand_off(&event).await {
+            return Ok(placeholder);
+        }
+    }
+
+    let timeouts = TimeoutConfig::from_env();
+    let fetched = tokio::task::spawn_blocking(move || fetch(&command, &secrets, &timeouts)).await?;
+    let report = match fetched {
+        Ok(report) => report,
+        Err((e, id)) => return SlackMessage::builder().text(e.user_message(&id)).build(),
+    };
+
+    if report.rows.is_empty() {
+        return SlackMessage::builder().text(format!("{}: nothing to report.", report.title)).build();
+    }
+    if report.rows.len() <= MAX_INLINE_ROWS {
+        return SlackMessage::builder().text(report.to_text()).build();
+    }
+
+    let key = format!("{}/{}-{}", req.team_id, Utc::now().format("%Y%m%dT%H%M%S"), req.trigger_id);
+    let url = ReportStore::from_env().export(&key, &report).await?;
+    let locale = Locale::for_team(&req.team_id).await;
+    let capped = if report.rows.len() >= MAX_REPORT_ROWS {
+        format!(" (capped at {})", locale.count(MAX_REPORT_ROWS as u64))
+    } else {
+        String::new()
+    };
+    SlackMessage::builder()
+        .text(format!(
+            "{}: {} rows{}. <{}|Download the CSV> (link expires in 24 hours)",
+            report.title,
+            locale.count(report.rows.len() as u64),
+            capped,
+            url
+        ))
+        .build()
+}
+
+/// Whether `user_id` is an admin of the operator's workspace. The default bot token only sees that
+/// workspace's users, so anyone from another workspace is refused.
+async fn is_operator(secrets: &Secrets, user_id: &str) -> Result<bool, Error> {
+    let (token, user_id) = (secrets.slack_bot_token.clone(), user_id.to_string());
+    let checked = tokio::task::spawn_blocking(move || slack::is_workspace_admin(&http::client(), &token, &user_id));
+    match checked.await? {
+        Ok(is_admin) => Ok(is_admin),
+        Err(e) => {
+            error!("Could not check whether the invoker is an operator admin: {}", e);
+            Ok(false)
+        }
+    }
+}
+
+/// Errors come back with the ID that failed, for the user-facing message.
+fn fetch(
+    command: &ReportCommand,
+    secrets: &Secrets,
+    timeouts: &TimeoutConfig,
+) -> Result<Report, (LookupError, String)> {
+    let (id, result) = match command {
+        ReportCommand::Transactions(id) => (id, transactions(id, secrets, timeouts)),
+        ReportCommand::Drops(id) => (id, drops(id, secrets, timeouts)),
+    };
+    result.map_err(|e| (e, id.clone()))
+}
+
+fn transactions(extension_id: &str, secrets: &Secrets, timeouts: &TimeoutConfig) -> Result<Report, LookupError> {
+    let client = twitch::client(timeouts)?;
+    let transactions = twitch::get_extension_transactions(&client, secrets, extension_id, MAX_REPORT_ROWS)?;
+
+    Ok(Report {
+        title: format!("Transactions for extension {}", extension_id),
+        header: vec!["id", "timestamp", "broadcaster", "user", "product_type", "sku", "product", "cost", "currency"],
+        rows: transactions
+            .into_iter()
+            .map(|t| {
+                vec![
+                    t.id,
+                    t.timestamp,
+                    t.broadcaster_login,
+                    t.user_login,
+                    t.product_type,
+                    t.product_data.sku,
+                    t.product_data.display_name,
+                    t.product_data.cost.amount.to_string(),
+                    t.product_data.cost.cost_type,
+                ]
+            })
+            .collect(),
+    })
+}
+
+fn drops(game_id: &str, secrets: &Secrets, timeouts: &TimeoutConfig) -> Result<Report, LookupError> {
+    let client = twitch::client(timeouts)?;
+    let entitlements = twitch::get_drop_entitlements(&client, secrets, game_id, MAX_REPORT_ROWS)?;
+
+    Ok(Report {
+        title: format!("Drops entitlements for game {}", game_id),
+        header: vec!["id", "benefit_id", "timestamp", "user_id", "game_id", "fulfillment_status", "last_updated"],
+        rows: entitlements
+            .into_iter()
+            .map(|e| {
+                vec![
+                    e.id,
+                    e.benefit_id,
+                    e.timestamp,
+                    e.user_id,
+                    e.game_id,
+                    e.fulfillment_status,
+                    e.last_updated,
+                ]
+            })
+            .collect(),
+    })
+}
@@ -0,0 +1,264 @@
+use log::{error, info};
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::args;
+use twitch_info_bot::idempotency::{Claim, IdempotencyStore};
+use twitch_info_bot::links::{self, AccountLink, LinkStore};
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::slack::{self, SlackMessage, SlashCommand};
+use twitch_info_bot::twitch::{self, Credentials, CustomReward, LookupError, TimeoutConfig};
+use twitch_info_bot::{error, local, logging, Error};
+
+const USAGE: &str = "Manage channel point rewards:\n\
+    `/trewards [channel] list`\n\
+    `/trewards [channel] queue`\n\
+    `/trewards [channel] pause <reward>`\n\
+    `/trewards [channel] resume <reward>`\n\
+    Add `--dry-run` to see what a pause or resume would do without making it.";
+
+const SCOPE: &str = "channel:manage:redemptions";
+
+/// Stop counting a reward's queue past this many redemptions.
+const MAX_QUEUE_COUNT: usize = 500;
+
+#[derive(Debug, PartialEq)]
+enum RewardCommand {
+    List,
+    Queue,
+    Pause(String),
+    Resume(String),
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(|event| async move { manage_rewards(event).await.or_else(error::reply) }).await
+}
+
+async fn manage_rewards(event: Value) -> Result<SlackMessage, Error> {
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return slack::malformed_request();
+        }
+    };
+    let secrets = secrets::verify_slack_request(&event, &req.token).await?.secrets;
+
+    let (text, dry_run) = args::take_dry_run(&req.text);
+    let (channel, command) = match parse_command(&text) {
+        Some(parsed) => parsed,
+        None => return SlackMessage::builder().text(USAGE).build(),
+    };
+
+    let store = LinkStore::from_env();
+    let link = match links::command_link(&store, &secrets, &req.team_id, &req.user_id, channel.as_deref(), SCOPE).await? {
+        Ok(link) => link,
+        Err(message) => return SlackMessage::builder().text(message).build(),
+    };
+
+    // Pausing and resuming are side effects, so a Slack retry must not toggle twice.
+    let action = match &command {
+        RewardCommand::List | RewardCommand::Queue => None,
+        RewardCommand::Pause(_) => Some("trewards:pause"),
+        RewardCommand::Resume(_) => Some("trewards:resume"),
+    };
+    let idempotency = IdempotencyStore::from_env();
+    let claim = match action {
+        Some(action) if !dry_run && !req.trigger_id.is_empty() => {
+            match idempotency.claim(action, &req.trigger_id).await? {
+                Claim::Duplicate(response) => {
+                    info!("Ignoring retried {} for trigger {}", action, req.trigger_id);
+                    let text = response.unwrap_or_else(|| "Still working on that…".to_string());
+                    return SlackMessage::builder().text(text).build();
+                }
+                Claim::New(key) => Some(key),
+            }
+        }
+        _ => None,
+    };
+
+    let timeouts = TimeoutConfig::from_env();
+    let ran = tokio::task::spawn_blocking(move || run(&command, &link, &secrets, &timeouts, dry_run)).await;
+    let text = match ran {
+        Ok(text) => text,
+        Err(e) => {
+            // Left without a response, the claim would answer retries with "Still working" for a day.
+            if let Some(key) = claim {
+                idempotency.release(key).await;
+            }
+            return Err(e.into());
+        }
+    };
+
+    if let Some(key) = claim {
+        idempotency.complete(&key, &text).await?;
+    }
+    if dry_run {
+        return SlackMessage::builder().ephemeral().text(text).build();
+    }
+    SlackMessage::builder().in_channel().text(text).build()
+}
+
+/// `[channel] <list|queue|pause|resume> [reward]`, where the reward is a title or reward ID.
+fn parse_command(text: &str) -> Option<(Option<String>, RewardCommand)> {
+    let mut words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+        return None;
+    }
+
+    let channel = match words[0] {
+        "list" | "queue" | "pause" | "resume" => None,
+        _ => Some(words.remove(0).to_ascii_lowercase()),
+    };
+    let reward = words.get(1..).map(|rest| rest.join(" ")).unwrap_or_default();
+
+    let command = match (words.first().cloned(), reward.is_empty()) {
+        (Some("list"), _) => RewardCommand::List,
+        (Some("queue"), _) => RewardCommand::Queue,
+        (Some("pause"), false) => RewardCommand::Pause(reward),
+        (Some("resume"), false) => RewardCommand::Resume(reward),
+        _ => return None,
+    };
+    Some((channel, command))
+}
+
+fn run(
+    command: &RewardCommand,
+    link: &AccountLink,
+    secrets: &Secrets,
+    timeouts: &TimeoutConfig,
+    dry_run: bool,
+) -> String {
+    match try_run(command, link, secrets, timeouts, dry_run) {
+        Ok(text) => text,
+        Err(LookupError::Unauthorized(403)) if matches!(command, RewardCommand::Pause(_) | RewardCommand::Resume(_)) => {
+            "Twitch only lets the app that created a reward pause or resume it, and this one was \
+             created somewhere else."
+                .to_string()
+        }
+        Err(e) => e.user_message(&link.login),
+    }
+}
+
+fn try_run(
+    command: &RewardCommand,
+    link: &AccountLink,
+    secrets: &Secrets,
+    timeouts: &TimeoutConfig,
+    dry_run: bool,
+) -> Result<String, LookupError> {
+    let client = twitch::client(timeouts)?;
+    let credentials = Credentials::user(secrets, link);
+    let rewards = twitch::get_custom_rewards(&client, credentials, &link.twitch_user_id)?;
+
+    let (name, paused) = match command {
+        RewardCommand::List => return Ok(render_list(&link.login, &rewards)),
+        RewardCommand::Queue => return render_queue(&client, credentials, link, &rewards),
+        RewardCommand::Pause(name) => (name, true),
+        RewardCommand::Resume(name) => (name, false),
+    };
+
+    let reward = match rewards
+        .iter()
+        .find(|r| r.id == *name || r.title.eq_ignore_ascii_case(name))
+    {
+        Some(reward) => reward,
+        None => return Ok(format!("{} has no reward called \"{}\"", link.login, name)),
+    };
+    if dry_run {
+        let verb = if paused { "pause" } else { "resume" };
+        return Ok(args::dry_run_report(&format!("{} *{}* on {}", verb, reward.title, link.login)));
+    }
+
+    let updated = twitch::set_reward_paused(&client, credentials, &link.twitch_user_id, &reward.id, paused)?;
+    Ok(format!(
+        "{} *{}* on {}",
+        if updated.is_paused { "⏸ Paused" } else { "▶️ Resumed" },
+        updated.title,
+        link.login
+    ))
+}
+
+fn render_list(login: &str, rewards: &[CustomReward]) -> String {
+    if rewards.is_empty() {
+        return format!("{} has no custom channel point rewards", login);
+    }
+
+    let lines: Vec<String> = rewards
+        .iter()
+        .map(|r| {
+            let state = if !r.is_enabled {
+                "disabled"
+            } else if r.is_paused {
+                "paused"
+            } else {
+                "active"
+            };
+            let redeemed = r
+                .redemptions_redeemed_current_stream
+                .map(|count| format!(", {} redeemed this stream", count))
+                .unwrap_or_default();
+            format!("• *{}* — {} points, {}{}", r.title, r.cost, state, redeemed)
+        })
+        .collect();
+
+    format!("Channel point rewards for {}:\n{}", login, lines.join("\n"))
+}
+
+/// Pending redemptions grouped by reward. Rewards made by other apps can't be read (Twitch
+/// answers 403) and are listed as such rather than failing the whole summary.
+fn render_queue(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    link: &AccountLink,
+    rewards: &[CustomReward],
+) -> Result<String, LookupError> {
+    let mut lines = vec![];
+    let mut unreadable = vec![];
+
+    for reward in rewards.iter().filter(|r| !r.should_redemptions_skip_request_queue) {
+        let pending = match twitch::get_unfulfilled_redemptions(
+            client,
+            credentials,
+            &link.twitch_user_id,
+            &reward.id,
+            MAX_QUEUE_COUNT,
+        ) {
+            Ok(pending) => pending,
+            Err(LookupError::Unauthorized(403)) => {
+                unreadable.push(reward.title.as_str());
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if pending.is_empty() {
+            continue;
+        }
+
+        let count = if pending.len() >= MAX_QUEUE_COUNT {
+            format!("{}+", MAX_QUEUE_COUNT)
+        } else {
+            pending.len().to_string()
+        };
+        let oldest = &pending[0];
+        lines.push(format!(
+            "• *{}* — {} pending (oldest from {} at {})",
+            reward.title, count, oldest.user_name, oldest.redeemed_at
+        ));
+    }
+
+    let mut text = if lines.is_empty() {
+        format!("No pending redemptions for {}", link.login)
+    } else {
+        format!("Pending redemptions for {}:\n{}", link.login, lines.join("\n"))
+    };
+    if !unreadable.is_empty() {
+        text.push_str(&format!(
+            "\n_Can't read queues for rewards created by other apps: {}_",
+            unreadable.join(", ")
+        ));
+    }
+    Ok(text)
+}
@@ -0,0 +1,187 @@
+use chrono::Utc;
+use log::{error, info, warn};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio;
+use twitch_info_bot::eventsub::{self, Subscription, SubscriptionRecord, SubscriptionStore};
+use twitch_info_bot::secrets::{self, Secrets, CURRENT, SECRET_ID};
+use twitch_info_bot::twitch::{self, LookupError, TimeoutConfig};
+use twitch_info_bot::{local, logging, Error};
+
+/// How long Twitch gets to verify the new subscriptions before the rotation is called off.
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Twitch takes webhook secrets of 10 to 100 ASCII characters.
+const SECRET_LENGTH: usize = 40;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(rotate).await
+}
+
+/// Rotates the EventSub webhook secret, run by hand or on a schedule:
+///
+/// 1. stages a new secret as `twitch_eventsub_next_secret`, which `teventsub` accepts alongside
+///    the current one;
+/// 2. creates a copy of every stored subscription signed with it, and waits for Twitch to verify
+///    them all;
+/// 3. moves each record to its copy and deletes the old subscription;
+/// 4. makes the new secret the current one.
+///
+/// The old subscriptions keep delivering until their copies are enabled, so alerts don't stop.
+/// If a copy can't be created or verified, the copies are deleted and the old subscriptions left
+/// alone; running the job again resumes with the staged secret.
+async fn rotate(_: Value) -> Result<Value, Error> {
+    let callback = match std::env::var("EVENTSUB_CALLBACK_URL") {
+        Ok(callback) => callback,
+        Err(_) => return Err("EVENTSUB_CALLBACK_URL is not set".into()),
+    };
+
+    let mut secrets = secrets::fetch_uncached(SECRET_ID, CURRENT).await?.secrets;
+    if secrets.twitch_eventsub_next_secret.is_empty() {
+        let next: String = rand::thread_rng().sample_iter(&Alphanumeric).take(SECRET_LENGTH).collect();
+        secrets::store_twitch_fields(&[("twitch_eventsub_next_secret", Some(&next))]).await?;
+        info!("Staged a new EventSub webhook secret");
+        secrets.twitch_eventsub_next_secret = next;
+    } else {
+        info!("Resuming the rotation to the staged EventSub webhook secret");
+    }
+
+    let store = SubscriptionStore::from_env();
+    let records = store.all().await?;
+    // Twitch refuses a second subscription with the same type, condition and callback, so the
+    // copies get a callback of their own.
+    let callback = format!("{}?rotated={}", callback.split('?').next().unwrap_or_default(), Utc::now().timestamp());
+    let blocking_secrets = secrets.clone();
+    let copies =
+        tokio::task::spawn_blocking(move || copy_subscriptions(records, &blocking_secrets, &callback)).await??;
+
+    let mut rotated = 0;
+    for (record, copy) in &copies {
+        store
+            .save(&SubscriptionRecord {
+                subscription_id: copy.id.clone(),
+                ..record.clone()
+            })
+            .await?;
+        let blocking_secrets = secrets.clone();
+        let old_id = record.subscription_id.clone();
+        let deleted = tokio::task::spawn_blocking(move || {
+            twitch::client(&TimeoutConfig::from_env())
+                .and_then(|client| eventsub::unsubscribe(&client, &blocking_secrets, &old_id))
+        })
+        .await?;
+        if let Err(e) = deleted {
+            // It still delivers, signed with the old secret, which `teventsub` stops accepting.
+            error!("Could not delete replaced subscription {}: {:?}", record.subscription_id, e);
+        }
+        store.forget(&record.subscription_id).await?;
+        rotated += 1;
+    }
+
+    let next = secrets.twitch_eventsub_next_secret.clone();
+    secrets::store_twitch_fields(&[("twitch_eventsub_secret", Some(&next)), ("twitch_eventsub_next_secret", None)])
+        .await?;
+    info!("Rotated the EventSub webhook secret across {} subscriptions", rotated);
+    Ok(json!({ "rotated": rotated }))
+}
+
+/// Creates a copy of each record's subscription signed with the next secret and waits until
+/// Twitch has verified them all. Records whose subscription Twitch no longer has are left out.
+/// On failure the copies made so far are deleted again.
+fn copy_subscriptions(
+    records: Vec<SubscriptionRecord>,
+    secrets: &Secrets,
+    callback: &str,
+) -> Result<Vec<(SubscriptionRecord, Subscription)>, Error> {
+    let client = twitch::client(&TimeoutConfig::from_env()).map_err(lookup_failed)?;
+    let existing: HashMap<String, Subscription> = eventsub::list_subscriptions(&client, secrets)
+        .map_err(lookup_failed)?
+        .into_iter()
+        .map(|subscription| (subscription.id.clone(), subscription))
+        .collect();
+
+    let mut copies = vec![];
+    let mut failure = None;
+    for record in records {
+        let old = match existing.get(&record.subscription_id) {
+            Some(old) => old,
+            None => {
+                warn!("Subscription {} is gone from Twitch; not copying it", record.subscription_id);
+                continue;
+            }
+        };
+        let next = &secrets.twitch_eventsub_next_secret;
+        let condition = old.condition.clone();
+        let created = eventsub::subscribe_with_secret(
+            &client,
+            secrets,
+            next,
+            &old.subscription_type,
+            &old.version,
+            condition,
+            callback,
+        );
+        match created {
+            Ok(copy) => copies.push((record, copy)),
+            Err(e) => {
+                failure = Some(format!("Could not copy subscription {}: {:?}", record.subscription_id, e));
+                break;
+            }
+        }
+    }
+
+    if failure.is_none() {
+        failure = wait_until_enabled(&client, secrets, &copies).err();
+    }
+    match failure {
+        None => Ok(copies),
+        Some(failure) => {
+            for (_, copy) in &copies {
+                if let Err(e) = eventsub::unsubscribe(&client, secrets, &copy.id) {
+                    error!("Could not delete copied subscription {}: {:?}", copy.id, e);
+                }
+            }
+            Err(failure.into())
+        }
+    }
+}
+
+fn wait_until_enabled(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    copies: &[(SubscriptionRecord, Subscription)],
+) -> Result<(), String> {
+    let started = Instant::now();
+    loop {
+        let statuses: HashMap<String, String> = eventsub::list_subscriptions(client, secrets)
+            .map_err(|e| format!("Could not list subscriptions: {:?}", e))?
+            .into_iter()
+            .map(|subscription| (subscription.id, subscription.status))
+            .collect();
+        let mut pending = 0;
+        for (_, copy) in copies {
+            match statuses.get(&copy.id).map(String::as_str) {
+                Some("enabled") => {}
+                Some("webhook_callback_verification_pending") => pending += 1,
+                status => return Err(format!("Subscription {} wasn't verified: {:?}", copy.id, status)),
+            }
+        }
+        if pending == 0 {
+            return Ok(());
+        }
+        if started.elapsed() > VERIFY_TIMEOUT {
+            return Err(format!("{} subscriptions still unverified after {:?}", pending, VERIFY_TIMEOUT));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn lookup_failed(e: LookupError) -> Error {
+    format!("Twitch lookup failed: {:?}", e).into()
+}
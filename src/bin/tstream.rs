@@ -0,0 +1,12 @@
+use tokio;
+use twitch_info_bot::command::Router;
+use twitch_info_bot::lookups::stream::Streams;
+use twitch_info_bot::{local, logging, Error};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    let router: &'static Router = Box::leak(Box::new(Router::with_defaults().command(Streams)));
+    local::serve(move |event| router.dispatch(event)).await
+}
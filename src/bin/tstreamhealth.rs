@@ -0,0 +1,120 @@
+use chrono::Utc;
+use log::error;
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::links::LinkStore;
+use twitch_info_bot::locale::Locale;
+use twitch_info_bot::render::format_duration;
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::slack::{self, Block, SlackMessage, SlashCommand, Text};
+use twitch_info_bot::twitch::{self, Ingest, LookupError, TimeoutConfig, TwitchStream, TwitchUser};
+use twitch_info_bot::{error, local, logging, Error};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(|event| async move { stream_health(event).await.or_else(error::reply) }).await
+}
+
+/// `/tstreamhealth [login]`: live status of the invoker's linked channel (or `login`) together
+/// with the health-related fields Helix exposes and the state of Twitch's ingest servers.
+async fn stream_health(event: Value) -> Result<SlackMessage, Error> {
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return slack::malformed_request();
+        }
+    };
+    let secrets = secrets::verify_slack_request(&event, &req.token).await?.secrets;
+
+    let login = match req.text.split_whitespace().next() {
+        Some(login) => login.to_ascii_lowercase(),
+        None => match LinkStore::from_env().get(&req.team_id, &req.user_id).await? {
+            Some(link) => link.login,
+            None => {
+                return SlackMessage::builder()
+                    .text("Link your Twitch account with `/tlink` first, or pass a channel: `/tstreamhealth <login>`")
+                    .build()
+            }
+        },
+    };
+
+    let timeouts = TimeoutConfig::from_env();
+    let lookup_login = login.clone();
+    let health = tokio::task::spawn_blocking(move || fetch_health(&lookup_login, &secrets, &timeouts)).await?;
+
+    match health {
+        Ok(Some((user, stream, ingests))) => {
+            render(&user, stream.as_ref(), &ingests, Locale::for_team(&req.team_id).await)
+        }
+        Ok(None) => SlackMessage::builder().text(format!("No Twitch user named {}", login)).build(),
+        Err(e) => SlackMessage::builder().text(e.user_message(&login)).build(),
+    }
+}
+
+fn fetch_health(
+    login: &str,
+    secrets: &Secrets,
+    timeouts: &TimeoutConfig,
+) -> Result<Option<(TwitchUser, Option<TwitchStream>, Vec<Ingest>)>, LookupError> {
+    let client = twitch::client(timeouts)?;
+    let user = match twitch::get_users_by_login(&client, &[login.to_string()], secrets)?.pop() {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+    let stream = twitch::get_streams(&client, &[user.id.clone()], secrets)?.pop();
+    // Ingest status is a nice-to-have; don't fail the report over it.
+    let ingests = twitch::get_ingests(&client).unwrap_or_default();
+
+    Ok(Some((user, stream, ingests)))
+}
+
+fn render(
+    user: &TwitchUser,
+    stream: Option<&TwitchStream>,
+    ingests: &[Ingest],
+    locale: Locale,
+) -> Result<SlackMessage, Error> {
+    let status = match stream {
+        Some(stream) => format!(
+            "🔴 *{}* is live ({})\n*Uptime:* {}\n*Viewers:* {}\n*Category:* {}\n*Title:* {}\n*Language:* {}{}",
+            user.display_name,
+            stream.stream_type,
+            stream.uptime(Utc::now()).map(format_duration).unwrap_or_else(|| "?".to_string()),
+            locale.count(stream.viewer_count),
+            if stream.game_name.is_empty() { "No category" } else { stream.game_name.as_str() },
+            stream.title,
+            stream.language,
+            if stream.is_mature { "\n*Mature:* yes" } else { "" }
+        ),
+        None => format!("⚫ *{}* is offline", user.display_name),
+    };
+
+    let available = ingests.iter().filter(|i| i.availability > 0.0).count();
+    let ingest_status = match ingests.iter().find(|i| i.default) {
+        Some(default) => format!(
+            "Ingests: {}/{} available, default {}{}",
+            available,
+            ingests.len(),
+            default.name,
+            if default.availability > 0.0 { "" } else { " (unavailable!)" }
+        ),
+        None if ingests.is_empty() => "Ingest status unavailable".to_string(),
+        None => format!("Ingests: {}/{} available", available, ingests.len()),
+    };
+
+    SlackMessage::builder()
+        .text(status.clone())
+        .block(Block::Section {
+            text: Text::Mrkdwn { text: status },
+            accessory: None,
+        })
+        .block(Block::Context {
+            elements: vec![Text::Mrkdwn {
+                text: format!("{} · Twitch doesn't expose bitrate or dropped frames through its API", ingest_status),
+            }],
+        })
+        .build()
+}
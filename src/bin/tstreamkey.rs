@@ -0,0 +1,178 @@
+use log::error;
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::args;
+use twitch_info_bot::audit::{AuditEntry, AuditLog};
+use twitch_info_bot::http;
+use twitch_info_bot::links::{self, LinkStore};
+use twitch_info_bot::secrets;
+use twitch_info_bot::slack::{self, SlackMessage, SlashCommand};
+use twitch_info_bot::twitch::{self, Credentials, TimeoutConfig};
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::{error, local, logging, Error};
+
+const USAGE: &str = "Retrieve your Twitch stream key by DM:\n\
+    `/tstreamkey` — send your linked channel's key to you\n\
+    `/tstreamkey grant @user` / `/tstreamkey revoke @user` — workspace admins only\n\
+    Add `--dry-run` to see what a command would do without doing it.";
+
+const SCOPE: &str = "channel:read:stream_key";
+
+#[derive(Debug, PartialEq)]
+enum KeyCommand {
+    Retrieve,
+    Grant(String),
+    Revoke(String),
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(|event| async move { stream_key(event).await.or_else(error::reply) }).await
+}
+
+/// `/tstreamkey [grant|revoke @user]`. Stream keys let anyone broadcast as the channel, so
+/// retrieval needs a grant from a workspace admin, every use is audited, and the key itself only
+/// ever goes out by DM. Replies in the channel are always ephemeral.
+async fn stream_key(event: Value) -> Result<SlackMessage, Error> {
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return slack::malformed_request();
+        }
+    };
+    let secrets = secrets::verify_slack_request(&event, &req.token).await?.secrets;
+
+    let (text, dry_run) = args::take_dry_run(&req.text);
+    let command = match parse_command(&text) {
+        Some(command) => command,
+        None => return SlackMessage::builder().ephemeral().text(USAGE).build(),
+    };
+
+    let workspaces = WorkspaceStore::from_env();
+    let token = workspaces
+        .bot_token(&req.team_id)
+        .await?
+        .unwrap_or_else(|| secrets.slack_bot_token.clone());
+    let audit = AuditLog::from_env();
+    let mut config = workspaces.load(&req.team_id).await?;
+
+    let (target, granted) = match command {
+        KeyCommand::Grant(target) => (target, true),
+        KeyCommand::Revoke(target) => (target, false),
+        KeyCommand::Retrieve => {
+            if !config.stream_key_grants.contains(&req.user_id) {
+                return SlackMessage::builder()
+                    .ephemeral()
+                    .text("Retrieving stream keys needs a grant from a workspace admin: `/tstreamkey grant @you`.")
+                    .build();
+            }
+
+            let store = LinkStore::from_env();
+            let link = match links::command_link(&store, &secrets, &req.team_id, &req.user_id, None, SCOPE).await? {
+                Ok(link) => link,
+                Err(message) => return SlackMessage::builder().ephemeral().text(message).build(),
+            };
+            if dry_run {
+                let action = format!("send {}'s stream key to you in a DM", link.login);
+                return SlackMessage::builder().ephemeral().text(args::dry_run_report(&action)).build();
+            }
+
+            audit
+                .record(&AuditEntry {
+                    team_id: req.team_id.clone(),
+                    user_id: req.user_id.clone(),
+                    action: "stream_key".to_string(),
+                    detail: format!("retrieve stream key for {}", link.login),
+                })
+                .await?;
+
+            let user_id = req.user_id.clone();
+            let timeouts = TimeoutConfig::from_env();
+            let text = tokio::task::spawn_blocking(move || -> Result<String, Error> {
+                let key = twitch::client(&timeouts).and_then(|client| {
+                    twitch::get_stream_key(&client, Credentials::user(&secrets, &link), &link.twitch_user_id)
+                });
+                let key = match key {
+                    Ok(key) => key,
+                    Err(e) => return Ok(e.user_message(&link.login)),
+                };
+
+                // Posting to a user ID lands in their DM with the bot.
+                let message = SlackMessage::builder()
+                    .text(format!(
+                        "Stream key for {}: `{}`\nDelete this message once you've copied it, and reset the key \
+                         from the Twitch dashboard if you think it has leaked.",
+                        link.login, key
+                    ))
+                    .build()?;
+                slack::post_message(&http::client(), &token, &user_id, &message)?;
+                Ok("Sent your stream key to you in a DM.".to_string())
+            })
+            .await??;
+
+            return SlackMessage::builder().ephemeral().text(text).build();
+        }
+    };
+
+    let invoker = req.user_id.clone();
+    let is_admin = tokio::task::spawn_blocking(move || {
+        slack::is_workspace_admin(&http::client(), &token, &invoker)
+    })
+    .await??;
+    if !is_admin {
+        return SlackMessage::builder()
+            .ephemeral()
+            .text("Only workspace admins can grant or revoke stream key access.")
+            .build();
+    }
+    if dry_run {
+        let action = format!("{} <@{}> stream key access", if granted { "grant" } else { "revoke" }, target);
+        return SlackMessage::builder().ephemeral().text(args::dry_run_report(&action)).build();
+    }
+
+    let entry = AuditEntry {
+        team_id: req.team_id.clone(),
+        user_id: req.user_id.clone(),
+        action: "stream_key_grant".to_string(),
+        detail: format!("{} stream key access for {}", if granted { "grant" } else { "revoke" }, target),
+    };
+    let previous = config.clone();
+    config.stream_key_grants.retain(|user| user != &target);
+    if granted {
+        config.stream_key_grants.push(target.clone());
+    }
+    workspaces.save_change(&audit, &entry, &previous, &config).await?;
+
+    let text = if granted {
+        format!("<@{}> can now retrieve their stream key with `/tstreamkey`.", target)
+    } else {
+        format!("<@{}> can no longer retrieve their stream key.", target)
+    };
+    SlackMessage::builder().ephemeral().text(text).build()
+}
+
+fn parse_command(text: &str) -> Option<KeyCommand> {
+    match text.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => Some(KeyCommand::Retrieve),
+        ["grant", user] => slack_user_id(user).map(KeyCommand::Grant),
+        ["revoke", user] => slack_user_id(user).map(KeyCommand::Revoke),
+        _ => None,
+    }
+}
+
+/// Accepts an escaped mention (`<@U123|name>`) or a bare user ID.
+fn slack_user_id(text: &str) -> Option<String> {
+    let id = text
+        .trim_start_matches("<@")
+        .trim_end_matches('>')
+        .split('|')
+        .next()
+        .unwrap_or_default();
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()) {
+        return None;
+    }
+    Some(id.to_string())
+}
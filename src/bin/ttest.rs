@@ -0,0 +1,137 @@
+use futures::future::BoxFuture;
+use std::time::Instant;
+use tokio;
+use twitch_info_bot::apptoken;
+use twitch_info_bot::command::{Command, Invocation, Router};
+use twitch_info_bot::http;
+use twitch_info_bot::links;
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::slack::{self, SlackMessage};
+use twitch_info_bot::twitch::{self, TimeoutConfig};
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::{local, logging, Error};
+
+/// A channel that always exists, for the Twitch lookup step.
+const PROBE_LOGIN: &str = "twitch";
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    let router: &'static Router = Box::leak(Box::new(Router::with_defaults().command(SelfTest)));
+    local::serve(move |event| router.dispatch(event)).await
+}
+
+/// `/ttest`: an end-to-end check admins can run without log access. Each step is timed and
+/// reported, and a failed step doesn't stop the ones after it.
+struct SelfTest;
+
+struct Step {
+    name: &'static str,
+    millis: u128,
+    outcome: Result<String, String>,
+}
+
+impl Command for SelfTest {
+    fn name(&self) -> &'static str {
+        "/ttest"
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>> {
+        Box::pin(async move {
+            let req = &invocation.req;
+            let token = WorkspaceStore::from_env()
+                .bot_token(&req.team_id)
+                .await?
+                .unwrap_or_else(|| invocation.secrets.slack_bot_token.clone());
+
+            let admin_token = token.clone();
+            let invoker = req.user_id.clone();
+            let is_admin = tokio::task::spawn_blocking(move || {
+                slack::is_workspace_admin(&http::client(), &admin_token, &invoker)
+            })
+            .await??;
+            if !is_admin {
+                return SlackMessage::builder()
+                    .ephemeral()
+                    .text("Only workspace admins can run the self-test.")
+                    .build();
+            }
+
+            let mut steps = vec![];
+            let started = Instant::now();
+            let verified = secrets::verify_slack_token(&req.token).await;
+            steps.push(Step {
+                name: "Slack token and secrets",
+                millis: started.elapsed().as_millis(),
+                outcome: verified.map(|_| "ok".to_string()).map_err(|e| e.to_string()),
+            });
+
+            let secrets = invocation.secrets.clone();
+            let user_id = req.user_id.clone();
+            let twitch_steps = tokio::task::spawn_blocking(move || twitch_and_slack_steps(&secrets, &token, &user_id));
+            steps.extend(twitch_steps.await?);
+
+            SlackMessage::builder().ephemeral().text(report(&steps)).build()
+        })
+    }
+}
+
+/// The blocking steps: validating the app token, a lookup that skips the cache, and a DM to the
+/// invoker.
+fn twitch_and_slack_steps(secrets: &Secrets, token: &str, user_id: &str) -> Vec<Step> {
+    let client = http::client();
+    let mut steps = vec![];
+
+    let started = Instant::now();
+    let validated = links::validate(&client, &apptoken::token(&client, secrets));
+    steps.push(Step {
+        name: "Twitch app token",
+        millis: started.elapsed().as_millis(),
+        outcome: validated
+            .map(|validation| format!("valid for another {}h", validation.expires_in / 3600))
+            .map_err(|e| e.to_string()),
+    });
+
+    let started = Instant::now();
+    let looked_up = twitch::client(&TimeoutConfig::from_env())
+        .and_then(|client| twitch::get_users_by_login(&client, &[PROBE_LOGIN.to_string()], secrets));
+    steps.push(Step {
+        name: "Twitch lookup (uncached)",
+        millis: started.elapsed().as_millis(),
+        outcome: match looked_up {
+            Ok(users) if !users.is_empty() => Ok(format!("found {}", PROBE_LOGIN)),
+            Ok(_) => Err(format!("Twitch didn't return {}", PROBE_LOGIN)),
+            Err(e) => Err(e.user_message(PROBE_LOGIN)),
+        },
+    });
+
+    let started = Instant::now();
+    let posted = SlackMessage::builder()
+        .text("Self-test message from the Twitch info bot. You can ignore or delete it.")
+        .build()
+        .and_then(|message| slack::post_message(&client, token, user_id, &message));
+    steps.push(Step {
+        name: "Slack postMessage",
+        millis: started.elapsed().as_millis(),
+        outcome: posted.map(|_| "sent you a DM".to_string()).map_err(|e| e.to_string()),
+    });
+
+    steps
+}
+
+fn report(steps: &[Step]) -> String {
+    let failed = steps.iter().filter(|step| step.outcome.is_err()).count();
+    let mut lines = vec![if failed == 0 {
+        "*Self-test passed*".to_string()
+    } else {
+        format!("*Self-test: {} of {} steps failed*", failed, steps.len())
+    }];
+    for step in steps {
+        lines.push(match &step.outcome {
+            Ok(detail) => format!("✅ {} — {}ms ({})", step.name, step.millis, detail),
+            Err(error) => format!("❌ {} — {}ms: {}", step.name, step.millis, error),
+        });
+    }
+    lines.join("\n")
+}
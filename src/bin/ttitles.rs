@@ -0,0 +1,184 @@
+use chrono::DateTime;
+use futures::future::BoxFuture;
+use log::{error, info};
+use serde_json::json;
+use tokio;
+use twitch_info_bot::args::{Arg, CommandSchema, Kind, Schema};
+use twitch_info_bot::budget;
+use twitch_info_bot::command::{Command, Invocation, Router};
+use twitch_info_bot::eventsub::{self, SubscriptionRecord, SubscriptionStore, CHANNEL_UPDATE};
+use twitch_info_bot::plans::Feature;
+use twitch_info_bot::slack::SlackMessage;
+use twitch_info_bot::titles::{TitleChange, TitleStore};
+use twitch_info_bot::twitch::{self, LookupError, TimeoutConfig, TwitchUser};
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::{local, logging, Error};
+
+/// How many changes `/ttitles` lists.
+const HISTORY_LIMIT: usize = 15;
+
+static SCHEMA: CommandSchema = CommandSchema {
+    command: "/ttitles",
+    summary: "Show a watched channel's recent title and category changes",
+    forms: &[Schema {
+        subcommand: None,
+        summary: "list the channel's changes, newest first",
+        args: &[Arg::required("channel", Kind::Login, "the channel's login")],
+        flags: &[],
+    }],
+    dry_run: false,
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    let router: &'static Router = Box::leak(Box::new(Router::with_defaults().command(Titles)));
+    local::serve(move |event| router.dispatch(event)).await
+}
+
+/// `/ttitles <channel>`: the title history recorded from `channel.update` notifications. The
+/// first lookup of a watchlist channel that isn't tracked yet starts tracking it.
+struct Titles;
+
+impl Command for Titles {
+    fn name(&self) -> &'static str {
+        "/ttitles"
+    }
+
+    fn schema(&self) -> Option<&'static CommandSchema> {
+        Some(&SCHEMA)
+    }
+
+    fn feature(&self, _invocation: &Invocation) -> Option<Feature> {
+        Some(Feature::Tracking)
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>> {
+        Box::pin(async move {
+            let req = &invocation.req;
+            let channel = invocation.args.text("channel").unwrap_or_default().to_string();
+
+            let lookup_channel = channel.clone();
+            let secrets = invocation.secrets.clone();
+            let user = tokio::task::spawn_blocking(move || -> Result<Option<TwitchUser>, LookupError> {
+                let client = twitch::client(&TimeoutConfig::from_env())?;
+                Ok(twitch::get_users_by_login(&client, &[lookup_channel], &secrets)?.pop())
+            })
+            .await?;
+            let user = match user {
+                Ok(Some(user)) => user,
+                Ok(None) => {
+                    return SlackMessage::builder()
+                        .ephemeral()
+                        .text(format!("No Twitch user named {}", channel))
+                        .build()
+                }
+                Err(e) => return SlackMessage::builder().ephemeral().text(e.user_message(&channel)).build(),
+            };
+
+            let history = TitleStore::from_env().recent(&user.id, HISTORY_LIMIT).await?;
+            let store = SubscriptionStore::from_env();
+            let tracked = store
+                .all_of_type(CHANNEL_UPDATE)
+                .await?
+                .iter()
+                .any(|record| tracks(record, &user));
+
+            let note = if tracked {
+                None
+            } else if WorkspaceStore::from_env().load(&req.team_id).await?.watchlist.contains(&user.login) {
+                Some(start_tracking(&invocation, &store, &user).await?)
+            } else {
+                Some(format!(
+                    "{} isn't on the watchlist, so title changes aren't being recorded.",
+                    user.display_name
+                ))
+            };
+
+            SlackMessage::builder()
+                .ephemeral()
+                .text(render(&user.display_name, &history, note))
+                .build()
+        })
+    }
+}
+
+/// Subscribes to the channel's `channel.update` notifications and says how that went.
+async fn start_tracking(
+    invocation: &Invocation,
+    store: &SubscriptionStore,
+    user: &TwitchUser,
+) -> Result<String, Error> {
+    let callback = match std::env::var("EVENTSUB_CALLBACK_URL") {
+        Ok(callback) => callback,
+        Err(_) => {
+            error!("EVENTSUB_CALLBACK_URL is not set");
+            return Ok("The bot isn't set up to receive Twitch events, so title changes can't be recorded.".to_string());
+        }
+    };
+
+    if let Some(refusal) = budget::refusal(&invocation.secrets, &user.login, 1).await {
+        return Ok(refusal);
+    }
+
+    let secrets = invocation.secrets.clone();
+    let condition = json!({ "broadcaster_user_id": user.id });
+    let subscription = tokio::task::spawn_blocking(move || {
+        let client = twitch::client(&TimeoutConfig::from_env())?;
+        eventsub::subscribe(&client, &secrets, CHANNEL_UPDATE, "1", condition, &callback)
+    })
+    .await?;
+    let subscription = match subscription {
+        Ok(subscription) => subscription,
+        Err(e) => return Ok(e.user_message(&user.login)),
+    };
+
+    info!("Created {} subscription {} for {}", CHANNEL_UPDATE, subscription.id, user.login);
+    store
+        .save(&SubscriptionRecord {
+            subscription_id: subscription.id,
+            subscription_type: CHANNEL_UPDATE.to_string(),
+            team_id: invocation.req.team_id.clone(),
+            slack_user_id: invocation.req.user_id.clone(),
+            channel: user.login.clone(),
+            broadcaster_id: user.id.clone(),
+            slack_channel: None,
+            shared: false,
+        })
+        .await?;
+    budget::check(&invocation.secrets).await;
+    Ok(format!("Started recording {}'s title changes.", user.display_name))
+}
+
+/// Whether `record` is a subscription to `user`'s channel: by broadcaster ID, so a rename
+/// doesn't look untracked, or by login for records saved without one.
+fn tracks(record: &SubscriptionRecord, user: &TwitchUser) -> bool {
+    match record.broadcaster_id.as_str() {
+        "" => record.channel == user.login,
+        id => id == user.id,
+    }
+}
+
+fn render(name: &str, history: &[TitleChange], note: Option<String>) -> String {
+    let mut lines = vec![format!("*Recent title changes for {}*", name)];
+    if history.is_empty() {
+        lines.push("No changes recorded yet.".to_string());
+    }
+    for change in history {
+        let when = match DateTime::parse_from_rfc3339(&change.changed_at) {
+            Ok(at) => format!("<!date^{}^{{date_short}} {{time}}|{}>", at.timestamp(), change.changed_at),
+            Err(_) => change.changed_at.clone(),
+        };
+        let category = if change.category_name.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", change.category_name)
+        };
+        lines.push(format!("• {} — {}{}", when, change.title, category));
+    }
+    if let Some(note) = note {
+        lines.push(format!("_{}_", note));
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,161 @@
+use log::{error, info};
+use serde_json::{json, Value};
+use tokio;
+use twitch_info_bot::args;
+use twitch_info_bot::audit::{AuditEntry, AuditLog};
+use twitch_info_bot::eventsub::{self, SubscriptionRecord, SubscriptionStore};
+use twitch_info_bot::links::{self, LinkStore};
+use twitch_info_bot::moderation::{UNBAN_REQUEST_EVENT, UNBAN_SCOPE};
+use twitch_info_bot::secrets;
+use twitch_info_bot::slack::{self, SlackMessage, SlashCommand};
+use twitch_info_bot::twitch::{self, TimeoutConfig};
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::{error, local, logging, Error};
+
+const USAGE: &str = "Post a channel's unban requests here, with buttons to approve or deny them:\n\
+    `/tunbans on [channel]` — start posting (defaults to your linked channel)\n\
+    `/tunbans off [channel]` — stop";
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(|event| async move { unban_requests(event).await.or_else(error::reply) }).await
+}
+
+/// `/tunbans on|off [channel]`: run by a linked moderator in the channel the requests should
+/// go to. The EventSub subscription is made for that moderator, who must be able to manage the
+/// channel's unban requests.
+async fn unban_requests(event: Value) -> Result<SlackMessage, Error> {
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return slack::malformed_request();
+        }
+    };
+    let secrets = secrets::verify_slack_request(&event, &req.token).await?.secrets;
+
+    let (text, dry_run) = args::take_dry_run(&req.text);
+    let mut words = text.split_whitespace();
+    let enable = match words.next() {
+        Some("on") => true,
+        Some("off") => false,
+        _ => return SlackMessage::builder().text(USAGE).build(),
+    };
+
+    let link_store = LinkStore::from_env();
+    let link = match links::command_link(&link_store, &secrets, &req.team_id, &req.user_id, None, UNBAN_SCOPE).await? {
+        Ok(link) => link,
+        Err(message) => return SlackMessage::builder().text(message).build(),
+    };
+    let channel = words.next().map_or_else(|| link.login.clone(), str::to_ascii_lowercase);
+
+    let store = SubscriptionStore::from_env();
+    let existing = store.find(&req.team_id, UNBAN_REQUEST_EVENT, &channel).await?;
+    let timeouts = TimeoutConfig::from_env();
+
+    if !enable {
+        let record = match existing {
+            Some(record) => record,
+            None => {
+                return SlackMessage::builder()
+                    .text(format!("Unban requests for {} aren't being posted.", channel))
+                    .build()
+            }
+        };
+        if dry_run {
+            let action = format!("remove the {} subscription for {}", UNBAN_REQUEST_EVENT, channel);
+            return SlackMessage::builder().text(args::dry_run_report(&action)).build();
+        }
+        let id = record.subscription_id.clone();
+        let unsubscribed = tokio::task::spawn_blocking(move || {
+            twitch::client(&timeouts).and_then(|client| eventsub::unsubscribe(&client, &secrets, &id))
+        })
+        .await?;
+        if let Err(e) = unsubscribed {
+            return SlackMessage::builder().text(e.user_message(&channel)).build();
+        }
+
+        store.delete(&record).await?;
+        return SlackMessage::builder()
+            .text(format!("Stopped posting unban requests for {}.", channel))
+            .build();
+    }
+
+    if existing.is_some() {
+        return SlackMessage::builder()
+            .text(format!("Unban requests for {} are already being posted.", channel))
+            .build();
+    }
+    if dry_run {
+        let action = format!(
+            "subscribe to {} for {} as {} and post the requests in this channel",
+            UNBAN_REQUEST_EVENT, channel, link.login
+        );
+        return SlackMessage::builder().text(args::dry_run_report(&action)).build();
+    }
+
+    let callback = match std::env::var("EVENTSUB_CALLBACK_URL") {
+        Ok(callback) => callback,
+        Err(_) => {
+            error!("EVENTSUB_CALLBACK_URL is not set");
+            return SlackMessage::builder()
+                .text("The bot isn't set up to receive Twitch events yet. Ask an admin to configure it.")
+                .build();
+        }
+    };
+
+    let lookup_channel = channel.clone();
+    let moderator_id = link.twitch_user_id.clone();
+    let subscription = tokio::task::spawn_blocking(move || {
+        let client = twitch::client(&timeouts)?;
+        let broadcaster = twitch::get_users_by_login(&client, &[lookup_channel], &secrets)?.pop();
+        match broadcaster {
+            Some(broadcaster) => {
+                let condition = json!({ "broadcaster_user_id": broadcaster.id, "moderator_user_id": moderator_id });
+                eventsub::subscribe(&client, &secrets, UNBAN_REQUEST_EVENT, "1", condition, &callback)
+                    .map(|subscription| Some((broadcaster.id, subscription)))
+            }
+            None => Ok(None),
+        }
+    })
+    .await?;
+
+    let (broadcaster_id, subscription) = match subscription {
+        Ok(Some(created)) => created,
+        Ok(None) => return SlackMessage::builder().text(format!("No Twitch user named {}", channel)).build(),
+        Err(e) => return SlackMessage::builder().text(e.user_message(&channel)).build(),
+    };
+
+    info!("Created {} subscription {} for {}", UNBAN_REQUEST_EVENT, subscription.id, channel);
+    store
+        .save(&SubscriptionRecord {
+            subscription_id: subscription.id,
+            subscription_type: UNBAN_REQUEST_EVENT.to_string(),
+            team_id: req.team_id.clone(),
+            slack_user_id: req.user_id.clone(),
+            channel: channel.clone(),
+            broadcaster_id,
+            slack_channel: None,
+            shared: false,
+        })
+        .await?;
+
+    let workspaces = WorkspaceStore::from_env();
+    let previous = workspaces.load(&req.team_id).await?;
+    let mut config = previous.clone();
+    config.moderation_channel = Some(req.channel_id.clone());
+    let entry = AuditEntry {
+        team_id: req.team_id.clone(),
+        user_id: req.user_id.clone(),
+        action: "unbans".to_string(),
+        detail: format!("post unban requests for {} in <#{}>", channel, req.channel_id),
+    };
+    workspaces.save_change(&AuditLog::from_env(), &entry, &previous, &config).await?;
+
+    SlackMessage::builder()
+        .in_channel()
+        .text(format!("Unban requests for {} will be posted in this channel.", channel))
+        .build()
+}
@@ -0,0 +1,152 @@
+use chrono::DateTime;
+use futures::future::BoxFuture;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use tokio;
+use twitch_info_bot::args::{self, CommandSchema, Schema};
+use twitch_info_bot::audit::{AuditEntry, AuditLog, RecordedEntry};
+use twitch_info_bot::command::{Command, Invocation, Router};
+use twitch_info_bot::http;
+use twitch_info_bot::slack::{self, SlackMessage};
+use twitch_info_bot::workspace::{self, WorkspaceStore};
+use twitch_info_bot::{local, logging, Error};
+
+/// How far back in the workspace's audit log to look for the invoker's last change.
+const HISTORY_LIMIT: usize = 200;
+
+static SCHEMA: CommandSchema = CommandSchema {
+    command: "/tundo",
+    summary: "Undo your last settings change",
+    forms: &[Schema {
+        subcommand: None,
+        summary: "put back what your most recent change to the bot's settings changed",
+        args: &[],
+        flags: &[],
+    }],
+    dry_run: true,
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    let router: &'static Router = Box::leak(Box::new(Router::with_defaults().command(Undo)));
+    local::serve(move |event| router.dispatch(event)).await
+}
+
+/// `/tundo`: puts back the fields the invoker's most recent config change touched. Only workspace
+/// admins can run it. Each undo is itself audited and marks the change it reverted, so running it
+/// again steps further back.
+struct Undo;
+
+impl Command for Undo {
+    fn name(&self) -> &'static str {
+        "/tundo"
+    }
+
+    fn schema(&self) -> Option<&'static CommandSchema> {
+        Some(&SCHEMA)
+    }
+
+    fn audit(&self, _invocation: &Invocation) -> Option<String> {
+        Some("undo their last settings change".to_string())
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>> {
+        Box::pin(undo(invocation))
+    }
+}
+
+async fn undo(invocation: Invocation) -> Result<SlackMessage, Error> {
+    let req = &invocation.req;
+    let workspaces = WorkspaceStore::from_env();
+    let token = workspaces
+        .bot_token(&req.team_id)
+        .await?
+        .unwrap_or_else(|| invocation.secrets.slack_bot_token.clone());
+    let invoker = req.user_id.clone();
+    let is_admin =
+        tokio::task::spawn_blocking(move || slack::is_workspace_admin(&http::client(), &token, &invoker)).await??;
+    if !is_admin {
+        return SlackMessage::builder()
+            .ephemeral()
+            .text("Only workspace admins can undo settings changes.")
+            .build();
+    }
+
+    let audit = AuditLog::from_env();
+    let history = audit.recent(&req.team_id, HISTORY_LIMIT).await?;
+    let (target, previous) = match last_change(&history, &req.user_id) {
+        Some(change) => change,
+        None => {
+            return SlackMessage::builder()
+                .ephemeral()
+                .text("You haven't made any settings changes that can be undone.")
+                .build()
+        }
+    };
+
+    let current = workspaces.load(&req.team_id).await?;
+    let conflict = match &target.applied {
+        Some(applied) => workspace::conflicting_field(&current, applied)?,
+        None => {
+            return SlackMessage::builder()
+                .ephemeral()
+                .text("That change was made before `/tundo` could check for later edits, so it can't be undone.")
+                .build()
+        }
+    };
+    if let Some(field) = conflict {
+        return SlackMessage::builder()
+            .ephemeral()
+            .text(format!(
+                "`{}` has been changed again since, so undoing this would overwrite that. Change it back by hand.",
+                field
+            ))
+            .build();
+    }
+
+    let description = format!("undo \"{}\" from {}", target.detail, when(&target.recorded_at));
+    if invocation.args.dry_run {
+        return SlackMessage::builder().ephemeral().text(args::dry_run_report(&description)).build();
+    }
+
+    let restored = workspace::restore_fields(&current, previous)?;
+    let entry = AuditEntry {
+        team_id: req.team_id.clone(),
+        user_id: req.user_id.clone(),
+        action: "undo".to_string(),
+        detail: description,
+    };
+    let changed = workspace::changed_fields(&current, &restored)?;
+    let applied = workspace::applied_fields(&restored, &changed)?;
+    audit.record_change(&entry, &changed, &applied, Some(&target.recorded_at)).await?;
+    workspaces.save(&req.team_id, &restored).await?;
+
+    SlackMessage::builder()
+        .ephemeral()
+        .text(format!("Undid \"{}\" from {}.", target.detail, when(&target.recorded_at)))
+        .build()
+}
+
+/// The user's newest change that hasn't been undone yet. Undos themselves are skipped, so
+/// `/tundo` never redoes anything.
+fn last_change<'a>(
+    history: &'a [RecordedEntry],
+    user_id: &str,
+) -> Option<(&'a RecordedEntry, &'a Map<String, Value>)> {
+    let undone: HashSet<&str> = history.iter().filter_map(|entry| entry.undoes.as_deref()).collect();
+
+    history
+        .iter()
+        .filter(|entry| entry.user_id == user_id && entry.undoes.is_none())
+        .filter(|entry| !undone.contains(entry.recorded_at.as_str()))
+        .find_map(|entry| entry.previous.as_ref().map(|previous| (entry, previous)))
+}
+
+fn when(recorded_at: &str) -> String {
+    match DateTime::parse_from_rfc3339(recorded_at) {
+        Ok(at) => format!("<!date^{}^{{date_short_pretty}} at {{time}}|{}>", at.timestamp(), recorded_at),
+        Err(_) => recorded_at.to_string(),
+    }
+}
@@ -0,0 +1,142 @@
+use chrono::{Timelike, Utc};
+use futures::future::join_all;
+use log::{error, info};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use tokio;
+use twitch_info_bot::cache::CacheStore;
+use twitch_info_bot::links::{self, AccountLink, LinkStore};
+use twitch_info_bot::query::{cache_key, LookupResult, UserQuery};
+use twitch_info_bot::secrets::{self, Secrets};
+use twitch_info_bot::twitch::{self, Credentials, LookupError, TimeoutConfig};
+use twitch_info_bot::workspace::WorkspaceStore;
+use twitch_info_bot::{local, logging, Error};
+
+/// UTC hours to warm in, as `start-end` (end exclusive, may wrap past midnight).
+const DEFAULT_WARM_HOURS: &str = "17-24";
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(warm).await
+}
+
+/// Runs every minute. Inside `CACHE_WARM_HOURS` it looks up every channel on any workspace's
+/// watchlist and caches the result under the key a `/tuser` of that channel alone reads, in both
+/// the card and compact forms, so lookups of watched channels are fresh cache hits. Outside those
+/// hours it does nothing.
+async fn warm(_event: Value) -> Result<Value, Error> {
+    let hours = std::env::var("CACHE_WARM_HOURS").unwrap_or_else(|_| DEFAULT_WARM_HOURS.to_string());
+    if !in_hours(&hours, Utc::now().hour()) {
+        return Ok(json!({ "warmed": 0 }));
+    }
+
+    // The workspaces watching each channel, any of which may have the broadcaster's link.
+    let mut watchers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (team_id, config) in WorkspaceStore::from_env().all().await? {
+        for login in config.watchlist {
+            watchers.entry(login.to_ascii_lowercase()).or_default().push(team_id.clone());
+        }
+    }
+    if watchers.is_empty() {
+        return Ok(json!({ "warmed": 0 }));
+    }
+
+    let secrets = secrets::current().await?;
+    let links = broadcaster_links(&watchers, &secrets).await;
+    let logins: Vec<String> = watchers.into_keys().collect();
+    let lookup = tokio::task::spawn_blocking(move || -> Result<_, LookupError> {
+        let client = twitch::client(&TimeoutConfig::from_env())?;
+        let users = twitch::get_users_by_login(&client, &logins, &secrets)?;
+        let ids: Vec<String> = users.iter().map(|user| user.id.clone()).collect();
+        let streams = twitch::get_streams(&client, &ids, &secrets)?;
+        // A card cached without its follower count would show without one; warm only the ones we got.
+        let followers: HashMap<String, u64> = users
+            .iter()
+            .filter_map(|user| {
+                let link = links.get(&user.login).filter(|link| link.twitch_user_id == user.id)?;
+                let credentials = Credentials::user(&secrets, link);
+                Some((user.id.clone(), twitch::get_follower_count(&client, credentials, &user.id).ok()?))
+            })
+            .collect();
+        Ok((users, streams, followers))
+    })
+    .await?;
+    let (users, streams, followers) = match lookup {
+        Ok(found) => found,
+        Err(e) => {
+            error!("Could not look up watched channels to warm the cache: {:?}", e);
+            return Ok(json!({ "warmed": 0 }));
+        }
+    };
+
+    let cache = CacheStore::from_env();
+    let mut writes = vec![];
+    for user in &users {
+        let query = UserQuery {
+            logins: vec![user.login.clone()],
+            ..Default::default()
+        };
+        let compact = LookupResult {
+            users: vec![user.clone()],
+            streams: streams.iter().filter(|stream| stream.user_id == user.id).cloned().collect(),
+            followers: HashMap::new(),
+            missing: vec![],
+            not_found: vec![],
+            suggestions: vec![],
+            left_out: vec![],
+        };
+        if let Some(&count) = followers.get(&user.id) {
+            let card = LookupResult {
+                followers: vec![(user.id.clone(), count)].into_iter().collect(),
+                ..compact.clone()
+            };
+            writes.push((cache_key(&query, false), card));
+        }
+        writes.push((cache_key(&query, true), compact));
+    }
+
+    let results = join_all(writes.iter().map(|(key, result)| cache.put(key, result))).await;
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    if let Some(Err(e)) = results.iter().find(|result| result.is_err()) {
+        error!("{} cache writes failed, e.g. {}", failed, e);
+    }
+
+    info!("Warmed the lookup cache for {} watched channels", users.len());
+    Ok(json!({ "warmed": users.len() }))
+}
+
+/// The broadcaster links for the watched channels, by login: for each, the first watching
+/// workspace it's linked in. Follower counts can only be read with one.
+async fn broadcaster_links(
+    watchers: &BTreeMap<String, Vec<String>>,
+    secrets: &Secrets,
+) -> HashMap<String, AccountLink> {
+    let store = LinkStore::from_env();
+    let mut found = HashMap::new();
+    for (login, teams) in watchers {
+        for team_id in teams {
+            match links::fresh_channel_link(&store, secrets, team_id, login).await {
+                Ok(Some(link)) => {
+                    found.insert(login.clone(), link);
+                    break;
+                }
+                Ok(None) => {}
+                Err(e) => error!("Could not load {}'s link in {} to warm its follower count: {}", login, team_id, e),
+            }
+        }
+    }
+    found
+}
+
+/// Whether `hour` falls in `start-end`. A malformed range warms around the clock rather than
+/// never.
+fn in_hours(hours: &str, hour: u32) -> bool {
+    let mut bounds = hours.splitn(2, '-').map(|bound| bound.trim().parse::<u32>());
+    match (bounds.next(), bounds.next()) {
+        (Some(Ok(start)), Some(Ok(end))) if start <= end => (start..end).contains(&hour),
+        (Some(Ok(start)), Some(Ok(end))) => hour >= start || hour < end,
+        _ => true,
+    }
+}
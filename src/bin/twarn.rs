@@ -0,0 +1,67 @@
+use log::error;
+use serde_json::Value;
+use tokio;
+use twitch_info_bot::args;
+use twitch_info_bot::audit::{AuditEntry, AuditLog};
+use twitch_info_bot::links::{self, LinkStore};
+use twitch_info_bot::moderation::{self, WarnRequest, WARN_SCOPE};
+use twitch_info_bot::secrets;
+use twitch_info_bot::slack::{self, SlackMessage, SlashCommand};
+use twitch_info_bot::twitch::{self, TimeoutConfig};
+use twitch_info_bot::{error, local, logging, Error};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    local::serve(|event| async move { warn_user(event).await.or_else(error::reply) }).await
+}
+
+/// `/twarn <channel> <user> <reason>`: warns a chat user as the invoker's linked moderator
+/// account, auditing the warning and its reason first.
+async fn warn_user(event: Value) -> Result<SlackMessage, Error> {
+    let req = match SlashCommand::from_event(&event) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Malformed slash command request: {}", e);
+            return slack::malformed_request();
+        }
+    };
+    let secrets = secrets::verify_slack_request(&event, &req.token).await?.secrets;
+
+    let (text, dry_run) = args::take_dry_run(&req.text);
+    let request = match WarnRequest::parse(&text) {
+        Ok(request) => request,
+        Err(message) => return SlackMessage::builder().text(message).build(),
+    };
+
+    let store = LinkStore::from_env();
+    let link = match links::command_link(&store, &secrets, &req.team_id, &req.user_id, None, WARN_SCOPE).await? {
+        Ok(link) => link,
+        Err(message) => return SlackMessage::builder().text(message).build(),
+    };
+
+    if dry_run {
+        let action = format!("as {}: {}", link.login, request.describe());
+        return SlackMessage::builder().ephemeral().text(args::dry_run_report(&action)).build();
+    }
+
+    AuditLog::from_env()
+        .record(&AuditEntry {
+            team_id: req.team_id.clone(),
+            user_id: req.user_id.clone(),
+            action: "warn".to_string(),
+            detail: format!("as {}: {}", link.login, request.describe()),
+        })
+        .await?;
+
+    let timeouts = TimeoutConfig::from_env();
+    let text = tokio::task::spawn_blocking(move || {
+        twitch::client(&timeouts)
+            .and_then(|client| moderation::warn(&client, &secrets, &link, &request))
+            .unwrap_or_else(|e| e.user_message(&request.user))
+    })
+    .await?;
+
+    SlackMessage::builder().ephemeral().text(text).build()
+}
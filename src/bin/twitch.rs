@@ -0,0 +1,25 @@
+use tokio;
+use twitch_info_bot::command::Router;
+use twitch_info_bot::lookups::clip::Clips;
+use twitch_info_bot::lookups::compare::Compare;
+use twitch_info_bot::lookups::game::Games;
+use twitch_info_bot::lookups::stream::Streams;
+use twitch_info_bot::lookups::user::Users;
+use twitch_info_bot::{local, logging, Error};
+
+/// `/twitch <user|stream|game|clip|compare> …`, and `/tuser`, `/tstream`, `/tgame`, `/tclip` and
+/// `/tcompare` themselves, from one function: point any of those slash commands at it.
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    logging::init().expect("Could not initiate logger");
+
+    let router = Router::with_defaults()
+        .umbrella("/twitch")
+        .command(Users)
+        .command(Streams)
+        .command(Games)
+        .command(Clips)
+        .command(Compare);
+    let router: &'static Router = Box::leak(Box::new(router));
+    local::serve(move |event| router.dispatch(event)).await
+}
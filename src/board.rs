@@ -0,0 +1,70 @@
+//! The pinned watchlist status board: rendering it and updating it in place. Used by `tboard`
+//! and by signed "refresh" links (`tgo`).
+
+use crate::deeplinks::{DeepLink, LinkAction};
+use crate::http;
+use crate::locale::Locale;
+use crate::render;
+use crate::secrets::Secrets;
+use crate::slack::{self, Block, SlackMessage, Text};
+use crate::twitch::{self, LookupError, TimeoutConfig};
+use crate::workspace::{BoardLocation, WorkspaceConfig};
+use crate::Error;
+use chrono::Utc;
+use simple_error::bail;
+
+pub async fn update(
+    team_id: &str,
+    config: &WorkspaceConfig,
+    location: &BoardLocation,
+    token: String,
+    secrets: &Secrets,
+) -> Result<(), Error> {
+    let board = message(team_id, config, secrets).await?;
+    let location = location.clone();
+    tokio::task::spawn_blocking(move || {
+        slack::update_message(&http::client(), &token, &location.channel, &location.ts, &board)
+    })
+    .await?
+}
+
+/// The whole watchlist's status, from one batched users lookup and one batched streams lookup.
+pub async fn message(team_id: &str, config: &WorkspaceConfig, secrets: &Secrets) -> Result<SlackMessage, Error> {
+    let logins = config.watchlist.clone();
+    let locale = Locale::for_workspace(config);
+    let lookup_secrets = secrets.clone();
+    let status = tokio::task::spawn_blocking(move || -> Result<String, LookupError> {
+        let client = twitch::client(&TimeoutConfig::from_env())?;
+        let users = twitch::get_users_by_login(&client, &logins, &lookup_secrets)?;
+        let ids: Vec<String> = users.iter().map(|u| u.id.clone()).collect();
+        let streams = twitch::get_streams(&client, &ids, &lookup_secrets)?;
+        Ok(render::compact(&users, &streams, Utc::now(), locale))
+    })
+    .await?;
+
+    let status = match status {
+        Ok(status) => status,
+        // Keep the board's last good content rather than overwriting it with an error.
+        Err(e) => bail!("Twitch lookup for the board failed: {:?}", e),
+    };
+
+    let now = Utc::now().timestamp();
+    let refresh = match DeepLink::new(LinkAction::RefreshBoard, team_id, "", "").url(&secrets.deep_link_secret) {
+        Some(url) => format!("<{}|refresh now>", url),
+        None => "`/tboard refresh` to update now".to_string(),
+    };
+    SlackMessage::builder()
+        .text(format!("Watchlist status\n{}", status))
+        .block(Block::Section {
+            text: Text::Mrkdwn {
+                text: format!("*Watchlist status*\n{}", status),
+            },
+            accessory: None,
+        })
+        .block(Block::Context {
+            elements: vec![Text::Mrkdwn {
+                text: format!("Updated <!date^{}^{{time}}|just now> · {}", now, refresh),
+            }],
+        })
+        .build()
+}
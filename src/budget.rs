@@ -0,0 +1,109 @@
+//! Keeping EventSub subscriptions within Twitch's cost budget. Subscriptions to a channel whose
+//! owner hasn't authorized the app cost 1 each, and once `max_total_cost` is spent Twitch refuses
+//! new ones, for every workspace at once.
+//!
+//! `/tfollow` and `/ttitles` check there's room before subscribing, and say why when there
+//! isn't. `/tunbans` subscriptions are made for moderators who have linked their account, so
+//! they cost nothing and aren't checked. As the budget fills past `EVENTSUB_COST_WARN_PERCENT`
+//! (default 80), the operator is warned in `ADMIN_SLACK_CHANNEL`: once on crossing it, and again
+//! at each further tenth.
+
+use crate::eventsub::{self, SubscriptionCost, SubscriptionStore};
+use crate::locale::Locale;
+use crate::notify;
+use crate::secrets::Secrets;
+use crate::twitch::{self, TimeoutConfig};
+use crate::Error;
+use log::{error, info, warn};
+
+const DEFAULT_WARN_PERCENT: u64 = 80;
+
+/// The app's current spend, from Helix.
+pub async fn current(secrets: &Secrets) -> Result<SubscriptionCost, Error> {
+    let secrets = secrets.clone();
+    let cost = tokio::task::spawn_blocking(move || {
+        let client = twitch::client(&TimeoutConfig::from_env())?;
+        eventsub::subscription_cost(&client, &secrets)
+    })
+    .await?;
+    cost.map_err(|e| format!("Could not read the EventSub cost: {:?}", e).into())
+}
+
+/// Why `cost` more subscriptions for `login` can't be made, or `None` if they fit. A budget that
+/// can't be read doesn't hold the command up; Twitch still refuses what doesn't fit.
+pub async fn refusal(secrets: &Secrets, login: &str, cost: u64) -> Option<String> {
+    let current = match current(secrets).await {
+        Ok(current) => current,
+        Err(e) => {
+            error!("{}; subscribing for {} anyway", e, login);
+            return None;
+        }
+    };
+    if current.allows(cost) {
+        return None;
+    }
+    warn!("Refused {} subscriptions for {}: the EventSub budget is at {:?}", cost, login, current);
+    let locale = Locale::default();
+    Some(format!(
+        "The bot can't watch {} right now: Twitch limits how many channels it can get events for, and it's \
+         used {} of {}. Channels whose owner has linked their Twitch account with `/tlink` don't count \
+         against the limit, so asking them to is one way; otherwise tell the bot's operator.",
+        login,
+        locale.count(current.total_cost),
+        locale.count(current.max_total_cost)
+    ))
+}
+
+/// Reads the spend and runs [`warn_if_filling`], after subscribing or on a schedule. Failures
+/// are only logged; they shouldn't fail whatever else the caller did.
+pub async fn check(secrets: &Secrets) {
+    let warned = match current(secrets).await {
+        Ok(cost) => warn_if_filling(secrets, &cost).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = warned {
+        error!("Could not check the EventSub cost budget: {}", e);
+    }
+}
+
+/// Warns the operator if the budget is fuller than when they were last told, and forgets the
+/// last warning once it's back under the threshold.
+pub async fn warn_if_filling(secrets: &Secrets, cost: &SubscriptionCost) -> Result<(), Error> {
+    let store = SubscriptionStore::from_env();
+    let percent = cost.percent_used();
+    if percent < warn_percent() {
+        if store.budget_warning().await?.is_some() {
+            store.set_budget_warning(None).await?;
+        }
+        return Ok(());
+    }
+
+    // 80, 90, 100: a warning per tenth rather than per subscription.
+    let level = percent / 10 * 10;
+    if store.budget_warning().await?.map_or(false, |warned| warned >= level) {
+        return Ok(());
+    }
+
+    let locale = Locale::default();
+    let text = format!(
+        ":warning: Twitch EventSub subscriptions have used {} of their {} cost limit ({}%, {} \
+         subscriptions). At the limit `/tfollow` and `/ttitles` stop working; channel owners linking \
+         with `/tlink` frees room.",
+        locale.count(cost.total_cost),
+        locale.count(cost.max_total_cost),
+        percent,
+        locale.count(cost.total)
+    );
+    if notify::operator(secrets, text).await? {
+        info!("Warned the operator that the EventSub budget is {}% spent", percent);
+        store.set_budget_warning(Some(level)).await?;
+    }
+    Ok(())
+}
+
+fn warn_percent() -> u64 {
+    std::env::var("EVENTSUB_COST_WARN_PERCENT")
+        .ok()
+        .and_then(|percent| percent.parse().ok())
+        .unwrap_or(DEFAULT_WARN_PERCENT)
+}
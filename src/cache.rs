@@ -0,0 +1,166 @@
+//! Lookup results cached in a [`Store`] (DynamoDB, or SQLite locally) so commands can answer
+//! without Twitch.
+//!
+//! Entries younger than `FRESH_SECS` are served as-is. Older ones are stale: the command tries
+//! Twitch first, and serves the stale entry ("as of 2m ago") only when Twitch is slow, down or
+//! rate limiting us. Lambda freezes as soon as a handler returns, so revalidation happens within
+//! the command's time budget rather than in the background. DynamoDB's TTL drops entries past
+//! `MAX_STALE_SECS`.
+//!
+//! A warm container also keeps recent entries in memory, in a bounded LRU (`MEMORY_CACHE_ENTRIES`,
+//! default 1000) consulted before DynamoDB, so fresh hits for hot channels skip the round trip.
+
+use crate::stage::Stage;
+use crate::store::{self, Attr, Attrs, Store};
+use crate::Error;
+use chrono::Utc;
+use moka::sync::Cache;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const DEFAULT_TABLE: &str = "tuser-cache";
+const DEFAULT_MEMORY_ENTRIES: u64 = 1000;
+
+pub const FRESH_SECS: i64 = 60;
+pub const MAX_STALE_SECS: i64 = 60 * 60;
+
+pub struct Cached<T> {
+    pub value: T,
+    /// Unix timestamp of when the value was fetched from Twitch.
+    pub fetched_at: i64,
+}
+
+impl<T> Cached<T> {
+    pub fn age_secs(&self) -> i64 {
+        (Utc::now().timestamp() - self.fetched_at).max(0)
+    }
+
+    pub fn is_fresh(&self) -> bool {
+        self.age_secs() < FRESH_SECS
+    }
+
+    /// "as of 2m ago", for labelling stale results.
+    pub fn as_of(&self) -> String {
+        let age = self.age_secs();
+        if age < 60 {
+            format!("as of {}s ago", age)
+        } else if age < 60 * 60 {
+            format!("as of {}m ago", age / 60)
+        } else {
+            format!("as of {}h ago", age / (60 * 60))
+        }
+    }
+}
+
+/// An entry as stored: the value's JSON and when it was fetched.
+#[derive(Clone)]
+struct Entry {
+    value: String,
+    fetched_at: i64,
+}
+
+impl Entry {
+    fn decode<T: DeserializeOwned>(&self) -> Result<Option<Cached<T>>, Error> {
+        let cached = Cached {
+            value: serde_json::from_str(&self.value)?,
+            fetched_at: self.fetched_at,
+        };
+        Ok(if cached.age_secs() > MAX_STALE_SECS { None } else { Some(cached) })
+    }
+}
+
+static MEMORY: OnceLock<Cache<String, Entry>> = OnceLock::new();
+
+fn memory() -> &'static Cache<String, Entry> {
+    MEMORY.get_or_init(|| {
+        let entries = std::env::var("MEMORY_CACHE_ENTRIES")
+            .ok()
+            .and_then(|entries| entries.parse().ok())
+            .unwrap_or(DEFAULT_MEMORY_ENTRIES);
+        Cache::builder()
+            .max_capacity(entries)
+            .time_to_live(Duration::from_secs(MAX_STALE_SECS as u64))
+            .build()
+    })
+}
+
+pub struct CacheStore {
+    store: Box<dyn Store>,
+    table: String,
+}
+
+impl CacheStore {
+    pub fn new(store: Box<dyn Store>, table: String) -> CacheStore {
+        CacheStore { store, table }
+    }
+
+    pub fn from_env() -> CacheStore {
+        let table = std::env::var("CACHE_TABLE").unwrap_or_else(|_| Stage::current().resource(DEFAULT_TABLE));
+        CacheStore::new(store::open(table.clone(), "cache_key"), table)
+    }
+
+    /// The cached value, fresh or stale. Entries past `MAX_STALE_SECS` that TTL hasn't removed
+    /// yet are treated as missing. A fresh entry in memory is served without asking DynamoDB; a
+    /// stale one is only used if DynamoDB has nothing newer.
+    pub async fn get<T: DeserializeOwned>(&self, cache_key: &str) -> Result<Option<Cached<T>>, Error> {
+        let remembered = memory().get(&self.memory_key(cache_key));
+        if let Some(entry) = &remembered {
+            if let Some(cached) = entry.decode::<T>()? {
+                if cached.is_fresh() {
+                    return Ok(Some(cached));
+                }
+            }
+        }
+
+        let stored = self.get_stored(cache_key).await?;
+        let newest = match (remembered, stored) {
+            (Some(remembered), Some(stored)) if remembered.fetched_at > stored.fetched_at => Some(remembered),
+            (_, Some(stored)) => {
+                memory().insert(self.memory_key(cache_key), stored.clone());
+                Some(stored)
+            }
+            (remembered, None) => remembered,
+        };
+        match newest {
+            Some(entry) => entry.decode(),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_stored(&self, cache_key: &str) -> Result<Option<Entry>, Error> {
+        let mut item = match self.store.get(cache_key).await? {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        let fetched_at = item.get("fetched_at").and_then(Attr::as_number).unwrap_or_default();
+        Ok(item
+            .remove("value")
+            .and_then(Attr::into_string)
+            .map(|value| Entry { value, fetched_at }))
+    }
+
+    pub async fn put<T: Serialize>(&self, cache_key: &str, value: &T) -> Result<(), Error> {
+        let now = Utc::now().timestamp();
+        let value = serde_json::to_string(value)?;
+        memory().insert(
+            self.memory_key(cache_key),
+            Entry {
+                value: value.clone(),
+                fetched_at: now,
+            },
+        );
+
+        let mut item = Attrs::new();
+        item.insert("value".to_string(), Attr::S(value));
+        item.insert("fetched_at".to_string(), Attr::N(now));
+        item.insert("expires_at".to_string(), Attr::N(now + MAX_STALE_SECS));
+        self.store.put(cache_key, item).await
+    }
+
+    /// Stores for different tables share the process's memory, so keys carry the table.
+    fn memory_key(&self, cache_key: &str) -> String {
+        format!("{}/{}", self.table, cache_key)
+    }
+}
@@ -0,0 +1,214 @@
+//! Slack cards for Twitch users, shared by the commands that show them: Block Kit sections, or
+//! the legacy attachments for workspaces that keep them (`legacy_cards`).
+
+use crate::locale::Locale;
+use crate::scrub::OutputFilter;
+use crate::slack::blocks::{self, Blocks};
+use crate::slack::{Block, Color, Element, SlackAttachment, Text};
+use crate::translate::Translation;
+use crate::twitch::{TwitchStream, TwitchUser};
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+/// Prefix of the card buttons' action IDs, `expand_card_<view>`, handled by `tinteract`.
+pub const EXPAND_ACTION: &str = "expand_card";
+/// Action ID of the "Add to watchlist" button, handled by `tinteract`.
+pub const WATCH_ACTION: &str = "watch_channel";
+/// How long the watch button on a card keeps working after the card was posted.
+pub const WATCH_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// The watch button's value: who it adds and until when. Slack signs the click, which says who
+/// made it, so the change is the clicker's; the value only comes from cards the bot posted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WatchRequest {
+    pub login: String,
+    /// Unix seconds.
+    pub expires_at: i64,
+}
+
+impl WatchRequest {
+    /// A button that works for [`WATCH_TTL_SECS`] from now.
+    pub fn new(login: &str) -> WatchRequest {
+        WatchRequest {
+            login: login.to_string(),
+            expires_at: Utc::now().timestamp() + WATCH_TTL_SECS,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at < Utc::now().timestamp()
+    }
+}
+
+/// What a user card shows besides the user.
+pub struct CardDetails<'a> {
+    pub followers: Option<u64>,
+    /// The user's stream: `Some(None)` when they're offline, `None` when live status wasn't
+    /// looked up.
+    pub live: Option<Option<&'a TwitchStream>>,
+    /// Whether to offer a button adding the user to the watchlist.
+    pub watch: bool,
+    pub translation: Option<&'a Translation>,
+}
+
+/// A user as Block Kit blocks: name, badge and description beside the profile picture, then
+/// whether they're live, the ID, account age and follower count under them, then a button to the
+/// channel and the card buttons. The description and stream title are posted to the channel,
+/// so they go through `filter`.
+pub fn user_blocks(user: &TwitchUser, details: CardDetails, locale: Locale, filter: &OutputFilter) -> Blocks {
+    let channel_url = format!("https://twitch.tv/{}", user.login);
+    let mut headline = format!("*<{}|{}>*", channel_url, user.display_name);
+    if let Some(badge) = badge(user) {
+        headline.push_str(&format!("  `{}`", badge));
+    }
+    if !user.description.is_empty() {
+        headline.push_str(&format!("\n{}", filter.scrub(&user.description)));
+    }
+
+    let mut facts = vec![];
+    facts.extend(live_status(details.live, locale, filter));
+    facts.push(format!("ID `{}`", user.id));
+    facts.extend(joined(user, Utc::now(), locale));
+    if let Some(followers) = details.followers {
+        facts.push(format!("{} followers", locale.count(followers)));
+    }
+    let translation = details.translation.map(|translation| {
+        format!("🌐 _Description, translated from `{}`:_ {}", translation.source_language, translation.text)
+    });
+
+    let mut actions = vec![blocks::link_button("open_channel", "Open channel", channel_url)];
+    actions.extend(drill_down_elements(user, details.watch));
+
+    Blocks::new()
+        .section_with_image(headline, &user.profile_image_url, format!("{}'s profile picture", user.display_name))
+        .context(vec![facts.join(" · ")])
+        .context(translation.into_iter().collect())
+        .actions(actions)
+}
+
+/// `Partner`, `Affiliate` or `Staff`, for the accounts Twitch marks as such.
+fn badge(user: &TwitchUser) -> Option<&'static str> {
+    match (user.broadcaster_type.as_str(), user.user_type.as_str()) {
+        (_, "staff") | (_, "admin") => Some("Staff"),
+        ("partner", _) => Some("Partner"),
+        ("affiliate", _) => Some("Affiliate"),
+        _ => None,
+    }
+}
+
+/// `🔴 Live: title — 21,304 viewers` or `⚫ Offline`, when live status was looked up.
+fn live_status(live: Option<Option<&TwitchStream>>, locale: Locale, filter: &OutputFilter) -> Option<String> {
+    match live? {
+        Some(stream) if stream.title.is_empty() => {
+            Some(format!("🔴 Live — {} viewers", locale.count(stream.viewer_count)))
+        }
+        Some(stream) => Some(format!(
+            "🔴 Live: {} — {} viewers",
+            filter.scrub(&stream.title),
+            locale.count(stream.viewer_count)
+        )),
+        None => Some("⚫ Offline".to_string()),
+    }
+}
+
+/// `Joined Jun 5, 2013 (11 years ago)`, for users whose creation date Twitch reported.
+fn joined(user: &TwitchUser, now: DateTime<Utc>, locale: Locale) -> Option<String> {
+    let created = user.created()?;
+    Some(format!("Joined {} ({} ago)", locale.date(&created), account_age(created, now)))
+}
+
+/// `11 years`, `3 months` or `5 days`, rounded down.
+fn account_age(created: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let days = now.signed_duration_since(created).num_days().max(0);
+    let (count, unit) = if days >= 365 {
+        (days / 365, "year")
+    } else if days >= 30 {
+        (days / 30, "month")
+    } else {
+        (days, "day")
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// The legacy attachment: name and ID beside the profile picture, then the same facts as
+/// [`user_blocks`] with the badge, the translated description and the card buttons.
+pub fn user_attachment(
+    user: &TwitchUser,
+    details: CardDetails,
+    locale: Locale,
+    filter: &OutputFilter,
+) -> SlackAttachment {
+    let mut facts = vec![];
+    facts.extend(badge(user).map(str::to_string));
+    facts.extend(live_status(details.live, locale, filter));
+    facts.extend(joined(user, Utc::now(), locale));
+    if let Some(followers) = details.followers {
+        facts.push(format!("{} followers", locale.count(followers)));
+    }
+
+    let mut blocks = vec![];
+    if !facts.is_empty() {
+        blocks.push(Block::Context {
+            elements: vec![Text::Mrkdwn {
+                text: facts.join(" · "),
+            }],
+        });
+    }
+    if let Some(translation) = details.translation {
+        blocks.push(Block::Context {
+            elements: vec![Text::Mrkdwn {
+                text: format!(
+                    "🌐 _Description, translated from `{}`:_ {}",
+                    translation.source_language, translation.text
+                ),
+            }],
+        });
+    }
+    blocks.push(drill_down_menu(user, details.watch));
+
+    SlackAttachment {
+        fallback: format!("{} ({}) on Twitch", user.display_name, user.login),
+        color: Color::TWITCH_PURPLE,
+        author_name: format!("{}: {}", user.display_name, user.id),
+        author_icon: user.profile_image_url.clone(),
+        blocks,
+    }
+}
+
+/// Buttons that expand the card in place with more about the user, their recent VODs or their
+/// clips, handled by `tinteract`; each value is `<view>:<user id>`. With `watch`, also one adding
+/// the user to the watchlist, carrying a [`WatchRequest`].
+pub fn drill_down_menu(user: &TwitchUser, watch: bool) -> Block {
+    Block::Actions {
+        elements: drill_down_elements(user, watch),
+    }
+}
+
+fn drill_down_elements(user: &TwitchUser, watch: bool) -> Vec<Element> {
+    let mut elements: Vec<Element> = [("info", "More info"), ("vods", "Recent VODs"), ("clips", "Clips")]
+        .iter()
+        .map(|(view, label)| Element::Button {
+            action_id: format!("{}_{}", EXPAND_ACTION, view),
+            text: Text::PlainText {
+                text: label.to_string(),
+            },
+            value: Some(format!("{}:{}", view, user.id)),
+            url: None,
+            style: None,
+            confirm: None,
+        })
+        .collect();
+    if watch {
+        elements.push(Element::Button {
+            action_id: WATCH_ACTION.to_string(),
+            text: Text::PlainText {
+                text: "Add to watchlist".to_string(),
+            },
+            value: serde_json::to_string(&WatchRequest::new(&user.login)).ok(),
+            url: None,
+            style: None,
+            confirm: None,
+        });
+    }
+    elements
+}
@@ -0,0 +1,696 @@
+//! The shared front half of every slash command. A [`Router`] decodes the request, verifies the
+//! Slack token and then runs its middleware (rate limiting, account links, cooldowns, auditing,
+//! metrics) before handing the request to the matching [`Command`], so a handler only has to do
+//! its own work.
+//!
+//! A binary registers its commands and leaks the router so the Lambda handler can borrow it:
+//!
+//! ```ignore
+//! let router: &'static Router = Box::leak(Box::new(Router::with_defaults().command(Blocks)));
+//! lambda::run(handler_fn(move |event| router.dispatch(event))).await
+//! ```
+//!
+//! One router can serve several commands, dispatching on the request's `command`. With an
+//! [`umbrella`](Router::umbrella) command it also serves them as subcommands of that one, by
+//! their [`keyword`](Command::keyword): `/twitch stream ninja` runs `/tstream ninja`.
+
+use crate::args::{Args, CommandSchema};
+use crate::audit::{AuditEntry, AuditLog};
+use crate::cache::CacheStore;
+use crate::concurrency::{Lease, TeamLimiter};
+use crate::config::{self, Config};
+use crate::deferred;
+use crate::error;
+use crate::help;
+use crate::links::{self, AccountLink, LinkStore};
+use crate::plans::{Feature, Plan};
+use crate::secrets::{self, Secrets};
+use crate::slack::{self, SlackMessage, SlashCommand};
+use crate::usage::UsageStore;
+use crate::workspace::WorkspaceStore;
+use crate::{metrics, ratelimit, Error};
+use futures::future::BoxFuture;
+use log::error;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Everything a command gets to work with once the middleware has let the request through.
+pub struct Invocation {
+    pub req: SlashCommand,
+    /// The text parsed against the command's schema; empty for commands without one.
+    pub args: Args,
+    pub secrets: Secrets,
+    /// The secrets' version, for [`secrets::refetch_after_auth_failure`].
+    pub secrets_version: Option<String>,
+    /// Whether the text ended in `quiet` or `private`. The reply is made ephemeral after the
+    /// command runs; a command that posts anything else has to keep that private itself.
+    pub quiet: bool,
+    /// The event the request came in, for handing the command to a deferred run (see `deferred`).
+    pub event: Value,
+    /// The invoker's Twitch link, present when the command declared a scope.
+    pub link: Option<AccountLink>,
+    /// Where this deployment runs and which Twitch endpoints it calls.
+    pub config: &'static Config,
+}
+
+pub trait Command: Send + Sync {
+    /// The slash command this handles, e.g. `/tblock`.
+    fn name(&self) -> &'static str;
+
+    /// What picks this command under an umbrella command: its name without the leading `/t`,
+    /// e.g. `block`.
+    fn keyword(&self) -> &'static str {
+        let name = self.name();
+        name.strip_prefix("/t").unwrap_or(name)
+    }
+
+    /// The arguments the command takes. With a schema, the router parses the text up front,
+    /// answers bad input and `help` with the usage, and only runs the command on valid input.
+    fn schema(&self) -> Option<&'static CommandSchema> {
+        None
+    }
+
+    /// The Twitch scope the invoker's linked account needs for this request, if any. Returning
+    /// `None` skips the link lookup.
+    fn scope(&self, _invocation: &Invocation) -> Option<&'static str> {
+        None
+    }
+
+    /// The plan feature this request uses, if it's one the free plan doesn't include.
+    fn feature(&self, _invocation: &Invocation) -> Option<Feature> {
+        None
+    }
+
+    /// How long a reply this command posted to a channel keeps answering the same request there.
+    /// Within that, the invoker is shown the earlier reply instead of the command running again.
+    fn cooldown(&self) -> Option<Duration> {
+        None
+    }
+
+    /// One line on what the command does, for the umbrella command's list.
+    fn summary(&self) -> &'static str {
+        self.schema().map_or("", |schema| schema.summary)
+    }
+
+    /// Whether the command leases its workspace's concurrency slot itself (see
+    /// [`crate::concurrency`]), e.g. only for the requests that call Twitch, instead of
+    /// [`TeamConcurrency`] leasing one around every request.
+    fn leases_own_slot(&self) -> bool {
+        false
+    }
+
+    /// `Some(detail)` for requests that should land in the audit log before they run.
+    fn audit(&self, _invocation: &Invocation) -> Option<String> {
+        None
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>>;
+}
+
+/// A step run around every command. `before` can answer the request itself by returning a
+/// message; the command and the remaining middleware are skipped then.
+pub trait Middleware: Send + Sync {
+    fn before<'a>(
+        &'a self,
+        _command: &'a dyn Command,
+        _invocation: &'a mut Invocation,
+    ) -> BoxFuture<'a, Result<Option<SlackMessage>, Error>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Sees the reply before it's sent, whichever step produced it.
+    fn replied<'a>(
+        &'a self,
+        _command: &'a dyn Command,
+        _req: &'a SlashCommand,
+        _reply: &'a SlackMessage,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Runs once the reply is ready, whichever step produced it.
+    fn after(&self, _command: &dyn Command, _req: &SlashCommand, _elapsed: Duration) {}
+
+    /// Runs instead of `replied` and `after` when a step fails, so anything `before` took can be
+    /// given back.
+    fn failed<'a>(&'a self, _command: &'a dyn Command, _req: &'a SlashCommand) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+}
+
+#[derive(Default)]
+pub struct Router {
+    commands: Vec<Box<dyn Command>>,
+    middleware: Vec<Box<dyn Middleware>>,
+    /// The slash command whose first word picks one of `commands`.
+    umbrella: Option<&'static str>,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// A router with the standard middleware, in order: usage counting, rate limiting, plan
+    /// entitlements, account links, channel cooldowns, per-workspace concurrency, auditing and
+    /// latency metrics.
+    pub fn with_defaults() -> Router {
+        Router::new()
+            .middleware(Usage::from_env())
+            .middleware(RateLimit::new(COMMANDS_PER_MINUTE))
+            .middleware(Entitlements::from_env())
+            .middleware(LinkCheck::from_env())
+            .middleware(Cooldown::from_env())
+            .middleware(TeamConcurrency::from_env())
+            .middleware(Audit::from_env())
+            .middleware(Metrics)
+    }
+
+    pub fn command(mut self, command: impl Command + 'static) -> Router {
+        self.commands.push(Box::new(command));
+        self
+    }
+
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Router {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Also serves the commands as subcommands of `name`, e.g. `/twitch`, so one slash command
+    /// (and one function) covers them all.
+    pub fn umbrella(mut self, name: &'static str) -> Router {
+        self.umbrella = Some(name);
+        self
+    }
+
+    /// Serves a slash command event. Failures the bot can name (see `error`) are answered with
+    /// a message rather than failing the invocation. In a deferred run the reply, either way, is
+    /// posted to the command's `response_url`.
+    pub async fn dispatch(&self, event: Value) -> Result<SlackMessage, Error> {
+        let reply = self.serve(&event).await.or_else(error::reply);
+        deferred::finish(&event, reply).await
+    }
+
+    async fn serve(&self, event: &Value) -> Result<SlackMessage, Error> {
+        let mut req = match SlashCommand::from_event(event) {
+            Ok(req) => req,
+            Err(e) => {
+                error!("Malformed slash command request: {}", e);
+                return slack::malformed_request();
+            }
+        };
+        let command = match self.route(&mut req) {
+            Some(command) => command,
+            None if self.umbrella == Some(req.command.as_str()) => {
+                secrets::verify_slack_request(event, &req.token).await?;
+                return SlackMessage::builder().ephemeral().text(self.umbrella_usage()).build();
+            }
+            None => {
+                error!("No handler registered for {:?}", req.command);
+                return slack::malformed_request();
+            }
+        };
+        let verified = secrets::verify_slack_request(event, &req.token).await?;
+        // The command already ran and left this run only its posting, so it isn't run, counted or
+        // audited again.
+        if let Some(delivery) = deferred::delivery(event)? {
+            return delivery.send(&req, &verified.secrets).await;
+        }
+        let quiet = take_quiet(&mut req.text);
+
+        let args = match command.schema() {
+            Some(schema) if help::is_request(&req.text) => {
+                return SlackMessage::builder().ephemeral().text(schema.usage()).build();
+            }
+            Some(schema) => match schema.parse(&req.text) {
+                Ok(args) => args,
+                Err(e) => return SlackMessage::builder().ephemeral().text(schema.error_message(&e)).build(),
+            },
+            None => Args::default(),
+        };
+
+        if deferred::should_defer(event, &req) {
+            if let Some(placeholder) = deferred::hand_off(event).await {
+                return Ok(placeholder);
+            }
+        }
+
+        let started = Instant::now();
+        let meta = req.clone();
+        let invocation = Invocation {
+            req,
+            args,
+            secrets: verified.secrets,
+            secrets_version: verified.version_id,
+            quiet,
+            event: event.clone(),
+            link: None,
+            config: config::get(),
+        };
+
+        let reply = match self.respond(command, invocation, &meta, quiet).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                for middleware in &self.middleware {
+                    middleware.failed(command, &meta).await;
+                }
+                return Err(e);
+            }
+        };
+        let elapsed = started.elapsed();
+        for middleware in &self.middleware {
+            middleware.after(command, &meta, elapsed);
+        }
+        Ok(reply)
+    }
+
+    /// The middleware and the command itself, up to the reply every `replied` has seen.
+    async fn respond(
+        &self,
+        command: &dyn Command,
+        mut invocation: Invocation,
+        meta: &SlashCommand,
+        quiet: bool,
+    ) -> Result<SlackMessage, Error> {
+        let mut reply = None;
+        for middleware in &self.middleware {
+            if let Some(message) = middleware.before(command, &mut invocation).await? {
+                reply = Some(message);
+                break;
+            }
+        }
+        let reply = match reply {
+            Some(message) => message,
+            None => command.run(invocation).await?,
+        };
+        let reply = if quiet { reply.only_for_invoker() } else { reply };
+
+        for middleware in &self.middleware {
+            middleware.replied(command, meta, &reply).await?;
+        }
+        Ok(reply)
+    }
+
+    /// The command `req` is for. A request to the umbrella command is rewritten to the one its
+    /// first word picks, with the rest of the text, so the middleware and the command see it as
+    /// if it had been sent that way.
+    fn route(&self, req: &mut SlashCommand) -> Option<&dyn Command> {
+        if self.umbrella != Some(req.command.as_str()) {
+            return self.find(&req.command);
+        }
+        let text = req.text.trim_start();
+        let (keyword, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let command = self.commands.iter().find(|c| c.keyword().eq_ignore_ascii_case(keyword))?;
+        let rest = rest.trim_start().to_string();
+        req.command = command.name().to_string();
+        req.text = rest;
+        Some(command.as_ref())
+    }
+
+    /// The umbrella command's subcommands, for `help` or a word that isn't one of them.
+    fn umbrella_usage(&self) -> String {
+        let umbrella = self.umbrella.unwrap_or_default();
+        let mut lines = vec![format!("`{} <command> …` runs any of these:", umbrella)];
+        for command in &self.commands {
+            lines.push(format!("• `{} {}`: {}", umbrella, command.keyword(), command.summary()));
+        }
+        if let Some(first) = self.commands.first() {
+            lines.push(format!("Add `help` after one for its usage, e.g. `{} {} help`.", umbrella, first.keyword()));
+        }
+        lines.join("\n")
+    }
+
+    /// The command registered under `name`. A router with a single command serves it whatever the
+    /// request says, since Slack only sends `command` on newer payloads.
+    fn find(&self, name: &str) -> Option<&dyn Command> {
+        match self.commands.as_slice() {
+            [only] => Some(only.as_ref()),
+            commands => commands.iter().find(|c| c.name() == name).map(|c| c.as_ref()),
+        }
+    }
+}
+
+/// Strips a trailing `quiet` or `private` (see [`slack::is_quiet_word`]) from a command's text,
+/// returning whether there was one. Only the last word counts, so a category or title that has
+/// the word in it is left alone.
+fn take_quiet(text: &mut String) -> bool {
+    let trimmed = text.trim_end();
+    let (rest, last) = trimmed.rsplit_once(char::is_whitespace).unwrap_or(("", trimmed));
+    if !slack::is_quiet_word(last) {
+        return false;
+    }
+    *text = rest.trim_end().to_string();
+    true
+}
+
+/// How many times one Slack user may run one command per minute in a container.
+const COMMANDS_PER_MINUTE: usize = 10;
+
+/// Refuses requests while Twitch has told us the bucket is empty, and stops one user from
+/// hammering a command. Counts are per container, which is enough to blunt a stuck key.
+pub struct RateLimit {
+    per_minute: usize,
+    recent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimit {
+    pub fn new(per_minute: usize) -> RateLimit {
+        RateLimit {
+            per_minute,
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn allow(&self, key: String) -> bool {
+        let now = Instant::now();
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        let calls = recent.entry(key).or_default();
+        while calls.front().map_or(false, |at| now.duration_since(*at) >= Duration::from_secs(60)) {
+            calls.pop_front();
+        }
+        if calls.len() >= self.per_minute {
+            return false;
+        }
+        calls.push_back(now);
+        true
+    }
+}
+
+impl Middleware for RateLimit {
+    fn before<'a>(
+        &'a self,
+        command: &'a dyn Command,
+        invocation: &'a mut Invocation,
+    ) -> BoxFuture<'a, Result<Option<SlackMessage>, Error>> {
+        Box::pin(async move {
+            if let Some(reset) = ratelimit::exhausted_until() {
+                let wait = reset.saturating_sub(ratelimit::now_secs());
+                let text = format!("Twitch is rate limiting the bot. Try again in {}s.", wait.max(1));
+                return Ok(Some(SlackMessage::builder().ephemeral().text(text).build()?));
+            }
+
+            let req = &invocation.req;
+            let key = format!("{}:{}:{}", req.team_id, req.user_id, command.name());
+            if !self.allow(key) {
+                let text = format!("You're running {} too quickly. Give it a minute.", command.name());
+                return Ok(Some(SlackMessage::builder().ephemeral().text(text).build()?));
+            }
+            Ok(None)
+        })
+    }
+}
+
+/// Answers with an upgrade message when the command uses a feature the workspace's plan doesn't
+/// include. Only commands that declare a feature cost a config load.
+pub struct Entitlements {
+    store: WorkspaceStore,
+}
+
+impl Entitlements {
+    pub fn from_env() -> Entitlements {
+        Entitlements {
+            store: WorkspaceStore::from_env(),
+        }
+    }
+}
+
+impl Middleware for Entitlements {
+    fn before<'a>(
+        &'a self,
+        command: &'a dyn Command,
+        invocation: &'a mut Invocation,
+    ) -> BoxFuture<'a, Result<Option<SlackMessage>, Error>> {
+        Box::pin(async move {
+            let feature = match command.feature(invocation) {
+                Some(feature) => feature,
+                None => return Ok(None),
+            };
+            let config = self.store.load(&invocation.req.team_id).await?;
+            if Plan::for_workspace(&config).allows(feature) {
+                return Ok(None);
+            }
+            Ok(Some(SlackMessage::builder().ephemeral().text(feature.upgrade_message()).build()?))
+        })
+    }
+}
+
+/// Looks up the invoker's Twitch link for commands that declare a scope, and answers with
+/// `/tlink` instructions when it's missing or lacks the scope.
+pub struct LinkCheck {
+    store: LinkStore,
+}
+
+impl LinkCheck {
+    pub fn from_env() -> LinkCheck {
+        LinkCheck {
+            store: LinkStore::from_env(),
+        }
+    }
+}
+
+impl Middleware for LinkCheck {
+    fn before<'a>(
+        &'a self,
+        command: &'a dyn Command,
+        invocation: &'a mut Invocation,
+    ) -> BoxFuture<'a, Result<Option<SlackMessage>, Error>> {
+        Box::pin(async move {
+            let scope = match command.scope(invocation) {
+                Some(scope) => scope,
+                None => return Ok(None),
+            };
+            let req = &invocation.req;
+            match links::command_link(&self.store, &invocation.secrets, &req.team_id, &req.user_id, None, scope)
+                .await?
+            {
+                Ok(link) => {
+                    invocation.link = Some(link);
+                    Ok(None)
+                }
+                Err(message) => Ok(Some(SlackMessage::builder().text(message).build()?)),
+            }
+        })
+    }
+}
+
+/// Cooldown for the lookups people tend to run again straight after someone else did.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Per-channel cooldowns for commands that declare one. A reply posted to the channel is kept in
+/// the lookup cache; running the same command there with the same text before the cooldown is up
+/// shows the invoker that reply, and how old it is, without asking Twitch again. A cache failure
+/// just means the command runs.
+pub struct Cooldown {
+    cache: CacheStore,
+}
+
+impl Cooldown {
+    pub fn from_env() -> Cooldown {
+        Cooldown {
+            cache: CacheStore::from_env(),
+        }
+    }
+
+    /// The same command and text in the same channel, however it was spaced or capitalized.
+    fn key(command: &dyn Command, req: &SlashCommand) -> String {
+        let text: Vec<String> = req.text.split_whitespace().map(str::to_lowercase).collect();
+        format!("cooldown:{}:{}:{}:{}", req.team_id, req.channel_id, command.name(), text.join(" "))
+    }
+}
+
+impl Middleware for Cooldown {
+    fn before<'a>(
+        &'a self,
+        command: &'a dyn Command,
+        invocation: &'a mut Invocation,
+    ) -> BoxFuture<'a, Result<Option<SlackMessage>, Error>> {
+        Box::pin(async move {
+            let cooldown = match command.cooldown() {
+                Some(cooldown) => cooldown,
+                None => return Ok(None),
+            };
+            let key = Cooldown::key(command, &invocation.req);
+            let earlier = match self.cache.get::<SlackMessage>(&key).await {
+                Ok(Some(earlier)) if earlier.age_secs() < cooldown.as_secs() as i64 => earlier,
+                Ok(_) => return Ok(None),
+                Err(e) => {
+                    error!("Could not read the cooldown for {}: {}", key, e);
+                    return Ok(None);
+                }
+            };
+            let note = format!(
+                "_`{} {}` was run here {}s ago, so here's that result rather than asking Twitch again._",
+                command.name(),
+                invocation.req.text.trim(),
+                earlier.age_secs()
+            );
+            Ok(Some(earlier.value.repeated(&note)))
+        })
+    }
+
+    fn replied<'a>(
+        &'a self,
+        command: &'a dyn Command,
+        req: &'a SlashCommand,
+        reply: &'a SlackMessage,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            // Errors, usage and replays of an earlier reply are all ephemeral, so only fresh
+            // results that went to the channel start a cooldown.
+            if command.cooldown().is_none() || !reply.is_in_channel() {
+                return Ok(());
+            }
+            if let Err(e) = self.cache.put(&Cooldown::key(command, req), reply).await {
+                error!("Could not start the cooldown for {}: {}", command.name(), e);
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Holds one of the workspace's concurrency slots (see [`crate::concurrency`]) while a command
+/// runs, answering that the workspace is busy when none is free. Replays from a cooldown come
+/// before it and don't take one. A command that fails gives its slot back too. If the slots
+/// can't be read the command runs anyway, as if there were no limit.
+pub struct TeamConcurrency {
+    limiter: TeamLimiter,
+    /// Leases by `trigger_id`, from `before` until the reply.
+    held: Mutex<HashMap<String, Lease>>,
+}
+
+impl TeamConcurrency {
+    pub fn from_env() -> TeamConcurrency {
+        TeamConcurrency {
+            limiter: TeamLimiter::from_env(),
+            held: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn release(&self, req: &SlashCommand) {
+        let lease = self.held.lock().unwrap_or_else(|e| e.into_inner()).remove(&req.trigger_id);
+        if let Some(lease) = lease {
+            self.limiter.release(lease).await;
+        }
+    }
+}
+
+impl Middleware for TeamConcurrency {
+    fn before<'a>(
+        &'a self,
+        command: &'a dyn Command,
+        invocation: &'a mut Invocation,
+    ) -> BoxFuture<'a, Result<Option<SlackMessage>, Error>> {
+        Box::pin(async move {
+            if self.limiter.slots() == 0 || command.leases_own_slot() {
+                return Ok(None);
+            }
+            match self.limiter.acquire(&invocation.req.team_id).await {
+                Ok(Some(lease)) => {
+                    let mut held = self.held.lock().unwrap_or_else(|e| e.into_inner());
+                    held.insert(invocation.req.trigger_id.clone(), lease);
+                    Ok(None)
+                }
+                Ok(None) => Ok(Some(SlackMessage::builder().ephemeral().text(self.limiter.busy_message()).build()?)),
+                Err(e) => {
+                    error!("Could not lease a concurrency slot for {}: {}", invocation.req.team_id, e);
+                    Ok(None)
+                }
+            }
+        })
+    }
+
+    fn replied<'a>(
+        &'a self,
+        _command: &'a dyn Command,
+        req: &'a SlashCommand,
+        _reply: &'a SlackMessage,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.release(req).await;
+            Ok(())
+        })
+    }
+
+    fn failed<'a>(&'a self, _command: &'a dyn Command, req: &'a SlashCommand) -> BoxFuture<'a, ()> {
+        Box::pin(self.release(req))
+    }
+}
+
+/// Writes the audit entry for commands that ask for one, before they run.
+pub struct Audit {
+    log: AuditLog,
+}
+
+impl Audit {
+    pub fn from_env() -> Audit {
+        Audit {
+            log: AuditLog::from_env(),
+        }
+    }
+}
+
+impl Middleware for Audit {
+    fn before<'a>(
+        &'a self,
+        command: &'a dyn Command,
+        invocation: &'a mut Invocation,
+    ) -> BoxFuture<'a, Result<Option<SlackMessage>, Error>> {
+        Box::pin(async move {
+            // A dry run doesn't act, so there's nothing to audit.
+            if invocation.args.dry_run {
+                return Ok(None);
+            }
+            let req = &invocation.req;
+            if let Some(detail) = command.audit(invocation) {
+                let entry = AuditEntry {
+                    team_id: req.team_id.clone(),
+                    user_id: req.user_id.clone(),
+                    action: command.name().trim_start_matches('/').to_string(),
+                    detail,
+                };
+                self.log.record(&entry).await?;
+            }
+            Ok(None)
+        })
+    }
+}
+
+/// Counts every invocation towards the workspace's monthly usage (`/tquota`), including ones
+/// the rate limiter turns away. A failure to count doesn't fail the command.
+pub struct Usage {
+    store: UsageStore,
+}
+
+impl Usage {
+    pub fn from_env() -> Usage {
+        Usage {
+            store: UsageStore::from_env(),
+        }
+    }
+}
+
+impl Middleware for Usage {
+    fn before<'a>(
+        &'a self,
+        _command: &'a dyn Command,
+        invocation: &'a mut Invocation,
+    ) -> BoxFuture<'a, Result<Option<SlackMessage>, Error>> {
+        Box::pin(async move {
+            if let Err(e) = self.store.record_invocation(&invocation.req.team_id).await {
+                error!("Could not record usage for workspace {}: {}", invocation.req.team_id, e);
+            }
+            Ok(None)
+        })
+    }
+}
+
+/// Emits `CommandLatency` per command.
+pub struct Metrics;
+
+impl Middleware for Metrics {
+    fn after(&self, command: &dyn Command, _req: &SlashCommand, elapsed: Duration) {
+        metrics::milliseconds("CommandLatency", elapsed.as_millis() as i64, &[("Command", command.name())]);
+    }
+}
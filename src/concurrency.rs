@@ -0,0 +1,157 @@
+//! Per-workspace limits on concurrent Twitch work, for a hosted deployment where every workspace
+//! shares the functions' concurrency pool and the app token's rate limit. `TEAM_CONCURRENCY`
+//! (default 0, off) is how many commands one workspace may have calling Twitch at once; a bulk
+//! lookup beyond that is turned away with a message rather than taking containers the other
+//! workspaces need.
+//!
+//! Each container serves one event at a time, so the slots live in DynamoDB: a workspace has
+//! `TEAM_CONCURRENCY` slot items, and a command leases a free one before it runs and gives it back
+//! when it's done. A lease expires on its own after [`LEASE`], so a function that times out or
+//! crashes doesn't keep its slot.
+
+use crate::config;
+use crate::stage::Stage;
+use crate::Error;
+use log::error;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    AttributeValue, DeleteItemError, DeleteItemInput, DynamoDb, DynamoDbClient, PutItemError, PutItemInput,
+};
+use rusoto_signature::region::Region;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_TABLE: &str = "tuser-concurrency";
+
+/// How long a slot stays leased if it isn't given back; longer than any command runs.
+pub const LEASE: Duration = Duration::from_secs(30);
+
+/// A slot held for one command, to [`release`](TeamLimiter::release) when it's done.
+#[derive(Debug, Clone)]
+pub struct Lease {
+    slot: String,
+    holder: String,
+}
+
+pub struct TeamLimiter {
+    client: DynamoDbClient,
+    table: String,
+    slots: usize,
+}
+
+impl TeamLimiter {
+    pub fn new(region: Region, table: String, slots: usize) -> TeamLimiter {
+        TeamLimiter {
+            client: DynamoDbClient::new(region),
+            table,
+            slots,
+        }
+    }
+
+    pub fn from_env() -> TeamLimiter {
+        let table = std::env::var("CONCURRENCY_TABLE").unwrap_or_else(|_| Stage::current().resource(DEFAULT_TABLE));
+        let slots = std::env::var("TEAM_CONCURRENCY").ok().and_then(|slots| slots.parse().ok()).unwrap_or(0);
+        TeamLimiter::new(config::get().region.clone(), table, slots)
+    }
+
+    /// How many commands a workspace may run at once; 0 when there's no limit.
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Leases a free slot for `team_id`, or `None` when all of them are taken. Without a limit
+    /// there's nothing to lease and every command gets a lease that holds nothing.
+    pub async fn acquire(&self, team_id: &str) -> Result<Option<Lease>, Error> {
+        let holder: String = rand::thread_rng().sample_iter(&Alphanumeric).take(16).collect();
+        if self.slots == 0 {
+            return Ok(Some(Lease {
+                slot: String::new(),
+                holder,
+            }));
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let expires_at = now + LEASE;
+        // Starting at a random slot keeps a workspace's commands from all contending for the first.
+        let first = rand::thread_rng().gen_range(0, self.slots);
+        for n in 0..self.slots {
+            let slot = format!("{}#{}", team_id, (first + n) % self.slots);
+            let mut item = key_attribute(&slot);
+            item.insert("holder".to_string(), string_value(&holder));
+            item.insert("expires_at".to_string(), number_value(expires_at.as_secs()));
+            let mut values = HashMap::new();
+            values.insert(":now".to_string(), number_value(now.as_secs()));
+
+            let put = self
+                .client
+                .put_item(PutItemInput {
+                    table_name: self.table.clone(),
+                    item,
+                    condition_expression: Some("attribute_not_exists(slot) OR expires_at < :now".to_string()),
+                    expression_attribute_values: Some(values),
+                    ..Default::default()
+                })
+                .await;
+            match put {
+                Ok(_) => return Ok(Some(Lease { slot, holder })),
+                Err(RusotoError::Service(PutItemError::ConditionalCheckFailed(_))) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Gives `lease`'s slot back, unless it expired and someone else has it now. A failure is
+    /// logged; the lease expires anyway.
+    pub async fn release(&self, lease: Lease) {
+        if lease.slot.is_empty() {
+            return;
+        }
+        let mut values = HashMap::new();
+        values.insert(":holder".to_string(), string_value(&lease.holder));
+        let delete = self
+            .client
+            .delete_item(DeleteItemInput {
+                table_name: self.table.clone(),
+                key: key_attribute(&lease.slot),
+                condition_expression: Some("holder = :holder".to_string()),
+                expression_attribute_values: Some(values),
+                ..Default::default()
+            })
+            .await;
+        match delete {
+            Ok(_) | Err(RusotoError::Service(DeleteItemError::ConditionalCheckFailed(_))) => {}
+            Err(e) => error!("Could not release concurrency slot {}: {}", lease.slot, e),
+        }
+    }
+
+    /// What a workspace that's at its limit is told.
+    pub fn busy_message(&self) -> String {
+        format!(
+            "Your workspace already has {} lookups running, the most it can at once. Try again in a moment.",
+            self.slots
+        )
+    }
+}
+
+fn key_attribute(slot: &str) -> HashMap<String, AttributeValue> {
+    let mut attrs = HashMap::new();
+    attrs.insert("slot".to_string(), string_value(slot));
+    attrs
+}
+
+fn string_value(value: &str) -> AttributeValue {
+    AttributeValue {
+        s: Some(value.to_string()),
+        ..Default::default()
+    }
+}
+
+fn number_value(value: u64) -> AttributeValue {
+    AttributeValue {
+        n: Some(value.to_string()),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,79 @@
+//! Where this deployment runs and what it talks to, read from the environment once at startup so
+//! a staging copy can live in another region with its own secret, and tests can point the Twitch
+//! and Slack calls at a mock server:
+//!
+//! - `AWS_REGION` (Lambda sets it; default `us-west-2`): the region of every table, bucket,
+//!   secret and AWS service the bot uses. `SECRETS_REGIONS` can still list replicas to fall back on.
+//! - `SECRET_ID` (default `prod/tuser`, renamed for the stage, see `stage`): the bot's secret.
+//! - `TWITCH_API_BASE` (default `https://api.twitch.tv/helix`): Helix.
+//! - `TWITCH_AUTH_BASE` (default `https://id.twitch.tv/oauth2`): Twitch's OAuth endpoints.
+//! - `TWITCH_GQL_BASE` (default `https://gql.twitch.tv/gql`) and `TWITCH_GQL_CLIENT_ID` (unset by
+//!   default): Twitch's own GraphQL API, for the channel trailers and panels Helix doesn't expose.
+//!   They're only looked up when the client ID is set.
+//! - `SLACK_API_BASE` (default `https://slack.com/api`): Slack's Web API.
+
+use crate::secrets::SECRET_ID;
+use crate::stage::Stage;
+use log::warn;
+use rusoto_signature::region::Region;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+pub const DEFAULT_REGION: Region = Region::UsWest2;
+pub const DEFAULT_TWITCH_API_BASE: &str = "https://api.twitch.tv/helix";
+pub const DEFAULT_TWITCH_AUTH_BASE: &str = "https://id.twitch.tv/oauth2";
+pub const DEFAULT_TWITCH_GQL_BASE: &str = "https://gql.twitch.tv/gql";
+pub const DEFAULT_SLACK_API_BASE: &str = "https://slack.com/api";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub region: Region,
+    /// The secret Secrets Manager is asked for, already named for the stage.
+    pub secret_id: String,
+    /// The Helix base URL, without a trailing `/`.
+    pub twitch_api_base: String,
+    /// The OAuth base URL, without a trailing `/`.
+    pub twitch_auth_base: String,
+    /// The GraphQL endpoint, without a trailing `/`.
+    pub twitch_gql_base: String,
+    /// The client ID GraphQL calls are made as; without one they aren't made.
+    pub twitch_gql_client_id: Option<String>,
+    /// The Slack Web API base URL, without a trailing `/`.
+    pub slack_api_base: String,
+}
+
+impl Config {
+    pub fn from_env() -> Config {
+        let region = match std::env::var("AWS_REGION") {
+            Ok(name) if !name.is_empty() => Region::from_str(&name).unwrap_or_else(|_| {
+                warn!("Unknown AWS_REGION {}, using {}", name, DEFAULT_REGION.name());
+                DEFAULT_REGION
+            }),
+            _ => DEFAULT_REGION,
+        };
+        Config {
+            region,
+            secret_id: var("SECRET_ID").unwrap_or_else(|| Stage::current().secret_id(SECRET_ID)),
+            twitch_api_base: base_url("TWITCH_API_BASE", DEFAULT_TWITCH_API_BASE),
+            twitch_auth_base: base_url("TWITCH_AUTH_BASE", DEFAULT_TWITCH_AUTH_BASE),
+            twitch_gql_base: base_url("TWITCH_GQL_BASE", DEFAULT_TWITCH_GQL_BASE),
+            twitch_gql_client_id: var("TWITCH_GQL_CLIENT_ID"),
+            slack_api_base: base_url("SLACK_API_BASE", DEFAULT_SLACK_API_BASE),
+        }
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// This process's configuration, read from the environment the first time it's asked for.
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::from_env)
+}
+
+fn var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn base_url(name: &str, default: &str) -> String {
+    var(name).unwrap_or_else(|| default.to_string()).trim_end_matches('/').to_string()
+}
@@ -0,0 +1,239 @@
+//! Telling the operator when a function crashes. [`install`] adds a panic hook that remembers
+//! what panicked and where; [`guard`] runs each event's handler and, when it panics or fails the
+//! invocation with an error, posts a short report to `OPS_SLACK_CHANNEL` (default
+//! `ADMIN_SLACK_CHANNEL`): the function, the request it was serving and what went wrong. Reports
+//! are cut to [`MAX_REPORT_CHARS`] and [`scrub`]bed of the bot's credentials, the workspace's bot
+//! token and the invoker's Twitch link, and anything that looks like a token. `local::serve` does
+//! both for every binary.
+//!
+//! Failures the bot answers with a message (a [`BotError`](crate::error::BotError)) aren't
+//! crashes and aren't reported.
+
+use crate::error::BotError;
+use crate::links::LinkStore;
+use crate::notify;
+use crate::secrets::{self, Secrets};
+use crate::slack::SlashCommand;
+use crate::workspace::WorkspaceStore;
+use crate::Error;
+use futures::FutureExt;
+use log::error;
+use serde_json::Value;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+
+/// Slack shows about this much of a message before "show more".
+pub const MAX_REPORT_CHARS: usize = 2500;
+/// How much of the command text goes into a report.
+const MAX_TEXT_CHARS: usize = 200;
+const REDACTED: &str = "[redacted]";
+
+/// The last panic's message and location, left by the hook for [`guard`] to report.
+static LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+/// Adds the panic hook, keeping the default one (which logs the panic to stderr) behind it.
+pub fn install() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "(no message)".to_string());
+        let location = info
+            .location()
+            .map(|location| format!(" at {}:{}", location.file(), location.line()))
+            .unwrap_or_default();
+        if let Ok(mut last) = LAST_PANIC.lock() {
+            *last = Some(format!("{}{}", message, location));
+        }
+        default(info);
+    }));
+}
+
+/// Runs `handler` on `event`, reporting a panic or an error before passing it on. A panic is
+/// passed on as an error, so the container lives on to serve the next event.
+pub async fn guard<F, Fut, O>(handler: &F, event: Value) -> Result<O, Error>
+where
+    F: Fn(Value) -> Fut,
+    Fut: Future<Output = Result<O, Error>>,
+{
+    let context = context(&event);
+    let req = SlashCommand::from_event(&event).ok().filter(|req| !req.command.is_empty());
+    match AssertUnwindSafe(handler(event)).catch_unwind().await {
+        Ok(Ok(output)) => {
+            // A panic in a blocking task the handler recovered from isn't this event's to report.
+            last_panic();
+            Ok(output)
+        }
+        Ok(Err(e)) if BotError::find(&e).is_some() => {
+            last_panic();
+            Err(e)
+        }
+        Ok(Err(e)) => {
+            // A blocking task that panicked fails its handler with a `JoinError`.
+            let what = match last_panic() {
+                Some(panic) => format!("failed: {} (after a panic: {})", e, panic),
+                None => format!("failed: {}", e),
+            };
+            report(&what, &context, req.as_ref()).await;
+            Err(e)
+        }
+        Err(_) => {
+            let panic = last_panic().unwrap_or_default();
+            report(&format!("panicked: {}", panic), &context, req.as_ref()).await;
+            Err(format!("Handler panicked: {}", panic).into())
+        }
+    }
+}
+
+fn last_panic() -> Option<String> {
+    LAST_PANIC.lock().ok().and_then(|mut last| last.take())
+}
+
+async fn report(what: &str, context: &str, req: Option<&SlashCommand>) {
+    let secrets = match secrets::current().await {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            error!("Could not load the secrets to report a crash ({}): {}", what, e);
+            return;
+        }
+    };
+    let function = std::env::var("AWS_LAMBDA_FUNCTION_NAME")
+        .ok()
+        .or_else(|| std::env::args().next())
+        .unwrap_or_default();
+    let text = format!(":rotating_light: `{}` {}\n{}", function, what, context);
+    let tokens = match req {
+        Some(req) => tokens(req).await,
+        None => vec![],
+    };
+    let text: String = scrub(&text, &secrets, &tokens).chars().take(MAX_REPORT_CHARS).collect();
+    if let Err(e) = notify::ops(&secrets, text).await {
+        error!("Could not post a crash report: {}", e);
+    }
+}
+
+/// The tokens a command could have used besides the bot's own: its workspace's bot token and the
+/// invoker's Twitch link. Ones that can't be loaded are left out; the patterns in [`scrub`] still
+/// catch Slack tokens.
+async fn tokens(req: &SlashCommand) -> Vec<String> {
+    let mut tokens = vec![];
+    match WorkspaceStore::from_env().bot_token(&req.team_id).await {
+        Ok(token) => tokens.extend(token),
+        Err(e) => error!("Could not load {}'s bot token to scrub a crash report: {}", req.team_id, e),
+    }
+    match LinkStore::from_env().get(&req.team_id, &req.user_id).await {
+        Ok(link) => tokens.extend(link.into_iter().flat_map(|link| vec![link.access_token, link.refresh_token])),
+        Err(e) => error!("Could not load {}'s Twitch link to scrub a crash report: {}", req.user_id, e),
+    }
+    tokens
+}
+
+/// What the function was doing: the slash command and who ran it where, or for other events
+/// (schedules, EventSub deliveries, interactions) the event's top-level fields.
+fn context(event: &Value) -> String {
+    match SlashCommand::from_event(event) {
+        Ok(req) if !req.command.is_empty() => {
+            let text: String = req.text.chars().take(MAX_TEXT_CHARS).collect();
+            format!(
+                "Serving `{} {}` for user {} in team {}, channel {}",
+                req.command, text, req.user_id, req.team_id, req.channel_id
+            )
+        }
+        _ => {
+            let fields: Vec<&str> =
+                event.as_object().map_or(vec![], |fields| fields.keys().map(String::as_str).collect());
+            format!("Serving an event with fields: {}", fields.join(", "))
+        }
+    }
+}
+
+/// `text` without any of the bot's credentials or the other `tokens`, Slack tokens (`xoxb-…` and
+/// the like) and stream keys (`live_…`) wherever they appear, or the word after `Bearer` or
+/// `OAuth`.
+pub fn scrub(text: &str, secrets: &Secrets, tokens: &[String]) -> String {
+    let mut text = text.to_string();
+    // Short values, like an unset secret's placeholder, would match all over.
+    let known = secrets.values().into_iter().chain(tokens.iter().map(String::as_str));
+    for value in known.filter(|value| value.len() >= 8) {
+        text = text.replace(value, REDACTED);
+    }
+    let text = redact_matches(&text, slack_token_len);
+    let text = redact_matches(&text, stream_key_len);
+
+    // Whitespace is kept as it was, so a report's lines and indentation survive.
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text.as_str();
+    let mut after_scheme = false;
+    while !rest.is_empty() {
+        let start = rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len());
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = &rest[..end];
+        if word.is_empty() {
+            break;
+        }
+        out.push_str(if after_scheme { REDACTED } else { word });
+        after_scheme = word.eq_ignore_ascii_case("bearer") || word.eq_ignore_ascii_case("oauth");
+        rest = &rest[end..];
+    }
+    out
+}
+
+/// `text` with every span `matches` measures replaced. `matches` is given the text from each
+/// position and says how long a match starting there is, if there is one.
+fn redact_matches(text: &str, matches: fn(&str) -> Option<usize>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        match matches(rest) {
+            Some(len) => {
+                out.push_str(REDACTED);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The length of characters `keep` accepts at the start of `text`.
+fn prefix_len(text: &str, keep: impl Fn(char) -> bool) -> usize {
+    text.find(|c: char| !keep(c)).unwrap_or(text.len())
+}
+
+/// A Slack token at the start of `text`: `xoxa-`, `xoxb-`, `xoxp-`, `xoxr-` or `xoxs-` and the
+/// word characters and dashes after it.
+fn slack_token_len(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix("xox")?;
+    let kind = rest.chars().next().filter(|c| "abprs".contains(*c))?;
+    let rest = rest[kind.len_utf8()..].strip_prefix('-')?;
+    let body = prefix_len(rest, |c| is_word_char(c) || c == '-');
+    if body == 0 {
+        return None;
+    }
+    Some(text.len() - rest.len() + body)
+}
+
+/// A Twitch stream key at the start of `text`: `live_`, digits, `_` and word characters.
+fn stream_key_len(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix("live_")?;
+    let digits = prefix_len(rest, |c| c.is_ascii_digit());
+    let rest = rest[digits..].strip_prefix('_').filter(|_| digits > 0)?;
+    let body = prefix_len(rest, is_word_char);
+    if body == 0 {
+        return None;
+    }
+    Some(text.len() - rest.len() + body)
+}
@@ -0,0 +1,145 @@
+//! Short signed links, embedded in Slack messages, that trigger an action when opened in a
+//! browser: refreshing the status board. They're for people reading from their phone who can't
+//! easily type a slash command. Opening a link can't say who opened it, and link previews and
+//! scanners open them too, so they're only for actions that change nothing anyone chose; adding
+//! to the watchlist is a Slack button instead (`cards::WATCH_ACTION`).
+//!
+//! A link carries its action, workspace, the Slack user it was made for, a target and an expiry
+//! in the query string, plus an HMAC over all of them keyed with `deep_link_secret`. `tgo`
+//! checks the signature and expiry before doing anything.
+
+use chrono::Utc;
+use hmac::{Hmac, Mac, NewMac};
+use serde_json::Value;
+use sha2::Sha256;
+use std::fmt;
+
+/// How long a link works after the message it's in was posted.
+pub const LINK_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+/// Bytes of the HMAC kept in the link. Links expire, so this keeps them short without making
+/// forgery practical.
+const SIGNATURE_BYTES: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkAction {
+    /// Update the workspace's pinned status board now.
+    RefreshBoard,
+}
+
+impl LinkAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkAction::RefreshBoard => "refresh",
+        }
+    }
+
+    fn from_str(action: &str) -> Option<LinkAction> {
+        match action {
+            "refresh" => Some(LinkAction::RefreshBoard),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeepLink {
+    pub action: LinkAction,
+    pub team_id: String,
+    /// Who the message with the link was posted for; empty for the status board's.
+    pub user_id: String,
+    /// What the action applies to; empty for a board refresh.
+    pub target: String,
+    /// Unix seconds.
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkError {
+    Malformed,
+    BadSignature,
+    Expired,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkError::Malformed => write!(f, "This link is incomplete. Try opening it from Slack again."),
+            LinkError::BadSignature => write!(f, "This link isn't valid. Try opening it from Slack again."),
+            LinkError::Expired => write!(f, "This link has expired. Run the command in Slack again."),
+        }
+    }
+}
+
+impl DeepLink {
+    /// A link for `action` that expires [`LINK_TTL_SECS`] from now.
+    pub fn new(action: LinkAction, team_id: &str, user_id: &str, target: &str) -> DeepLink {
+        DeepLink {
+            action,
+            team_id: team_id.to_string(),
+            user_id: user_id.to_string(),
+            target: target.to_string(),
+            expires_at: Utc::now().timestamp() + LINK_TTL_SECS,
+        }
+    }
+
+    /// The signed URL, or `None` when links aren't configured (no `DEEP_LINK_BASE_URL` or no
+    /// secret), in which case messages leave the link off.
+    pub fn url(&self, secret: &str) -> Option<String> {
+        let base = std::env::var("DEEP_LINK_BASE_URL").ok().filter(|base| !base.is_empty())?;
+        if secret.is_empty() {
+            return None;
+        }
+        let query = serde_urlencoded::to_string(&[
+            ("a", self.action.as_str()),
+            ("t", self.team_id.as_str()),
+            ("u", self.user_id.as_str()),
+            ("v", self.target.as_str()),
+            ("e", self.expires_at.to_string().as_str()),
+            ("s", hex::encode(self.signature(secret)?).as_str()),
+        ])
+        .ok()?;
+        Some(format!("{}/go?{}", base.trim_end_matches('/'), query))
+    }
+
+    /// Checks a request's `queryStringParameters` against `secret` and returns the link if it's
+    /// intact and hasn't expired.
+    pub fn verify(secret: &str, params: &Value) -> Result<DeepLink, LinkError> {
+        let param = |name: &str| params.get(name).and_then(Value::as_str).ok_or(LinkError::Malformed);
+        let link = DeepLink {
+            action: LinkAction::from_str(param("a")?).ok_or(LinkError::Malformed)?,
+            team_id: param("t")?.to_string(),
+            user_id: param("u")?.to_string(),
+            target: param("v").unwrap_or("").to_string(),
+            expires_at: param("e")?.parse().map_err(|_| LinkError::Malformed)?,
+        };
+        let signature = hex::decode(param("s")?).map_err(|_| LinkError::Malformed)?;
+
+        if secret.is_empty() || signature.len() != SIGNATURE_BYTES {
+            return Err(LinkError::BadSignature);
+        }
+        let expected = link.signature(secret).ok_or(LinkError::BadSignature)?;
+        // Compare every byte so the time taken doesn't say how much of a forgery was right.
+        if expected.iter().zip(&signature).fold(0, |diff, (a, b)| diff | (a ^ b)) != 0 {
+            return Err(LinkError::BadSignature);
+        }
+        if link.expires_at < Utc::now().timestamp() {
+            return Err(LinkError::Expired);
+        }
+        Ok(link)
+    }
+
+    fn signature(&self, secret: &str) -> Option<Vec<u8>> {
+        let code = self.mac(secret)?.finalize().into_bytes();
+        Some(code[..SIGNATURE_BYTES].to_vec())
+    }
+
+    fn mac(&self, secret: &str) -> Option<Hmac<Sha256>> {
+        let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).ok()?;
+        for part in &[self.action.as_str(), self.team_id.as_str(), self.user_id.as_str(), self.target.as_str()] {
+            mac.update(part.as_bytes());
+            mac.update(b"\n");
+        }
+        mac.update(self.expires_at.to_string().as_bytes());
+        Some(mac)
+    }
+}
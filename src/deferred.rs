@@ -0,0 +1,191 @@
+//! Deferred replies, for when a cold start, the secrets fetch and Twitch together could take
+//! longer than the three seconds Slack waits for a slash command. With `DEFER_RESPONSES=true` a
+//! command answers at once with an ephemeral "looking that up" and invokes its own function
+//! again, asynchronously, with the same event; that second run does the work and posts the reply
+//! to the command's `response_url`.
+//!
+//! The second run sees [`DEFERRED`] on the event. It's set on the invocation payload, which
+//! only something allowed to invoke the function can write, and the Slack request inside is
+//! verified again all the same. Lambda doesn't retry the second run (the functions set
+//! `maximumRetryAttempts: 0`), since a retry would post the reply again; a run that fails posts
+//! an apology instead, so the placeholder isn't the last the user hears.
+//!
+//! A command that has its reply but too much of it to post in time (`/tuser`'s cards in parts)
+//! hands the rendered [`Delivery`] to the second run instead, with [`hand_off_delivery`]; that run
+//! posts it without running the command, or its middleware, again.
+//!
+//! `tevents` hands off bulk lookups of shared files the same way, with [`invoke_self`], since the
+//! Events API also wants an answer within three seconds and has no placeholder to show.
+
+use crate::config;
+use crate::error::BotError;
+use crate::http;
+use crate::secrets::Secrets;
+use crate::slack::{self, SlackMessage, SlashCommand};
+use crate::workspace::WorkspaceStore;
+use crate::Error;
+use log::error;
+use rusoto_lambda::{InvocationRequest, Lambda, LambdaClient};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Marks an event as the deferred run of a command.
+pub const DEFERRED: &str = "deferred";
+/// Carries a deferred run's [`Delivery`], when the command already ran.
+pub const DELIVERY: &str = "delivery";
+
+/// What a command that already ran leaves the deferred run to post.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Delivery {
+    /// Sent to the command's `response_url`, in order.
+    pub messages: Vec<SlackMessage>,
+    /// Uploaded to the command's channel.
+    pub file: Option<DeliveryFile>,
+    /// The answer to the command, once the rest is posted.
+    pub reply: SlackMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeliveryFile {
+    pub name: String,
+    pub content: String,
+}
+
+impl Delivery {
+    /// Posts the messages and the file, then returns the reply.
+    pub async fn send(self, req: &SlashCommand, secrets: &Secrets) -> Result<SlackMessage, Error> {
+        let token = match &self.file {
+            Some(_) => WorkspaceStore::from_env()
+                .bot_token(&req.team_id)
+                .await?
+                .unwrap_or_else(|| secrets.slack_bot_token.clone()),
+            None => String::new(),
+        };
+        let (response_url, channel) = (req.response_url.clone(), req.channel_id.clone());
+        let Delivery { messages, file, reply } = self;
+        tokio::task::spawn_blocking(move || -> Result<(), Error> {
+            let client = http::client();
+            for message in &messages {
+                slack::respond(&client, &response_url, message)?;
+            }
+            if let Some(file) = file {
+                slack::upload_file(&client, &token, &channel, &file.name, &file.content)?;
+            }
+            Ok(())
+        })
+        .await??;
+        Ok(reply)
+    }
+}
+
+pub fn enabled() -> bool {
+    std::env::var("DEFER_RESPONSES").map_or(false, |value| value == "true")
+}
+
+pub fn is_deferred(event: &Value) -> bool {
+    event.get(DEFERRED).and_then(Value::as_bool) == Some(true)
+}
+
+/// Whether this request should be handed off: deferral is on, this isn't the deferred run
+/// already, and there's a `response_url` to answer on, which typed commands (`dm`) don't have.
+pub fn should_defer(event: &Value, req: &SlashCommand) -> bool {
+    enabled() && !is_deferred(event) && !req.response_url.is_empty()
+}
+
+/// Starts the deferred run and returns the placeholder to answer Slack with. If the function
+/// can't be invoked, returns `None` and the command should just run now.
+pub async fn hand_off(event: &Value) -> Option<SlackMessage> {
+    match invoke_self(event).await {
+        Ok(()) => SlackMessage::builder().ephemeral().text("_Looking that up…_").build().ok(),
+        Err(e) => {
+            error!("Could not defer the reply, answering inline: {}", e);
+            None
+        }
+    }
+}
+
+/// Like [`hand_off`], for a command that already ran: the deferred run only makes `delivery`.
+pub async fn hand_off_delivery(event: &Value, delivery: &Delivery) -> Option<SlackMessage> {
+    let handed_off = match serde_json::to_value(delivery) {
+        Ok(delivery) => invoke(event, Some(delivery)).await,
+        Err(e) => Err(e.into()),
+    };
+    match handed_off {
+        Ok(()) => SlackMessage::builder().ephemeral().text("_Posting the results…_").build().ok(),
+        Err(e) => {
+            error!("Could not defer the delivery, posting it inline: {}", e);
+            None
+        }
+    }
+}
+
+/// The [`Delivery`] this deferred run is for, if the command already ran.
+pub fn delivery(event: &Value) -> Result<Option<Delivery>, Error> {
+    match event.get(DELIVERY) {
+        Some(delivery) if is_deferred(event) => Ok(Some(serde_json::from_value(delivery.clone())?)),
+        _ => Ok(None),
+    }
+}
+
+/// Runs this function again, asynchronously, with `event` marked [`DEFERRED`].
+pub async fn invoke_self(event: &Value) -> Result<(), Error> {
+    invoke(event, None).await
+}
+
+async fn invoke(event: &Value, delivery: Option<Value>) -> Result<(), Error> {
+    let function = std::env::var("AWS_LAMBDA_FUNCTION_NAME")?;
+    let mut event = event.clone();
+    match event.as_object_mut() {
+        Some(fields) => {
+            fields.insert(DEFERRED.to_string(), Value::Bool(true));
+            if let Some(delivery) = delivery {
+                fields.insert(DELIVERY.to_string(), delivery);
+            }
+        }
+        None => return Err(BotError::BadRequest("Deferred event is not an object".to_string()).into()),
+    };
+    LambdaClient::new(config::get().region.clone())
+        .invoke(InvocationRequest {
+            function_name: function,
+            invocation_type: Some("Event".to_string()),
+            payload: Some(serde_json::to_vec(&event)?.into()),
+            ..Default::default()
+        })
+        .await?;
+    Ok(())
+}
+
+/// Sent to `response_url` when a deferred run fails without a reply of its own.
+const FAILED: &str = "Sorry, something went wrong and that couldn't be finished. Please try again in a moment.";
+
+/// Ends a run: in a deferred one, posts `reply` to the command's `response_url`, or if the run
+/// failed, an apology, before passing the failure on. Outside one, `reply` is returned as is.
+pub async fn finish(event: &Value, reply: Result<SlackMessage, Error>) -> Result<SlackMessage, Error> {
+    if !is_deferred(event) {
+        return reply;
+    }
+    let response_url = SlashCommand::from_event(event).map(|req| req.response_url).unwrap_or_default();
+    match reply {
+        Ok(reply) => {
+            deliver(&response_url, reply.clone()).await?;
+            Ok(reply)
+        }
+        Err(e) => {
+            match SlackMessage::builder().ephemeral().text(FAILED).build() {
+                Ok(apology) => {
+                    if let Err(posted) = deliver(&response_url, apology).await {
+                        error!("Could not tell the user a deferred run failed: {}", posted);
+                    }
+                }
+                Err(built) => error!("Could not build the deferred failure message: {}", built),
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Posts the deferred run's reply where Slack expects it.
+pub async fn deliver(response_url: &str, reply: SlackMessage) -> Result<(), Error> {
+    let response_url = response_url.to_string();
+    tokio::task::spawn_blocking(move || slack::respond(&http::client(), &response_url, &reply)).await?
+}
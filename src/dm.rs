@@ -0,0 +1,197 @@
+//! Slash commands typed as messages, for users who can't run slash commands where they are. A DM
+//! to the bot (`message.im`) or an @-mention (`app_mention`) reading `tuser ninja` or
+//! `/tuser ninja` is turned into the request Slack would have sent for `/tuser ninja`, signed the
+//! same way, and handed to that command's function. It goes through the same parser and
+//! middleware as the slash command, and the reply is posted back where the message was.
+//!
+//! Commands that answer through `response_url` or open modals can't reply this way.
+
+use crate::config;
+use crate::secrets::{self, Secrets, SLACK_SIGNATURE, SLACK_TIMESTAMP};
+use crate::slack;
+use crate::stage;
+use crate::Error;
+use rusoto_lambda::{InvocationRequest, Lambda, LambdaClient};
+use serde_json::{json, Map, Value};
+use simple_error::bail;
+
+/// The commands that can be typed, by function name.
+pub const COMMANDS: &[&str] = &[
+    "tautomod",
+    "tban",
+    "tblock",
+    "tboard",
+    "tclip",
+    "tcompare",
+    "tfollow",
+    "tfollows",
+    "tgame",
+    "tlink",
+    "tquota",
+    "traid",
+    "treminders",
+    "treport",
+    "trewards",
+    "tstream",
+    "tstreamhealth",
+    "tstreamkey",
+    "ttest",
+    "ttitles",
+    "tunbans",
+    "tundo",
+    "tuser",
+    "twarn",
+];
+
+/// A message sent to the bot that may hold a command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub team_id: String,
+    pub user_id: String,
+    pub channel_id: String,
+    pub ts: String,
+    /// Set for @-mentions, which are answered in a thread; DMs are answered inline.
+    pub thread_ts: Option<String>,
+    pub text: String,
+}
+
+impl Message {
+    /// The message in an Events API `event`, if it's a DM or mention from a person. The bot's own
+    /// messages, edits and other subtypes are skipped.
+    pub fn from_event(team_id: &str, event: &Value) -> Option<Message> {
+        let field = |name: &str| event.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+        let is_mention = match event.get("type").and_then(Value::as_str) {
+            Some("app_mention") => true,
+            Some("message") if event.get("channel_type").and_then(Value::as_str) == Some("im") => false,
+            _ => return None,
+        };
+        if event.get("bot_id").is_some() || event.get("subtype").is_some() {
+            return None;
+        }
+
+        let ts = field("ts");
+        let thread_ts = if is_mention {
+            Some(event.get("thread_ts").and_then(Value::as_str).unwrap_or(&ts).to_string())
+        } else {
+            None
+        };
+        Some(Message {
+            team_id: team_id.to_string(),
+            user_id: field("user"),
+            channel_id: field("channel"),
+            ts,
+            thread_ts,
+            text: field("text"),
+        })
+    }
+}
+
+/// A command read out of a message: `/tuser` and the text after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedCommand {
+    pub command: String,
+    pub text: String,
+}
+
+impl TypedCommand {
+    /// `tuser ninja`, `/tuser ninja` or `<@U123> tuser ninja`; `None` when the first word isn't
+    /// one of [`COMMANDS`].
+    pub fn parse(text: &str) -> Option<TypedCommand> {
+        let mut text = text.trim();
+        if let Some(rest) = text.strip_prefix("<@") {
+            text = rest.splitn(2, '>').nth(1)?.trim_start();
+        }
+        let mut words = text.splitn(2, char::is_whitespace);
+        let name = words.next()?.trim_start_matches('/').to_ascii_lowercase();
+        if !COMMANDS.contains(&name.as_str()) {
+            return None;
+        }
+        Some(TypedCommand {
+            command: format!("/{}", name),
+            text: words.next().unwrap_or_default().trim().to_string(),
+        })
+    }
+
+    fn function(&self) -> &str {
+        self.command.trim_start_matches('/')
+    }
+
+    /// The API Gateway event Slack's slash command request would have arrived as, signed with
+    /// the signing secret when there is one (otherwise the verification token vouches for it).
+    pub fn slash_event(&self, message: &Message, secrets: &Secrets, now: i64) -> Result<Value, Error> {
+        let body = serde_urlencoded::to_string(&[
+            ("token", secrets.slack_token.as_str()),
+            ("command", self.command.as_str()),
+            ("text", self.text.as_str()),
+            ("team_id", message.team_id.as_str()),
+            ("user_id", message.user_id.as_str()),
+            ("channel_id", message.channel_id.as_str()),
+        ])?;
+
+        let mut headers = Map::new();
+        if !secrets.slack_signing_secret.is_empty() {
+            let timestamp = now.to_string();
+            let signature = secrets::sign_slack_request(&secrets.slack_signing_secret, &timestamp, &body)?;
+            headers.insert(SLACK_TIMESTAMP.to_string(), Value::String(timestamp));
+            headers.insert(SLACK_SIGNATURE.to_string(), Value::String(signature));
+        }
+        Ok(json!({ "body": body, "headers": headers }))
+    }
+}
+
+/// Runs the command's function and returns the reply it produced.
+pub async fn invoke(command: &TypedCommand, event: &Value) -> Result<Value, Error> {
+    let function = format!("{}{}", function_prefix(), command.function());
+    let resp = LambdaClient::new(config::get().region.clone())
+        .invoke(InvocationRequest {
+            function_name: function.clone(),
+            payload: Some(serde_json::to_vec(event)?.into()),
+            ..Default::default()
+        })
+        .await?;
+    if let Some(error) = resp.function_error {
+        bail!("{} failed: {}", function, error);
+    }
+    Ok(serde_json::from_slice(resp.payload.as_deref().unwrap_or(b"null"))?)
+}
+
+/// `COMMAND_FUNCTION_PREFIX`, or this function's deployed name without its own last part:
+/// `rust-lambda-dev-tevents` gives `rust-lambda-dev-`.
+fn function_prefix() -> String {
+    if let Ok(prefix) = std::env::var("COMMAND_FUNCTION_PREFIX") {
+        return prefix;
+    }
+    let own = std::env::var("AWS_LAMBDA_FUNCTION_NAME").unwrap_or_default();
+    match own.rfind('-') {
+        Some(i) => own[..=i].to_string(),
+        None => String::new(),
+    }
+}
+
+/// Posts a command's reply where the message was. In a channel, ephemeral replies are shown
+/// only to the sender, as they would be for the slash command.
+pub fn post_reply(
+    client: &reqwest::blocking::Client,
+    token: &str,
+    message: &Message,
+    reply: Value,
+) -> Result<(), Error> {
+    let mut body = match reply {
+        Value::Object(body) => body,
+        other => bail!("Command reply is not a message: {}", other),
+    };
+    let ephemeral = body.remove("response_type").and_then(|t| t.as_str().map(|t| t == "ephemeral"));
+    if let Some(thread_ts) = &message.thread_ts {
+        body.insert("thread_ts".to_string(), Value::String(thread_ts.clone()));
+    }
+
+    if message.thread_ts.is_some() && ephemeral == Some(true) {
+        body.insert("channel".to_string(), Value::String(stage::slack_channel(&message.channel_id)));
+        body.insert("user".to_string(), Value::String(message.user_id.clone()));
+        slack::call(client, token, "chat.postEphemeral", &Value::Object(body))?;
+    } else {
+        body.insert("channel".to_string(), Value::String(stage::slack_channel(&message.channel_id)));
+        slack::call(client, token, "chat.postMessage", &Value::Object(body))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! Noticing when Helix responses stop looking like the structs they're decoded into. Twitch adds
+//! fields without notice and occasionally drops or renames one; serde ignores the first and
+//! defaults the second, so either would otherwise go unseen until a card renders wrong.
+//!
+//! Tracked types keep whatever they have no field for in an `extra` map. Each decoded item is
+//! checked against the fields Twitch documents for the endpoint: a documented field that's
+//! absent is `missing`, an undocumented one is `unknown`. Both are logged once per field per
+//! process and counted in the `HelixSchemaDrift` metric on every response that shows them.
+
+use crate::metrics;
+use crate::twitch::LookupError;
+use log::{error, warn};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+
+/// A Helix item whose shape is watched for drift.
+pub trait Tracked {
+    /// The endpoint, as it appears in logs and in the metric's `Endpoint` dimension.
+    const ENDPOINT: &'static str;
+    /// The fields Twitch documents for each item, used or not.
+    const FIELDS: &'static [&'static str];
+
+    /// What the item had that the struct has no field for.
+    fn extra(&self) -> &HashMap<String, Value>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Change {
+    Missing,
+    Unknown,
+}
+
+impl Change {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Change::Missing => "missing",
+            Change::Unknown => "unknown",
+        }
+    }
+}
+
+/// How `item`, decoded from `raw`, differs from what Twitch documents for `T`, by field name.
+pub fn changes<T: Tracked>(raw: &Value, item: &T) -> Vec<(Change, String)> {
+    let mut changes = vec![];
+    if let Some(fields) = raw.as_object() {
+        for field in T::FIELDS.iter().filter(|field| !fields.contains_key(**field)) {
+            changes.push((Change::Missing, field.to_string()));
+        }
+    }
+    let mut unknown: Vec<&String> = item.extra().keys().filter(|key| !T::FIELDS.contains(&key.as_str())).collect();
+    unknown.sort();
+    changes.extend(unknown.into_iter().map(|key| (Change::Unknown, key.clone())));
+    changes
+}
+
+/// Decodes a Helix `data` list into `T`s, reporting any drift from the documented fields.
+pub fn decode<T: Tracked + DeserializeOwned>(items: Vec<Value>) -> Result<Vec<T>, LookupError> {
+    let mut decoded = Vec::with_capacity(items.len());
+    let mut drift = BTreeSet::new();
+    for raw in items {
+        let item: T = serde_json::from_value(raw.clone()).map_err(|e| {
+            error!("Could not decode a {} item from Helix: {}", T::ENDPOINT, e);
+            LookupError::Decode
+        })?;
+        drift.extend(changes(&raw, &item));
+        decoded.push(item);
+    }
+    for (change, field) in drift {
+        report(T::ENDPOINT, change, &field);
+    }
+    Ok(decoded)
+}
+
+fn report(endpoint: &str, change: Change, field: &str) {
+    static REPORTED: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    let key = format!("{}:{}:{}", endpoint, change.as_str(), field);
+    let first = REPORTED
+        .get_or_init(Default::default)
+        .lock()
+        .map(|mut reported| reported.insert(key))
+        .unwrap_or(true);
+    if first {
+        warn!("Helix {} response has {} field {:?}", endpoint, change.as_str(), field);
+    }
+    metrics::count(
+        "HelixSchemaDrift",
+        1,
+        &[("Endpoint", endpoint), ("Field", field), ("Change", change.as_str())],
+    );
+}
@@ -0,0 +1,84 @@
+//! Failures the bot can name. Functions still return the boxed [`Error`] so anything can be `?`d,
+//! but the ones worth telling apart are raised as a [`BotError`], and command handlers turn
+//! those into a reply ([`reply`]) instead of failing the invocation. What the user sees is
+//! [`BotError::user_message`]; the details go to the log.
+//!
+//! Errors that aren't a `BotError` (Slack or DynamoDB failing, say) still fail the invocation, so
+//! they show up in Lambda's error metrics.
+
+use crate::slack::SlackMessage;
+use crate::Error;
+use log::error;
+use thiserror::Error as ThisError;
+
+#[derive(ThisError, Debug)]
+pub enum BotError {
+    /// The request can't be served as sent. The message is written for whoever sent it.
+    #[error("bad request: {0}")]
+    BadRequest(String),
+    /// The request isn't from who it claims to be: a bad Slack token or signature.
+    #[error("authentication failed: {0}")]
+    AuthFailure(String),
+    /// The bot's own credentials couldn't be loaded.
+    #[error("secrets: {0}")]
+    SecretsError(String),
+    #[error("Twitch API returned {status}: {message}")]
+    TwitchApi { status: u16, message: String },
+    /// A request or response didn't have the expected shape.
+    #[error("could not decode {0}")]
+    Decode(String),
+}
+
+impl BotError {
+    /// A short name for logs and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BotError::BadRequest(_) => "bad_request",
+            BotError::AuthFailure(_) => "auth_failure",
+            BotError::SecretsError(_) => "secrets",
+            BotError::TwitchApi { .. } => "twitch_api",
+            BotError::Decode(_) => "decode",
+        }
+    }
+
+    pub fn user_message(&self) -> String {
+        match self {
+            BotError::BadRequest(message) => message.clone(),
+            BotError::AuthFailure(_) => "This request couldn't be verified as coming from Slack.".to_string(),
+            BotError::SecretsError(_) => {
+                "The bot couldn't load its credentials. Try again in a moment, and tell an admin if it keeps \
+                 happening."
+                    .to_string()
+            }
+            BotError::TwitchApi { status, .. } => {
+                format!("Twitch returned an error (HTTP {}). Try again later.", status)
+            }
+            BotError::Decode(_) => "The bot got something it couldn't read. Try again in a moment.".to_string(),
+        }
+    }
+
+    /// A Twitch endpoint outside Helix (auth, say) answering `status` to `request`.
+    pub fn twitch_api(status: reqwest::StatusCode, request: &str) -> BotError {
+        BotError::TwitchApi {
+            status: status.as_u16(),
+            message: format!("{} returned HTTP {}", request, status),
+        }
+    }
+
+    /// The `BotError` inside a boxed error, if that's what it is.
+    pub fn find(error: &Error) -> Option<&BotError> {
+        error.downcast_ref()
+    }
+}
+
+/// The ephemeral reply for a handler's failure, logging the details. Errors that aren't a
+/// [`BotError`] are passed on.
+pub fn reply(error: Error) -> Result<SlackMessage, Error> {
+    match BotError::find(&error) {
+        Some(bot_error) => {
+            error!("Command failed ({}): {}", bot_error.kind(), bot_error);
+            SlackMessage::builder().ephemeral().text(bot_error.user_message()).build()
+        }
+        None => Err(error),
+    }
+}
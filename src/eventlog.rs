@@ -0,0 +1,202 @@
+//! A record of every EventSub message the callback receives and what became of it, kept for
+//! `EVENTLOG_RETENTION_DAYS` (default 30). When an alert never showed up, the log tells "Twitch
+//! never sent it" (no entry) from "we dropped it" (an entry saying why).
+//!
+//! Entries are keyed by message id. A redelivery adds its outcome (usually `duplicate`) to the
+//! entry's `outcomes` rather than replacing the first attempt's, so a failure followed by a
+//! duplicate still shows the failure; `outcome` is the latest and `attempts` counts them.
+//!
+//! Only messages whose signature checked out are logged. Anyone can post to the callback, and
+//! logging what they send would let them fill the table or overwrite the entry for a real
+//! message id. Requests without a message id aren't from Twitch either.
+
+use crate::eventsub::{self, Delivery, MESSAGE_ID, MESSAGE_TIMESTAMP, MESSAGE_TYPE};
+use crate::stage::Stage;
+use crate::store::{self, Attr, Attrs, Condition, Store};
+use crate::Error;
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+use simple_error::bail;
+
+const DEFAULT_TABLE: &str = "tuser-eventsub-log";
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+/// How many times to re-read an entry another delivery of the same message changed underneath us.
+const RECORD_ATTEMPTS: usize = 3;
+
+/// What the callback did with a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The notification was posted or recorded.
+    Handled,
+    /// Handled, but there was nothing to post; the reason says why (free plan, no channel, ...).
+    Skipped(String),
+    /// Another delivery of the same message was handled first.
+    Duplicate,
+    /// The signature didn't check out, so the message was ignored.
+    Rejected,
+    /// The subscription isn't in the EventSub table any more.
+    UnknownSubscription,
+    /// Handling failed, so Twitch will redeliver it.
+    Failed(String),
+    /// A verification challenge was answered.
+    Verified,
+    /// Twitch revoked the subscription; the detail has its reason and what the bot did about it.
+    Revoked(String),
+    /// A message type or subscription the bot doesn't handle.
+    Ignored(String),
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Handled => "handled",
+            Outcome::Skipped(_) => "skipped",
+            Outcome::Duplicate => "duplicate",
+            Outcome::Rejected => "rejected",
+            Outcome::UnknownSubscription => "unknown_subscription",
+            Outcome::Failed(_) => "failed",
+            Outcome::Verified => "verified",
+            Outcome::Revoked(_) => "revoked",
+            Outcome::Ignored(_) => "ignored",
+        }
+    }
+
+    pub fn detail(&self) -> Option<&str> {
+        match self {
+            Outcome::Skipped(detail)
+            | Outcome::Failed(detail)
+            | Outcome::Revoked(detail)
+            | Outcome::Ignored(detail) => Some(detail),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoggedEvent {
+    pub message_id: String,
+    /// `notification`, `webhook_callback_verification` or `revocation`.
+    pub message_type: String,
+    pub subscription_type: String,
+    pub subscription_id: String,
+    pub broadcaster_id: String,
+    /// When Twitch says it sent the message.
+    pub sent_at: String,
+    pub received_at: String,
+    pub outcome: String,
+    pub detail: Option<String>,
+    /// Set once the signature checks out; unverified messages aren't recorded.
+    pub verified: bool,
+}
+
+impl LoggedEvent {
+    /// What the headers say about a delivery, before its body has been trusted or read.
+    pub fn received(event: &Value) -> LoggedEvent {
+        let header = |name: &str| eventsub::header(event, name).unwrap_or_default().to_string();
+        LoggedEvent {
+            message_id: header(MESSAGE_ID),
+            message_type: header(MESSAGE_TYPE),
+            subscription_type: String::new(),
+            subscription_id: String::new(),
+            broadcaster_id: String::new(),
+            sent_at: header(MESSAGE_TIMESTAMP),
+            received_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            outcome: String::new(),
+            detail: None,
+            verified: false,
+        }
+    }
+
+    /// Fills in the subscription and broadcaster from the verified body.
+    pub fn delivery(&mut self, delivery: &Delivery) {
+        let subscription = &delivery.subscription;
+        self.subscription_type = subscription.subscription_type.clone();
+        self.subscription_id = subscription.id.clone();
+        self.broadcaster_id = delivery
+            .event
+            .as_ref()
+            .and_then(|event| event.get("broadcaster_user_id"))
+            .or_else(|| subscription.condition.get("broadcaster_user_id"))
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+    }
+
+    pub fn outcome(&mut self, outcome: &Outcome) {
+        self.outcome = outcome.name().to_string();
+        self.detail = outcome.detail().map(str::to_string);
+    }
+}
+
+pub struct EventLog {
+    store: Box<dyn Store>,
+    retention_days: i64,
+}
+
+impl EventLog {
+    pub fn new(store: Box<dyn Store>) -> EventLog {
+        EventLog {
+            store,
+            retention_days: DEFAULT_RETENTION_DAYS,
+        }
+    }
+
+    pub fn from_env() -> EventLog {
+        let table = std::env::var("EVENTLOG_TABLE").unwrap_or_else(|_| Stage::current().resource(DEFAULT_TABLE));
+        let mut log = EventLog::new(store::open(table, "message_id"));
+        if let Some(days) = std::env::var("EVENTLOG_RETENTION_DAYS").ok().and_then(|days| days.parse().ok()) {
+            log.retention_days = days;
+        }
+        log
+    }
+
+    /// Adds the delivery's outcome to the message's entry, after those of earlier deliveries.
+    /// Unverified messages are skipped.
+    pub async fn record(&self, event: &LoggedEvent) -> Result<(), Error> {
+        if event.message_id.is_empty() || !event.verified {
+            return Ok(());
+        }
+        let this_attempt = json!({
+            "received_at": event.received_at,
+            "outcome": event.outcome,
+            "detail": event.detail,
+        });
+
+        for _ in 0..RECORD_ATTEMPTS {
+            let previous = self.store.get(&event.message_id).await?;
+            let attempts = previous.as_ref().and_then(|previous| previous.get("attempts")).and_then(Attr::as_number);
+            let mut outcomes: Vec<Value> = match previous.and_then(|mut previous| previous.remove("outcomes")) {
+                Some(Attr::S(outcomes)) => serde_json::from_str(&outcomes)?,
+                _ => vec![],
+            };
+            outcomes.push(this_attempt.clone());
+
+            let string = |value: &str| Attr::S(value.to_string());
+            let mut attrs = Attrs::new();
+            attrs.insert("message_type".to_string(), string(&event.message_type));
+            attrs.insert("subscription_type".to_string(), string(&event.subscription_type));
+            attrs.insert("subscription_id".to_string(), string(&event.subscription_id));
+            attrs.insert("broadcaster_id".to_string(), string(&event.broadcaster_id));
+            attrs.insert("sent_at".to_string(), string(&event.sent_at));
+            let first_received = outcomes[0]["received_at"].as_str().unwrap_or(&event.received_at).to_string();
+            attrs.insert("received_at".to_string(), Attr::S(first_received));
+            attrs.insert("outcome".to_string(), string(&event.outcome));
+            if let Some(detail) = &event.detail {
+                attrs.insert("detail".to_string(), string(detail));
+            }
+            attrs.insert("outcomes".to_string(), Attr::S(Value::Array(outcomes).to_string()));
+            attrs.insert("attempts".to_string(), Attr::N(attempts.unwrap_or(0) + 1));
+            let expires_at = Utc::now().timestamp() + self.retention_days * 24 * 60 * 60;
+            attrs.insert("expires_at".to_string(), Attr::N(expires_at));
+
+            let condition = match attempts {
+                Some(attempts) => Condition::Equals("attempts", Attr::N(attempts)),
+                None => Condition::Absent,
+            };
+            if self.store.put_if(&event.message_id, attrs, condition).await? {
+                return Ok(());
+            }
+        }
+        bail!("Gave up logging EventSub message {} after {} attempts", event.message_id, RECORD_ATTEMPTS)
+    }
+}
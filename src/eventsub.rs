@@ -0,0 +1,446 @@
+//! Twitch EventSub over webhooks: creating subscriptions, verifying the callbacks Twitch sends,
+//! and remembering which Slack workspace each subscription belongs to.
+//!
+//! When Twitch revokes a subscription for a reason that may pass, `teventsub` creates it again,
+//! or keeps it as a [`RevokedSubscription`] until the user grants the app access again.
+//!
+//! Twitch caps what the app's subscriptions may cost in total (see [`SubscriptionCost`]); the
+//! `budget` module keeps the subscribing commands under it.
+//!
+//! Every subscription shares the `twitch_eventsub_secret` from Secrets Manager, which Twitch
+//! uses to sign each delivery. `trotate` replaces it without an alerting gap: it stages the new
+//! secret as `twitch_eventsub_next_secret` (which deliveries may be signed with too), recreates
+//! every subscription with it, and only deletes the old subscriptions once the new ones are
+//! enabled.
+
+pub use crate::http::header;
+use crate::secrets::Secrets;
+use crate::stage::Stage;
+use crate::store::{self, Attr, Attrs, Store};
+use crate::twitch::{self, helix_base, helix_call, Credentials, HelixList, LookupError, TimeoutConfig};
+use crate::Error;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use reqwest::Method;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+const DEFAULT_TABLE: &str = "tuser-eventsub";
+/// Where [`SubscriptionStore::budget_warning`] is kept; index keys start with a team ID instead.
+const BUDGET_WARNING_KEY: &str = "budget:warned";
+
+pub const MESSAGE_ID: &str = "Twitch-Eventsub-Message-Id";
+pub const MESSAGE_TIMESTAMP: &str = "Twitch-Eventsub-Message-Timestamp";
+pub const MESSAGE_SIGNATURE: &str = "Twitch-Eventsub-Message-Signature";
+pub const MESSAGE_TYPE: &str = "Twitch-Eventsub-Message-Type";
+
+pub const STREAM_ONLINE: &str = "stream.online";
+/// Only subscribed for `/tfollow`, whose channels hear when a stream ends too.
+pub const STREAM_OFFLINE: &str = "stream.offline";
+/// Title and category changes, recorded for `/ttitles`.
+pub const CHANNEL_UPDATE: &str = "channel.update";
+
+/// Revocation statuses that say nothing about whether the subscription is still wanted.
+/// `notification_failures_exceeded` means the callback stopped answering for a while (it's
+/// answering now, or the revocation wouldn't have arrived); `authorization_revoked` means the
+/// user took back the app's access, which they can grant again with `/tlink`. Twitch's others
+/// (`user_removed`, `moderator_removed`, `version_removed`) are for good.
+pub const TRANSIENT_REVOCATIONS: &[&str] = &["notification_failures_exceeded", "authorization_revoked"];
+
+/// Deliveries older than this are rejected as possible replays, as Twitch recommends.
+const MAX_MESSAGE_AGE_SECS: i64 = 10 * 60;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Subscription {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub subscription_type: String,
+    pub status: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub condition: Value,
+}
+
+/// The body of an EventSub delivery: a `challenge` during verification, an `event` for
+/// notifications, and just the subscription for revocations.
+#[derive(Deserialize, Debug)]
+pub struct Delivery {
+    pub subscription: Subscription,
+    pub challenge: Option<String>,
+    pub event: Option<Value>,
+}
+
+/// [`verify`] against the webhook secret, or during a rotation the one being rotated to.
+pub fn verify_delivery(secrets: &Secrets, event: &Value, body: &str) -> bool {
+    verify(&secrets.twitch_eventsub_secret, event, body)
+        || (!secrets.twitch_eventsub_next_secret.is_empty()
+            && verify(&secrets.twitch_eventsub_next_secret, event, body))
+}
+
+/// Checks a delivery's `sha256=` signature over message id, timestamp and raw body, and that
+/// the timestamp is recent.
+pub fn verify(secret: &str, event: &Value, body: &str) -> bool {
+    let (id, timestamp, signature) = match (
+        header(event, MESSAGE_ID),
+        header(event, MESSAGE_TIMESTAMP),
+        header(event, MESSAGE_SIGNATURE),
+    ) {
+        (Some(id), Some(timestamp), Some(signature)) => (id, timestamp, signature),
+        _ => return false,
+    };
+
+    let fresh = DateTime::parse_from_rfc3339(timestamp)
+        .map(|sent| (Utc::now() - sent.with_timezone(&Utc)).num_seconds().abs() <= MAX_MESSAGE_AGE_SECS)
+        .unwrap_or(false);
+    let expected = match signature.strip_prefix("sha256=").and_then(|hex| hex::decode(hex).ok()) {
+        Some(expected) => expected,
+        None => return false,
+    };
+
+    let mut mac = match Hmac::<Sha256>::new_varkey(secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(id.as_bytes());
+    mac.update(timestamp.as_bytes());
+    mac.update(body.as_bytes());
+    fresh && mac.verify(&expected).is_ok()
+}
+
+/// The `Twitch-Eventsub-Message-Signature` Twitch would send, for replaying deliveries.
+pub fn sign(secret: &str, id: &str, timestamp: &str, body: &str) -> Option<String> {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).ok()?;
+    mac.update(id.as_bytes());
+    mac.update(timestamp.as_bytes());
+    mac.update(body.as_bytes());
+    Some(format!("sha256={}", hex::encode(mac.finalize().into_bytes())))
+}
+
+/// Creates a webhook subscription with the app token. Twitch checks the condition against the
+/// scopes users have granted the app, so the relevant user must have linked first.
+pub fn subscribe(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    subscription_type: &str,
+    version: &str,
+    condition: Value,
+    callback: &str,
+) -> Result<Subscription, LookupError> {
+    let secret = &secrets.twitch_eventsub_secret;
+    subscribe_with_secret(client, secrets, secret, subscription_type, version, condition, callback)
+}
+
+/// [`subscribe`] with a webhook `secret` other than the current one, for rotating it.
+pub fn subscribe_with_secret(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    secret: &str,
+    subscription_type: &str,
+    version: &str,
+    condition: Value,
+    callback: &str,
+) -> Result<Subscription, LookupError> {
+    let url = format!("{}/eventsub/subscriptions", helix_base());
+    let body = json!({
+        "type": subscription_type,
+        "version": version,
+        "condition": condition,
+        "transport": {
+            "method": "webhook",
+            "callback": callback,
+            "secret": secret,
+        },
+    });
+    let credentials = Credentials::app(secrets);
+    let mut created = helix_call::<HelixList<Subscription>>(client, Method::POST, &url, credentials, Some(&body))?;
+    created.data.pop().ok_or(LookupError::Decode)
+}
+
+pub fn unsubscribe(client: &reqwest::blocking::Client, secrets: &Secrets, id: &str) -> Result<(), LookupError> {
+    let url = format!("{}/eventsub/subscriptions?id={}", helix_base(), id);
+    match helix_call::<Value>(client, Method::DELETE, &url, Credentials::app(secrets), None) {
+        // Already gone (revoked, or deleted from elsewhere).
+        Ok(_) | Err(LookupError::Rejected(404)) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Every subscription the app has, in any status.
+pub fn list_subscriptions(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+) -> Result<Vec<Subscription>, LookupError> {
+    let url = format!("{}/eventsub/subscriptions?first=100", helix_base());
+    twitch::get_all_pages(client, Credentials::app(secrets), &url, usize::MAX)
+}
+
+/// A subscription Twitch revoked for a reason that may pass (see [`TRANSIENT_REVOCATIONS`]),
+/// kept with what it takes to create it again.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RevokedSubscription {
+    pub record: SubscriptionRecord,
+    pub version: String,
+    pub condition: Value,
+    /// Twitch's reason, e.g. `authorization_revoked`.
+    pub status: String,
+}
+
+impl RevokedSubscription {
+    pub fn new(record: SubscriptionRecord, subscription: &Subscription) -> RevokedSubscription {
+        RevokedSubscription {
+            record,
+            version: subscription.version.clone(),
+            condition: subscription.condition.clone(),
+            status: subscription.status.clone(),
+        }
+    }
+
+    /// Whether the Twitch user is one the condition names, as broadcaster or moderator.
+    pub fn concerns(&self, twitch_user_id: &str) -> bool {
+        self.condition
+            .as_object()
+            .map_or(false, |condition| condition.values().any(|value| value.as_str() == Some(twitch_user_id)))
+    }
+
+    /// Creates the subscription again, with the current webhook secret.
+    pub fn resubscribe(
+        &self,
+        client: &reqwest::blocking::Client,
+        secrets: &Secrets,
+        callback: &str,
+    ) -> Result<Subscription, LookupError> {
+        let record = &self.record;
+        let version = if self.version.is_empty() { "1" } else { self.version.as_str() };
+        subscribe(client, secrets, &record.subscription_type, version, self.condition.clone(), callback)
+    }
+}
+
+/// How much of the app's EventSub budget is spent. A subscription costs 1 unless the user its
+/// condition names has authorized the app, and Twitch refuses new ones once `total_cost` would
+/// pass `max_total_cost`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SubscriptionCost {
+    /// How many subscriptions the app has, whatever they cost.
+    pub total: u64,
+    pub total_cost: u64,
+    pub max_total_cost: u64,
+}
+
+impl SubscriptionCost {
+    pub fn remaining(&self) -> u64 {
+        self.max_total_cost.saturating_sub(self.total_cost)
+    }
+
+    /// Whether subscriptions costing `cost` more still fit.
+    pub fn allows(&self, cost: u64) -> bool {
+        cost <= self.remaining()
+    }
+
+    /// `total_cost` as a whole percentage of the limit, rounded down.
+    pub fn percent_used(&self) -> u64 {
+        if self.max_total_cost == 0 {
+            return 100;
+        }
+        self.total_cost * 100 / self.max_total_cost
+    }
+}
+
+/// The app's current [`SubscriptionCost`], which Helix reports beside the subscription list.
+pub fn subscription_cost(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+) -> Result<SubscriptionCost, LookupError> {
+    let url = format!("{}/eventsub/subscriptions?first=1", helix_base());
+    helix_call(client, Method::GET, &url, Credentials::app(secrets), None)
+}
+
+/// Who set up a subscription, so its notifications can be routed back to their workspace.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubscriptionRecord {
+    pub subscription_id: String,
+    pub subscription_type: String,
+    pub team_id: String,
+    pub slack_user_id: String,
+    /// Login of the channel the subscription watches.
+    pub channel: String,
+    /// Twitch user ID of that channel, which unlike the login survives a rename. Empty in records
+    /// saved before it was kept.
+    #[serde(default)]
+    pub broadcaster_id: String,
+    /// The Slack channel `/tfollow` posts to; `None` for subscriptions whose alerts go to the
+    /// workspace's alert channel.
+    #[serde(default)]
+    pub slack_channel: Option<String>,
+    /// Set when `/tfollow` took over a subscription the alert channel was already using: both get
+    /// its alerts, and `/tfollow stop` hands it back instead of deleting it.
+    #[serde(default)]
+    pub shared: bool,
+}
+
+/// Subscriptions by id, plus an index from `(team, type, channel)` to the subscription id.
+pub struct SubscriptionStore {
+    store: Box<dyn Store>,
+}
+
+impl SubscriptionStore {
+    pub fn new(store: Box<dyn Store>) -> SubscriptionStore {
+        SubscriptionStore { store }
+    }
+
+    pub fn from_env() -> SubscriptionStore {
+        let table = std::env::var("EVENTSUB_TABLE").unwrap_or_else(|_| Stage::current().resource(DEFAULT_TABLE));
+        SubscriptionStore::new(store::open(table, "subscription_key"))
+    }
+
+    pub async fn get(&self, subscription_id: &str) -> Result<Option<SubscriptionRecord>, Error> {
+        match self.get_attribute(&id_key(subscription_id), "record").await? {
+            Some(record) => Ok(Some(serde_json::from_str(&record)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn find(
+        &self,
+        team_id: &str,
+        subscription_type: &str,
+        channel: &str,
+    ) -> Result<Option<SubscriptionRecord>, Error> {
+        match self.get_attribute(&index_key(team_id, subscription_type, channel), "subscription_id").await? {
+            Some(id) => self.get(&id).await,
+            None => Ok(None),
+        }
+    }
+
+    pub async fn save(&self, record: &SubscriptionRecord) -> Result<(), Error> {
+        let mut item = Attrs::new();
+        item.insert("record".to_string(), Attr::S(serde_json::to_string(record)?));
+        self.store.put(&id_key(&record.subscription_id), item).await?;
+
+        let mut index = Attrs::new();
+        index.insert("subscription_id".to_string(), Attr::S(record.subscription_id.clone()));
+        let index_key = index_key(&record.team_id, &record.subscription_type, &record.channel);
+        self.store.put(&index_key, index).await
+    }
+
+    /// Every stored subscription of one type, across workspaces.
+    pub async fn all_of_type(&self, subscription_type: &str) -> Result<Vec<SubscriptionRecord>, Error> {
+        self.scan_records(Some(subscription_type)).await
+    }
+
+    async fn scan_records(&self, subscription_type: Option<&str>) -> Result<Vec<SubscriptionRecord>, Error> {
+        let mut records = vec![];
+        // Index items have no record attribute, so only the `sub:` items come through here.
+        for (_, mut item) in self.store.scan(&["record"]).await? {
+            if let Some(record) = item.remove("record").and_then(Attr::into_string) {
+                let record: SubscriptionRecord = serde_json::from_str(&record)?;
+                if subscription_type.map_or(true, |wanted| record.subscription_type == wanted) {
+                    records.push(record);
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    /// Every stored subscription, across workspaces and types.
+    pub async fn all(&self) -> Result<Vec<SubscriptionRecord>, Error> {
+        self.scan_records(None).await
+    }
+
+    /// Drops the record of a subscription that has been replaced, leaving the index to its
+    /// replacement.
+    pub async fn forget(&self, subscription_id: &str) -> Result<(), Error> {
+        self.store.delete(&id_key(subscription_id)).await
+    }
+
+    pub async fn delete(&self, record: &SubscriptionRecord) -> Result<(), Error> {
+        self.store.delete(&index_key(&record.team_id, &record.subscription_type, &record.channel)).await?;
+        self.store.delete(&id_key(&record.subscription_id)).await
+    }
+
+    /// Keeps a revoked subscription to create again later, replacing its record.
+    pub async fn save_revoked(&self, revoked: &RevokedSubscription) -> Result<(), Error> {
+        self.delete(&revoked.record).await?;
+        let mut item = Attrs::new();
+        item.insert("revoked".to_string(), Attr::S(serde_json::to_string(revoked)?));
+        self.store.put(&revoked_key(&revoked.record.subscription_id), item).await
+    }
+
+    /// Creates a revoked subscription again and moves its record to the new one. `Ok(Err(_))` is
+    /// Twitch refusing, e.g. while the user's authorization is still revoked.
+    pub async fn restore(
+        &self,
+        revoked: &RevokedSubscription,
+        secrets: &Secrets,
+    ) -> Result<Result<SubscriptionRecord, LookupError>, Error> {
+        let callback = std::env::var("EVENTSUB_CALLBACK_URL").map_err(|_| "EVENTSUB_CALLBACK_URL is not set")?;
+        let (blocking_revoked, secrets) = (revoked.clone(), secrets.clone());
+        let created = tokio::task::spawn_blocking(move || {
+            let client = twitch::client(&TimeoutConfig::from_env())?;
+            blocking_revoked.resubscribe(&client, &secrets, &callback)
+        })
+        .await?;
+        let subscription = match created {
+            Ok(subscription) => subscription,
+            Err(e) => return Ok(Err(e)),
+        };
+
+        let record = SubscriptionRecord {
+            subscription_id: subscription.id,
+            ..revoked.record.clone()
+        };
+        self.save(&record).await?;
+        self.forget(&revoked.record.subscription_id).await?;
+        self.forget_revoked(&revoked.record.subscription_id).await?;
+        Ok(Ok(record))
+    }
+
+    /// Every revoked subscription waiting to be created again.
+    pub async fn all_revoked(&self) -> Result<Vec<RevokedSubscription>, Error> {
+        let mut revoked = vec![];
+        for (_, mut item) in self.store.scan(&["revoked"]).await? {
+            if let Some(value) = item.remove("revoked").and_then(Attr::into_string) {
+                revoked.push(serde_json::from_str(&value)?);
+            }
+        }
+        Ok(revoked)
+    }
+
+    /// Drops a revoked subscription that has been created again or given up on.
+    pub async fn forget_revoked(&self, subscription_id: &str) -> Result<(), Error> {
+        self.store.delete(&revoked_key(subscription_id)).await
+    }
+
+    /// The percentage of the cost budget the operator was last warned at, if it's still that full.
+    pub async fn budget_warning(&self) -> Result<Option<u64>, Error> {
+        Ok(self.get_attribute(BUDGET_WARNING_KEY, "percent").await?.and_then(|percent| percent.parse().ok()))
+    }
+
+    pub async fn set_budget_warning(&self, percent: Option<u64>) -> Result<(), Error> {
+        match percent {
+            Some(percent) => {
+                let mut item = Attrs::new();
+                item.insert("percent".to_string(), Attr::S(percent.to_string()));
+                self.store.put(BUDGET_WARNING_KEY, item).await
+            }
+            None => self.store.delete(BUDGET_WARNING_KEY).await,
+        }
+    }
+
+    async fn get_attribute(&self, subscription_key: &str, name: &str) -> Result<Option<String>, Error> {
+        Ok(self.store.get(subscription_key).await?.and_then(|mut item| item.remove(name)).and_then(Attr::into_string))
+    }
+}
+
+fn id_key(subscription_id: &str) -> String {
+    format!("sub:{}", subscription_id)
+}
+
+fn revoked_key(subscription_id: &str) -> String {
+    format!("revoked:{}", subscription_id)
+}
+
+fn index_key(team_id: &str, subscription_type: &str, channel: &str) -> String {
+    format!("{}:{}:{}", team_id, subscription_type, channel)
+}
@@ -0,0 +1,158 @@
+//! `/tfollows me [query]`: the channels a linked user follows, filtered by an optional query and
+//! shown a page at a time. The Previous/Next buttons carry the query and page to the
+//! interactivity endpoint, which re-fetches and replaces the message.
+//!
+//! `/tfollows live` summarises which followed channels are live, grouped by game.
+
+use crate::links::AccountLink;
+use crate::secrets::Secrets;
+use crate::slack::{Block, Element, SlackMessage, Text};
+use crate::twitch::{self, Credentials, FollowedChannel, LookupError, TwitchStream};
+use crate::Error;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const SCOPE: &str = "user:read:follows";
+pub const PAGE_ACTION: &str = "follows_page";
+pub const PAGE_SIZE: usize = 20;
+/// Follows read per lookup; filtering happens on the bot's side, so this bounds the search.
+pub const MAX_FOLLOWS: usize = 2000;
+/// Live followed streams read for the summary.
+pub const MAX_LIVE: usize = 500;
+/// Channels named per game in the summary before the rest are just counted.
+const NAMES_PER_GAME: usize = 3;
+
+/// Which page of which query a pagination button shows.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FollowsPage {
+    pub query: String,
+    pub page: usize,
+}
+
+/// The user's follows whose login or display name contains the query (case-insensitively).
+pub fn fetch(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    link: &AccountLink,
+    query: &str,
+) -> Result<Vec<FollowedChannel>, LookupError> {
+    let credentials = Credentials::user(secrets, link);
+    let follows = twitch::get_followed_channels(client, credentials, &link.twitch_user_id, MAX_FOLLOWS)?;
+    let query = query.to_lowercase();
+
+    Ok(follows
+        .into_iter()
+        .filter(|f| {
+            query.is_empty()
+                || f.broadcaster_login.contains(&query)
+                || f.broadcaster_name.to_lowercase().contains(&query)
+        })
+        .collect())
+}
+
+pub fn render(login: &str, follows: &[FollowedChannel], current: &FollowsPage) -> Result<SlackMessage, Error> {
+    let filter = if current.query.is_empty() {
+        String::new()
+    } else {
+        format!(" matching \"{}\"", current.query)
+    };
+    if follows.is_empty() {
+        return SlackMessage::builder()
+            .text(format!("{} doesn't follow any channels{}.", login, filter))
+            .build();
+    }
+
+    let pages = (follows.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+    let page = current.page.min(pages - 1);
+    let lines: Vec<String> = follows
+        .iter()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|f| {
+            let since = f.followed_at.split('T').next().unwrap_or_default();
+            format!("• <https://twitch.tv/{}|{}> — since {}", f.broadcaster_login, f.broadcaster_name, since)
+        })
+        .collect();
+
+    let summary = format!("{} follows {} channels{}", login, follows.len(), filter);
+    let mut message = SlackMessage::builder().ephemeral().text(summary.clone()).block(Block::Section {
+        text: Text::Mrkdwn {
+            text: format!("*{}*\n{}", summary, lines.join("\n")),
+        },
+        accessory: None,
+    });
+
+    if pages > 1 {
+        let mut buttons = vec![];
+        if page > 0 {
+            buttons.push(page_button("Previous", &current.query, page - 1)?);
+        }
+        if page + 1 < pages {
+            buttons.push(page_button("Next", &current.query, page + 1)?);
+        }
+        message = message
+            .block(Block::Context {
+                elements: vec![Text::Mrkdwn {
+                    text: format!("Page {} of {}", page + 1, pages),
+                }],
+            })
+            .block(Block::Actions { elements: buttons });
+    }
+
+    message.build()
+}
+
+fn page_button(label: &str, query: &str, page: usize) -> Result<Element, Error> {
+    let target = FollowsPage {
+        query: query.to_string(),
+        page,
+    };
+    Ok(Element::Button {
+        // Action ids must be unique within a block.
+        action_id: format!("{}_{}", PAGE_ACTION, label.to_ascii_lowercase()),
+        text: Text::PlainText { text: label.to_string() },
+        value: Some(serde_json::to_string(&target)?),
+        url: None,
+        style: None,
+        confirm: None,
+    })
+}
+
+pub fn fetch_live(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    link: &AccountLink,
+) -> Result<Vec<TwitchStream>, LookupError> {
+    twitch::get_followed_streams(client, Credentials::user(secrets, link), &link.twitch_user_id, MAX_LIVE)
+}
+
+/// "3 followed channels live in Factorio: a, b, c", one line per game, busiest game first.
+pub fn live_summary(login: &str, streams: &[TwitchStream]) -> String {
+    if streams.is_empty() {
+        return format!("None of the channels {} follows are live right now.", login);
+    }
+
+    let mut by_game: BTreeMap<&str, Vec<&TwitchStream>> = BTreeMap::new();
+    for stream in streams {
+        let game = if stream.game_name.is_empty() { "no category" } else { stream.game_name.as_str() };
+        by_game.entry(game).or_default().push(stream);
+    }
+    let mut games: Vec<(&str, Vec<&TwitchStream>)> = by_game.into_iter().collect();
+    // Stable, so games with the same count stay alphabetical.
+    games.sort_by(|a, b| b.1.len().cmp(&a.1.len()));
+
+    let mut lines = vec![format!("*{} followed channels live*", streams.len())];
+    for (game, streams) in games {
+        let mut names: Vec<String> = streams
+            .iter()
+            .take(NAMES_PER_GAME)
+            .map(|s| format!("<https://twitch.tv/{}|{}>", s.user_login, s.user_name))
+            .collect();
+        if streams.len() > NAMES_PER_GAME {
+            names.push(format!("{} more", streams.len() - NAMES_PER_GAME));
+        }
+        let noun = if streams.len() == 1 { "channel" } else { "channels" };
+        lines.push(format!("• {} followed {} live in {}: {}", streams.len(), noun, game, names.join(", ")));
+    }
+    lines.join("\n")
+}
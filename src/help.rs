@@ -0,0 +1,82 @@
+//! Help replies for commands that parse their own text (those with a
+//! [`CommandSchema`](crate::args::CommandSchema) get theirs from the router). They're ephemeral,
+//! since only the person asking needs them, and laid out in Block Kit: what the command does, its
+//! forms, the options that go with any of them, and examples; the plain text is the
+//! notification fallback.
+
+use crate::slack::blocks::Blocks;
+use crate::slack::SlackMessage;
+use crate::Error;
+
+/// What a command's help says about it.
+pub struct Help {
+    /// The slash command, e.g. `/tuser`.
+    pub command: &'static str,
+    pub summary: &'static str,
+    pub forms: &'static [Form],
+    /// Flags and keywords that can go with any form, with what they do.
+    pub options: &'static [(&'static str, &'static str)],
+    /// Whole command texts, without the command.
+    pub examples: &'static [&'static str],
+}
+
+/// One way to call a command.
+pub struct Form {
+    /// The text after the command, e.g. `search: <words>`.
+    pub syntax: &'static str,
+    pub summary: &'static str,
+}
+
+/// Whether `text` asks for help rather than naming something: `help`, `--help`, `-h` or `?`.
+pub fn is_request(text: &str) -> bool {
+    matches!(text.trim().to_ascii_lowercase().as_str(), "help" | "--help" | "-h" | "?")
+}
+
+impl Help {
+    pub fn message(&self) -> Result<SlackMessage, Error> {
+        SlackMessage::builder().ephemeral().text(self.text()).blocks(self.blocks().into_blocks()).build()
+    }
+
+    /// `problem` with the input, followed by the help, for text that didn't parse.
+    pub fn error_message(&self, problem: &str) -> Result<SlackMessage, Error> {
+        let blocks = Blocks::new().section(format!(":warning: {}", problem)).divider().extend(self.blocks());
+        SlackMessage::builder()
+            .ephemeral()
+            .text(format!("{}\n{}", problem, self.text()))
+            .blocks(blocks.into_blocks())
+            .build()
+    }
+
+    pub fn text(&self) -> String {
+        let mut lines = vec![format!("{}:", self.summary)];
+        lines.extend(self.form_lines());
+        if !self.examples.is_empty() {
+            lines.push(format!("Example: `{} {}`", self.command, self.examples[0]));
+        }
+        lines.join("\n")
+    }
+
+    pub fn blocks(&self) -> Blocks {
+        let mut blocks = Blocks::new()
+            .section(format!("*{}*: {}", self.command, self.summary))
+            .section(self.form_lines().join("\n"));
+        if !self.options.is_empty() {
+            let options: Vec<String> =
+                self.options.iter().map(|(option, summary)| format!("• `{}`: {}", option, summary)).collect();
+            blocks = blocks.section(format!("*Options*\n{}", options.join("\n")));
+        }
+        if !self.examples.is_empty() {
+            let examples: Vec<String> =
+                self.examples.iter().map(|example| format!("`{} {}`", self.command, example)).collect();
+            blocks = blocks.context(vec![format!("Examples: {}", examples.join(" · "))]);
+        }
+        blocks
+    }
+
+    fn form_lines(&self) -> Vec<String> {
+        self.forms
+            .iter()
+            .map(|form| format!("• `{} {}`: {}", self.command, form.syntax, form.summary))
+            .collect()
+    }
+}
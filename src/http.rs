@@ -0,0 +1,71 @@
+use crate::error::BotError;
+use crate::Error;
+use serde_json::{json, Value};
+
+/// Sent on every Twitch and Slack request, and included in logged errors, so either side can tell
+/// which bot and version a request came from.
+pub const USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/camr/twitch-info-bot)"
+);
+
+/// A blocking client that identifies itself with [`USER_AGENT`].
+pub fn client() -> reqwest::blocking::Client {
+    reqwest::blocking::Client::builder()
+        .user_agent(USER_AGENT)
+        .build()
+        // Only fails when the TLS backend can't start, in which case the default client can't either.
+        .unwrap_or_else(|_| reqwest::blocking::Client::new())
+}
+
+/// An API Gateway event's body as the client sent it. API Gateway base64-encodes bodies it
+/// doesn't treat as text (often including Slack's form posts) and says so in `isBase64Encoded`.
+pub fn body(event: &Value) -> Result<String, Error> {
+    let body = match event.get("body").and_then(Value::as_str) {
+        Some(body) => body,
+        None => return Err(BotError::BadRequest("The request had no body.".to_string()).into()),
+    };
+    if event.get("isBase64Encoded").and_then(Value::as_bool).unwrap_or(false) {
+        return Ok(String::from_utf8(base64::decode(body)?)?);
+    }
+    Ok(body.to_string())
+}
+
+/// Looks up an API Gateway event header regardless of how the client cased it.
+pub fn header<'a>(event: &'a Value, name: &str) -> Option<&'a str> {
+    event
+        .get("headers")?
+        .as_object()?
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .and_then(|(_, value)| value.as_str())
+}
+
+/// A minimal HTML response for browser-facing redirects (OAuth callbacks).
+pub fn html_page(status: u16, message: &str) -> Value {
+    json!({
+        "statusCode": status,
+        "headers": { "Content-Type": "text/html; charset=utf-8" },
+        "body": format!("<!doctype html><title>Twitch info bot</title><p>{}</p>", message),
+    })
+}
+
+/// Sends the browser on to `location`, e.g. an OAuth authorize page.
+pub fn redirect(location: &str) -> Value {
+    json!({
+        "statusCode": 302,
+        "headers": { "Location": location },
+        "body": "",
+    })
+}
+
+/// A plain-text response, e.g. for echoing webhook verification challenges.
+pub fn text_response(status: u16, body: &str) -> Value {
+    json!({
+        "statusCode": status,
+        "headers": { "Content-Type": "text/plain" },
+        "body": body,
+    })
+}
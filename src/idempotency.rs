@@ -0,0 +1,90 @@
+//! Idempotency keys for side-effecting Slack actions.
+//!
+//! Slack retries commands and interactions it thinks timed out, reusing the original
+//! `trigger_id`. Anything that changes state on Twitch (clips, announcements, EventSub
+//! subscriptions) claims a key for its trigger before running, so a retry finds the claim and
+//! replays the stored response instead of repeating the side effect. An action that fails
+//! `release`s its claim, so a retry runs it again rather than finding a claim with no response.
+
+use crate::stage::Stage;
+use crate::store::{self, Attr, Attrs, Condition, Store};
+use crate::Error;
+use log::error;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_TABLE: &str = "tuser-idempotency";
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A key this invocation claimed, holding the token that tells its claim from a later one.
+#[derive(Debug, Clone)]
+pub struct ClaimedKey {
+    pub key: String,
+    token: String,
+}
+
+pub enum Claim {
+    /// First time this trigger has been seen; run the action and `complete` the key, or
+    /// `release` it if the action failed.
+    New(ClaimedKey),
+    /// A previous invocation already claimed the trigger. Holds its response once it finished.
+    Duplicate(Option<String>),
+}
+
+pub struct IdempotencyStore {
+    store: Box<dyn Store>,
+    ttl: Duration,
+}
+
+impl IdempotencyStore {
+    pub fn new(store: Box<dyn Store>) -> IdempotencyStore {
+        IdempotencyStore {
+            store,
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    pub fn from_env() -> IdempotencyStore {
+        let table = std::env::var("IDEMPOTENCY_TABLE").unwrap_or_else(|_| Stage::current().resource(DEFAULT_TABLE));
+        IdempotencyStore::new(store::open(table, "idempotency_key"))
+    }
+
+    pub fn key(action: &str, trigger_id: &str) -> String {
+        format!("{}:{}", action, trigger_id)
+    }
+
+    pub async fn claim(&self, action: &str, trigger_id: &str) -> Result<Claim, Error> {
+        let key = IdempotencyStore::key(action, trigger_id);
+        let expires_at = SystemTime::now().duration_since(UNIX_EPOCH)? + self.ttl;
+
+        let token: String = rand::thread_rng().sample_iter(&Alphanumeric).take(16).collect();
+
+        let mut item = Attrs::new();
+        item.insert("token".to_string(), Attr::S(token.clone()));
+        item.insert("expires_at".to_string(), Attr::N(expires_at.as_secs() as i64));
+        if self.store.put_if(&key, item, Condition::Absent).await? {
+            return Ok(Claim::New(ClaimedKey { key, token }));
+        }
+        Ok(Claim::Duplicate(self.stored_response(&key).await?))
+    }
+
+    /// Records the response of a finished action so retries can replay it.
+    pub async fn complete(&self, claimed: &ClaimedKey, response: &str) -> Result<(), Error> {
+        self.store.set(&claimed.key, "response", Attr::S(response.to_string())).await
+    }
+
+    /// Gives up a claim whose action failed, so the next delivery of the trigger runs it again.
+    /// Only this invocation's claim is removed, not one a retry has taken since. A failure is
+    /// logged; the claim then just lasts until it expires.
+    pub async fn release(&self, claimed: ClaimedKey) {
+        let condition = Condition::Equals("token", Attr::S(claimed.token));
+        if let Err(e) = self.store.delete_if(&claimed.key, condition).await {
+            error!("Could not release idempotency key {}: {}", claimed.key, e);
+        }
+    }
+
+    async fn stored_response(&self, key: &str) -> Result<Option<String>, Error> {
+        Ok(self.store.get(key).await?.and_then(|mut item| item.remove("response")).and_then(Attr::into_string))
+    }
+}
@@ -0,0 +1,55 @@
+pub mod alerts;
+pub mod apptoken;
+pub mod args;
+pub mod audit;
+pub mod board;
+pub mod budget;
+pub mod cache;
+pub mod cards;
+pub mod command;
+pub mod concurrency;
+pub mod config;
+pub mod crash;
+pub mod deeplinks;
+pub mod deferred;
+pub mod dm;
+pub mod drift;
+pub mod error;
+pub mod eventlog;
+pub mod eventsub;
+pub mod follows;
+pub mod help;
+pub mod http;
+pub mod idempotency;
+pub mod links;
+pub mod local;
+pub mod locale;
+pub mod logging;
+pub mod lookups;
+pub mod maintenance;
+pub mod metrics;
+pub mod mocktwitch;
+pub mod moderation;
+pub mod notify;
+pub mod onboarding;
+pub mod overlap;
+pub mod planner;
+pub mod plans;
+pub mod query;
+pub mod raids;
+pub mod ratelimit;
+pub mod render;
+pub mod reports;
+pub mod scrub;
+pub mod secrets;
+pub mod shadow;
+pub mod slack;
+pub mod stage;
+pub mod store;
+pub mod titles;
+pub mod translate;
+pub mod twitch;
+pub mod usage;
+pub mod workspace;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
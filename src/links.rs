@@ -0,0 +1,372 @@
+//! Twitch accounts linked to Slack users through Twitch's authorization code flow.
+//!
+//! `/tlink` stores a one-time `state` nonce for the invoking Slack user and sends them to Twitch;
+//! the OAuth callback trades the nonce back for the user and stores their tokens. Commands that
+//! act as a broadcaster or moderator go through `fresh_link`, which refreshes expired tokens.
+//!
+//! Each link is also indexed by the Twitch login within the workspace, so the channel's moderators
+//! can run channel commands (`/trewards somechannel list`) against a broadcaster who linked their
+//! account, without needing that channel's credentials themselves.
+
+use crate::config;
+use crate::error::BotError;
+use crate::http;
+use crate::ratelimit::now_secs;
+use crate::secrets::Secrets;
+use crate::stage::Stage;
+use crate::store::{self, Attr, Attrs, Store};
+use crate::twitch::{self, Credentials, TimeoutConfig};
+use crate::Error;
+use serde_derive::{Deserialize, Serialize};
+
+/// Twitch's OAuth base URL, `TWITCH_AUTH_BASE` (see `config`).
+pub fn auth_base() -> &'static str {
+    &config::get().twitch_auth_base
+}
+
+/// Scopes requested when linking, covering every command that acts on the user's behalf.
+pub const SCOPES: &[&str] = &[
+    "channel:manage:redemptions",
+    "moderation:read",
+    "moderator:read:automod_settings",
+    "moderator:manage:banned_users",
+    "moderator:manage:unban_requests",
+    "user:read:follows",
+    "user:read:blocked_users",
+    "user:manage:blocked_users",
+    "channel:read:stream_key",
+    "channel:manage:raids",
+    "moderator:manage:warnings",
+    "moderator:read:chatters",
+];
+
+const DEFAULT_TABLE: &str = "tuser-links";
+const STATE_TTL_SECS: u64 = 10 * 60;
+/// Refresh tokens this close to expiry rather than risk them expiring mid-command.
+const REFRESH_MARGIN_SECS: u64 = 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountLink {
+    pub twitch_user_id: String,
+    pub login: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Unix timestamp after which `access_token` needs refreshing.
+    pub expires_at: u64,
+}
+
+impl AccountLink {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+pub struct LinkStore {
+    store: Box<dyn Store>,
+}
+
+impl LinkStore {
+    pub fn new(store: Box<dyn Store>) -> LinkStore {
+        LinkStore { store }
+    }
+
+    pub fn from_env() -> LinkStore {
+        let table = std::env::var("LINK_TABLE").unwrap_or_else(|_| Stage::current().resource(DEFAULT_TABLE));
+        LinkStore::new(store::open(table, "link_key"))
+    }
+
+    pub async fn get(&self, team_id: &str, user_id: &str) -> Result<Option<AccountLink>, Error> {
+        match self.get_attribute(&user_key(team_id, user_id), "link").await? {
+            Some(link) => Ok(Some(serde_json::from_str(&link)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn save(&self, team_id: &str, user_id: &str, link: &AccountLink) -> Result<(), Error> {
+        let mut item = Attrs::new();
+        item.insert("link".to_string(), Attr::S(serde_json::to_string(link)?));
+        self.store.put(&user_key(team_id, user_id), item).await?;
+
+        let mut index = Attrs::new();
+        index.insert("slack_user".to_string(), Attr::S(user_id.to_string()));
+        self.store.put(&channel_key(team_id, &link.login), index).await
+    }
+
+    /// The Slack user in `team_id` who linked the Twitch channel `login`, if anyone did.
+    pub async fn user_for_channel(&self, team_id: &str, login: &str) -> Result<Option<String>, Error> {
+        self.get_attribute(&channel_key(team_id, login), "slack_user").await
+    }
+
+    pub async fn delete(&self, team_id: &str, user_id: &str) -> Result<(), Error> {
+        if let Some(link) = self.get(team_id, user_id).await? {
+            self.store.delete(&channel_key(team_id, &link.login)).await?;
+        }
+        self.store.delete(&user_key(team_id, user_id)).await
+    }
+
+    /// Starts a link for a Slack user, returning the `state` to send through Twitch's OAuth flow.
+    pub async fn begin(&self, team_id: &str, user_id: &str) -> Result<String, Error> {
+        let state = format!("{:032x}", rand::random::<u128>());
+
+        let mut item = Attrs::new();
+        item.insert("slack_user".to_string(), Attr::S(user_key(team_id, user_id)));
+        item.insert("expires_at".to_string(), Attr::N((now_secs() + STATE_TTL_SECS) as i64));
+        self.store.put(&format!("state:{}", state), item).await?;
+
+        Ok(state)
+    }
+
+    /// Consumes a `state` from the OAuth callback, returning the `(team_id, user_id)` it was issued to.
+    /// `None` for a state that's unknown or more than ten minutes old, which the table's TTL may
+    /// not have swept yet.
+    pub async fn finish(&self, state: &str) -> Result<Option<(String, String)>, Error> {
+        let state_key = format!("state:{}", state);
+        let item = self.store.get(&state_key).await?;
+        self.store.delete(&state_key).await?;
+
+        let mut item = match item {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        let expires_at = item.get("expires_at").and_then(Attr::as_number).unwrap_or_default();
+        if expires_at <= now_secs() as i64 {
+            return Ok(None);
+        }
+        let slack_user = item.remove("slack_user").and_then(Attr::into_string);
+        Ok(slack_user.and_then(|user| {
+            let mut parts = user.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(team), Some(user)) => Some((team.to_string(), user.to_string())),
+                _ => None,
+            }
+        }))
+    }
+
+    async fn get_attribute(&self, link_key: &str, name: &str) -> Result<Option<String>, Error> {
+        Ok(self.store.get(link_key).await?.and_then(|mut item| item.remove(name)).and_then(Attr::into_string))
+    }
+}
+
+/// The user's link with a usable access token, refreshing (and re-saving) it when it's about to
+/// expire. `None` if the user never linked an account.
+pub async fn fresh_link(
+    store: &LinkStore,
+    secrets: &Secrets,
+    team_id: &str,
+    user_id: &str,
+) -> Result<Option<AccountLink>, Error> {
+    let link = match store.get(team_id, user_id).await? {
+        Some(link) => link,
+        None => return Ok(None),
+    };
+    if link.expires_at > now_secs() + REFRESH_MARGIN_SECS {
+        return Ok(Some(link));
+    }
+
+    let secrets = secrets.clone();
+    let refreshed = tokio::task::spawn_blocking(move || refresh(&http::client(), &secrets, &link))
+        .await??;
+    store.save(team_id, user_id, &refreshed).await?;
+    Ok(Some(refreshed))
+}
+
+/// Like `fresh_link`, but for the broadcaster who linked the channel `login`. Used by channel
+/// commands invoked as `/command <channel> ...`.
+pub async fn fresh_channel_link(
+    store: &LinkStore,
+    secrets: &Secrets,
+    team_id: &str,
+    login: &str,
+) -> Result<Option<AccountLink>, Error> {
+    match store.user_for_channel(team_id, &login.to_ascii_lowercase()).await? {
+        Some(user_id) => fresh_link(store, secrets, team_id, &user_id).await,
+        None => Ok(None),
+    }
+}
+
+/// Picks the link a channel command runs as: the broadcaster of `channel` when given, otherwise
+/// the invoker's own. Only the broadcaster, or someone whose own link Twitch lists as one of the
+/// channel's moderators, may act with the broadcaster's link. `Ok(Err(message))` explains to the
+/// user why there is no usable link.
+pub async fn command_link(
+    store: &LinkStore,
+    secrets: &Secrets,
+    team_id: &str,
+    user_id: &str,
+    channel: Option<&str>,
+    scope: &str,
+) -> Result<Result<AccountLink, String>, Error> {
+    let link = match channel {
+        Some(channel) => {
+            let owner = store.user_for_channel(team_id, &channel.to_ascii_lowercase()).await?;
+            let link = match &owner {
+                Some(owner) => fresh_link(store, secrets, team_id, owner).await?,
+                None => None,
+            };
+            let link = match link {
+                Some(link) => link,
+                None => {
+                    return Ok(Err(format!(
+                        "{} hasn't linked their Twitch account in this workspace. They can run `/tlink`.",
+                        channel
+                    )))
+                }
+            };
+            if owner.as_deref() != Some(user_id) && !moderates(store, secrets, team_id, user_id, &link).await? {
+                return Ok(Err(format!(
+                    "Only {} or one of their moderators can do that. Moderators need to link their own Twitch \
+                     account with `/tlink` first.",
+                    channel
+                )));
+            }
+            link
+        }
+        None => match fresh_link(store, secrets, team_id, user_id).await? {
+            Some(link) => link,
+            None => return Ok(Err("Link your Twitch account with `/tlink` first.".to_string())),
+        },
+    };
+
+    if !link.has_scope(scope) {
+        return Ok(Err(format!(
+            "The Twitch link for {} is missing the `{}` permission. Run `/tlink` again to grant it.",
+            link.login, scope
+        )));
+    }
+    Ok(Ok(link))
+}
+
+/// Whether the Slack user `user_id` has linked a Twitch account that moderates `broadcaster`'s
+/// channel. Asking Twitch needs the broadcaster's `moderation:read`; without it, or if Twitch can't
+/// say, the answer is no.
+async fn moderates(
+    store: &LinkStore,
+    secrets: &Secrets,
+    team_id: &str,
+    user_id: &str,
+    broadcaster: &AccountLink,
+) -> Result<bool, Error> {
+    let invoker = match store.get(team_id, user_id).await? {
+        Some(invoker) => invoker,
+        None => return Ok(false),
+    };
+    if !broadcaster.has_scope("moderation:read") {
+        return Ok(false);
+    }
+    let (secrets, broadcaster) = (secrets.clone(), broadcaster.clone());
+    let checked = tokio::task::spawn_blocking(move || {
+        let client = twitch::client(&TimeoutConfig::from_env())?;
+        let credentials = Credentials::user(&secrets, &broadcaster);
+        twitch::is_moderator(&client, credentials, &broadcaster.twitch_user_id, &invoker.twitch_user_id)
+    })
+    .await?;
+    Ok(checked.unwrap_or(false))
+}
+
+pub fn authorize_url(client_id: &str, redirect_uri: &str, state: &str) -> Result<String, Error> {
+    let scope = SCOPES.join(" ");
+    let query = serde_urlencoded::to_string(&[
+        ("client_id", client_id),
+        ("redirect_uri", redirect_uri),
+        ("response_type", "code"),
+        ("scope", scope.as_str()),
+        ("state", state),
+    ])?;
+    Ok(format!("{}/authorize?{}", auth_base(), query))
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Validation {
+    /// Empty for app tokens, which aren't tied to a user.
+    #[serde(default)]
+    pub user_id: String,
+    #[serde(default)]
+    pub login: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    pub expires_in: u64,
+}
+
+pub fn exchange_code(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    code: &str,
+    redirect_uri: &str,
+) -> Result<AccountLink, Error> {
+    let tokens = token_request(
+        client,
+        &[
+            ("client_id", secrets.twitch_client_id.as_str()),
+            ("client_secret", secrets.twitch_client_secret.as_str()),
+            ("code", code),
+            ("grant_type", "authorization_code"),
+            ("redirect_uri", redirect_uri),
+        ],
+    )?;
+    link_from_tokens(client, tokens)
+}
+
+pub fn refresh(client: &reqwest::blocking::Client, secrets: &Secrets, link: &AccountLink) -> Result<AccountLink, Error> {
+    let tokens = token_request(
+        client,
+        &[
+            ("client_id", secrets.twitch_client_id.as_str()),
+            ("client_secret", secrets.twitch_client_secret.as_str()),
+            ("grant_type", "refresh_token"),
+            ("refresh_token", link.refresh_token.as_str()),
+        ],
+    )?;
+    link_from_tokens(client, tokens)
+}
+
+pub fn validate(client: &reqwest::blocking::Client, access_token: &str) -> Result<Validation, Error> {
+    let resp = client
+        .get(&format!("{}/validate", auth_base()))
+        .header("Authorization", format!("OAuth {}", access_token))
+        .send()?;
+    if resp.status() != 200 {
+        return Err(BotError::twitch_api(resp.status(), "Twitch token validation").into());
+    }
+    Ok(resp.json()?)
+}
+
+fn token_request(client: &reqwest::blocking::Client, params: &[(&str, &str)]) -> Result<TokenResponse, Error> {
+    let resp = client.post(&format!("{}/token", auth_base())).form(params).send()?;
+    if resp.status() != 200 {
+        return Err(BotError::twitch_api(resp.status(), "Twitch token request").into());
+    }
+    Ok(resp.json()?)
+}
+
+fn link_from_tokens(client: &reqwest::blocking::Client, tokens: TokenResponse) -> Result<AccountLink, Error> {
+    let validation = validate(client, &tokens.access_token)?;
+    if validation.user_id.is_empty() {
+        return Err(BotError::Decode("a Twitch token validation without a user".to_string()).into());
+    }
+
+    Ok(AccountLink {
+        twitch_user_id: validation.user_id,
+        login: validation.login,
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        scopes: validation.scopes,
+        expires_at: now_secs() + tokens.expires_in,
+    })
+}
+
+fn user_key(team_id: &str, user_id: &str) -> String {
+    format!("{}:{}", team_id, user_id)
+}
+
+fn channel_key(team_id: &str, login: &str) -> String {
+    format!("channel:{}:{}", team_id, login)
+}
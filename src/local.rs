@@ -0,0 +1,131 @@
+//! `--local`: runs one of the bot's functions on this machine, with no AWS account or Twitch
+//! app behind it. Any function binary takes the flag:
+//!
+//! ```text
+//! cargo run --features sqlite --bin tuser -- --local ninja pokimane
+//! cargo run --features sqlite --bin twarmcache -- --local < /dev/null
+//! ```
+//!
+//! Words after the flag become the text of a slash command for the binary's command (`/tuser`
+//! above); otherwise every JSON event on stdin is handled in turn, and a scheduled job with no
+//! input runs once with an empty event. Replies are printed as JSON.
+//!
+//! The flag fills in, unless they're already set, `SECRETS_SOURCE=env` (credentials from
+//! environment variables, see `secrets`) with a random `SLACK_TOKEN` for this run,
+//! `STORE_BACKEND=sqlite` (the bot's tables in a local file, see `store`) and
+//! `TWITCH_MOCK_DIR=local/twitch` (Helix answered from fixtures, see `mocktwitch`). Those settings only take effect with the flag, so a deployed function that
+//! happens to have them set still uses Secrets Manager and Twitch.
+
+use crate::config;
+use crate::crash;
+use crate::stage::Stage;
+use crate::Error;
+use log::info;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::io::{IsTerminal, Read};
+use std::sync::Arc;
+
+pub const FLAG: &str = "--local";
+
+pub fn requested() -> bool {
+    std::env::args().any(|arg| arg == FLAG)
+}
+
+/// Runs `handler` under Lambda, or with `--local`, on the events given here. Either way a
+/// panicking or failing handler is reported to the operator (see `crash`). An unknown `STAGE`
+/// fails here, before any event is handled.
+pub async fn serve<F, Fut, O>(handler: F) -> Result<(), Error>
+where
+    F: Fn(Value) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<O, Error>> + Send,
+    O: Serialize + Send,
+{
+    Stage::from_env()?;
+    crash::install();
+    if !requested() {
+        log_config();
+        let handler = Arc::new(handler);
+        return lambda::run(lambda::handler_fn(move |event| {
+            let handler = handler.clone();
+            async move { crash::guard(handler.as_ref(), event).await }
+        }))
+        .await;
+    }
+
+    configure();
+    log_config();
+    for event in events()? {
+        match crash::guard(&handler, event).await {
+            Ok(output) => println!("{}", serde_json::to_string_pretty(&output)?),
+            Err(e) => eprintln!("Handler failed: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Reads the configuration before the first event, so a bad setting shows up at startup.
+fn log_config() {
+    let config = config::get();
+    info!(
+        "Running in {} with secret {}, Twitch at {} and {}",
+        config.region.name(),
+        config.secret_id,
+        config.twitch_api_base,
+        config.twitch_auth_base
+    );
+}
+
+fn configure() {
+    let slack_token: String = rand::thread_rng().sample_iter(&Alphanumeric).take(24).collect();
+    for (name, value) in &[
+        ("SECRETS_SOURCE", "env"),
+        ("SLACK_TOKEN", slack_token.as_str()),
+        ("STORE_BACKEND", "sqlite"),
+        ("TWITCH_MOCK_DIR", "local/twitch"),
+    ] {
+        if std::env::var_os(name).is_none() {
+            std::env::set_var(name, value);
+        }
+    }
+}
+
+fn events() -> Result<Vec<Value>, Error> {
+    let args: Vec<String> = std::env::args().skip_while(|arg| arg != FLAG).skip(1).collect();
+    if !args.is_empty() {
+        return Ok(vec![slash_command(&args.join(" "))]);
+    }
+
+    let mut input = String::new();
+    if !std::io::stdin().is_terminal() {
+        std::io::stdin().read_to_string(&mut input)?;
+    }
+    let events = serde_json::Deserializer::from_str(&input)
+        .into_iter::<Value>()
+        .collect::<Result<Vec<Value>, _>>()?;
+    if events.is_empty() {
+        return Ok(vec![json!({})]);
+    }
+    Ok(events)
+}
+
+/// A slash command for this binary's command from a made-up local workspace, carrying the
+/// verification token the env secrets hold.
+fn slash_command(text: &str) -> Value {
+    let command = std::env::args()
+        .next()
+        .as_deref()
+        .and_then(|program| std::path::Path::new(program).file_stem()?.to_str().map(str::to_string))
+        .unwrap_or_default();
+    json!({
+        "token": std::env::var("SLACK_TOKEN").unwrap_or_default(),
+        "command": format!("/{}", command),
+        "text": text,
+        "team_id": "TLOCAL",
+        "user_id": "ULOCAL",
+        "channel_id": "CLOCAL",
+    })
+}
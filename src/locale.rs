@@ -0,0 +1,121 @@
+//! Number and date formatting for a workspace's locale, shared by every renderer so a German
+//! workspace sees `21.304` where an American one sees `21,304`.
+
+use crate::workspace::{WorkspaceConfig, WorkspaceStore};
+use chrono::{DateTime, TimeZone};
+use log::error;
+
+/// The locales offered in setup, as `(tag, label)`.
+pub const LOCALES: &[(&str, &str)] = &[
+    ("en-US", "English (US)"),
+    ("en-GB", "English (UK)"),
+    ("de-DE", "Deutsch"),
+    ("es-ES", "Español"),
+    ("fr-FR", "Français"),
+    ("it-IT", "Italiano"),
+    ("nl-NL", "Nederlands"),
+    ("pt-BR", "Português (Brasil)"),
+    ("sv-SE", "Svenska"),
+    ("ja-JP", "日本語"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DateOrder {
+    /// `Mar 5, 2021`
+    MonthDayYear,
+    /// `2021-03-05`
+    YearMonthDay,
+    /// `05.03.2021`, with the given separator.
+    DayMonthYear(char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Locale {
+    /// Thousands separator.
+    grouping: char,
+    dates: DateOrder,
+}
+
+impl Default for Locale {
+    fn default() -> Locale {
+        Locale {
+            grouping: ',',
+            dates: DateOrder::MonthDayYear,
+        }
+    }
+}
+
+impl Locale {
+    /// The locale for a BCP 47 tag like `de-DE` or `pt_BR`. Only the language decides the
+    /// format, except for English; unknown tags fall back to US English.
+    pub fn from_tag(tag: &str) -> Locale {
+        let tag = tag.replace('_', "-").to_ascii_lowercase();
+        let language = tag.split('-').next().unwrap_or("");
+        let (grouping, dates) = match language {
+            "en" if tag == "en" || tag == "en-us" => (',', DateOrder::MonthDayYear),
+            "en" => (',', DateOrder::DayMonthYear('/')),
+            "de" | "nl" | "it" | "es" | "pt" | "da" | "tr" => ('.', DateOrder::DayMonthYear('.')),
+            // French groups with a narrow no-break space; Swedish and these others with a
+            // no-break space.
+            "fr" => ('\u{202f}', DateOrder::DayMonthYear('/')),
+            "sv" | "fi" | "nb" | "pl" | "cs" => ('\u{a0}', DateOrder::YearMonthDay),
+            "ja" | "zh" | "ko" => (',', DateOrder::YearMonthDay),
+            _ => return Locale::default(),
+        };
+        Locale { grouping, dates }
+    }
+
+    pub fn for_workspace(config: &WorkspaceConfig) -> Locale {
+        config.locale.as_deref().map(Locale::from_tag).unwrap_or_default()
+    }
+
+    /// Loads the workspace's locale for commands that don't otherwise need its config. A failed
+    /// load just means default formatting.
+    pub async fn for_team(team_id: &str) -> Locale {
+        match WorkspaceStore::from_env().load(team_id).await {
+            Ok(config) => Locale::for_workspace(&config),
+            Err(e) => {
+                error!("Could not load the locale for workspace {}: {}", team_id, e);
+                Locale::default()
+            }
+        }
+    }
+
+    /// `21304` -> `21,304` (or `21.304`, `21 304`, ...).
+    pub fn count(&self, count: u64) -> String {
+        let digits = count.to_string();
+        let mut out = String::with_capacity(digits.len() * 2);
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                out.push(self.grouping);
+            }
+            out.push(c);
+        }
+        out
+    }
+
+    pub fn date<Tz: TimeZone>(&self, date: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        match self.dates {
+            DateOrder::MonthDayYear => date.format("%b %-d, %Y").to_string(),
+            DateOrder::YearMonthDay => date.format("%Y-%m-%d").to_string(),
+            DateOrder::DayMonthYear(separator) => {
+                date.format(&format!("%d{}%m{}%Y", separator, separator)).to_string()
+            }
+        }
+    }
+
+    /// The date plus a 24-hour (12-hour for US English) time and the offset's name.
+    pub fn date_time<Tz: TimeZone>(&self, date: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        let time = match self.dates {
+            DateOrder::MonthDayYear => date.format("%-I:%M %p %Z"),
+            _ => date.format("%H:%M %Z"),
+        };
+        format!("{} {}", self.date(date), time)
+    }
+}
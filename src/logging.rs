@@ -0,0 +1,105 @@
+//! Logger configured from the environment at startup.
+//!
+//! `RUST_LOG` takes `env_logger`-style directives (`info`, `twitch_info_bot=debug,rusoto_core=warn`)
+//! and defaults to `info` (`debug` when `STAGE` is `dev`). `LOG_FORMAT=json` emits one JSON object
+//! per line for CloudWatch Insights; anything else gets plain text.
+
+use crate::stage::Stage;
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use serde_json::json;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Json,
+}
+
+struct Logger {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+    format: Format,
+}
+
+pub fn init() -> Result<(), SetLoggerError> {
+    let filters = std::env::var("RUST_LOG").unwrap_or_default();
+    let format = match std::env::var("LOG_FORMAT").as_ref().map(String::as_str) {
+        Ok("json") => Format::Json,
+        _ => Format::Text,
+    };
+
+    let logger = Logger::new(&filters, format);
+    let max_level = logger
+        .modules
+        .iter()
+        .map(|(_, level)| *level)
+        .fold(logger.default, std::cmp::max);
+
+    log::set_boxed_logger(Box::new(logger))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+impl Logger {
+    fn new(filters: &str, format: Format) -> Logger {
+        let mut logger = Logger {
+            default: Stage::current().default_log_level(),
+            modules: vec![],
+            format,
+        };
+
+        for directive in filters.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let mut parts = directive.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(level), None) => match LevelFilter::from_str(level) {
+                    Ok(level) => logger.default = level,
+                    Err(_) => logger.modules.push((level.to_string(), LevelFilter::Trace)),
+                },
+                (Some(module), Some(level)) => {
+                    if let Ok(level) = LevelFilter::from_str(level) {
+                        logger.modules.push((module.to_string(), level));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Longest module prefix wins, so check the most specific directives first.
+        logger.modules.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        logger
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(module, _)| target == module || target.starts_with(&format!("{}::", module)))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        match self.format {
+            Format::Text => println!("{:<5} [{}] {}", record.level(), record.target(), record.args()),
+            Format::Json => println!(
+                "{}",
+                json!({
+                    "level": record.level().to_string(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                })
+            ),
+        }
+    }
+
+    fn flush(&self) {}
+}
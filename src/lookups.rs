@@ -0,0 +1,9 @@
+//! The lookup commands, each a [`Command`](crate::command::Command) of its own. Every one has a
+//! binary of its own, and the `twitch` binary serves them all from one function, either under
+//! their own names or as subcommands of `/twitch`.
+
+pub mod clip;
+pub mod compare;
+pub mod game;
+pub mod stream;
+pub mod user;
@@ -0,0 +1,190 @@
+//! `/tclip`: a clip, or a channel's most viewed clips.
+
+use crate::args::{Arg, CommandSchema, Kind, Schema};
+use crate::command::{self, Command, Invocation};
+use crate::locale::Locale;
+use crate::scrub::OutputFilter;
+use crate::secrets::Secrets;
+use crate::slack::{Block, Color, Element, SlackAttachment, SlackMessage, Text};
+use crate::twitch::{self, LookupError, TimeoutConfig, TwitchClip};
+use crate::workspace::WorkspaceStore;
+use crate::Error;
+use chrono::{Duration, Utc};
+use futures::future::BoxFuture;
+
+/// Clips listed when `top` doesn't give a count, and the most it may ask for.
+const DEFAULT_TOP: usize = 5;
+const MAX_TOP: usize = 10;
+
+static SCHEMA: CommandSchema = CommandSchema {
+    command: "/tclip",
+    summary: "Look up a Twitch clip, or a channel's top clips",
+    forms: &[Schema {
+        subcommand: None,
+        summary: "show a clip, or a channel's most viewed clips, e.g. `/tclip ninja top 5 7d`",
+        args: &[
+            Arg::required("clip", Kind::Word, "a clip URL or slug, or a channel's login"),
+            Arg::optional("top", Kind::Word, "the word `top`, to list a channel's clips"),
+            Arg::optional("count", Kind::Number, "how many clips, up to 10 (default 5)"),
+            Arg::optional("period", Kind::Duration, "only clips made this recently, e.g. `7d`"),
+        ],
+        flags: &[],
+    }],
+    dry_run: false,
+};
+
+/// `/tclip <url or slug>` and `/tclip <login> [top [count] [period]]`: a card per clip.
+pub struct Clips;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Lookup {
+    Clip(String),
+    /// A login, unless no such channel exists and it turns out to be a clip's slug.
+    ChannelOrClip(String),
+    Channel {
+        login: String,
+        count: usize,
+        period: Option<u64>,
+    },
+}
+
+impl Command for Clips {
+    fn name(&self) -> &'static str {
+        "/tclip"
+    }
+
+    fn schema(&self) -> Option<&'static CommandSchema> {
+        Some(&SCHEMA)
+    }
+
+    fn cooldown(&self) -> Option<std::time::Duration> {
+        Some(command::DEFAULT_COOLDOWN)
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>> {
+        Box::pin(async move {
+            let args = &invocation.args;
+            let clip = args.text("clip").unwrap_or_default().to_string();
+            let lookup = match args.text("top") {
+                Some(word) if !word.eq_ignore_ascii_case("top") => {
+                    return SlackMessage::builder()
+                        .ephemeral()
+                        .text(format!("Didn't expect `{}`.\n{}", word, SCHEMA.usage()))
+                        .build()
+                }
+                Some(_) => Lookup::Channel {
+                    login: clip.trim_start_matches('@').to_ascii_lowercase(),
+                    count: args.number("count").map_or(DEFAULT_TOP, |count| count as usize).clamp(1, MAX_TOP),
+                    period: args.number("period"),
+                },
+                None => parse_clip(&clip),
+            };
+
+            let secrets = invocation.secrets.clone();
+            let clips = tokio::task::spawn_blocking(move || clips(lookup, &secrets)).await?;
+            let clips = match clips {
+                Ok(clips) if clips.is_empty() => {
+                    return SlackMessage::builder()
+                        .ephemeral()
+                        .text(format!("No clips found for {}.", clip))
+                        .build()
+                }
+                Ok(clips) => clips,
+                Err(e) => return SlackMessage::builder().ephemeral().text(e.user_message(&clip)).build(),
+            };
+
+            let config = WorkspaceStore::from_env().load(&invocation.req.team_id).await?;
+            let (locale, filter) = (Locale::for_workspace(&config), OutputFilter::for_workspace(&config));
+            let titles: Vec<String> = clips.iter().map(|clip| filter.scrub(&clip.title)).collect();
+            SlackMessage::builder()
+                .in_channel()
+                .text(titles.join(", "))
+                .attachments(clips.iter().map(|clip| clip_attachment(clip, locale, &filter)))
+                .build()
+        })
+    }
+}
+
+/// Clip URLs (`clips.twitch.tv/<slug>`, `twitch.tv/<login>/clip/<slug>`) give their slug. Slugs
+/// usually have a `-<suffix>` or are too long for a login; anything else may be either.
+fn parse_clip(word: &str) -> Lookup {
+    // Slack wraps URLs as `<https://...>` or `<https://...|label>`.
+    let word = word.trim_start_matches('<').trim_end_matches('>');
+    let word = word.split('|').next().unwrap_or_default();
+    let path = word
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.")
+        .trim_start_matches("m.");
+    let path = path.split(|c| c == '?' || c == '#').next().unwrap_or_default();
+
+    if let Some(slug) = path.strip_prefix("clips.twitch.tv/") {
+        return Lookup::Clip(slug.trim_end_matches('/').to_string());
+    }
+    if let Some(rest) = path.strip_prefix("twitch.tv/") {
+        let parts: Vec<&str> = rest.trim_end_matches('/').split('/').collect();
+        if let [_, "clip", slug] = parts.as_slice() {
+            return Lookup::Clip(slug.to_string());
+        }
+        return Lookup::ChannelOrClip(parts[0].to_ascii_lowercase());
+    }
+
+    let word = word.trim_start_matches('@');
+    if word.contains('-') || word.len() > 25 {
+        Lookup::Clip(word.to_string())
+    } else {
+        Lookup::ChannelOrClip(word.to_string())
+    }
+}
+
+fn clips(lookup: Lookup, secrets: &Secrets) -> Result<Vec<TwitchClip>, LookupError> {
+    let client = twitch::client(&TimeoutConfig::from_env())?;
+    let (login, count, period) = match lookup {
+        Lookup::Clip(slug) => return Ok(twitch::get_clip(&client, &slug, secrets)?.into_iter().collect()),
+        Lookup::ChannelOrClip(word) => (word, DEFAULT_TOP, None),
+        Lookup::Channel { login, count, period } => (login, count, period),
+    };
+
+    match twitch::get_users_by_login(&client, &[login.to_ascii_lowercase()], secrets)?.pop() {
+        Some(user) => {
+            let since = period.map(|secs| Utc::now() - Duration::seconds(secs as i64));
+            twitch::get_top_clips(&client, &user.id, count, since, secrets)
+        }
+        None => Ok(twitch::get_clip(&client, &login, secrets)?.into_iter().collect()),
+    }
+}
+
+fn clip_attachment(clip: &TwitchClip, locale: Locale, filter: &OutputFilter) -> SlackAttachment {
+    let title = filter.scrub(&clip.title);
+    let mut details = vec![
+        format!("*<{}|{}>*", clip.url, title),
+        format!("Clipped by {} · {} views", clip.creator_name, locale.count(clip.view_count)),
+    ];
+    if let Ok(created) = chrono::DateTime::parse_from_rfc3339(&clip.created_at) {
+        details.push(format!(
+            "<!date^{}^{{date_short_pretty}}|{}>",
+            created.timestamp(),
+            locale.date(&created)
+        ));
+    }
+
+    SlackAttachment {
+        fallback: format!("{} ({})", title, clip.url),
+        color: Color::TWITCH_PURPLE,
+        author_name: clip.broadcaster_name.clone(),
+        author_icon: String::new(),
+        blocks: vec![Block::Section {
+            text: Text::Mrkdwn {
+                text: details.join("\n"),
+            },
+            accessory: if clip.thumbnail_url.is_empty() {
+                None
+            } else {
+                Some(Element::Image {
+                    image_url: clip.thumbnail_url.clone(),
+                    alt_text: format!("{} thumbnail", title),
+                })
+            },
+        }],
+    }
+}
@@ -0,0 +1,259 @@
+//! `/tcompare`: two channels side by side, with an optional estimate of how much their audiences
+//! overlap (see [`overlap`]).
+
+use crate::args::{Arg, CommandSchema, Flag, Kind, Schema};
+use crate::command::{self, Command, Invocation};
+use crate::links::{self, AccountLink, LinkStore};
+use crate::locale::Locale;
+use crate::overlap::{self, Sample};
+use crate::render;
+use crate::secrets::Secrets;
+use crate::slack::{Block, Color, SlackAttachment, SlackMessage, Text};
+use crate::twitch::{self, Credentials, LookupError, TimeoutConfig, TwitchStream, TwitchUser};
+use crate::workspace::WorkspaceStore;
+use crate::Error;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use futures::future::BoxFuture;
+use log::error;
+use std::time::Duration;
+
+/// What the overlap estimate samples from each channel: its most viewed clips from the last
+/// [`CLIP_DAYS`] days, and one page of its chatters.
+const SAMPLE_CLIPS: usize = 100;
+const SAMPLE_CHATTERS: usize = 1000;
+const CLIP_DAYS: i64 = 30;
+
+const CHATTERS_SCOPE: &str = "moderator:read:chatters";
+
+static SCHEMA: CommandSchema = CommandSchema {
+    command: "/tcompare",
+    summary: "Compare two Twitch channels",
+    forms: &[Schema {
+        subcommand: None,
+        summary: "followers, live status and account age side by side",
+        args: &[
+            Arg::required("first", Kind::Login, "a channel's login"),
+            Arg::required("second", Kind::Login, "the other channel's login"),
+        ],
+        flags: &[Flag {
+            name: "overlap",
+            value: None,
+            help: "also estimate how much their audiences overlap, from recent clip creators and chatters",
+        }],
+    }],
+    dry_run: false,
+};
+
+/// `/tcompare <login> <login> [--overlap]`: a card per channel, and with `--overlap` a rough
+/// estimate of their shared audience.
+pub struct Compare;
+
+struct Channel {
+    user: TwitchUser,
+    stream: Option<TwitchStream>,
+    /// `None` if Twitch wouldn't say.
+    followers: Option<u64>,
+}
+
+impl Command for Compare {
+    fn name(&self) -> &'static str {
+        "/tcompare"
+    }
+
+    fn schema(&self) -> Option<&'static CommandSchema> {
+        Some(&SCHEMA)
+    }
+
+    fn cooldown(&self) -> Option<Duration> {
+        Some(command::DEFAULT_COOLDOWN)
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>> {
+        Box::pin(async move {
+            let first = invocation.args.text("first").unwrap_or_default().to_string();
+            let second = invocation.args.text("second").unwrap_or_default().to_string();
+            if first == second {
+                return SlackMessage::builder().ephemeral().text("Pick two different channels to compare.").build();
+            }
+            let logins = vec![first, second];
+            let asked = logins.join(" and ");
+
+            let store = LinkStore::from_env();
+            let mut channel_links = vec![];
+            for login in &logins {
+                channel_links.push(channel_link(&store, &invocation, login).await);
+            }
+            let chat_links = if invocation.args.flag("overlap") {
+                let mut chat_links = vec![];
+                for (login, broadcaster) in logins.iter().zip(&channel_links) {
+                    chat_links.push(chat_link(&store, &invocation, login, broadcaster.as_ref()).await);
+                }
+                Some(chat_links)
+            } else {
+                None
+            };
+
+            let secrets = invocation.secrets.clone();
+            let compared =
+                tokio::task::spawn_blocking(move || compare(&logins, channel_links, chat_links, &secrets)).await?;
+            let (channels, samples) = match compared {
+                Ok((channels, _)) if channels.len() < 2 => {
+                    return SlackMessage::builder()
+                        .ephemeral()
+                        .text(format!("Couldn't find both of {} on Twitch.", asked))
+                        .build()
+                }
+                Ok(compared) => compared,
+                Err(e) => return SlackMessage::builder().ephemeral().text(e.user_message(&asked)).build(),
+            };
+
+            let config = WorkspaceStore::from_env().load(&invocation.req.team_id).await?;
+            let locale = Locale::for_workspace(&config);
+            let mut attachments: Vec<SlackAttachment> =
+                channels.iter().map(|channel| channel_attachment(channel, locale)).collect();
+            if let Some([a, b]) = samples.as_deref() {
+                let text = overlap::describe(&channels[0].user.display_name, a, &channels[1].user.display_name, b);
+                attachments.push(SlackAttachment {
+                    fallback: "Estimated audience overlap".to_string(),
+                    color: Color::TWITCH_PURPLE,
+                    author_name: String::new(),
+                    author_icon: String::new(),
+                    blocks: vec![Block::Section {
+                        text: Text::Mrkdwn { text },
+                        accessory: None,
+                    }],
+                });
+            }
+            let users: Vec<TwitchUser> = channels.iter().map(|channel| channel.user.clone()).collect();
+            let streams: Vec<TwitchStream> = channels.iter().filter_map(|channel| channel.stream.clone()).collect();
+            SlackMessage::builder()
+                .in_channel()
+                .text(render::summary(&users, Some(&streams[..])))
+                .attachments(attachments)
+                .build()
+        })
+    }
+}
+
+/// The link of whoever linked the channel `login` here, which its follower count is read with.
+/// `None` if nobody did or it couldn't be loaded; the card then shows the count as unknown.
+async fn channel_link(store: &LinkStore, invocation: &Invocation, login: &str) -> Option<AccountLink> {
+    let (secrets, req) = (&invocation.secrets, &invocation.req);
+    match links::fresh_channel_link(store, secrets, &req.team_id, login).await {
+        Ok(link) => link,
+        Err(e) => {
+            error!("Could not load {}'s Twitch link for its follower count: {}", login, e);
+            None
+        }
+    }
+}
+
+/// A link that can see `login`'s chat: the `broadcaster`'s own if they linked it here, otherwise
+/// the invoker's, which works if they moderate the channel. `None` without one, or if a link
+/// couldn't be loaded; the estimate then goes on clips alone.
+async fn chat_link(
+    store: &LinkStore,
+    invocation: &Invocation,
+    login: &str,
+    broadcaster: Option<&AccountLink>,
+) -> Option<AccountLink> {
+    if let Some(link) = broadcaster.filter(|link| link.has_scope(CHATTERS_SCOPE)) {
+        return Some(link.clone());
+    }
+    let (secrets, req) = (&invocation.secrets, &invocation.req);
+    let invoker = links::fresh_link(store, secrets, &req.team_id, &req.user_id).await;
+    match invoker {
+        Ok(link) => link.filter(|link| link.has_scope(CHATTERS_SCOPE)),
+        Err(e) => {
+            error!("Could not load a Twitch link to sample {}'s chat: {}", login, e);
+            None
+        }
+    }
+}
+
+/// The channels in request order and, given links to try for their chats, a sample of each one's
+/// audience. `channel_links` are the channels' own links, by login order, for their follower
+/// counts.
+fn compare(
+    logins: &[String],
+    channel_links: Vec<Option<AccountLink>>,
+    chat_links: Option<Vec<Option<AccountLink>>>,
+    secrets: &Secrets,
+) -> Result<(Vec<Channel>, Option<Vec<Sample>>), LookupError> {
+    let client = twitch::client(&TimeoutConfig::from_env())?;
+    let mut users = twitch::get_users_by_login(&client, logins, secrets)?;
+    users.sort_by_key(|user| logins.iter().position(|login| *login == user.login));
+    let ids: Vec<String> = users.iter().map(|user| user.id.clone()).collect();
+    let mut streams = twitch::get_streams(&client, &ids, secrets)?;
+
+    let samples = match chat_links {
+        Some(chat_links) if users.len() == 2 => {
+            let broadcasters: Vec<&str> = ids.iter().map(String::as_str).collect();
+            let since = Utc::now() - ChronoDuration::days(CLIP_DAYS);
+            let mut samples = vec![];
+            for (user, link) in users.iter().zip(chat_links) {
+                let clips = twitch::get_top_clips(&client, &user.id, SAMPLE_CLIPS, Some(since), secrets)?;
+                // Twitch refuses a link that doesn't moderate the channel; that just leaves chat out.
+                let chatters = link.and_then(|link| {
+                    let credentials = Credentials::user(secrets, &link);
+                    twitch::get_chatters(&client, credentials, &user.id, &link.twitch_user_id, SAMPLE_CHATTERS).ok()
+                });
+                samples.push(Sample::new(&clips, chatters.as_deref(), &broadcasters));
+            }
+            Some(samples)
+        }
+        _ => None,
+    };
+
+    let channels = users
+        .into_iter()
+        .map(|user| {
+            let stream = streams
+                .iter()
+                .position(|stream| stream.user_id == user.id)
+                .map(|i| streams.remove(i));
+            let link = channel_links.iter().flatten().find(|link| link.twitch_user_id == user.id);
+            let followers = link.and_then(|link| {
+                twitch::get_follower_count(&client, Credentials::user(secrets, link), &user.id).ok()
+            });
+            Channel {
+                user,
+                stream,
+                followers,
+            }
+        })
+        .collect();
+    Ok((channels, samples))
+}
+
+fn channel_attachment(channel: &Channel, locale: Locale) -> SlackAttachment {
+    let user = &channel.user;
+    let mut lines = vec![format!("*<https://twitch.tv/{}|{}>*", user.login, user.display_name)];
+    lines.push(match channel.followers {
+        Some(followers) => format!("{} followers", locale.count(followers)),
+        None => "Followers unknown".to_string(),
+    });
+    lines.push(match &channel.stream {
+        Some(stream) => format!("🔴 Live with {} viewers", locale.count(stream.viewer_count)),
+        None => "⚫ Offline".to_string(),
+    });
+    if let Ok(created) = DateTime::parse_from_rfc3339(&user.created_at) {
+        lines.push(format!("On Twitch since {}", locale.date(&created)));
+    }
+    if !user.broadcaster_type.is_empty() {
+        lines.push(format!("Twitch {}", user.broadcaster_type));
+    }
+
+    SlackAttachment {
+        fallback: format!("{} on Twitch", user.display_name),
+        color: if channel.stream.is_some() { Color::LIVE } else { Color::OFFLINE },
+        author_name: user.display_name.clone(),
+        author_icon: user.profile_image_url.clone(),
+        blocks: vec![Block::Section {
+            text: Text::Mrkdwn {
+                text: lines.join("\n"),
+            },
+            accessory: None,
+        }],
+    }
+}
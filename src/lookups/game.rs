@@ -0,0 +1,191 @@
+//! `/tgame`: Twitch categories, with box art and their top live streams.
+
+use crate::args::{Arg, CommandSchema, Kind, Schema};
+use crate::command::{self, Command, Invocation};
+use crate::locale::Locale;
+use crate::scrub::OutputFilter;
+use crate::secrets::Secrets;
+use crate::slack::{Block, Color, Element, SlackAttachment, SlackMessage, Text};
+use crate::twitch::{self, GameQuery, LookupError, TimeoutConfig, TwitchGame, TwitchStream};
+use crate::workspace::WorkspaceStore;
+use crate::Error;
+use futures::future::BoxFuture;
+use std::time::Duration;
+
+/// Categories shown for a search; each costs a streams request.
+const SEARCH_RESULTS: usize = 5;
+/// Live streams listed on each category's card.
+const TOP_STREAMS: usize = 3;
+
+static SCHEMA: CommandSchema = CommandSchema {
+    command: "/tgame",
+    summary: "Look up a Twitch category",
+    forms: &[
+        Schema {
+            subcommand: Some("igdb"),
+            summary: "find the category for an IGDB game ID",
+            args: &[Arg::required("id", Kind::Number, "the game's IGDB ID")],
+            flags: &[],
+        },
+        Schema {
+            subcommand: Some("search"),
+            summary: "search categories by name",
+            args: &[Arg::required("query", Kind::Text, "part of the name, e.g. `factor`")],
+            flags: &[],
+        },
+        Schema {
+            subcommand: None,
+            summary: "look up a category by its exact name or Twitch ID, searching if there's no exact match",
+            args: &[Arg::required("game", Kind::Text, "the category name, e.g. `Factorio`, or its ID")],
+            flags: &[],
+        },
+    ],
+    dry_run: false,
+};
+
+/// `/tgame <name or id>`, `/tgame search <query>` and `/tgame igdb <id>`: a card per matching
+/// category with its box art and top live streams.
+pub struct Games;
+
+enum Lookup {
+    /// An exact lookup; a name that doesn't match falls back to a search.
+    Exact(GameQuery),
+    Search(String),
+}
+
+struct Category {
+    game: TwitchGame,
+    streams: Vec<TwitchStream>,
+    /// Search results don't say whether IGDB lists the game.
+    searched: bool,
+}
+
+impl Command for Games {
+    fn name(&self) -> &'static str {
+        "/tgame"
+    }
+
+    fn schema(&self) -> Option<&'static CommandSchema> {
+        Some(&SCHEMA)
+    }
+
+    fn cooldown(&self) -> Option<Duration> {
+        Some(command::DEFAULT_COOLDOWN)
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>> {
+        Box::pin(async move {
+            let (lookup, asked) = match invocation.args.subcommand {
+                Some("igdb") => {
+                    let id = invocation.args.number("id").unwrap_or_default().to_string();
+                    (Lookup::Exact(GameQuery::IgdbId(id.clone())), format!("IGDB ID {}", id))
+                }
+                Some("search") => {
+                    let query = invocation.args.text("query").unwrap_or_default().to_string();
+                    (Lookup::Search(query.clone()), query)
+                }
+                _ => {
+                    let game = invocation.args.text("game").unwrap_or_default().to_string();
+                    let query = if game.bytes().all(|b| b.is_ascii_digit()) {
+                        GameQuery::Id(game.clone())
+                    } else {
+                        GameQuery::Name(game.clone())
+                    };
+                    (Lookup::Exact(query), game)
+                }
+            };
+
+            let secrets = invocation.secrets.clone();
+            let categories = tokio::task::spawn_blocking(move || categories(lookup, &secrets)).await?;
+            let categories = match categories {
+                Ok(categories) if categories.is_empty() => {
+                    return SlackMessage::builder()
+                        .ephemeral()
+                        .text(format!("No Twitch category found for {}.", asked))
+                        .build()
+                }
+                Ok(categories) => categories,
+                Err(e) => return SlackMessage::builder().ephemeral().text(e.user_message(&asked)).build(),
+            };
+
+            let config = WorkspaceStore::from_env().load(&invocation.req.team_id).await?;
+            let (locale, filter) = (Locale::for_workspace(&config), OutputFilter::for_workspace(&config));
+            let names: Vec<&str> = categories.iter().map(|category| category.game.name.as_str()).collect();
+            SlackMessage::builder()
+                .in_channel()
+                .text(names.join(", "))
+                .attachments(categories.iter().map(|category| game_attachment(category, locale, &filter)))
+                .build()
+        })
+    }
+}
+
+/// The matching categories, each with its top live streams.
+fn categories(lookup: Lookup, secrets: &Secrets) -> Result<Vec<Category>, LookupError> {
+    let client = twitch::client(&TimeoutConfig::from_env())?;
+    let (games, searched) = match lookup {
+        Lookup::Exact(query) => {
+            let games = twitch::get_games(&client, &query, secrets)?;
+            match query {
+                GameQuery::Name(name) if games.is_empty() => {
+                    (twitch::search_categories(&client, &name, SEARCH_RESULTS, secrets)?, true)
+                }
+                _ => (games, false),
+            }
+        }
+        Lookup::Search(query) => (twitch::search_categories(&client, &query, SEARCH_RESULTS, secrets)?, true),
+    };
+
+    Ok(games
+        .into_iter()
+        .map(|game| {
+            // The card still identifies the category without its streams.
+            let streams = twitch::get_top_streams(&client, &game.id, TOP_STREAMS, secrets).unwrap_or_default();
+            Category {
+                game,
+                streams,
+                searched,
+            }
+        })
+        .collect())
+}
+
+fn game_attachment(category: &Category, locale: Locale, filter: &OutputFilter) -> SlackAttachment {
+    let game = &category.game;
+    let mut details = vec![format!("*{}*", game.name), format!("Twitch ID: `{}`", game.id)];
+    match game.igdb_url() {
+        Some(url) => details.push(format!("IGDB: <{}|{}>", url, game.igdb_id)),
+        None if category.searched => {}
+        None => details.push("Not listed on IGDB".to_string()),
+    }
+    if category.streams.is_empty() {
+        details.push("_Nobody is live in this category._".to_string());
+    } else {
+        details.push("*Top live streams*".to_string());
+    }
+    for stream in &category.streams {
+        details.push(format!(
+            "• <https://twitch.tv/{}|{}> · {} viewers · {}",
+            stream.user_login,
+            stream.user_name,
+            locale.count(stream.viewer_count),
+            filter.scrub(&stream.title)
+        ));
+    }
+
+    SlackAttachment {
+        fallback: format!("{} (Twitch category {})", game.name, game.id),
+        color: Color::TWITCH_PURPLE,
+        author_name: game.name.clone(),
+        author_icon: game.box_art(52, 72),
+        blocks: vec![Block::Section {
+            text: Text::Mrkdwn {
+                text: details.join("\n"),
+            },
+            accessory: Some(Element::Image {
+                image_url: game.box_art(144, 192),
+                alt_text: format!("{} box art", game.name),
+            }),
+        }],
+    }
+}
@@ -0,0 +1,175 @@
+//! `/tstream`: whether channels are live, with their stream details or when they last streamed.
+
+use crate::args::{Arg, CommandSchema, Kind, Schema};
+use crate::command::{self, Command, Invocation};
+use crate::locale::Locale;
+use crate::render;
+use crate::scrub::OutputFilter;
+use crate::secrets::Secrets;
+use crate::slack::{Block, Color, SlackAttachment, SlackMessage, Text};
+use crate::twitch::{self, LookupError, TimeoutConfig, TwitchStream, TwitchUser, TwitchVideo};
+use crate::workspace::WorkspaceStore;
+use crate::Error;
+use chrono::Utc;
+use futures::future::BoxFuture;
+use std::time::Duration;
+
+/// Channels one `/tstream` looks up. Each offline channel costs a videos request.
+const MAX_CHANNELS: usize = 10;
+
+static SCHEMA: CommandSchema = CommandSchema {
+    command: "/tstream",
+    summary: "Show whether channels are live, with title, category, viewers and uptime",
+    forms: &[Schema {
+        subcommand: None,
+        summary: "check up to 10 channels",
+        args: &[Arg::required("channels", Kind::Text, "logins separated by spaces or commas")],
+        flags: &[],
+    }],
+    dry_run: false,
+};
+
+/// `/tstream <logins>`: a card per channel, live ones with their stream details and offline ones
+/// with when they last streamed.
+pub struct Streams;
+
+/// A channel and what's known about its broadcast.
+struct Status {
+    user: TwitchUser,
+    stream: Option<TwitchStream>,
+    last_broadcast: Option<TwitchVideo>,
+}
+
+impl Command for Streams {
+    fn name(&self) -> &'static str {
+        "/tstream"
+    }
+
+    fn schema(&self) -> Option<&'static CommandSchema> {
+        Some(&SCHEMA)
+    }
+
+    fn cooldown(&self) -> Option<Duration> {
+        Some(command::DEFAULT_COOLDOWN)
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>> {
+        Box::pin(async move {
+            let text = invocation.args.text("channels").unwrap_or_default();
+            let logins: Vec<String> = text
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .map(|login| login.trim_start_matches('@').to_ascii_lowercase())
+                .filter(|login| !login.is_empty())
+                .collect();
+            if logins.len() > MAX_CHANNELS {
+                return SlackMessage::builder()
+                    .ephemeral()
+                    .text(format!("`/tstream` checks up to {} channels at a time.", MAX_CHANNELS))
+                    .build();
+            }
+
+            let secrets = invocation.secrets.clone();
+            let lookup_logins = logins.clone();
+            let statuses = tokio::task::spawn_blocking(move || statuses(&lookup_logins, &secrets)).await?;
+            let statuses = match statuses {
+                Ok(statuses) if statuses.is_empty() => {
+                    return SlackMessage::builder()
+                        .ephemeral()
+                        .text(format!("No Twitch users found for {}", text))
+                        .build()
+                }
+                Ok(statuses) => statuses,
+                Err(e) => return SlackMessage::builder().ephemeral().text(e.user_message(text)).build(),
+            };
+
+            // The reply is posted to the channel, so titles go through the workspace's filter.
+            let config = WorkspaceStore::from_env().load(&invocation.req.team_id).await?;
+            let (locale, filter) = (Locale::for_workspace(&config), OutputFilter::for_workspace(&config));
+            let users: Vec<TwitchUser> = statuses.iter().map(|status| status.user.clone()).collect();
+            let streams: Vec<TwitchStream> = statuses.iter().filter_map(|status| status.stream.clone()).collect();
+            SlackMessage::builder()
+                .in_channel()
+                .text(render::summary(&users, Some(&streams[..])))
+                .attachments(statuses.iter().map(|status| status_attachment(status, locale, &filter)))
+                .build()
+        })
+    }
+}
+
+/// The users in request order, their live streams, and for offline ones their last broadcast.
+fn statuses(logins: &[String], secrets: &Secrets) -> Result<Vec<Status>, LookupError> {
+    let client = twitch::client(&TimeoutConfig::from_env())?;
+    let users = twitch::get_users_by_login(&client, logins, secrets)?;
+    let ids: Vec<String> = users.iter().map(|user| user.id.clone()).collect();
+    let mut streams = twitch::get_streams(&client, &ids, secrets)?;
+
+    let mut statuses = vec![];
+    for user in users {
+        let stream = streams
+            .iter()
+            .position(|stream| stream.user_id == user.id)
+            .map(|i| streams.remove(i));
+        // Only a nice-to-have; an offline card without it still answers the question.
+        let last_broadcast = match stream {
+            Some(_) => None,
+            None => twitch::get_last_broadcast(&client, &user.id, secrets).ok().flatten(),
+        };
+        statuses.push(Status {
+            user,
+            stream,
+            last_broadcast,
+        });
+    }
+    Ok(statuses)
+}
+
+fn status_attachment(status: &Status, locale: Locale, filter: &OutputFilter) -> SlackAttachment {
+    let user = &status.user;
+    let link = format!("<https://twitch.tv/{}|{}>", user.login, user.display_name);
+    let (color, text) = match &status.stream {
+        Some(stream) => {
+            let category = if stream.game_name.is_empty() {
+                "No category"
+            } else {
+                stream.game_name.as_str()
+            };
+            let uptime = stream
+                .uptime(Utc::now())
+                .map(render::format_duration)
+                .unwrap_or_else(|| "?".to_string());
+            (
+                Color::LIVE,
+                format!(
+                    "🔴 *{}* is live\n{}\n{} · {} viewers · up {}",
+                    link,
+                    filter.scrub(&stream.title),
+                    category,
+                    locale.count(stream.viewer_count),
+                    uptime
+                ),
+            )
+        }
+        None => {
+            let since = match status.last_broadcast.as_ref().and_then(TwitchVideo::ended_at) {
+                Some(ended) => format!(
+                    "offline since <!date^{}^{{date_short_pretty}} {{time}}|{}>",
+                    ended.timestamp(),
+                    locale.date_time(&ended)
+                ),
+                None => "offline".to_string(),
+            };
+            (Color::OFFLINE, format!("⚫ *{}* is {}", link, since))
+        }
+    };
+
+    SlackAttachment {
+        fallback: format!("{} on Twitch", user.display_name),
+        color,
+        author_name: user.display_name.clone(),
+        author_icon: user.profile_image_url.clone(),
+        blocks: vec![Block::Section {
+            text: Text::Mrkdwn { text },
+            accessory: None,
+        }],
+    }
+}
@@ -0,0 +1,508 @@
+//! `/tuser`: Twitch users by login, ID or channel URL, as cards or one line each, or a search
+//! for channels by name. Results are cached (see `cache`), served stale while Twitch is down, and
+//! compared with what the invoker saw last time.
+
+use crate::cache::CacheStore;
+use crate::cards::{self, CardDetails};
+use crate::command::{Command, Invocation};
+use crate::concurrency::TeamLimiter;
+use crate::deferred::{self, Delivery, DeliveryFile};
+use crate::error::BotError;
+use crate::help::{self, Form, Help};
+use crate::locale::Locale;
+use crate::query::{self, cache_key, parse_command_text, LookupResult, UserQuery, NOCACHE, QUIET};
+use crate::render;
+use crate::scrub::OutputFilter;
+use crate::secrets::{self, Secrets, VersionedSecrets, SECRET_ID};
+use crate::shadow;
+use crate::slack::blocks::Blocks;
+use crate::slack::{self, ResponseType, SlackMessage, SlashCommand};
+use crate::translate::{Translation, Translator};
+use crate::twitch::{LookupError, TimeoutConfig, TwitchUser};
+use crate::usage::UsageStore;
+use crate::workspace::{WorkspaceConfig, WorkspaceStore};
+use crate::Error;
+use chrono::Utc;
+use futures::future::BoxFuture;
+use futures::stream::{self, StreamExt};
+use log::{error, info};
+use serde_json::Value;
+
+static HELP: Help = Help {
+    command: "/tuser",
+    summary: "Look up Twitch users by login, ID, or channel URL",
+    forms: &[
+        Form {
+            syntax: "<login, id or url> [more...]",
+            summary: "a card per user, with live status and follower count",
+        },
+        Form {
+            syntax: "search: <words>",
+            summary: "search channels by name, for when you don't know the exact login",
+        },
+    ],
+    options: &[
+        ("--compact", "one line per user instead of cards"),
+        ("nocache", "ask Twitch even if the bot looked them up a moment ago"),
+        ("quiet", "show the results only to you; `private` works too"),
+    ],
+    examples: &["camr, muxy 44322889", "https://twitch.tv/pokimane --compact", "search: ninj"],
+};
+
+/// A lookup rerun by the same person in the same channel within this long shows what changed.
+const SESSION_SECS: i64 = 30 * 60;
+/// How many descriptions are sent to the translator at once.
+const TRANSLATE_FAN_OUT: usize = 5;
+
+/// `/tuser <logins, IDs or URLs>` and `/tuser search: <words>`. It leases a concurrency slot
+/// only when it has to go to Twitch, not for answers from the cache.
+pub struct Users;
+
+impl Command for Users {
+    fn name(&self) -> &'static str {
+        "/tuser"
+    }
+
+    fn summary(&self) -> &'static str {
+        HELP.summary
+    }
+
+    fn leases_own_slot(&self) -> bool {
+        true
+    }
+
+    fn run(&self, invocation: Invocation) -> BoxFuture<'_, Result<SlackMessage, Error>> {
+        Box::pin(search_for_users(invocation))
+    }
+}
+
+async fn search_for_users(invocation: Invocation) -> Result<SlackMessage, Error> {
+    let started = tokio::time::Instant::now();
+    let timeouts = TimeoutConfig::from_env();
+    let Invocation {
+        req,
+        secrets,
+        secrets_version,
+        quiet,
+        event,
+        ..
+    } = invocation;
+    let mut secrets = VersionedSecrets {
+        version_id: secrets_version,
+        secrets,
+    };
+
+    if req.text.trim().is_empty() || help::is_request(&req.text) {
+        return HELP.message();
+    }
+
+    let query = match parse_command_text(&req.text) {
+        Ok(query) => query,
+        Err(e) => match BotError::find(&e) {
+            Some(BotError::BadRequest(problem)) => return HELP.error_message(problem),
+            _ => return Err(e),
+        },
+    };
+
+    let responses = if deferred::is_deferred(&event) {
+        // The final reply goes to response_url too.
+        slack::MAX_RESPONSES - 1
+    } else {
+        slack::MAX_RESPONSES
+    };
+
+    let workspace = load_workspace(&req.team_id).await;
+    let compact = query.has_flag("compact") || workspace.compact_output;
+    let results = ResponseType::for_results(quiet || query.has_flag(QUIET));
+
+    let cache = CacheStore::from_env();
+    let cache_key = cache_key(&query, compact);
+    // `nocache` only skips the read; what Twitch says is still cached for the next lookup.
+    let cached = if query.has_flag(NOCACHE) {
+        None
+    } else {
+        match cache.get::<LookupResult>(&cache_key).await {
+            Ok(cached) => cached,
+            Err(e) => {
+                error!("Could not read the lookup cache: {}", e);
+                None
+            }
+        }
+    };
+    record_cache(&req.team_id, cached.as_ref().map_or(false, |cached| cached.is_fresh())).await;
+
+    let (users_result, stale_note) = match cached {
+        Some(cached) if cached.is_fresh() => (Ok(cached.value), None),
+        cached => {
+            // Only a lookup that goes to Twitch takes one of the workspace's slots.
+            let limiter = TeamLimiter::from_env();
+            // Slots that can't be read don't stop the lookup; it runs as if there were no limit.
+            let lease = match limiter.acquire(&req.team_id).await {
+                Ok(Some(lease)) => Some(lease),
+                Ok(None) => return SlackMessage::builder().ephemeral().text(limiter.busy_message()).build(),
+                Err(e) => {
+                    error!("Could not lease a concurrency slot for {}: {}", req.team_id, e);
+                    None
+                }
+            };
+            let deadline = started + timeouts.command_budget;
+            let mut users_result = lookup_users(&req.team_id, &query, &secrets, timeouts, deadline, compact).await;
+            if let Err(LookupError::Unauthorized(_)) = users_result {
+                match secrets::refetch_after_auth_failure(SECRET_ID, &secrets).await {
+                    Ok(Some(rotated)) => {
+                        secrets = rotated;
+                        users_result = lookup_users(&req.team_id, &query, &secrets, timeouts, deadline, compact).await;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        if let Some(lease) = lease {
+                            limiter.release(lease).await;
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+            if let Some(lease) = lease {
+                limiter.release(lease).await;
+            }
+
+            match (users_result, cached) {
+                (Ok(result), _) => {
+                    // Partial results would hide the missing fields for the whole cache lifetime.
+                    if result.missing.is_empty() {
+                        if let Err(e) = cache.put(&cache_key, &result).await {
+                            error!("Could not write the lookup cache: {}", e);
+                        }
+                    }
+                    (Ok(result), None)
+                }
+                (Err(e), Some(stale)) if serve_stale_on(&e) => {
+                    info!("Serving a cached lookup ({}) after {:?}", stale.as_of(), e);
+                    let note = stale_banner(&e, &stale.as_of());
+                    (Ok(stale.value), Some(note))
+                }
+                (Err(e), _) => (Err(e), None),
+            }
+        }
+    };
+
+    // A stale result could be older than what they saw last time, so it isn't compared.
+    let session_key = format!("session:{}:{}:{}:{}", req.team_id, req.channel_id, req.user_id, cache_key);
+    let changes: Vec<String> = match (&users_result, &stale_note) {
+        (Ok(result), None) if !result.users.is_empty() => {
+            since_last_look(&cache, &session_key, result, &workspace).await
+        }
+        _ => vec![],
+    };
+    let changes: Vec<String> = changes.iter().map(|change| format!("• {}", change)).collect();
+    let changes_note = if changes.is_empty() {
+        None
+    } else {
+        Some(format!("_Since you last looked:_\n{}", changes.join("\n")))
+    };
+    let changes = changes_note.as_ref().map(|note| format!("\n{}", note)).unwrap_or_default();
+
+    match users_result {
+        // Only the person searching needs to pick from the matches.
+        Ok(result) if query.search.is_some() => {
+            let text = result.suggestions.first().map(render::search_results).unwrap_or_default();
+            SlackMessage::builder().ephemeral().text(text).build()
+        }
+        // Twitch answered and knows none of them, which isn't a failed lookup.
+        Ok(result) if result.users.is_empty() => {
+            let mut lines = vec![format!("Twitch has no users called {}.", result.not_found.join(", "))];
+            lines.extend(result.did_you_mean());
+            SlackMessage::builder().response_type(results).text(lines.join("\n")).build()
+        }
+        Ok(result) if compact => {
+            let mut text = if result.missing.is_empty() {
+                render::compact(&result.users, &result.streams, Utc::now(), Locale::for_workspace(&workspace))
+            } else {
+                let names: Vec<String> = result.users.iter().map(|u| format!("• {}", u.display_name)).collect();
+                format!("{}\n_Twitch was too slow to report {}._", names.join("\n"), result.missing.join(", "))
+            };
+            if let Some(note) = result.not_found_note() {
+                text = format!("{}\n{}", text, note);
+            }
+            if let Some(note) = stale_note {
+                text = format!("{}\n{}", text, note);
+            }
+            SlackMessage::builder().response_type(results).text(format!("{}{}", text, changes)).build()
+        }
+        Ok(result) => {
+            let translations = if workspace.translate_descriptions {
+                let deadline = started + timeouts.command_budget;
+                translate_descriptions(&result.users, &OutputFilter::for_workspace(&workspace), deadline).await
+            } else {
+                vec![None; result.users.len()]
+            };
+            let streams = Some(result.streams.as_slice()).filter(|_| result.has_live_status());
+            let details = result.users.iter().zip(&translations).map(|(user, translation)| CardDetails {
+                followers: result.followers.get(&user.id).copied(),
+                live: streams.map(|streams| streams.iter().find(|s| s.user_id == user.id)),
+                watch: !workspace.watchlist.contains(&user.login),
+                translation: translation.as_ref(),
+            });
+            let (locale, filter) = (Locale::for_workspace(&workspace), OutputFilter::for_workspace(&workspace));
+            let mut message = SlackMessage::builder().response_type(results);
+            if workspace.legacy_cards {
+                message = message.attachments(
+                    result.users.iter().zip(details).map(|(user, details)| {
+                        cards::user_attachment(user, details, locale, &filter)
+                    }),
+                );
+            } else {
+                // With blocks the message text is only the notification, so the banner needs one.
+                let mut layout = match &stale_note {
+                    Some(note) => Blocks::new().context(vec![note.clone()]).divider(),
+                    None => Blocks::new(),
+                };
+                for (i, (user, details)) in result.users.iter().zip(details).enumerate() {
+                    if i > 0 {
+                        layout = layout.divider();
+                    }
+                    layout = layout.extend(cards::user_blocks(user, details, locale, &filter));
+                }
+                if let Some(note) = result.not_found_note() {
+                    layout = layout.divider().context(vec![note]);
+                }
+                // Like the banner, the changes would otherwise only be in the notification text.
+                if let Some(note) = changes_note {
+                    layout = layout.divider().context(vec![note]);
+                }
+                message = message.blocks(layout.into_blocks());
+            }
+            let summary = match result.not_found_note() {
+                Some(note) => format!("{}\n{}{}", render::summary(&result.users, streams), note, changes),
+                None => format!("{}{}", render::summary(&result.users, streams), changes),
+            };
+            message = match stale_note {
+                Some(note) => message.text(format!("{}\n{}", note, summary)),
+                None => message.text(summary),
+            };
+            let parts = message.build()?.split();
+            if parts.len() == 1 {
+                return Ok(parts.into_iter().next().expect("split returns a part"));
+            }
+            send_in_parts(&event, &req, &secrets.secrets, parts, responses, &result, &workspace).await
+        }
+        // Only the person asking needs to know it failed.
+        Err(e) => SlackMessage::builder()
+            .ephemeral()
+            .text(e.user_message(&req.text))
+            .build(),
+    }
+}
+
+/// English translations of the users' descriptions, in order, scrubbed since cards are posted to
+/// the channel. At most [`TRANSLATE_FAN_OUT`] are asked for at once, and those not back by
+/// `deadline` are left out. A translation that fails or is left out just leaves that card
+/// without one.
+async fn translate_descriptions(
+    users: &[TwitchUser],
+    filter: &OutputFilter,
+    deadline: tokio::time::Instant,
+) -> Vec<Option<Translation>> {
+    let translator = &Translator::from_env();
+    let mut pending = stream::iter(users.iter().enumerate())
+        .map(|(i, user)| async move { (i, translator.to_english(&user.description).await) })
+        .buffer_unordered(TRANSLATE_FAN_OUT);
+
+    let mut translations = vec![None; users.len()];
+    loop {
+        match tokio::time::timeout_at(deadline, pending.next()).await {
+            Ok(Some((i, Ok(translation)))) => {
+                translations[i] = translation.map(|translation| Translation {
+                    text: filter.scrub(&translation.text),
+                    ..translation
+                })
+            }
+            Ok(Some((_, Err(e)))) => error!("Could not translate a channel description: {}", e),
+            Ok(None) => break,
+            Err(_) => {
+                info!("Out of time translating descriptions; the rest are left untranslated");
+                break;
+            }
+        }
+    }
+    translations
+}
+
+/// Failures where a slightly old answer beats no answer. Rejections and auth problems aren't
+/// among them: they say something about the request or the bot, not whether Twitch is up.
+fn serve_stale_on(error: &LookupError) -> bool {
+    match error {
+        LookupError::Timeout
+        | LookupError::Outage(_)
+        | LookupError::RateLimited(_)
+        | LookupError::Maintenance(_)
+        | LookupError::Request => true,
+        LookupError::Unauthorized(_) | LookupError::Rejected(_) | LookupError::Decode => false,
+    }
+}
+
+/// The banner over cached results, saying why they're cached.
+fn stale_banner(error: &LookupError, as_of: &str) -> String {
+    match error {
+        LookupError::Maintenance(until) => format!(
+            "🛠 _Twitch maintenance: these results are cached, {}. Twitch expects to be back \
+             <!date^{}^{{time}}|shortly>._",
+            as_of, until
+        ),
+        _ => format!("_Twitch isn't responding, so these results are cached, {}._", as_of),
+    }
+}
+
+/// Delivers cards that were too big for one message. Up to `responses` parts go to the
+/// command's `response_url` in order; more than that and the results are uploaded as a text
+/// file to the channel instead, unless they were asked for privately, when only the first
+/// `responses` parts are sent. Posting them all could run past the three seconds Slack waits
+/// for an answer, so outside a deferred run (see `deferred`) the rendered parts are handed to
+/// one, which only posts them, and Slack gets the placeholder meanwhile.
+async fn send_in_parts(
+    event: &Value,
+    req: &SlashCommand,
+    secrets: &Secrets,
+    parts: Vec<SlackMessage>,
+    responses: usize,
+    result: &LookupResult,
+    workspace: &WorkspaceConfig,
+) -> Result<SlackMessage, Error> {
+    let hand_off = !deferred::is_deferred(event) && !req.response_url.is_empty();
+    // The deferred run's own reply goes to response_url too.
+    let responses = if hand_off { responses.min(slack::MAX_RESPONSES - 1) } else { responses };
+    let delivery = delivery(parts, responses, result, workspace)?;
+    if hand_off {
+        if let Some(placeholder) = deferred::hand_off_delivery(event, &delivery).await {
+            return Ok(placeholder);
+        }
+    }
+    delivery.send(req, secrets).await
+}
+
+/// How [`send_in_parts`] posts `parts`.
+fn delivery(
+    parts: Vec<SlackMessage>,
+    responses: usize,
+    result: &LookupResult,
+    workspace: &WorkspaceConfig,
+) -> Result<Delivery, Error> {
+    let count = parts.len();
+    if count <= responses {
+        info!("Sending {} users as {} messages", result.users.len(), count);
+        return Ok(Delivery {
+            messages: parts,
+            file: None,
+            reply: SlackMessage::builder()
+                .ephemeral()
+                .text(format!("That's a lot of users, so the results are split over {} messages.", count))
+                .build()?,
+        });
+    }
+
+    // A file would be seen by the whole channel, so results meant only for the invoker stop at
+    // what the response URL takes.
+    if !parts[0].is_in_channel() {
+        info!("Sending {} of {} messages of private results", responses, count);
+        return Ok(Delivery {
+            messages: parts.into_iter().take(responses).collect(),
+            file: None,
+            reply: SlackMessage::builder()
+                .ephemeral()
+                .text(format!(
+                    "That's more users than fit in private messages, so only the first {} of {} parts are shown. \
+                     Look up fewer at once to see the rest.",
+                    responses, count
+                ))
+                .build()?,
+        });
+    }
+
+    info!("Uploading {} users as a file; they'd take {} messages", result.users.len(), count);
+    let locale = Locale::for_workspace(workspace);
+    // The file is posted to the channel like the cards would have been, so it's scrubbed the same.
+    let content = render::compact(&result.users, &result.streams, Utc::now(), locale);
+    Ok(Delivery {
+        messages: vec![],
+        file: Some(DeliveryFile {
+            name: "twitch-users.txt".to_string(),
+            content: OutputFilter::for_workspace(workspace).scrub(&content),
+        }),
+        reply: SlackMessage::builder()
+            .ephemeral()
+            .text(format!("Too many users for messages, so the {} results are attached as a file.", result.users.len()))
+            .build()?,
+    })
+}
+
+/// What changed since this person last ran the same lookup in the channel, if that was within
+/// `SESSION_SECS`, then remembers `result` as what they've now seen. The lookup cache holds the
+/// snapshots; failing to read or write one just loses the comparison.
+async fn since_last_look(
+    cache: &CacheStore,
+    session_key: &str,
+    result: &LookupResult,
+    workspace: &WorkspaceConfig,
+) -> Vec<String> {
+    let previous = match cache.get::<LookupResult>(session_key).await {
+        Ok(previous) => previous.filter(|previous| previous.age_secs() < SESSION_SECS),
+        Err(e) => {
+            error!("Could not read the previous lookup for {}: {}", session_key, e);
+            None
+        }
+    };
+    if let Err(e) = cache.put(session_key, result).await {
+        error!("Could not remember the lookup for {}: {}", session_key, e);
+    }
+    match previous {
+        Some(previous) => render::changes(
+            &previous.value,
+            result,
+            previous.value.has_live_status() && result.has_live_status(),
+            Locale::for_workspace(workspace),
+            &OutputFilter::for_workspace(workspace),
+        ),
+        None => vec![],
+    }
+}
+
+/// Counts whether the cache answered the lookup, for `/tquota`; the router counts the invocation.
+async fn record_cache(team_id: &str, cache_hit: bool) {
+    if let Err(e) = UsageStore::from_env().record_cache(team_id, cache_hit).await {
+        error!("Could not record cache usage for workspace {}: {}", team_id, e);
+    }
+}
+
+async fn load_workspace(team_id: &str) -> WorkspaceConfig {
+    if team_id.is_empty() {
+        return WorkspaceConfig::default();
+    }
+
+    match WorkspaceStore::from_env().load(team_id).await {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Could not load config for workspace {}, using defaults: {}", team_id, e);
+            WorkspaceConfig::default()
+        }
+    }
+}
+
+async fn lookup_users(
+    team_id: &str,
+    query: &UserQuery,
+    secrets: &VersionedSecrets,
+    timeouts: TimeoutConfig,
+    deadline: tokio::time::Instant,
+    compact: bool,
+) -> Result<LookupResult, LookupError> {
+    let api = shadow::api(secrets.secrets.clone(), team_id);
+    let lookup = query::lookup(api.as_ref(), query, &timeouts, deadline.into_std(), compact);
+
+    match tokio::time::timeout_at(deadline, lookup).await {
+        Ok(result) => result,
+        Err(_elapsed) => {
+            error!("Twitch lookup exceeded the {:?} command budget", timeouts.command_budget);
+            Err(LookupError::Timeout)
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Tracks Twitch maintenance windows. Helix answers with a server error whose body says it's
+//! under maintenance, usually with a `Retry-After` saying for how long; a 503 that doesn't say
+//! so is an ordinary outage. Once one is seen, app requests are refused locally
+//! (so lookups fall back to cached data) except for an occasional probe. The first probe that
+//! succeeds ends the window.
+//!
+//! Like the rate-limit bucket, the state is kept process-wide: every request in a container talks
+//! to the same Twitch.
+
+use crate::ratelimit::now_secs;
+use log::{info, warn};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::sync::Mutex;
+
+/// How often a request is let through to check whether maintenance is over.
+const PROBE_INTERVAL_SECS: u64 = 30;
+/// How long to assume maintenance lasts when Twitch doesn't say.
+const DEFAULT_WINDOW_SECS: u64 = 5 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Window {
+    /// Unix seconds Twitch said maintenance would last until, or our guess.
+    until: u64,
+    last_probe: u64,
+}
+
+static WINDOW: Mutex<Option<Window>> = Mutex::new(None);
+
+/// Whether a failed response is Twitch's maintenance page rather than an ordinary outage, and if
+/// so until when (Unix seconds): `Retry-After` from now, or [`DEFAULT_WINDOW_SECS`] without one.
+pub fn detect(status: u16, headers: &HeaderMap, body: &str) -> Option<u64> {
+    if !(500..600).contains(&status) || !body.to_ascii_lowercase().contains("maintenance") {
+        return None;
+    }
+    let retry_after = headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    Some(now_secs() + retry_after.unwrap_or(DEFAULT_WINDOW_SECS))
+}
+
+/// Starts (or extends) a maintenance window ending at `until`.
+pub fn begin(until: u64) {
+    let mut window = WINDOW.lock().unwrap_or_else(|e| e.into_inner());
+    if window.is_none() {
+        warn!("Twitch is under maintenance; serving cached data until probes succeed");
+    }
+    *window = Some(Window {
+        until: until.max(window.map_or(0, |w| w.until)),
+        last_probe: now_secs(),
+    });
+}
+
+/// Ends the maintenance window after a request got through.
+pub fn end() {
+    let mut window = WINDOW.lock().unwrap_or_else(|e| e.into_inner());
+    if window.take().is_some() {
+        info!("Twitch maintenance is over");
+    }
+}
+
+/// `Some(until)` if a request should be refused without asking Twitch. Lets one request through
+/// as a probe every [`PROBE_INTERVAL_SECS`] and once the expected end has passed.
+pub fn refuse() -> Option<u64> {
+    let mut guard = WINDOW.lock().unwrap_or_else(|e| e.into_inner());
+    let window = guard.as_mut()?;
+    let now = now_secs();
+    if now >= window.until || now.saturating_sub(window.last_probe) >= PROBE_INTERVAL_SECS {
+        window.last_probe = now;
+        return None;
+    }
+    Some(window.until)
+}
@@ -0,0 +1,179 @@
+//! Canned Helix responses, for running the bot without Twitch. With `TWITCH_MOCK_DIR` set under
+//! `--local` (and only then, so a deployed function always talks to Twitch), Helix requests are
+//! answered from JSON files in that directory instead of the network:
+//! `GET /helix/users?login=ninja` reads `users.json` and `/helix/channels/followers` reads
+//! `channels_followers.json`.
+//!
+//! Each file holds a Helix response body. Its `data` is narrowed to the items whose fields match
+//! the request's parameters (`login=ninja` keeps items with `"login": "ninja"`), so one
+//! `users.json` answers every lookup the way Twitch would, leaving out unknown users. Endpoints
+//! without a file answer with an empty `data`, and requests that change something are only
+//! logged.
+//!
+//! [`MockTwitch`] is the in-memory counterpart for tests: a [`TwitchApi`] holding the users,
+//! streams and follower counts to answer with, and the failures to answer with instead.
+
+use crate::local;
+use crate::twitch::{helix_base, ChannelMatch, LookupError, TimeoutConfig, TwitchApi, TwitchStream, TwitchUser};
+use futures::future::BoxFuture;
+use log::{error, info};
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+pub fn dir() -> Option<PathBuf> {
+    if !local::requested() {
+        return None;
+    }
+    std::env::var("TWITCH_MOCK_DIR").ok().filter(|dir| !dir.is_empty()).map(PathBuf::from)
+}
+
+pub fn enabled() -> bool {
+    dir().is_some()
+}
+
+/// The canned answer to a Helix request for `url`, decoded as `T`.
+pub fn respond<T: DeserializeOwned>(method: &Method, url: &str) -> Result<T, LookupError> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let path = path.strip_prefix(helix_base()).unwrap_or(path);
+    let endpoint = path.split("/helix/").last().unwrap_or(path).trim_matches('/').replace('/', "_");
+
+    if method != Method::GET {
+        info!("Mock Twitch: {} {} (not sent)", method, url);
+        return decode(Value::Null);
+    }
+
+    let file = dir().unwrap_or_default().join(format!("{}.json", endpoint));
+    let mut body = match std::fs::read_to_string(&file) {
+        Ok(contents) => serde_json::from_str(&contents).map_err(|e| {
+            error!("Mock Twitch: {} isn't valid JSON: {}", file.display(), e);
+            LookupError::Decode
+        })?,
+        Err(_) => json!({ "data": [], "total": 0, "pagination": {} }),
+    };
+
+    let mut params: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (name, value) in query.split('&').filter_map(|param| param.split_once('=')) {
+        params.entry(name).or_default().push(value);
+    }
+    if let Some(Value::Array(items)) = body.get_mut("data") {
+        items.retain(|item| matches(item, &params));
+    }
+    decode(body)
+}
+
+/// Whether the item agrees with every parameter it has a field for. Parameters it has no field
+/// for (`first`, `after`) don't narrow anything.
+fn matches(item: &Value, params: &HashMap<&str, Vec<&str>>) -> bool {
+    params.iter().all(|(name, values)| match item.get(*name) {
+        Some(Value::String(field)) => values.iter().any(|value| field.eq_ignore_ascii_case(value)),
+        Some(field) => values.iter().any(|value| field.to_string() == *value),
+        None => true,
+    })
+}
+
+fn decode<T: DeserializeOwned>(body: Value) -> Result<T, LookupError> {
+    serde_json::from_value(body).map_err(|e| {
+        error!("Mock Twitch: response doesn't fit {}: {}", std::any::type_name::<T>(), e);
+        LookupError::Decode
+    })
+}
+
+/// Answers [`TwitchApi`] calls from memory, matching ids and logins the way Helix does. A call
+/// whose kind (`"users"`, `"streams"`, `"followers"` or `"search"`) is in `failing` fails with
+/// that error.
+#[derive(Default, Clone)]
+pub struct MockTwitch {
+    pub users: Vec<TwitchUser>,
+    pub streams: Vec<TwitchStream>,
+    pub followers: HashMap<String, u64>,
+    pub failing: HashMap<&'static str, LookupError>,
+}
+
+impl MockTwitch {
+    fn fail(&self, kind: &str) -> Result<(), LookupError> {
+        match self.failing.get(kind) {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TwitchApi for MockTwitch {
+    fn users<'a>(
+        &'a self,
+        ids: &'a [String],
+        logins: &'a [String],
+        _timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<TwitchUser>, LookupError>> {
+        Box::pin(async move {
+            self.fail("users")?;
+            Ok(self
+                .users
+                .iter()
+                .filter(|user| {
+                    ids.contains(&user.id) || logins.iter().any(|login| login.eq_ignore_ascii_case(&user.login))
+                })
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn streams<'a>(
+        &'a self,
+        user_ids: &'a [String],
+        _timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<TwitchStream>, LookupError>> {
+        Box::pin(async move {
+            self.fail("streams")?;
+            Ok(self.streams.iter().filter(|stream| user_ids.contains(&stream.user_id)).cloned().collect())
+        })
+    }
+
+    fn follower_counts<'a>(
+        &'a self,
+        users: &'a [TwitchUser],
+        _timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<HashMap<String, u64>, LookupError>> {
+        Box::pin(async move {
+            self.fail("followers")?;
+            Ok(users
+                .iter()
+                .map(|user| (user.id.clone(), self.followers.get(&user.id).copied().unwrap_or_default()))
+                .collect())
+        })
+    }
+
+    /// Users whose login or display name contains `query`, ignoring case.
+    fn search_channels<'a>(
+        &'a self,
+        query: &'a str,
+        count: usize,
+        _timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<ChannelMatch>, LookupError>> {
+        Box::pin(async move {
+            self.fail("search")?;
+            let query = query.to_lowercase();
+            Ok(self
+                .users
+                .iter()
+                .filter(|user| user.login.contains(&query) || user.display_name.to_lowercase().contains(&query))
+                .take(count)
+                .map(|user| {
+                    let stream = self.streams.iter().find(|stream| stream.user_id == user.id);
+                    ChannelMatch {
+                        id: user.id.clone(),
+                        broadcaster_login: user.login.clone(),
+                        display_name: user.display_name.clone(),
+                        is_live: stream.is_some(),
+                        game_name: stream.map(|stream| stream.game_name.clone()).unwrap_or_default(),
+                        title: stream.map(|stream| stream.title.clone()).unwrap_or_default(),
+                        extra: HashMap::new(),
+                    }
+                })
+                .collect())
+        })
+    }
+}
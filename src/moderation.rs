@@ -0,0 +1,329 @@
+//! Moderator actions run from Slack with a linked moderator's token. Bans are previewed
+//! ephemerally to the invoker, whose button (behind Slack's confirmation dialog) carries the
+//! serialized request to the interactivity endpoint, which audits and then executes it. Unban
+//! requests arrive over EventSub and are posted with Approve/Deny buttons that work the same way.
+
+use crate::links::AccountLink;
+use crate::secrets::Secrets;
+use crate::slack::{Block, ConfirmDialog, Element, SlackMessage, Text};
+use crate::twitch::{self, Credentials, LookupError};
+use crate::Error;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const BAN_SCOPE: &str = "moderator:manage:banned_users";
+pub const BAN_ACTION: &str = "confirm_ban";
+pub const WARN_SCOPE: &str = "moderator:manage:warnings";
+pub const UNBAN_SCOPE: &str = "moderator:manage:unban_requests";
+pub const UNBAN_REQUEST_EVENT: &str = "channel.unban_request.create";
+pub const RESOLVE_UNBAN_ACTION: &str = "resolve_unban";
+/// Twitch's longest timeout is two weeks; anything without a duration is a permanent ban.
+pub const MAX_TIMEOUT_SECS: u32 = 14 * 24 * 60 * 60;
+
+pub const WARN_USAGE: &str = "Send a user an official warning in a channel you moderate:\n\
+    `/twarn <channel> <user> <reason>`\n\
+    They have to acknowledge it before they can chat again.";
+
+pub const BAN_USAGE: &str = "Ban or time out a user in a channel you moderate:\n\
+    `/tban <channel> <user> [duration] [reason]`\n\
+    Durations look like `600`, `10m`, `2h`, `1d` or `1w`; leave it out for a permanent ban.";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BanRequest {
+    pub channel: String,
+    pub user: String,
+    /// Timeout length in seconds; `None` bans permanently.
+    pub duration: Option<u32>,
+    pub reason: String,
+}
+
+impl BanRequest {
+    /// Parses `<channel> <user> [duration] [reason...]`. A third word that reads as a duration
+    /// is taken as one; otherwise it starts the reason.
+    pub fn parse(text: &str) -> Result<BanRequest, String> {
+        let mut words = text.split_whitespace();
+        let (channel, user) = match (words.next(), words.next()) {
+            (Some(channel), Some(user)) => (channel, user),
+            _ => return Err(BAN_USAGE.to_string()),
+        };
+
+        let mut rest: Vec<&str> = words.collect();
+        let duration = match rest.first().map(|word| parse_duration(word)) {
+            Some(Some(Ok(secs))) => {
+                rest.remove(0);
+                Some(secs)
+            }
+            Some(Some(Err(message))) => return Err(message),
+            _ => None,
+        };
+
+        Ok(BanRequest {
+            channel: channel.trim_start_matches('@').to_ascii_lowercase(),
+            user: user.trim_start_matches('@').to_ascii_lowercase(),
+            duration,
+            reason: rest.join(" "),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let action = match self.duration {
+            Some(secs) => format!("Time out {} in {} for {}s", self.user, self.channel, secs),
+            None => format!("Permanently ban {} from {}", self.user, self.channel),
+        };
+        if self.reason.is_empty() {
+            action
+        } else {
+            format!("{} (reason: {})", action, self.reason)
+        }
+    }
+
+    /// The ephemeral preview whose button confirms the ban.
+    pub fn confirmation(&self) -> Result<SlackMessage, Error> {
+        let label = if self.duration.is_some() { "Time out" } else { "Ban" };
+        let description = self.describe();
+
+        SlackMessage::builder()
+            .ephemeral()
+            .text(format!("{}?", description))
+            .block(Block::Section {
+                text: Text::Mrkdwn {
+                    text: format!("{}?", description),
+                },
+                accessory: Some(Element::Button {
+                    action_id: BAN_ACTION.to_string(),
+                    text: Text::PlainText { text: label.to_string() },
+                    value: Some(serde_json::to_string(self)?),
+                    url: None,
+                    style: Some("danger".to_string()),
+                    confirm: Some(ConfirmDialog::new("Are you sure?", description, label)),
+                }),
+            })
+            .build()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WarnRequest {
+    pub channel: String,
+    pub user: String,
+    pub reason: String,
+}
+
+impl WarnRequest {
+    /// Parses `<channel> <user> <reason...>`; Twitch requires a reason for warnings.
+    pub fn parse(text: &str) -> Result<WarnRequest, String> {
+        let mut words = text.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(channel), Some(user)) => {
+                let reason = words.collect::<Vec<_>>().join(" ");
+                if reason.is_empty() {
+                    return Err(WARN_USAGE.to_string());
+                }
+                Ok(WarnRequest {
+                    channel: channel.trim_start_matches('@').to_ascii_lowercase(),
+                    user: user.trim_start_matches('@').to_ascii_lowercase(),
+                    reason,
+                })
+            }
+            _ => Err(WARN_USAGE.to_string()),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!("Warn {} in {} (reason: {})", self.user, self.channel, self.reason)
+    }
+}
+
+/// Accepts plain seconds or a number with an `s`/`m`/`h`/`d`/`w` suffix. `None` means the word
+/// isn't a duration at all; `Some(Err)` means it is one Twitch won't accept.
+fn parse_duration(word: &str) -> Option<Result<u32, String>> {
+    let split = word.find(|c: char| !c.is_ascii_digit()).unwrap_or(word.len());
+    let (digits, unit) = word.split_at(split);
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let amount: u64 = digits.parse().ok()?;
+
+    let secs = amount.saturating_mul(multiplier);
+    if secs == 0 || secs > u64::from(MAX_TIMEOUT_SECS) {
+        return Some(Err(
+            "Timeouts must be between 1 second and 2 weeks; leave the duration out for a permanent ban.".to_string(),
+        ));
+    }
+    Some(Ok(secs as u32))
+}
+
+/// Looks up the broadcaster and target user IDs, or says which login doesn't exist.
+fn resolve_ids(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    channel: &str,
+    user: &str,
+) -> Result<Result<(String, String), String>, LookupError> {
+    let users = twitch::get_users_by_login(client, &[channel.to_string(), user.to_string()], secrets)?;
+    let find = |login: &str| users.iter().find(|u| u.login == login).map(|u| u.id.clone());
+
+    Ok(match (find(channel), find(user)) {
+        (Some(broadcaster_id), Some(user_id)) => Ok((broadcaster_id, user_id)),
+        (None, _) => Err(format!("No Twitch user named {}", channel)),
+        (_, None) => Err(format!("No Twitch user named {}", user)),
+    })
+}
+
+/// Resolves both logins and issues the ban as the linked moderator, returning the Slack reply.
+pub fn ban(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    moderator: &AccountLink,
+    request: &BanRequest,
+) -> Result<String, LookupError> {
+    let (broadcaster_id, user_id) = match resolve_ids(client, secrets, &request.channel, &request.user)? {
+        Ok(ids) => ids,
+        Err(message) => return Ok(message),
+    };
+
+    let result = twitch::ban_user(
+        client,
+        Credentials::user(secrets, moderator),
+        &broadcaster_id,
+        &moderator.twitch_user_id,
+        &user_id,
+        request.duration,
+        &request.reason,
+    );
+    match result {
+        Ok(()) => Ok(format!("Done: {}", request.describe())),
+        Err(LookupError::Rejected(400)) => Ok(format!(
+            "Twitch refused to act on {}: they may already be banned, or be a moderator or the broadcaster.",
+            request.user
+        )),
+        Err(LookupError::Unauthorized(403)) => Ok(format!(
+            "{} isn't a moderator in {}'s channel.",
+            moderator.login, request.channel
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// The decision carried by an unban request's Approve or Deny button.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnbanResolution {
+    pub broadcaster_id: String,
+    pub channel: String,
+    pub request_id: String,
+    pub user: String,
+    pub approve: bool,
+}
+
+/// The moderation-channel post for a `channel.unban_request.create` event.
+pub fn unban_request_message(event: &Value) -> Result<SlackMessage, Error> {
+    let field = |name: &str| event.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+    let (channel, user) = (field("broadcaster_user_login"), field("user_login"));
+    let summary = format!("{} asked to be unbanned from {}", user, channel);
+
+    let button = |approve: bool| -> Result<Element, Error> {
+        let resolution = UnbanResolution {
+            broadcaster_id: field("broadcaster_user_id"),
+            channel: channel.clone(),
+            request_id: field("id"),
+            user: user.clone(),
+            approve,
+        };
+        Ok(Element::Button {
+            action_id: format!("{}_{}", RESOLVE_UNBAN_ACTION, if approve { "approve" } else { "deny" }),
+            text: Text::PlainText {
+                text: if approve { "Approve" } else { "Deny" }.to_string(),
+            },
+            value: Some(serde_json::to_string(&resolution)?),
+            url: None,
+            style: Some(if approve { "primary" } else { "danger" }.to_string()),
+            confirm: None,
+        })
+    };
+
+    SlackMessage::builder()
+        .in_channel()
+        .text(summary.clone())
+        .block(Block::Section {
+            text: Text::Mrkdwn {
+                text: format!("*{}*\n>{}", summary, field("text")),
+            },
+            accessory: None,
+        })
+        .block(Block::Actions {
+            elements: vec![button(true)?, button(false)?],
+        })
+        .build()
+}
+
+/// Resolves an unban request as the linked moderator who clicked, returning the Slack reply.
+pub fn resolve_unban(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    moderator: &AccountLink,
+    resolution: &UnbanResolution,
+) -> Result<String, LookupError> {
+    let result = twitch::resolve_unban_request(
+        client,
+        Credentials::user(secrets, moderator),
+        &resolution.broadcaster_id,
+        &moderator.twitch_user_id,
+        &resolution.request_id,
+        resolution.approve,
+    );
+    let verb = if resolution.approve { "approved" } else { "denied" };
+    match result {
+        Ok(()) => Ok(format!(
+            "{} {} the unban request from {} in {}",
+            moderator.login, verb, resolution.user, resolution.channel
+        )),
+        Err(LookupError::Rejected(400)) => Ok(format!(
+            "The unban request from {} was already resolved.",
+            resolution.user
+        )),
+        Err(LookupError::Unauthorized(403)) => Ok(format!(
+            "{} isn't a moderator in {}'s channel.",
+            moderator.login, resolution.channel
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// Resolves both logins and issues the warning as the linked moderator, returning the Slack reply.
+pub fn warn(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    moderator: &AccountLink,
+    request: &WarnRequest,
+) -> Result<String, LookupError> {
+    let (broadcaster_id, user_id) = match resolve_ids(client, secrets, &request.channel, &request.user)? {
+        Ok(ids) => ids,
+        Err(message) => return Ok(message),
+    };
+
+    let result = twitch::warn_user(
+        client,
+        Credentials::user(secrets, moderator),
+        &broadcaster_id,
+        &moderator.twitch_user_id,
+        &user_id,
+        &request.reason,
+    );
+    match result {
+        Ok(()) => Ok(format!("Done: {}", request.describe())),
+        Err(LookupError::Rejected(400)) => Ok(format!(
+            "Twitch refused to warn {}: they may be banned already, or be a moderator or the broadcaster.",
+            request.user
+        )),
+        Err(LookupError::Unauthorized(403)) => Ok(format!(
+            "{} isn't a moderator in {}'s channel.",
+            moderator.login, request.channel
+        )),
+        Err(e) => Err(e),
+    }
+}
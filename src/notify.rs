@@ -0,0 +1,411 @@
+//! Where go-live alerts go. Each destination is a [`NotificationSink`]; a [`Fanout`] sends one
+//! notification to all of a workspace's sinks at once, and a sink that fails is logged and
+//! counted without stopping the others. The sinks that took a notification are remembered, so a
+//! retry after a failure only goes to the ones that didn't. Adding a destination means a new sink
+//! and a [`SinkConfig`] variant, not a change to the code that decides what to announce.
+//!
+//! Slack is always a sink when the workspace has a channel for the alert. The others come from
+//! the workspace's stored `notification_sinks`:
+//!
+//! ```json
+//! [{ "type": "discord", "webhook_url": "https://discord.com/api/webhooks/..." },
+//!  { "type": "email", "to": ["mods@example.com"] }]
+//! ```
+
+use crate::alerts::AlertWindowStore;
+use crate::config;
+use crate::http;
+use crate::metrics;
+use crate::secrets::Secrets;
+use crate::slack::{self, SlackMessage};
+use crate::workspace::WorkspaceConfig;
+use crate::Error;
+use futures::future::{join_all, BoxFuture};
+use log::{error, info, warn};
+use rusoto_ses::{Body, Content, Destination, Message, SendEmailRequest, Ses, SesClient};
+use rusoto_sns::{PublishInput, Sns, SnsClient};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use simple_error::bail;
+use std::time::Instant;
+
+/// SNS refuses longer subjects.
+const MAX_SUBJECT_CHARS: usize = 100;
+
+/// One thing to announce, in a form each sink can render its own way.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub team_id: String,
+    /// The same for every attempt at one announcement, e.g. `pokimane:40123456789`, so a retry
+    /// skips the sinks that already took it. Empty sends to every sink every time.
+    pub key: String,
+    /// Plain text, for subjects and previews: `pokimane is live on Twitch`.
+    pub subject: String,
+    /// The announcement in Slack mrkdwn; sinks that don't speak it get plain links instead.
+    pub text: String,
+    pub url: Option<String>,
+    /// Slack only: added to alerts posted on their own, not to coalesced ones.
+    pub footer: String,
+    /// Slack only: a richer message to post, on its own, instead of `text`.
+    pub card: Option<SlackMessage>,
+}
+
+pub trait NotificationSink: Send + Sync {
+    /// Names the sink in logs and metrics, e.g. `discord`.
+    fn name(&self) -> &'static str;
+
+    /// Whether the notification has failed when this sink didn't take it, even if others did.
+    fn required(&self) -> bool {
+        false
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+/// A destination as stored in a workspace's config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkConfig {
+    /// A Discord channel's incoming webhook.
+    Discord { webhook_url: String },
+    /// Any URL that takes the notification as a JSON POST.
+    Webhook { url: String },
+    Sns { topic_arn: String },
+    /// Sent through SES from `NOTIFY_EMAIL_FROM`.
+    Email { to: Vec<String> },
+}
+
+impl SinkConfig {
+    pub fn sink(&self) -> Box<dyn NotificationSink> {
+        match self {
+            SinkConfig::Discord { webhook_url } => Box::new(DiscordSink {
+                webhook_url: webhook_url.clone(),
+            }),
+            SinkConfig::Webhook { url } => Box::new(WebhookSink { url: url.clone() }),
+            SinkConfig::Sns { topic_arn } => Box::new(SnsSink {
+                client: SnsClient::new(config::get().region.clone()),
+                topic_arn: topic_arn.clone(),
+            }),
+            SinkConfig::Email { to } => Box::new(EmailSink {
+                client: SesClient::new(config::get().region.clone()),
+                from: std::env::var("NOTIFY_EMAIL_FROM").unwrap_or_default(),
+                to: to.clone(),
+            }),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Fanout {
+    sinks: Vec<Box<dyn NotificationSink>>,
+}
+
+impl Fanout {
+    pub fn new() -> Fanout {
+        Fanout::default()
+    }
+
+    /// The workspace's configured sinks; add Slack with [`Fanout::sink`] where there's a channel.
+    pub fn for_workspace(config: &WorkspaceConfig) -> Fanout {
+        Fanout {
+            sinks: config.notification_sinks.iter().map(SinkConfig::sink).collect(),
+        }
+    }
+
+    pub fn sink(mut self, sink: impl NotificationSink + 'static) -> Fanout {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Sends to every sink that hasn't taken the notification yet, concurrently, and returns how
+    /// many took it this time. Fails when a [`required`](NotificationSink::required) sink or every
+    /// sink failed, so the caller can leave it to be retried; the sinks that took it are recorded
+    /// under the notification's `key` and aren't sent it again.
+    pub async fn send(&self, notification: &Notification) -> Result<usize, Error> {
+        let windows = AlertWindowStore::from_env();
+        let done = if notification.key.is_empty() {
+            vec![]
+        } else {
+            windows.sent_to(&notification.team_id, &notification.key).await.unwrap_or_else(|e| {
+                error!("Could not read which sinks took {}, sending to all: {}", notification.key, e);
+                vec![]
+            })
+        };
+        // Sinks are told apart by position, which is stable while the workspace's config is.
+        let pending: Vec<(String, &dyn NotificationSink)> = self
+            .sinks
+            .iter()
+            .enumerate()
+            .map(|(i, sink)| (format!("{}#{}", sink.name(), i), sink.as_ref()))
+            .filter(|(id, _)| !done.contains(id))
+            .collect();
+        let results = join_all(pending.iter().map(|(_, sink)| async move {
+            let started = Instant::now();
+            let result = sink.send(notification).await;
+            let outcome = if result.is_ok() { "ok" } else { "error" };
+            let elapsed = started.elapsed().as_millis() as i64;
+            metrics::milliseconds("NotificationSinkLatency", elapsed, &[("Sink", sink.name()), ("Outcome", outcome)]);
+            if let Err(e) = &result {
+                error!("Could not notify {} for workspace {}: {}", sink.name(), notification.team_id, e);
+            }
+            result
+        }))
+        .await;
+
+        let took: Vec<String> = pending
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| result.is_ok())
+            .map(|((id, _), _)| id.clone())
+            .collect();
+        let failed: Vec<&str> = pending
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| result.is_err())
+            .map(|((_, sink), _)| sink.name())
+            .collect();
+        if !notification.key.is_empty() && !took.is_empty() {
+            if let Err(e) = windows.add_sent(&notification.team_id, &notification.key, &took).await {
+                error!("Could not record which sinks took {}: {}", notification.key, e);
+            }
+        }
+        let required_failed =
+            pending.iter().zip(&results).any(|((_, sink), result)| sink.required() && result.is_err());
+        if required_failed || (took.is_empty() && !results.is_empty()) {
+            bail!("Notification sinks failed for workspace {}: {}", notification.team_id, failed.join(", "));
+        }
+        info!("Sent \"{}\" to {} of {} sinks", notification.subject, took.len(), results.len());
+        Ok(took.len())
+    }
+}
+
+/// Tells the bot's operator about something that isn't any one workspace's to fix, in
+/// `ADMIN_SLACK_CHANNEL` with the default bot token. Without the channel it's only logged.
+/// Returns whether it was posted.
+pub async fn operator(secrets: &Secrets, text: String) -> Result<bool, Error> {
+    let channel = match std::env::var("ADMIN_SLACK_CHANNEL") {
+        Ok(channel) if !channel.is_empty() => channel,
+        _ => {
+            warn!("ADMIN_SLACK_CHANNEL is not set, so the operator wasn't told: {}", text);
+            return Ok(false);
+        }
+    };
+    post_to_operator(secrets, channel, text).await
+}
+
+/// [`operator`] for crash reports, which can go to their own `OPS_SLACK_CHANNEL` instead.
+pub async fn ops(secrets: &Secrets, text: String) -> Result<bool, Error> {
+    match std::env::var("OPS_SLACK_CHANNEL") {
+        Ok(channel) if !channel.is_empty() => post_to_operator(secrets, channel, text).await,
+        _ => operator(secrets, text).await,
+    }
+}
+
+async fn post_to_operator(secrets: &Secrets, channel: String, text: String) -> Result<bool, Error> {
+    let message = SlackMessage::builder().text(text).build()?;
+    let token = secrets.slack_bot_token.clone();
+    tokio::task::spawn_blocking(move || slack::post_message(&http::client(), &token, &channel, &message)).await??;
+    Ok(true)
+}
+
+/// A Slack channel. Alerts are coalesced into the channel's open window (see `alerts`) unless
+/// the notification brings its own card or `coalesce_minutes` is 0.
+pub struct SlackSink {
+    pub team_id: String,
+    pub channel: String,
+    pub token: String,
+    pub coalesce_minutes: u32,
+}
+
+impl NotificationSink for SlackSink {
+    fn name(&self) -> &'static str {
+        "slack"
+    }
+
+    /// Slack is where the workspace asked for the alert; the others are extras.
+    fn required(&self) -> bool {
+        true
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            match &notification.card {
+                Some(card) => {
+                    let (token, channel, card) = (self.token.clone(), self.channel.clone(), card.clone());
+                    tokio::task::spawn_blocking(move || slack::post(&http::client(), &token, &channel, &card))
+                        .await??;
+                }
+                None => {
+                    let line = notification.text.clone();
+                    AlertWindowStore::from_env()
+                        .deliver(
+                            &self.team_id,
+                            &self.channel,
+                            line,
+                            &notification.footer,
+                            self.coalesce_minutes,
+                            self.token.clone(),
+                        )
+                        .await?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+pub struct DiscordSink {
+    webhook_url: String,
+}
+
+impl NotificationSink for DiscordSink {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<(), Error>> {
+        let content = rewrite_links(&notification.text, |url, label| format!("[{}]({})", label, url));
+        post_json(self.webhook_url.clone(), json!({ "content": format!("🔴 {}", content) }))
+    }
+}
+
+pub struct WebhookSink {
+    url: String,
+}
+
+impl NotificationSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<(), Error>> {
+        post_json(
+            self.url.clone(),
+            json!({
+                "team_id": notification.team_id,
+                "subject": notification.subject,
+                "text": plain_text(&notification.text),
+                "url": notification.url,
+            }),
+        )
+    }
+}
+
+pub struct SnsSink {
+    client: SnsClient,
+    topic_arn: String,
+}
+
+impl NotificationSink for SnsSink {
+    fn name(&self) -> &'static str {
+        "sns"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.client
+                .publish(PublishInput {
+                    topic_arn: Some(self.topic_arn.clone()),
+                    subject: Some(notification.subject.chars().take(MAX_SUBJECT_CHARS).collect()),
+                    message: plain_text(&notification.text),
+                    ..Default::default()
+                })
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+pub struct EmailSink {
+    client: SesClient,
+    from: String,
+    to: Vec<String>,
+}
+
+impl NotificationSink for EmailSink {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            if self.from.is_empty() {
+                bail!("NOTIFY_EMAIL_FROM is not set");
+            }
+            let content = |data: String| Content {
+                charset: Some("UTF-8".to_string()),
+                data,
+            };
+            self.client
+                .send_email(SendEmailRequest {
+                    source: self.from.clone(),
+                    destination: Destination {
+                        to_addresses: Some(self.to.clone()),
+                        ..Default::default()
+                    },
+                    message: Message {
+                        subject: content(notification.subject.clone()),
+                        body: Body {
+                            text: Some(content(plain_text(&notification.text))),
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+fn post_json(url: String, body: serde_json::Value) -> BoxFuture<'static, Result<(), Error>> {
+    Box::pin(async move {
+        tokio::task::spawn_blocking(move || -> Result<(), Error> {
+            let resp = http::client().post(&url).json(&body).send()?;
+            if !resp.status().is_success() {
+                bail!("{} answered {}", url, resp.status());
+            }
+            Ok(())
+        })
+        .await?
+    })
+}
+
+/// Slack's `<url|label>` links as `label (url)`.
+fn plain_text(text: &str) -> String {
+    rewrite_links(text, |url, label| {
+        if url == label {
+            url.to_string()
+        } else {
+            format!("{} ({})", label, url)
+        }
+    })
+}
+
+/// Rewrites each `<url|label>` or `<url>` in Slack mrkdwn with `link(url, label)`. Mentions and
+/// dates (`<@U123>`, `<!date^…|fallback>`) become their label.
+fn rewrite_links(text: &str, link: impl Fn(&str, &str) -> String) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        out.push_str(&rest[..start]);
+        let inner = &rest[start + 1..end];
+        let (target, label) = inner.split_once('|').unwrap_or((inner, inner));
+        if target.starts_with(|c| c == '@' || c == '!' || c == '#') {
+            out.push_str(label);
+        } else {
+            out.push_str(&link(target, label));
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,192 @@
+//! Setup wizard sent to whoever installs the app into a workspace: a DM with a button that
+//! opens a modal for the digest channel, timezone, locale, alert routing and grouping, and initial
+//! watchlist.
+
+use crate::alerts::DEFAULT_COALESCE_MINUTES;
+use crate::locale::LOCALES;
+use crate::plans::{Feature, Plan};
+use crate::slack::{Block, Element, SlackMessage, Text};
+use crate::workspace::WorkspaceConfig;
+use crate::Error;
+use serde_json::{json, Value};
+
+pub const OPEN_ACTION: &str = "open_onboarding";
+pub const CALLBACK_ID: &str = "onboarding";
+
+const TIMEZONES: &[&str] = &[
+    "UTC",
+    "America/Los_Angeles",
+    "America/Denver",
+    "America/Chicago",
+    "America/New_York",
+    "America/Sao_Paulo",
+    "Europe/London",
+    "Europe/Berlin",
+    "Asia/Tokyo",
+    "Australia/Sydney",
+];
+
+pub fn welcome_message() -> Result<SlackMessage, Error> {
+    SlackMessage::builder()
+        .text("Thanks for installing the Twitch info bot!")
+        .block(Block::Section {
+            text: Text::Mrkdwn {
+                text: "Thanks for installing the Twitch info bot! Take a minute to pick where digests \
+                       and go-live alerts should go, and which channels to watch."
+                    .to_string(),
+            },
+            accessory: None,
+        })
+        .block(Block::Actions {
+            elements: vec![Element::Button {
+                action_id: OPEN_ACTION.to_string(),
+                text: Text::PlainText {
+                    text: "Set up the bot".to_string(),
+                },
+                value: None,
+                url: None,
+                style: None,
+                confirm: None,
+            }],
+        })
+        .build()
+}
+
+pub fn wizard_view(current: &WorkspaceConfig) -> Value {
+    let timezone_options: Vec<Value> = TIMEZONES
+        .iter()
+        .map(|tz| json!({ "text": { "type": "plain_text", "text": tz }, "value": tz }))
+        .collect();
+    let current_timezone = current.timezone.clone().unwrap_or_else(|| "UTC".to_string());
+    let locale_option =
+        |(tag, label): &(&str, &str)| json!({ "text": { "type": "plain_text", "text": label }, "value": tag });
+    let locale_options: Vec<Value> = LOCALES.iter().map(locale_option).collect();
+    let current_locale = current.locale.as_deref().unwrap_or("en-US");
+    let current_locale = LOCALES.iter().find(|(tag, _)| *tag == current_locale).unwrap_or(&LOCALES[0]);
+
+    let mut digest = json!({ "type": "conversations_select", "action_id": "value",
+        "filter": { "include": ["public", "private"] } });
+    if let Some(channel) = &current.digest_channel {
+        digest["initial_conversation"] = json!(channel);
+    }
+    let mut digest_block = json!({ "type": "input", "block_id": "digest_channel", "optional": true,
+        "label": { "type": "plain_text", "text": "Daily digest channel" }, "element": digest });
+    if !Plan::for_workspace(current).allows(Feature::Digests) {
+        digest_block["hint"] = json!({ "type": "plain_text", "text": Feature::Digests.upgrade_message() });
+    }
+    let mut alerts = json!({ "type": "conversations_select", "action_id": "value",
+        "filter": { "include": ["public", "private"] } });
+    if let Some(channel) = &current.alert_channel {
+        alerts["initial_conversation"] = json!(channel);
+    }
+
+    let minutes = current.alert_coalesce_minutes.unwrap_or(DEFAULT_COALESCE_MINUTES);
+    let coalesce = json!({ "type": "number_input", "action_id": "value", "is_decimal_allowed": false,
+        "min_value": "0", "initial_value": minutes.to_string() });
+
+    let debug_option = json!({ "text": { "type": "plain_text", "text": "Show alert latency on go-live alerts" },
+                               "value": "debug_footer" });
+    let mut debug = json!({ "type": "checkboxes", "action_id": "value", "options": [debug_option] });
+    if current.debug_footer {
+        debug["initial_options"] = json!([debug_option]);
+    }
+    let translate_option = json!({ "text": { "type": "plain_text", "text": "Translate descriptions to English" },
+                                   "value": "translate_descriptions" });
+    let mut translate = json!({ "type": "checkboxes", "action_id": "value", "options": [translate_option] });
+    if current.translate_descriptions {
+        translate["initial_options"] = json!([translate_option]);
+    }
+    let legacy_option = json!({ "text": { "type": "plain_text", "text": "Use the classic card layout" },
+                                "value": "legacy_cards" });
+    let mut legacy = json!({ "type": "checkboxes", "action_id": "value", "options": [legacy_option] });
+    if current.legacy_cards {
+        legacy["initial_options"] = json!([legacy_option]);
+    }
+
+    json!({
+        "type": "modal",
+        "callback_id": CALLBACK_ID,
+        "title": { "type": "plain_text", "text": "Twitch info bot setup" },
+        "submit": { "type": "plain_text", "text": "Save" },
+        "blocks": [
+            digest_block,
+            { "type": "input", "block_id": "timezone",
+              "label": { "type": "plain_text", "text": "Timezone" },
+              "element": { "type": "static_select", "action_id": "value", "options": timezone_options,
+                           "initial_option": { "text": { "type": "plain_text", "text": current_timezone },
+                                               "value": current_timezone } } },
+            { "type": "input", "block_id": "locale",
+              "label": { "type": "plain_text", "text": "Number and date format" },
+              "element": { "type": "static_select", "action_id": "value", "options": locale_options,
+                           "initial_option": locale_option(current_locale) } },
+            { "type": "input", "block_id": "alert_channel", "optional": true,
+              "label": { "type": "plain_text", "text": "Go-live alert channel" }, "element": alerts },
+            { "type": "input", "block_id": "alert_coalesce_minutes", "optional": true,
+              "label": { "type": "plain_text", "text": "Group go-live alerts (minutes)" },
+              "hint": { "type": "plain_text",
+                        "text": "Channels going live this close together share one alert; 0 posts each on its own" },
+              "element": coalesce },
+            { "type": "input", "block_id": "watchlist", "optional": true,
+              "label": { "type": "plain_text", "text": "Channels to watch" },
+              "hint": { "type": "plain_text", "text": "Twitch logins separated by commas or spaces" },
+              "element": { "type": "plain_text_input", "action_id": "value",
+                           "initial_value": current.watchlist.join(", ") } },
+            { "type": "input", "block_id": "banned_words", "optional": true,
+              "label": { "type": "plain_text", "text": "Words to mask" },
+              "hint": { "type": "plain_text",
+                        "text": "Masked in stream titles and descriptions the bot posts, separated by commas" },
+              "element": { "type": "plain_text_input", "action_id": "value",
+                           "initial_value": current.banned_words.join(", ") } },
+            { "type": "input", "block_id": "translate_descriptions", "optional": true,
+              "label": { "type": "plain_text", "text": "Lookup cards" }, "element": translate },
+            { "type": "input", "block_id": "legacy_cards", "optional": true,
+              "label": { "type": "plain_text", "text": "Card layout" }, "element": legacy },
+            { "type": "input", "block_id": "debug_footer", "optional": true,
+              "label": { "type": "plain_text", "text": "Debugging" }, "element": debug },
+        ]
+    })
+}
+
+/// Copies a submitted wizard's `view.state.values` onto `config`.
+pub fn apply_submission(config: &mut WorkspaceConfig, values: &Value) {
+    config.digest_channel = field(values, "digest_channel")["selected_conversation"].as_str().map(String::from);
+    config.alert_channel = field(values, "alert_channel")["selected_conversation"].as_str().map(String::from);
+    if let Some(tz) = field(values, "timezone")["selected_option"]["value"].as_str() {
+        config.timezone = Some(tz.to_string());
+    }
+    if let Some(locale) = field(values, "locale")["selected_option"]["value"].as_str() {
+        config.locale = Some(locale.to_string());
+    }
+    config.alert_coalesce_minutes = field(values, "alert_coalesce_minutes")["value"]
+        .as_str()
+        .and_then(|minutes| minutes.trim().parse().ok());
+    config.watchlist = field(values, "watchlist")["value"]
+        .as_str()
+        .unwrap_or_default()
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|login| !login.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect();
+    config.banned_words = field(values, "banned_words")["value"]
+        .as_str()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect();
+    config.translate_descriptions = field(values, "translate_descriptions")["selected_options"]
+        .as_array()
+        .map_or(false, |selected| !selected.is_empty());
+    config.legacy_cards = field(values, "legacy_cards")["selected_options"]
+        .as_array()
+        .map_or(false, |selected| !selected.is_empty());
+    config.debug_footer = field(values, "debug_footer")["selected_options"]
+        .as_array()
+        .map_or(false, |selected| !selected.is_empty());
+}
+
+/// Every input in the wizard uses `value` as its action ID, so a block ID is enough to find it.
+fn field<'a>(values: &'a Value, block: &str) -> &'a Value {
+    &values[block]["value"]
+}
@@ -0,0 +1,88 @@
+//! A rough audience overlap between two channels, for `/tcompare --overlap`. Twitch only tells a
+//! broadcaster who follows them, so this compares samples of each audience instead: who made the
+//! channel's recent clips and, where a linked account can see it (the broadcaster's own, or a
+//! moderator's, with `moderator:read:chatters`), who's in its chat right now. Samples are small
+//! and lean towards the most active viewers, so the result is always shown as an estimate.
+
+use crate::twitch::{Chatter, TwitchClip};
+use std::collections::HashSet;
+
+/// Fewer viewers in either sample than this and there's nothing worth estimating from.
+pub const MIN_SAMPLE: usize = 10;
+
+/// The viewers seen in one channel's audience, by user ID.
+#[derive(Debug, Clone, Default)]
+pub struct Sample {
+    pub clippers: HashSet<String>,
+    /// `None` when no linked account could see the chat.
+    pub chatters: Option<HashSet<String>>,
+}
+
+impl Sample {
+    /// The sample from a channel's clips and chatters, leaving out the `broadcasters` being
+    /// compared, who clip and chat in their own channels and each other's without being anyone's
+    /// audience.
+    pub fn new(clips: &[TwitchClip], chatters: Option<&[Chatter]>, broadcasters: &[&str]) -> Sample {
+        let keep = |id: &String| !id.is_empty() && !broadcasters.contains(&id.as_str());
+        Sample {
+            clippers: clips.iter().map(|clip| clip.creator_id.clone()).filter(keep).collect(),
+            chatters: chatters
+                .map(|chatters| chatters.iter().map(|chatter| chatter.user_id.clone()).filter(keep).collect()),
+        }
+    }
+
+    pub fn viewers(&self) -> HashSet<&String> {
+        self.clippers.iter().chain(self.chatters.iter().flatten()).collect()
+    }
+
+    /// Where the sample came from, e.g. `12 clip creators and 240 chatters`.
+    pub fn sources(&self) -> String {
+        let clippers = format!("{} clip creators", self.clippers.len());
+        match &self.chatters {
+            Some(chatters) => format!("{} and {} chatters", clippers, chatters.len()),
+            None => clippers,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Estimate {
+    /// Viewers in both samples.
+    pub shared: usize,
+    /// The size of the smaller sample, which `percent` is of.
+    pub smaller: usize,
+    pub percent: u32,
+}
+
+/// How many viewers the samples share, as a share of the smaller one; `None` if either is too
+/// small to say (see [`MIN_SAMPLE`]).
+pub fn estimate(a: &Sample, b: &Sample) -> Option<Estimate> {
+    let (a, b) = (a.viewers(), b.viewers());
+    let smaller = a.len().min(b.len());
+    if smaller < MIN_SAMPLE {
+        return None;
+    }
+    let shared = a.intersection(&b).count();
+    Some(Estimate {
+        shared,
+        smaller,
+        percent: (shared * 100 / smaller) as u32,
+    })
+}
+
+/// The estimate as it's shown, saying plainly what it's based on.
+pub fn describe(a_name: &str, a: &Sample, b_name: &str, b: &Sample) -> String {
+    let basis = format!("{}: {}; {}: {}", a_name, a.sources(), b_name, b.sources());
+    match estimate(a, b) {
+        Some(estimate) => format!(
+            "*Estimated audience overlap: ~{}%*\n{} of {} sampled viewers also turned up in the other channel. \
+             _A rough estimate from recent clip creators and chatters, not follower data ({})._",
+            estimate.percent, estimate.shared, estimate.smaller, basis
+        ),
+        None => format!(
+            "*Audience overlap: not enough to estimate*\n_Each channel needs at least {} recent clip creators \
+             or chatters to compare ({})._",
+            MIN_SAMPLE, basis
+        ),
+    }
+}
@@ -0,0 +1,64 @@
+//! Planning lookups bigger than one Helix request. Helix takes at most [`MAX_IDS_PER_REQUEST`]
+//! `id`/`login` parameters per request and rejects a URL with more, so a `/tuser` lookup is
+//! capped at [`MAX_LOOKUP_USERS`] and split into batches, which are fetched side by side and
+//! merged in order. List endpoints that page (clips, videos, streams) ask for at most
+//! [`MAX_PAGE_SIZE`] items a page and follow the cursor for the rest.
+
+use crate::query::UserQuery;
+use crate::twitch::{self, MAX_IDS_PER_REQUEST};
+use std::collections::HashSet;
+
+/// The most users one lookup asks Twitch about. More than that is left out of the reply with a
+/// note; a Slack reply has no room to show them anyway.
+pub const MAX_LOOKUP_USERS: usize = 200;
+
+/// Helix's largest `first` for paginated list endpoints.
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// `query` thinned to what one lookup asks for: repeats dropped, then at most `cap` users, IDs
+/// before logins as in the request URLs. Also returns the users left out, in the same order.
+pub fn cap(query: &UserQuery, cap: usize) -> (UserQuery, Vec<String>) {
+    let mut seen = HashSet::new();
+    let mut left_out = vec![];
+    let mut keep = |items: &[String]| -> Vec<String> {
+        let mut kept = vec![];
+        for item in items {
+            if !seen.insert(item.clone()) {
+                continue;
+            }
+            if seen.len() <= cap {
+                kept.push(item.clone());
+            } else {
+                left_out.push(item.clone());
+            }
+        }
+        kept
+    };
+    let ids = keep(&query.ids);
+    let logins = keep(&query.logins);
+    let capped = UserQuery {
+        ids,
+        logins,
+        flags: query.flags.clone(),
+        search: query.search.clone(),
+    };
+    (capped, left_out)
+}
+
+/// The Get Users requests for `ids` and `logins`, each within Helix's parameter limit.
+pub fn user_batches(ids: &[String], logins: &[String]) -> Vec<String> {
+    let params: Vec<String> = ids
+        .iter()
+        .map(|id| format!("id={}", id))
+        .chain(logins.iter().map(|login| format!("login={}", login)))
+        .collect();
+    params
+        .chunks(MAX_IDS_PER_REQUEST)
+        .map(|chunk| format!("{}/users?{}", twitch::helix_base(), chunk.join("&")))
+        .collect()
+}
+
+/// The `first` to ask a paginated endpoint for when `count` items are wanted.
+pub fn page_size(count: usize) -> usize {
+    count.clamp(1, MAX_PAGE_SIZE)
+}
@@ -0,0 +1,66 @@
+//! Free and Pro plans for a hosted, multi-tenant deployment. The features that cost the most to
+//! run (title tracking, digests and EventSub go-live alerts) are Pro only, and using one on the
+//! free plan gets an upgrade message instead.
+//!
+//! A workspace's plan is set by the operator in its stored config; workspaces without one get
+//! `DEFAULT_PLAN` (default `pro`, so a self-hosted install has everything).
+
+use crate::workspace::WorkspaceConfig;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Plan {
+    Free,
+    Pro,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Feature {
+    /// Recording title changes from `channel.update` (`/ttitles`).
+    Tracking,
+    /// Posting daily digests.
+    Digests,
+    /// Go-live alerts from `stream.online` deliveries.
+    Alerts,
+}
+
+impl Plan {
+    pub fn default_plan() -> Plan {
+        match std::env::var("DEFAULT_PLAN").unwrap_or_default().to_ascii_lowercase().as_str() {
+            "free" => Plan::Free,
+            _ => Plan::Pro,
+        }
+    }
+
+    pub fn for_workspace(config: &WorkspaceConfig) -> Plan {
+        config.plan.unwrap_or_else(Plan::default_plan)
+    }
+
+    pub fn allows(self, _feature: Feature) -> bool {
+        match self {
+            Plan::Free => false,
+            Plan::Pro => true,
+        }
+    }
+}
+
+impl Feature {
+    fn describe(self) -> &'static str {
+        match self {
+            Feature::Tracking => "Title tracking",
+            Feature::Digests => "Daily digests",
+            Feature::Alerts => "Go-live alerts",
+        }
+    }
+
+    /// What a free workspace is told when it asks for this feature, pointing at `UPGRADE_URL`
+    /// when it's set.
+    pub fn upgrade_message(self) -> String {
+        let how = match std::env::var("UPGRADE_URL") {
+            Ok(url) if !url.is_empty() => format!("<{}|Upgrade this workspace> to use it.", url),
+            _ => "Ask the bot's operator to upgrade this workspace.".to_string(),
+        };
+        format!("{} is part of the Pro plan. {}", self.describe(), how)
+    }
+}
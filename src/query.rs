@@ -0,0 +1,466 @@
+//! User lookups (`/tuser`): parsing their text (Twitch IDs, logins and channel URLs, in any mix)
+//! and running them against a [`TwitchApi`]. Logins Twitch doesn't know are searched for with
+//! Search Channels, to suggest what was meant; text starting with `search:` only searches.
+
+use crate::error::BotError;
+use crate::planner::{self, MAX_LOOKUP_USERS};
+use crate::slack;
+use crate::twitch::{self, ChannelMatch, LookupError, TimeoutConfig, TwitchApi, TwitchStream, TwitchUser};
+use crate::Error;
+use futures::future;
+use log::error;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct UserQuery {
+    pub ids: Vec<String>,
+    pub logins: Vec<String>,
+    /// `--flags`, lowercased and without their dashes.
+    pub flags: Vec<String>,
+    /// What to search channels for instead of looking users up, from `search: <words>`.
+    pub search: Option<String>,
+}
+
+/// Typed anywhere in the text (or as `--nocache`), skips the cache read and asks Twitch. A
+/// channel with this login can still be looked up by URL or ID.
+pub const NOCACHE: &str = "nocache";
+
+/// Typed anywhere in the text, as `quiet` or `private` (or either as a `--flag`), shows the
+/// results only to whoever ran the lookup.
+pub const QUIET: &str = "quiet";
+
+/// Starts text that searches channels (`search: ninj`) rather than naming them exactly.
+pub const SEARCH_PREFIX: &str = "search:";
+
+/// Matches offered per search, and how many unknown logins are searched for.
+const SUGGESTIONS: usize = 5;
+const MAX_SUGGESTED_LOGINS: usize = 3;
+
+/// Enrichment steps stop this close to the lookup's deadline, leaving time to render and reply.
+const RENDER_MARGIN: Duration = Duration::from_millis(150);
+
+impl UserQuery {
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+
+    /// Collects `item` if it's a `--flag` or the bare [`NOCACHE`] or [`QUIET`] keyword.
+    fn add_flag(&mut self, item: &str) -> bool {
+        if slack::is_quiet_word(item) {
+            self.flags.push(QUIET.to_string());
+        } else if item.starts_with('-') {
+            self.flags.push(item.trim_start_matches('-').to_ascii_lowercase());
+        } else if item.eq_ignore_ascii_case(NOCACHE) {
+            self.flags.push(NOCACHE.to_string());
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// The requested logins and IDs that none of `users` answers to, in the order asked. Twitch
+    /// leaves unknown ones out of its response rather than failing the lookup.
+    pub fn not_found(&self, users: &[TwitchUser]) -> Vec<String> {
+        let logins = self
+            .logins
+            .iter()
+            .filter(|login| !users.iter().any(|user| user.login.eq_ignore_ascii_case(login)));
+        let ids = self.ids.iter().filter(|id| !users.iter().any(|user| &user.id == *id));
+        logins.chain(ids).cloned().collect()
+    }
+}
+
+/// What a lookup found, as rendered and cached.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LookupResult {
+    pub users: Vec<TwitchUser>,
+    pub streams: Vec<TwitchStream>,
+    /// Follower counts by user ID, for the Block Kit cards.
+    #[serde(default)]
+    pub followers: HashMap<String, u64>,
+    /// Enrichment steps that timed out or failed, so the rendering can leave their fields off.
+    #[serde(default)]
+    pub missing: Vec<String>,
+    /// Requested logins and IDs Twitch has no user for.
+    #[serde(default)]
+    pub not_found: Vec<String>,
+    /// Channels matching a `search:` or, for lookups, the logins in `not_found`.
+    #[serde(default)]
+    pub suggestions: Vec<Suggestions>,
+    /// Requested logins and IDs past [`MAX_LOOKUP_USERS`], which weren't looked up.
+    #[serde(default)]
+    pub left_out: Vec<String>,
+}
+
+/// The channels Search Channels matched to one term.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Suggestions {
+    pub term: String,
+    pub channels: Vec<ChannelMatch>,
+}
+
+impl Suggestions {
+    /// `<https://twitch.tv/ninja|Ninja> (live), <https://twitch.tv/ninjaa|Ninjaa>`
+    pub fn links(&self) -> String {
+        let links: Vec<String> = self
+            .channels
+            .iter()
+            .map(|channel| {
+                let live = if channel.is_live { " (live)" } else { "" };
+                format!("<https://twitch.tv/{}|{}>{}", channel.broadcaster_login, channel.display_name, live)
+            })
+            .collect();
+        links.join(", ")
+    }
+}
+
+impl LookupResult {
+    /// `_Not found: foo, bar_` with a "did you mean" line for each that has suggestions, and
+    /// how many users were left out past the cap; `None` when every requested user was shown.
+    pub fn not_found_note(&self) -> Option<String> {
+        let mut lines = vec![];
+        if !self.not_found.is_empty() {
+            lines.push(format!("_Not found: {}_", self.not_found.join(", ")));
+            lines.extend(self.did_you_mean());
+        }
+        if !self.left_out.is_empty() {
+            lines.push(format!(
+                "_Only the first {} users were looked up; {} more were left out._",
+                MAX_LOOKUP_USERS,
+                self.left_out.len()
+            ));
+        }
+        Some(lines.join("\n")).filter(|note| !note.is_empty())
+    }
+
+    /// "Instead of `ninjaa`, did you mean <…|Ninja> (live)?" for each term with suggestions.
+    pub fn did_you_mean(&self) -> Vec<String> {
+        self.suggestions
+            .iter()
+            .filter(|suggestions| !suggestions.channels.is_empty())
+            .map(|suggestions| format!("Instead of `{}`, did you mean {}?", suggestions.term, suggestions.links()))
+            .collect()
+    }
+
+    /// Whether `streams` says who's live, rather than being empty because that step failed.
+    pub fn has_live_status(&self) -> bool {
+        !self.missing.iter().any(|step| step == "live status")
+    }
+}
+
+/// Looks up the users, then runs the enrichment steps side by side: live status, follower
+/// counts unless the output is `compact` (which has no cards to show them on), and suggestions
+/// for logins Twitch doesn't know. Only the users lookup is required; each enrichment step gets
+/// its own timeout (capped by what's left before `deadline`) and a failure is recorded in
+/// `missing` instead of failing the command. A `search:` query only searches. Past
+/// [`MAX_LOOKUP_USERS`] (after dropping repeats) the rest are recorded in `left_out` instead.
+pub async fn lookup(
+    api: &dyn TwitchApi,
+    query: &UserQuery,
+    timeouts: &TimeoutConfig,
+    deadline: Instant,
+    compact: bool,
+) -> Result<LookupResult, LookupError> {
+    if let Some(term) = &query.search {
+        let channels = api.search_channels(term, SUGGESTIONS, *timeouts).await?;
+        return Ok(LookupResult {
+            users: vec![],
+            streams: vec![],
+            followers: HashMap::new(),
+            missing: vec![],
+            not_found: vec![],
+            suggestions: vec![Suggestions {
+                term: term.clone(),
+                channels,
+            }],
+            left_out: vec![],
+        });
+    }
+
+    let (query, left_out) = planner::cap(query, MAX_LOOKUP_USERS);
+    let query = &query;
+    let users = api.users(&query.ids, &query.logins, *timeouts).await?;
+    let ids: Vec<String> = users.iter().map(|u| u.id.clone()).collect();
+    let not_found = query.not_found(&users);
+    let mut missing = vec![];
+
+    let streams = async {
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+        api.streams(&ids, enrichment_step(timeouts, deadline)?).await
+    };
+    let followers = async {
+        if compact || ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        api.follower_counts(&users, enrichment_step(timeouts, deadline)?).await
+    };
+    let suggestions = async {
+        let unknown: Vec<&String> =
+            not_found.iter().filter(|term| query.logins.contains(term)).take(MAX_SUGGESTED_LOGINS).collect();
+        if unknown.is_empty() {
+            return Ok(vec![]);
+        }
+        let step = enrichment_step(timeouts, deadline)?;
+        let searches = unknown.into_iter().map(|login| async move {
+            let channels = api.search_channels(login, SUGGESTIONS, step).await?;
+            Ok::<_, LookupError>(Suggestions {
+                term: login.clone(),
+                channels,
+            })
+        });
+        future::join_all(searches).await.into_iter().collect::<Result<Vec<_>, LookupError>>()
+    };
+    let (streams, followers, suggestions) = future::join3(streams, followers, suggestions).await;
+
+    let streams = streams.unwrap_or_else(|e| {
+        error!("Live status lookup failed, rendering without it: {:?}", e);
+        missing.push("live status".to_string());
+        vec![]
+    });
+    let followers = followers.unwrap_or_else(|e| {
+        error!("Follower count lookup failed, rendering without it: {:?}", e);
+        missing.push("follower counts".to_string());
+        HashMap::new()
+    });
+    let suggestions = suggestions.unwrap_or_else(|e| {
+        error!("Channel search failed, rendering without suggestions: {:?}", e);
+        missing.push("suggestions".to_string());
+        vec![]
+    });
+
+    Ok(LookupResult {
+        not_found,
+        users,
+        streams,
+        followers,
+        missing,
+        suggestions,
+        left_out,
+    })
+}
+
+/// Timeouts for the next enrichment step, or `Timeout` if there's no time left for one.
+fn enrichment_step(timeouts: &TimeoutConfig, deadline: Instant) -> Result<TimeoutConfig, LookupError> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining <= RENDER_MARGIN {
+        return Err(LookupError::Timeout);
+    }
+    Ok(timeouts.for_step(timeouts.enrichment.min(remaining - RENDER_MARGIN)))
+}
+
+/// Splits slash command text into Twitch user IDs and logins. Commas and whitespace both separate
+/// items, `--flags` (and the bare [`NOCACHE`] and [`QUIET`] keywords) are collected separately,
+/// and channel URLs (`https://twitch.tv/foo`, including Slack's `<url|label>` wrapping) are
+/// reduced to their login.
+/// Text starting with [`SEARCH_PREFIX`] is a search for the words after it instead.
+pub fn parse_command_text(text: &str) -> Result<UserQuery, Error> {
+    let mut query = UserQuery::default();
+
+    let text = text.trim_start();
+    if text.get(..SEARCH_PREFIX.len()).map_or(false, |prefix| prefix.eq_ignore_ascii_case(SEARCH_PREFIX)) {
+        let mut words = vec![];
+        for word in text[SEARCH_PREFIX.len()..].split_whitespace() {
+            if !query.add_flag(word) {
+                words.push(word);
+            }
+        }
+        if words.is_empty() {
+            return Err(BotError::BadRequest("Nothing to search for after `search:`".to_string()).into());
+        }
+        query.search = Some(words.join(" "));
+        return Ok(query);
+    }
+
+    for item in text.split(|c: char| c.is_whitespace() || c == ',') {
+        if item.is_empty() || query.add_flag(item) {
+            continue;
+        }
+
+        let name = login_from_url(item).unwrap_or(item);
+        if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) {
+            query.ids.push(name.to_string());
+        } else if is_valid_login(name) {
+            query.logins.push(name.to_ascii_lowercase());
+        } else {
+            return Err(BotError::BadRequest(format!("'{}' is not a valid Twitch username or ID", item)).into());
+        }
+    }
+
+    if query.ids.len() == 0 && query.logins.len() == 0 {
+        return Err(BotError::BadRequest("No valid Twitch usernames or IDs found".to_string()).into());
+    }
+
+    Ok(query)
+}
+
+/// The login in a channel URL, or `None` when `item` isn't one.
+pub fn login_from_url(item: &str) -> Option<&str> {
+    let item = item.trim_start_matches('<').trim_end_matches('>');
+    let item = item.split('|').next().unwrap_or(item);
+    let item = item
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.")
+        .trim_start_matches("m.");
+
+    if item.starts_with("twitch.tv/") {
+        Some(item["twitch.tv/".len()..].split(|c| c == '/' || c == '?').next().unwrap_or(""))
+    } else {
+        None
+    }
+}
+
+pub fn is_valid_login(login: &str) -> bool {
+    login.len() > 0 && login.len() <= 25 && login.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// The lookup as one Get Users URL, which identifies it in cache keys; see [`twitch::users_url`].
+pub fn generate_api_url(query: &UserQuery) -> String {
+    twitch::users_url(&query.ids, &query.logins)
+}
+
+/// Where the lookup's result is cached. Card lookups include follower counts and compact ones
+/// don't, so they're cached separately. (Card entries from before cards showed live status were
+/// under `:false`, and are left to expire.)
+pub fn cache_key(query: &UserQuery, compact: bool) -> String {
+    let shown = if compact { "true" } else { "cards" };
+    match &query.search {
+        Some(term) => format!("tuser:search:{}:{}", term.to_lowercase(), shown),
+        None => format!("tuser:{}:{}", generate_api_url(query), shown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn assert_well_formed(url: &str) {
+        let params = url
+            .strip_prefix("https://api.twitch.tv/helix/users?")
+            .expect("unexpected URL prefix");
+        assert!(!params.is_empty(), "empty query in {}", url);
+
+        for param in params.split('&') {
+            let (key, value) = param.split_at(param.find('=').expect("param without ="));
+            let value = &value[1..];
+            match key {
+                "id" => assert!(!value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()), "bad id in {}", url),
+                "login" => assert!(
+                    is_valid_login(value) && value.bytes().all(|b| !b.is_ascii_uppercase()),
+                    "bad login in {}",
+                    url
+                ),
+                _ => panic!("unexpected param {} in {}", key, url),
+            }
+        }
+    }
+
+    fn item() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "[a-zA-Z0-9_]{1,25}",
+            "[0-9]{1,12}",
+            "[a-zA-Z0-9_]{1,25}".prop_map(|l| format!("https://www.twitch.tv/{}", l)),
+            "[a-zA-Z0-9_]{1,25}".prop_map(|l| format!("<https://twitch.tv/{}|twitch.tv/{}>", l, l)),
+            "--[a-z]{1,10}",
+        ]
+    }
+
+    fn separator() -> impl Strategy<Value = &'static str> {
+        prop_oneof![Just(" "), Just(","), Just(", "), Just("  "), Just("\t"), Just(",,")]
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_text_never_builds_a_malformed_url(text in "\\PC*") {
+            match parse_command_text(&text) {
+                Ok(UserQuery { search: Some(term), ids, logins, .. }) => {
+                    prop_assert!(!term.is_empty() && ids.is_empty() && logins.is_empty());
+                }
+                Ok(query) => assert_well_formed(&generate_api_url(&query)),
+                Err(_) => {}
+            }
+        }
+
+        #[test]
+        fn generated_mixes_build_valid_queries(items in prop::collection::vec((item(), separator()), 1..20)) {
+            let text: String = items.iter().map(|(item, sep)| format!("{}{}", item, sep)).collect();
+            let names = items.iter().filter(|(item, _)| !item.starts_with('-')).count();
+
+            match parse_command_text(&text) {
+                Ok(query) => {
+                    prop_assert_eq!(query.ids.len() + query.logins.len(), names);
+                    assert_well_formed(&generate_api_url(&query));
+                }
+                Err(_) => prop_assert_eq!(names, 0),
+            }
+        }
+    }
+
+    #[test]
+    fn nocache_is_a_keyword_unless_it_is_a_url() {
+        let query = parse_command_text("ninja NoCache").unwrap();
+        assert_eq!(query.logins, vec!["ninja"]);
+        assert!(query.has_flag(NOCACHE));
+
+        let query = parse_command_text("https://twitch.tv/nocache").unwrap();
+        assert_eq!(query.logins, vec!["nocache"]);
+        assert!(!query.has_flag(NOCACHE));
+    }
+
+    #[test]
+    fn quiet_and_private_are_the_same_keyword() {
+        for text in ["ninja quiet", "PRIVATE ninja", "ninja --quiet", "ninja --private"] {
+            let query = parse_command_text(text).unwrap();
+            assert_eq!(query.logins, vec!["ninja"], "{}", text);
+            assert!(query.has_flag(QUIET), "{}", text);
+        }
+        assert!(!parse_command_text("https://twitch.tv/quiet").unwrap().has_flag(QUIET));
+    }
+
+    #[test]
+    fn search_prefix_searches_the_words_after_it() {
+        let query = parse_command_text("  Search: ninj  fort --compact nocache").unwrap();
+        assert_eq!(query.search.as_deref(), Some("ninj fort"));
+        assert!(query.logins.is_empty() && query.ids.is_empty());
+        assert!(query.has_flag("compact") && query.has_flag(NOCACHE));
+        assert!(cache_key(&query, true).starts_with("tuser:search:ninj fort:"));
+
+        assert!(parse_command_text("search: --compact").is_err());
+        // Only as a prefix; elsewhere it's just not a valid login.
+        assert!(parse_command_text("ninja search:foo").is_err());
+    }
+
+    fn user(id: &str, login: &str) -> TwitchUser {
+        TwitchUser {
+            user_type: String::new(),
+            id: id.to_string(),
+            login: login.to_string(),
+            display_name: login.to_string(),
+            broadcaster_type: String::new(),
+            description: String::new(),
+            profile_image_url: String::new(),
+            offline_image_url: String::new(),
+            created_at: String::new(),
+            view_count: 0,
+            extra: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn not_found_lists_requested_users_twitch_left_out() {
+        let query = parse_command_text("ninja foo 123 https://twitch.tv/Pokimane 456").unwrap();
+        let users = vec![user("1", "ninja"), user("2", "pokimane"), user("456", "bar")];
+        assert_eq!(query.not_found(&users), vec!["foo", "123"]);
+
+        let everyone: Vec<TwitchUser> = [("1", "ninja"), ("2", "foo"), ("3", "pokimane"), ("123", "x"), ("456", "y")]
+            .iter()
+            .map(|&(id, login)| user(id, login))
+            .collect();
+        assert!(query.not_found(&everyone).is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+//! `/traid`: raids started from Slack by the broadcaster whose linked account is raiding. The
+//! command shows the target's current stream and a button (behind Slack's confirmation dialog)
+//! that carries the raid to the interactivity endpoint, which starts it.
+
+use crate::links::AccountLink;
+use crate::locale::Locale;
+use crate::secrets::Secrets;
+use crate::slack::{Block, ConfirmDialog, Element, SlackMessage, Text};
+use crate::twitch::{self, Credentials, LookupError, TwitchStream, TwitchUser};
+use crate::Error;
+use serde_derive::{Deserialize, Serialize};
+
+pub const SCOPE: &str = "channel:manage:raids";
+pub const CONFIRM_ACTION: &str = "confirm_raid";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RaidRequest {
+    pub from: String,
+    pub from_id: String,
+    pub to: String,
+    pub to_id: String,
+}
+
+/// Only the broadcaster can raid out of their channel, so `from` has to be the invoker's own link.
+pub fn check_raider(link: &AccountLink, from: &str) -> Result<(), String> {
+    if link.login == from {
+        Ok(())
+    } else {
+        Err(format!(
+            "Your Twitch link is for {}, so you can only raid from {}.",
+            link.login, link.login
+        ))
+    }
+}
+
+/// The preview shown before a raid starts: the target's title and viewers, or that they're offline.
+pub fn confirmation(
+    request: &RaidRequest,
+    target: &TwitchUser,
+    stream: Option<&TwitchStream>,
+    locale: Locale,
+) -> Result<SlackMessage, Error> {
+    let status = match stream {
+        Some(stream) => format!(
+            "🔴 *{}* is live with {} viewers: {}",
+            target.display_name,
+            locale.count(stream.viewer_count),
+            stream.title
+        ),
+        None => format!("⚫ *{}* is offline right now.", target.display_name),
+    };
+    let question = format!("Raid {} from {}?", target.display_name, request.from);
+
+    SlackMessage::builder()
+        .ephemeral()
+        .text(question.clone())
+        .block(Block::Section {
+            text: Text::Mrkdwn {
+                text: format!("{}\n{}", question, status),
+            },
+            accessory: Some(Element::Button {
+                action_id: CONFIRM_ACTION.to_string(),
+                text: Text::PlainText {
+                    text: "Start raid".to_string(),
+                },
+                value: Some(serde_json::to_string(request)?),
+                url: None,
+                style: Some("primary".to_string()),
+                confirm: Some(ConfirmDialog::new("Start the raid?", question, "Raid")),
+            }),
+        })
+        .build()
+}
+
+/// Starts the raid out of `link`'s own channel, whatever the button's `from_id` says.
+pub fn start(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    link: &AccountLink,
+    request: &RaidRequest,
+) -> Result<String, LookupError> {
+    match twitch::start_raid(client, Credentials::user(secrets, link), &link.twitch_user_id, &request.to_id) {
+        Ok(()) => Ok(format!(
+            "Raid from {} to {} is starting. Twitch gives chat 90 seconds to join; `/traid cancel` stops it.",
+            request.from, request.to
+        )),
+        Err(LookupError::Rejected(409)) => Ok(format!("{} is already raiding another channel.", request.from)),
+        Err(LookupError::Rejected(400)) => Ok(format!(
+            "Twitch won't let {} raid {} (they may have raids disabled or blocked {}).",
+            request.from, request.to, request.from
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn cancel(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    link: &AccountLink,
+) -> Result<String, LookupError> {
+    match twitch::cancel_raid(client, Credentials::user(secrets, link), &link.twitch_user_id) {
+        Ok(()) => Ok(format!("Cancelled the pending raid from {}.", link.login)),
+        Err(LookupError::Rejected(404)) => Ok(format!("{} has no raid in progress.", link.login)),
+        Err(e) => Err(e),
+    }
+}
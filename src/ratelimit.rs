@@ -0,0 +1,64 @@
+//! Tracks the Helix rate-limit bucket from Twitch's `Ratelimit-*` response headers.
+//!
+//! Every Twitch call in a container shares the app token's bucket, so the last reported state is
+//! kept process-wide. Once Twitch says the bucket is empty, requests are refused locally until
+//! the reset time instead of spending a round trip on a guaranteed 429.
+
+use reqwest::header::HeaderMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Unix timestamp (seconds) at which the bucket refills.
+    pub reset: u64,
+}
+
+static BUCKET: Mutex<Option<Bucket>> = Mutex::new(None);
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The last bucket Twitch reported, if any request has been made yet.
+pub fn bucket() -> Option<Bucket> {
+    *BUCKET.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn record(headers: &HeaderMap) {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<u64>().ok());
+
+    if let (Some(limit), Some(remaining), Some(reset)) =
+        (header("ratelimit-limit"), header("ratelimit-remaining"), header("ratelimit-reset"))
+    {
+        *BUCKET.lock().unwrap_or_else(|e| e.into_inner()) = Some(Bucket {
+            limit: limit as u32,
+            remaining: remaining as u32,
+            reset,
+        });
+    }
+}
+
+/// Marks the bucket as empty until `reset`, for a 429 that didn't carry usable headers.
+pub fn exhaust(reset: u64) {
+    let mut bucket = BUCKET.lock().unwrap_or_else(|e| e.into_inner());
+    let limit = bucket.map(|b| b.limit).unwrap_or_default();
+    *bucket = Some(Bucket {
+        limit,
+        remaining: 0,
+        reset,
+    });
+}
+
+/// `Some(reset)` while the bucket is known to be empty.
+pub fn exhausted_until() -> Option<u64> {
+    match bucket() {
+        Some(bucket) if bucket.remaining == 0 && bucket.reset > now_secs() => Some(bucket.reset),
+        _ => None,
+    }
+}
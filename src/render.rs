@@ -0,0 +1,131 @@
+//! Text renderers shared by the lookup commands.
+
+use crate::locale::Locale;
+use crate::query::{LookupResult, Suggestions};
+use crate::scrub::OutputFilter;
+use crate::twitch::{TwitchStream, TwitchUser};
+use chrono::{DateTime, Utc};
+
+/// One line per channel, live channels first:
+/// `🔴 pokimane — Just Chatting — 21,304 viewers — 3h12m`.
+pub fn compact(users: &[TwitchUser], streams: &[TwitchStream], now: DateTime<Utc>, locale: Locale) -> String {
+    let mut lines: Vec<(bool, String)> = users
+        .iter()
+        .map(|user| match streams.iter().find(|s| s.user_id == user.id) {
+            Some(stream) => (
+                true,
+                format!(
+                    "🔴 {} — {} — {} viewers — {}",
+                    user.display_name,
+                    if stream.game_name.is_empty() { "No category" } else { stream.game_name.as_str() },
+                    locale.count(stream.viewer_count),
+                    stream.uptime(now).map(format_duration).unwrap_or_else(|| "?".to_string())
+                ),
+            ),
+            None => (false, format!("⚫ {} — offline", user.display_name)),
+        })
+        .collect();
+
+    lines.sort_by_key(|(live, _)| !*live);
+    lines.into_iter().map(|(_, line)| line).collect::<Vec<_>>().join("\n")
+}
+
+/// A one-line plain-text summary of a lookup, for a message's `text` fallback:
+/// `Twitch users: pokimane (live, Just Chatting), xqc (offline)`. Without `streams` (live status
+/// wasn't looked up) it just names the users.
+pub fn summary(users: &[TwitchUser], streams: Option<&[TwitchStream]>) -> String {
+    let users: Vec<String> = users
+        .iter()
+        .map(|user| match streams.map(|streams| streams.iter().find(|s| s.user_id == user.id)) {
+            Some(Some(stream)) if !stream.game_name.is_empty() => {
+                format!("{} (live, {})", user.display_name, stream.game_name)
+            }
+            Some(Some(_)) => format!("{} (live)", user.display_name),
+            Some(None) => format!("{} (offline)", user.display_name),
+            None => user.display_name.clone(),
+        })
+        .collect();
+    format!("Twitch users: {}", users.join(", "))
+}
+
+/// What changed for each user between two lookups of the same channels: going live or offline,
+/// viewer deltas and new titles (through `filter`) or categories when `with_streams` (both looked
+/// up live status), and follower deltas where both have a count. Users in only one are left out.
+pub fn changes(
+    previous: &LookupResult,
+    current: &LookupResult,
+    with_streams: bool,
+    locale: Locale,
+    filter: &OutputFilter,
+) -> Vec<String> {
+    let mut lines = vec![];
+    for user in current.users.iter().filter(|user| previous.users.iter().any(|u| u.id == user.id)) {
+        let name = &user.display_name;
+        if with_streams {
+            let before = previous.streams.iter().find(|s| s.user_id == user.id);
+            match (before, current.streams.iter().find(|s| s.user_id == user.id)) {
+                (None, Some(_)) => lines.push(format!("🔴 {} went live", name)),
+                (Some(_), None) => lines.push(format!("⚫ {} went offline", name)),
+                (Some(before), Some(after)) => {
+                    if after.viewer_count != before.viewer_count {
+                        let delta = signed(after.viewer_count, before.viewer_count, locale);
+                        lines.push(format!("{}: {} viewers ({})", name, locale.count(after.viewer_count), delta));
+                    }
+                    if after.title != before.title {
+                        lines.push(format!("{} changed their title to “{}”", name, filter.scrub(&after.title)));
+                    }
+                    if after.game_name != before.game_name && !after.game_name.is_empty() {
+                        lines.push(format!("{} switched to {}", name, after.game_name));
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+        if let (Some(&before), Some(&after)) = (previous.followers.get(&user.id), current.followers.get(&user.id)) {
+            if after != before {
+                lines.push(format!("{}: {} followers", name, signed(after, before, locale)));
+            }
+        }
+    }
+    lines
+}
+
+/// `+1,204` or `-87`.
+fn signed(after: u64, before: u64, locale: Locale) -> String {
+    if after >= before {
+        format!("+{}", locale.count(after - before))
+    } else {
+        format!("-{}", locale.count(before - after))
+    }
+}
+
+/// What a `search:` found, one channel per line with whether it's live and its category:
+/// `• <https://twitch.tv/ninja|Ninja> — 🔴 live, Fortnite`.
+pub fn search_results(suggestions: &Suggestions) -> String {
+    if suggestions.channels.is_empty() {
+        return format!("No Twitch channels match “{}”.", suggestions.term);
+    }
+    let mut lines = vec![format!("*Twitch channels matching “{}”*", suggestions.term)];
+    for channel in &suggestions.channels {
+        let mut line = format!("• <https://twitch.tv/{}|{}>", channel.broadcaster_login, channel.display_name);
+        match (channel.is_live, channel.game_name.is_empty()) {
+            (true, false) => line.push_str(&format!(" — 🔴 live, {}", channel.game_name)),
+            (true, true) => line.push_str(" — 🔴 live"),
+            (false, false) => line.push_str(&format!(" — {}", channel.game_name)),
+            (false, true) => {}
+        }
+        lines.push(line);
+    }
+    lines.push("_Look one up with `/tuser <login>`._".to_string());
+    lines.join("\n")
+}
+
+/// `3h12m`, or just `12m` under an hour.
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+    if minutes >= 60 {
+        format!("{}h{:02}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
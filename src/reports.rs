@@ -0,0 +1,100 @@
+//! Tabular reports (extension transactions, drops entitlements). Small ones are shown inline in
+//! Slack; larger ones are written to S3 as CSV and shared through a short-lived presigned link.
+
+use crate::config;
+use crate::stage::Stage;
+use crate::Error;
+use rusoto_credential::{DefaultCredentialsProvider, ProvideAwsCredentials};
+use rusoto_s3::util::{PreSignedRequest, PreSignedRequestOption};
+use rusoto_s3::{GetObjectRequest, PutObjectRequest, S3Client, S3};
+use rusoto_signature::region::Region;
+use std::time::Duration;
+
+const DEFAULT_BUCKET: &str = "tuser-reports";
+/// Presigned download links stay valid this long.
+const LINK_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Reports with more rows than this go to S3 instead of into the message.
+pub const MAX_INLINE_ROWS: usize = 20;
+
+pub struct Report {
+    pub title: String,
+    pub header: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Report {
+    pub fn to_csv(&self) -> String {
+        let mut csv = csv_line(self.header.iter().cloned());
+        for row in &self.rows {
+            csv.push_str(&csv_line(row.iter().map(String::as_str)));
+        }
+        csv
+    }
+
+    /// The rows as one bullet per line, for reports small enough to show inline.
+    pub fn to_text(&self) -> String {
+        let lines: Vec<String> = self.rows.iter().map(|row| format!("• {}", row.join(" — "))).collect();
+        format!("*{}*\n{}", self.title, lines.join("\n"))
+    }
+}
+
+fn csv_line<'a>(fields: impl Iterator<Item = &'a str>) -> String {
+    let fields: Vec<String> = fields
+        .map(|field| {
+            if field.contains(|c| c == ',' || c == '"' || c == '\n') {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    format!("{}\n", fields.join(","))
+}
+
+pub struct ReportStore {
+    client: S3Client,
+    region: Region,
+    bucket: String,
+}
+
+impl ReportStore {
+    pub fn new(region: Region, bucket: String) -> ReportStore {
+        ReportStore {
+            client: S3Client::new(region.clone()),
+            region,
+            bucket,
+        }
+    }
+
+    pub fn from_env() -> ReportStore {
+        let bucket = std::env::var("REPORTS_BUCKET").unwrap_or_else(|_| Stage::current().resource(DEFAULT_BUCKET));
+        ReportStore::new(config::get().region.clone(), bucket)
+    }
+
+    /// Uploads the report as `<key>.csv` and returns a presigned URL to download it.
+    pub async fn export(&self, key: &str, report: &Report) -> Result<String, Error> {
+        let key = format!("{}.csv", key);
+        self.client
+            .put_object(PutObjectRequest {
+                bucket: self.bucket.clone(),
+                key: key.clone(),
+                body: Some(report.to_csv().into_bytes().into()),
+                content_type: Some("text/csv".to_string()),
+                ..Default::default()
+            })
+            .await?;
+
+        let credentials = DefaultCredentialsProvider::new()?.credentials().await?;
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            key,
+            ..Default::default()
+        };
+        Ok(request.get_presigned_url(
+            &self.region,
+            &credentials,
+            &PreSignedRequestOption { expires_in: LINK_TTL },
+        ))
+    }
+}
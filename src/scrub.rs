@@ -0,0 +1,114 @@
+//! Masks what shouldn't be reposted from Twitch into shared Slack channels: email addresses and
+//! phone numbers in channel titles and descriptions, and any words the workspace has banned.
+//! Text only the invoker sees (ephemeral replies) goes out unfiltered.
+
+use crate::workspace::WorkspaceConfig;
+
+/// Fewest digits a run needs to be taken for a phone number; dates and counts have fewer.
+const MIN_PHONE_DIGITS: usize = 10;
+const MAX_PHONE_DIGITS: usize = 15;
+const EMAIL_MASK: &str = "[email removed]";
+const PHONE_MASK: &str = "[phone removed]";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputFilter {
+    /// Lowercased.
+    banned_words: Vec<String>,
+}
+
+impl OutputFilter {
+    pub fn new(banned_words: &[String]) -> OutputFilter {
+        OutputFilter {
+            banned_words: banned_words
+                .iter()
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn for_workspace(config: &WorkspaceConfig) -> OutputFilter {
+        OutputFilter::new(&config.banned_words)
+    }
+
+    /// `text` with emails, phone numbers and banned words masked. Words are split on any
+    /// whitespace, so one after a newline or tab is caught too, and the whitespace is kept as it was.
+    pub fn scrub(&self, text: &str) -> String {
+        let text = mask_phone_numbers(text);
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text.as_str();
+        while !rest.is_empty() {
+            let word_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            out.push_str(&self.scrub_word(&rest[..word_len]));
+            let space_len = rest[word_len..].find(|c: char| !c.is_whitespace()).unwrap_or(rest.len() - word_len);
+            out.push_str(&rest[word_len..word_len + space_len]);
+            rest = &rest[word_len + space_len..];
+        }
+        out
+    }
+
+    fn scrub_word(&self, word: &str) -> String {
+        let bare = word.trim_matches(|c: char| !c.is_alphanumeric() && c != '@' && c != '.' && c != '_');
+        let bare = bare.trim_end_matches('.');
+        if is_email(bare) {
+            return word.replacen(bare, EMAIL_MASK, 1);
+        }
+
+        let letters = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if !letters.is_empty() && self.banned_words.contains(&letters.to_lowercase()) {
+            let stars: String = letters.chars().map(|_| '*').collect();
+            return word.replacen(letters, &stars, 1);
+        }
+        word.to_string()
+    }
+}
+
+fn is_email(word: &str) -> bool {
+    let mut parts = word.splitn(2, '@');
+    match (parts.next(), parts.next()) {
+        (Some(local), Some(domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains('@')
+        }
+        _ => false,
+    }
+}
+
+/// Replaces runs of digits and phone punctuation (`+`, spaces, `-`, `.`, parentheses) that hold
+/// a phone number's worth of digits and are written like one: starting with `+`, or with a `-` or
+/// `.` between digits. Bare digits (Twitch IDs) and numbers separated only by spaces ("2021 2022
+/// 2023 finals") are left alone.
+fn mask_phone_numbers(text: &str) -> String {
+    let is_part = |c: char| c.is_ascii_digit() || "+-.() ".contains(c);
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit() || c == '+' || c == '(') {
+        out.push_str(&rest[..start]);
+        let run_len = rest[start..].find(|c: char| !is_part(c)).unwrap_or(rest.len() - start);
+        let run = &rest[start..start + run_len];
+        // Keep trailing separators (the space before the next word, a full stop) out of the mask.
+        let number = run.trim_end_matches(|c: char| !c.is_ascii_digit());
+        let digits = number.chars().filter(char::is_ascii_digit).count();
+
+        if (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits) && looks_like_phone(number) {
+            out.push_str(PHONE_MASK);
+            out.push_str(&run[number.len()..]);
+        } else {
+            out.push_str(run);
+        }
+        rest = &rest[start + run_len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn looks_like_phone(number: &str) -> bool {
+    let punctuated = number.as_bytes().windows(3).any(|w| {
+        w[0].is_ascii_digit() && (w[1] == b'-' || w[1] == b'.') && w[2].is_ascii_digit()
+    });
+    number.starts_with('+') || punctuated
+}
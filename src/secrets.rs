@@ -0,0 +1,685 @@
+//! Bot credentials stored as JSON in Secrets Manager, by default as one blob under `SECRET_ID`.
+//!
+//! Rotation writes the new value under `AWSPENDING`, tests it, then promotes it to `AWSCURRENT`.
+//! Callers that see an auth failure use `refetch_after_auth_failure` to pick up a value rotated
+//! after theirs was loaded instead of failing the request.
+//!
+//! With `SECRETS_LAYOUT=split` the blob is instead three secrets, one per [`Purpose`], merged on
+//! fetch; `SECRETS_PARTS` narrows a function to the parts it reads. Either way secrets are read
+//! through a [`SecretsProvider`]; `SECRETS_SOURCE=env` swaps Secrets Manager for environment
+//! variables ([`EnvProvider`]), for running locally; it's only honored under `--local`, so a
+//! deployed function can't be talked out of Secrets Manager.
+//!
+//! Fetched values are cached per container for `SECRETS_CACHE_TTL_SECS` (default 5 minutes), so
+//! warm invocations don't each call Secrets Manager. Auth-failure refetches skip the cache.
+//!
+//! The secret is replicated across regions. `SECRETS_REGIONS` lists them in order of preference
+//! (default `us-west-2`), and a fetch falls through to the next region when one is unreachable.
+
+use crate::config;
+use crate::error::BotError;
+use crate::http;
+use crate::local;
+use crate::ratelimit::now_secs;
+use crate::stage::Stage;
+use crate::Error;
+use chrono::Utc;
+use futures::future::BoxFuture;
+use hmac::{Hmac, Mac, NewMac};
+use log::{error, info, warn};
+use rusoto_core::RusotoError;
+use rusoto_secretsmanager::{
+    GetSecretValueError, GetSecretValueRequest, GetSecretValueResponse, PutSecretValueRequest, SecretsManager,
+    SecretsManagerClient,
+};
+use rusoto_signature::region::Region;
+use serde_derive::Deserialize;
+use serde_json::{Map, Value};
+use sha2::Sha256;
+use simple_error::bail;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Named for prod; fetches use `SECRET_ID` from the environment, or the current stage's copy
+/// (`dev/tuser`, ...).
+pub const SECRET_ID: &str = "prod/tuser";
+
+pub const SLACK_SIGNATURE: &str = "X-Slack-Signature";
+pub const SLACK_TIMESTAMP: &str = "X-Slack-Request-Timestamp";
+
+/// Signed requests older (or newer) than this are rejected as possible replays, as Slack recommends.
+const MAX_SLACK_REQUEST_AGE_SECS: i64 = 5 * 60;
+
+pub const CURRENT: &str = "AWSCURRENT";
+pub const PENDING: &str = "AWSPENDING";
+
+/// What [`EnvProvider`] fills the required Twitch credentials with when their variables aren't
+/// set; `--local` answers Helix from fixtures, which don't check them.
+pub const LOCAL_SECRET: &str = "local";
+
+/// How long a fetched secret is reused when `SECRETS_CACHE_TTL_SECS` isn't set.
+const DEFAULT_CACHE_TTL_SECS: u64 = 5 * 60;
+
+struct Cached {
+    secret_id: String,
+    version_stage: String,
+    fetched_at: u64,
+    secrets: VersionedSecrets,
+}
+
+static CACHE: Mutex<Vec<Cached>> = Mutex::new(Vec::new());
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Secrets {
+    pub slack_token: String,
+    pub twitch_client_id: String,
+    pub twitch_client_secret: String,
+    pub twitch_app_token: String,
+    /// `xoxb-` token for Web API calls (posting messages, reading shared files).
+    #[serde(default)]
+    pub slack_bot_token: String,
+    /// App credentials for the OAuth install flow.
+    #[serde(default)]
+    pub slack_client_id: String,
+    #[serde(default)]
+    pub slack_client_secret: String,
+    /// Shared secret Twitch signs EventSub webhook deliveries with.
+    #[serde(default)]
+    pub twitch_eventsub_secret: String,
+    /// The webhook secret being rotated to (`trotate`); deliveries signed with it are accepted
+    /// until it replaces `twitch_eventsub_secret`.
+    #[serde(default)]
+    pub twitch_eventsub_next_secret: String,
+    /// Slack's signing secret. When set, requests are checked against their `X-Slack-Signature`
+    /// and the verification token is ignored.
+    #[serde(default)]
+    pub slack_signing_secret: String,
+    /// Key for the signed action links in Slack messages (`deeplinks`).
+    #[serde(default)]
+    pub deep_link_secret: String,
+}
+
+impl Secrets {
+    /// Every credential that's set, for taking them out of text that leaves the bot (see `crash`).
+    pub fn values(&self) -> Vec<&str> {
+        [
+            &self.slack_token,
+            &self.twitch_client_secret,
+            &self.twitch_app_token,
+            &self.slack_bot_token,
+            &self.slack_client_secret,
+            &self.twitch_eventsub_secret,
+            &self.twitch_eventsub_next_secret,
+            &self.slack_signing_secret,
+            &self.deep_link_secret,
+        ]
+        .iter()
+        .map(|value| value.as_str())
+        .filter(|value| !value.is_empty())
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VersionedSecrets {
+    pub version_id: Option<String>,
+    pub secrets: Secrets,
+}
+
+pub fn regions() -> Vec<Region> {
+    let regions: Vec<Region> = std::env::var("SECRETS_REGIONS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| match Region::from_str(name) {
+            Ok(region) => Some(region),
+            Err(_) => {
+                warn!("Ignoring unknown region {} in SECRETS_REGIONS", name);
+                None
+            }
+        })
+        .collect();
+
+    if regions.is_empty() {
+        vec![config::get().region.clone()]
+    } else {
+        regions
+    }
+}
+
+/// A secret version as stored: its version id and string value.
+#[derive(Debug, Clone)]
+pub struct SecretValue {
+    pub version_id: Option<String>,
+    pub value: String,
+}
+
+/// Where secrets come from. `get` returns `None` when the secret has no version in that stage.
+pub trait SecretsProvider: Send + Sync {
+    fn get<'a>(
+        &'a self,
+        secret_id: &'a str,
+        version_stage: &'a str,
+    ) -> BoxFuture<'a, Result<Option<SecretValue>, Error>>;
+}
+
+/// Secrets Manager, trying each of [`regions`] in turn.
+pub struct SecretsManagerProvider {
+    regions: Vec<Region>,
+}
+
+impl SecretsManagerProvider {
+    pub fn from_env() -> SecretsManagerProvider {
+        SecretsManagerProvider { regions: regions() }
+    }
+}
+
+impl SecretsProvider for SecretsManagerProvider {
+    fn get<'a>(
+        &'a self,
+        secret_id: &'a str,
+        version_stage: &'a str,
+    ) -> BoxFuture<'a, Result<Option<SecretValue>, Error>> {
+        Box::pin(async move {
+            let resp = match fetch_with_fallback(&self.regions, secret_id, version_stage).await {
+                Ok(resp) => resp,
+                Err(RusotoError::Service(GetSecretValueError::ResourceNotFound(_))) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            match resp.secret_string {
+                Some(value) => Ok(Some(SecretValue {
+                    version_id: resp.version_id,
+                    value,
+                })),
+                None => {
+                    let message = format!("Secret {} ({}) has no string value", secret_id, version_stage);
+                    Err(BotError::SecretsError(message).into())
+                }
+            }
+        })
+    }
+}
+
+/// Credentials from environment variables, each named after its field in capitals
+/// (`TWITCH_CLIENT_ID` for `twitch_client_id`). Every secret id reads the same variables, and
+/// only `AWSCURRENT` exists, so nothing is ever mid-rotation. The Twitch fields every secret must
+/// have default to [`LOCAL_SECRET`], so a local run starts with none set; `SLACK_TOKEN` never
+/// defaults, since it's what a request has to present (`--local` sets a random one).
+pub struct EnvProvider;
+
+impl EnvProvider {
+    const REQUIRED: [&'static str; 3] = ["twitch_client_id", "twitch_client_secret", "twitch_app_token"];
+    const OPTIONAL: [&'static str; 7] = [
+        "slack_bot_token",
+        "slack_client_id",
+        "slack_client_secret",
+        "twitch_eventsub_secret",
+        "twitch_eventsub_next_secret",
+        "slack_signing_secret",
+        "deep_link_secret",
+    ];
+}
+
+impl SecretsProvider for EnvProvider {
+    fn get<'a>(
+        &'a self,
+        _secret_id: &'a str,
+        version_stage: &'a str,
+    ) -> BoxFuture<'a, Result<Option<SecretValue>, Error>> {
+        Box::pin(async move {
+            if version_stage != CURRENT {
+                return Ok(None);
+            }
+            let var = |field: &str| std::env::var(field.to_ascii_uppercase()).ok().filter(|value| !value.is_empty());
+            let mut fields = Map::new();
+            match var("slack_token") {
+                Some(token) => fields.insert("slack_token".to_string(), Value::String(token)),
+                None => bail!("SECRETS_SOURCE=env needs SLACK_TOKEN set"),
+            };
+            for field in EnvProvider::REQUIRED.iter() {
+                let value = var(field).unwrap_or_else(|| LOCAL_SECRET.to_string());
+                fields.insert(field.to_string(), Value::String(value));
+            }
+            for field in EnvProvider::OPTIONAL.iter() {
+                if let Some(value) = var(field) {
+                    fields.insert(field.to_string(), Value::String(value));
+                }
+            }
+            Ok(Some(SecretValue {
+                version_id: Some("env".to_string()),
+                value: Value::Object(fields).to_string(),
+            }))
+        })
+    }
+}
+
+/// Whether secrets come from the environment rather than Secrets Manager: only under `--local`.
+pub fn env_source() -> bool {
+    local::requested() && std::env::var("SECRETS_SOURCE").map_or(false, |source| source == "env")
+}
+
+/// The separately stored parts of the bot's credentials when `SECRETS_LAYOUT` is `split`. Each
+/// can be rotated on its own, and IAM can grant a function only the parts it reads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Purpose {
+    /// The Slack verification token, signing secret and OAuth app credentials.
+    Slack,
+    /// Twitch app credentials, app token and the EventSub webhook secret.
+    Twitch,
+    /// The default bot token and keys the bot signs its own links with.
+    Bot,
+}
+
+impl Purpose {
+    pub const ALL: [Purpose; 3] = [Purpose::Slack, Purpose::Twitch, Purpose::Bot];
+
+    /// The stage's secret holding this part, overridable with `SLACK_SECRET_ID`,
+    /// `TWITCH_SECRET_ID` and `BOT_SECRET_ID`.
+    pub fn secret_id(self) -> String {
+        let (var, default) = match self {
+            Purpose::Slack => ("SLACK_SECRET_ID", "prod/tuser/slack"),
+            Purpose::Twitch => ("TWITCH_SECRET_ID", "prod/tuser/twitch"),
+            Purpose::Bot => ("BOT_SECRET_ID", "prod/tuser/bot"),
+        };
+        std::env::var(var).unwrap_or_else(|_| Stage::current().secret_id(default))
+    }
+
+    fn from_name(name: &str) -> Option<Purpose> {
+        match name {
+            "slack" => Some(Purpose::Slack),
+            "twitch" => Some(Purpose::Twitch),
+            "bot" => Some(Purpose::Bot),
+            _ => None,
+        }
+    }
+
+    /// The [`Secrets`] fields this part holds.
+    fn fields(self) -> &'static [&'static str] {
+        match self {
+            Purpose::Slack => &["slack_token", "slack_signing_secret", "slack_client_id", "slack_client_secret"],
+            Purpose::Twitch => &[
+                "twitch_client_id",
+                "twitch_client_secret",
+                "twitch_app_token",
+                "twitch_eventsub_secret",
+                "twitch_eventsub_next_secret",
+            ],
+            Purpose::Bot => &["slack_bot_token", "deep_link_secret"],
+        }
+    }
+}
+
+/// The parts this function reads with the split layout: those `SECRETS_PARTS` lists (e.g.
+/// `slack,bot`), or all of them when it's unset, so IAM only has to grant a function those.
+pub fn parts() -> Result<Vec<Purpose>, Error> {
+    let names = match std::env::var("SECRETS_PARTS") {
+        Ok(names) if !names.trim().is_empty() => names,
+        _ => return Ok(Purpose::ALL.to_vec()),
+    };
+    names
+        .split(',')
+        .map(str::trim)
+        .map(|name| {
+            Purpose::from_name(name)
+                .ok_or_else(|| BotError::SecretsError(format!("Unknown SECRETS_PARTS entry {:?}", name)).into())
+        })
+        .collect()
+}
+
+/// Whether the credentials are split into per-purpose secrets rather than the one `SECRET_ID`.
+pub fn split_layout() -> bool {
+    std::env::var("SECRETS_LAYOUT").map_or(false, |layout| layout == "split")
+}
+
+pub fn cache_ttl_secs() -> u64 {
+    std::env::var("SECRETS_CACHE_TTL_SECS")
+        .ok()
+        .and_then(|ttl| ttl.parse().ok())
+        .unwrap_or(DEFAULT_CACHE_TTL_SECS)
+}
+
+/// The bot's current credentials, for handlers that don't need the version.
+pub async fn current() -> Result<Secrets, Error> {
+    Ok(fetch(SECRET_ID, CURRENT).await?.secrets)
+}
+
+/// `secret_id` in `version_stage`, from this container's cache while it's fresh.
+pub async fn fetch(secret_id: &str, version_stage: &str) -> Result<VersionedSecrets, Error> {
+    if let Some(cached) = cached(secret_id, version_stage) {
+        return Ok(cached);
+    }
+    fetch_uncached(secret_id, version_stage).await
+}
+
+/// Fetches from Secrets Manager (or the environment) and refreshes the cache with the result.
+pub async fn fetch_uncached(secret_id: &str, version_stage: &str) -> Result<VersionedSecrets, Error> {
+    let fetched = if env_source() {
+        fetch_from(&EnvProvider, secret_id, version_stage).await?
+    } else {
+        fetch_from(&SecretsManagerProvider::from_env(), secret_id, version_stage).await?
+    };
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    cache.retain(|entry| entry.secret_id != secret_id || entry.version_stage != version_stage);
+    cache.push(Cached {
+        secret_id: secret_id.to_string(),
+        version_stage: version_stage.to_string(),
+        fetched_at: now_secs(),
+        secrets: fetched.clone(),
+    });
+    Ok(fetched)
+}
+
+fn cached(secret_id: &str, version_stage: &str) -> Option<VersionedSecrets> {
+    let cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let ttl = cache_ttl_secs();
+    cache
+        .iter()
+        .find(|entry| entry.secret_id == secret_id && entry.version_stage == version_stage)
+        .filter(|entry| now_secs().saturating_sub(entry.fetched_at) < ttl)
+        .map(|entry| entry.secrets.clone())
+}
+
+/// Loads `secret_id` in `version_stage` from `provider`. With the split layout, `SECRET_ID`
+/// stands for all the per-purpose secrets merged together.
+pub async fn fetch_from(
+    provider: &dyn SecretsProvider,
+    secret_id: &str,
+    version_stage: &str,
+) -> Result<VersionedSecrets, Error> {
+    if secret_id == SECRET_ID && split_layout() {
+        return fetch_split(provider, version_stage).await;
+    }
+
+    let secret_id = resolve(secret_id);
+    match provider.get(&secret_id, version_stage).await? {
+        Some(secret) => Ok(VersionedSecrets {
+            version_id: secret.version_id,
+            secrets: serde_json::from_str(&secret.value)
+                .map_err(|e| BotError::SecretsError(format!("Secret {} doesn't parse: {}", secret_id, e)))?,
+        }),
+        None => Err(no_version(&secret_id, version_stage)),
+    }
+}
+
+/// The secret to ask the provider for: the configured one (`SECRET_ID`, see `config`) for the
+/// bot's secret, otherwise the stage's copy of `secret_id`.
+fn resolve(secret_id: &str) -> String {
+    if secret_id == SECRET_ID {
+        config::get().secret_id.clone()
+    } else {
+        Stage::current().secret_id(secret_id)
+    }
+}
+
+/// Merges the per-purpose secrets this function reads (see [`parts`]); the fields of the others
+/// are left empty. For `AWSPENDING`, parts that aren't mid-rotation use their current value, so a
+/// rotation only has to stage the part it changes. The version id names every part's version
+/// read, so rotating any of them changes it.
+async fn fetch_split(provider: &dyn SecretsProvider, version_stage: &str) -> Result<VersionedSecrets, Error> {
+    let parts = parts()?;
+    let mut merged = Map::new();
+    let mut versions = vec![];
+    let mut staged = false;
+
+    for purpose in Purpose::ALL.iter().filter(|purpose| !parts.contains(purpose)) {
+        for field in purpose.fields() {
+            merged.insert(field.to_string(), Value::String(String::new()));
+        }
+    }
+    for purpose in &parts {
+        let secret_id = purpose.secret_id();
+        let secret = match provider.get(&secret_id, version_stage).await? {
+            Some(secret) => {
+                staged = true;
+                secret
+            }
+            None if version_stage != CURRENT => match provider.get(&secret_id, CURRENT).await? {
+                Some(secret) => secret,
+                None => return Err(no_version(&secret_id, CURRENT)),
+            },
+            None => return Err(no_version(&secret_id, version_stage)),
+        };
+        match serde_json::from_str(&secret.value) {
+            Ok(Value::Object(fields)) => merged.extend(fields),
+            _ => return Err(not_an_object(&secret_id)),
+        }
+        versions.push(format!("{}:{}", secret_id, secret.version_id.unwrap_or_default()));
+    }
+
+    if !staged {
+        return Err(BotError::SecretsError(format!("No per-purpose secret has a {} version", version_stage)).into());
+    }
+    Ok(VersionedSecrets {
+        version_id: Some(versions.join(",")),
+        secrets: serde_json::from_value(Value::Object(merged))
+            .map_err(|e| BotError::SecretsError(format!("Merged secrets don't parse: {}", e)))?,
+    })
+}
+
+fn no_version(secret_id: &str, version_stage: &str) -> Error {
+    BotError::SecretsError(format!("Secret {} has no {} version", secret_id, version_stage)).into()
+}
+
+fn not_an_object(secret_id: &str) -> Error {
+    BotError::SecretsError(format!("Secret {} isn't a JSON object", secret_id)).into()
+}
+
+/// Saves a new Twitch app token as the current version of the secret holding it, keeping its
+/// other fields. Containers that already cached the old value pick it up when their cache expires.
+pub async fn store_twitch_app_token(token: &str) -> Result<(), Error> {
+    if env_source() {
+        info!("Secrets come from the environment, so the refreshed Twitch app token isn't saved");
+        return Ok(());
+    }
+    let secret_id = store_twitch_fields(&[("twitch_app_token", Some(token))]).await?;
+    info!("Saved a refreshed Twitch app token to {}", secret_id);
+    Ok(())
+}
+
+/// Sets fields of the secret holding the Twitch credentials, or removes those given as `None`,
+/// keeping its other fields, and returns the secret's id. The result becomes its current version.
+pub async fn store_twitch_fields(changes: &[(&str, Option<&str>)]) -> Result<String, Error> {
+    if env_source() {
+        let message = "Secrets come from the environment, so they can't be changed".to_string();
+        return Err(BotError::SecretsError(message).into());
+    }
+    let secret_id = if split_layout() {
+        Purpose::Twitch.secret_id()
+    } else {
+        resolve(SECRET_ID)
+    };
+    let mut fields = match SecretsManagerProvider::from_env().get(&secret_id, CURRENT).await? {
+        Some(secret) => match serde_json::from_str(&secret.value) {
+            Ok(Value::Object(fields)) => fields,
+            _ => return Err(not_an_object(&secret_id)),
+        },
+        None => return Err(no_version(&secret_id, CURRENT)),
+    };
+    for &(field, value) in changes {
+        match value {
+            Some(value) => fields.insert(field.to_string(), Value::String(value.to_string())),
+            None => fields.remove(field),
+        };
+    }
+
+    // Writes go to the primary region; Secrets Manager replicates them to the others.
+    SecretsManagerClient::new(regions().remove(0))
+        .put_secret_value(PutSecretValueRequest {
+            secret_id: secret_id.clone(),
+            secret_string: Some(Value::Object(fields).to_string()),
+            ..Default::default()
+        })
+        .await?;
+    CACHE.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    Ok(secret_id)
+}
+
+async fn fetch_with_fallback(
+    regions: &[Region],
+    secret_id: &str,
+    version_stage: &str,
+) -> Result<GetSecretValueResponse, RusotoError<GetSecretValueError>> {
+    let last = regions.len() - 1;
+
+    for (i, region) in regions.iter().cloned().enumerate() {
+        let name = region.name().to_string();
+        let resp = SecretsManagerClient::new(region)
+            .get_secret_value(GetSecretValueRequest {
+                secret_id: secret_id.to_string(),
+                version_id: None,
+                version_stage: Some(version_stage.to_string()),
+            })
+            .await;
+
+        match resp {
+            Ok(resp) => return Ok(resp),
+            // A missing version is missing in every replica, so don't bother asking the others.
+            Err(e @ RusotoError::Service(GetSecretValueError::ResourceNotFound(_))) => return Err(e),
+            Err(e) if i == last => return Err(e),
+            Err(e) => warn!("Could not fetch secret {} from {}, trying next region: {}", secret_id, name, e),
+        }
+    }
+
+    unreachable!("regions() always returns at least one region")
+}
+
+/// Looks for a newer secret version than `stale`: a promoted `AWSCURRENT`, or failing that an
+/// in-progress `AWSPENDING`. Returns `None` when nothing has been rotated.
+pub async fn refetch_after_auth_failure(
+    secret_id: &str,
+    stale: &VersionedSecrets,
+) -> Result<Option<VersionedSecrets>, Error> {
+    let current = fetch_uncached(secret_id, CURRENT).await?;
+    if current.version_id != stale.version_id {
+        info!("Secret {} was rotated to version {:?}", secret_id, current.version_id);
+        return Ok(Some(current));
+    }
+
+    match fetch_uncached(secret_id, PENDING).await {
+        Ok(pending) if pending.version_id != stale.version_id => {
+            info!("Trying pending version {:?} of secret {}", pending.version_id, secret_id);
+            Ok(Some(pending))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Checks a request's Slack verification token and returns the secrets to serve it with. A token
+/// that is mid-rotation and only present under `AWSPENDING` is accepted too.
+pub async fn verify_slack_token(token: &str) -> Result<VersionedSecrets, Error> {
+    if token.is_empty() {
+        error!("Slack request received with empty token");
+        return Err(BotError::AuthFailure("No Slack token provided".to_string()).into());
+    }
+
+    let current = fetch(SECRET_ID, CURRENT).await?;
+    if current.secrets.slack_token == token {
+        return Ok(current);
+    }
+
+    match fetch(SECRET_ID, PENDING).await {
+        Ok(pending) if pending.secrets.slack_token == token => {
+            info!("Slack token matched pending secret version {:?}", pending.version_id);
+            Ok(current)
+        }
+        _ => {
+            error!("Slack request received with incorrect token");
+            Err(BotError::AuthFailure("Bad Slack token provided".to_string()).into())
+        }
+    }
+}
+
+/// Authenticates a Slack request and returns the secrets to serve it with. Once a signing secret
+/// is configured the request must carry a valid `X-Slack-Signature`; until then the legacy
+/// verification `token` is checked instead. During a rotation a signature made with the pending
+/// signing secret is accepted too.
+pub async fn verify_slack_request(event: &Value, token: &str) -> Result<VersionedSecrets, Error> {
+    let current = fetch(SECRET_ID, CURRENT).await?;
+    if current.secrets.slack_signing_secret.is_empty() {
+        return verify_slack_token(token).await;
+    }
+
+    let now = Utc::now().timestamp();
+    let error = match verify_slack_signature(&current.secrets.slack_signing_secret, event, now) {
+        Ok(()) => return Ok(current),
+        Err(error) => error,
+    };
+    if error == SignatureError::Mismatch {
+        if let Ok(pending) = fetch(SECRET_ID, PENDING).await {
+            let secret = &pending.secrets.slack_signing_secret;
+            if !secret.is_empty() && verify_slack_signature(secret, event, now).is_ok() {
+                info!("Slack signature matched pending secret version {:?}", pending.version_id);
+                return Ok(current);
+            }
+        }
+    }
+
+    error!("Slack request rejected: {}", error);
+    Err(BotError::AuthFailure(format!("Bad Slack request signature: {}", error)).into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignatureError {
+    /// No signature or timestamp header, or no body to check them against.
+    Missing,
+    /// The timestamp is outside the replay window.
+    Stale,
+    Mismatch,
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            SignatureError::Missing => "missing signature headers",
+            SignatureError::Stale => "timestamp outside the replay window",
+            SignatureError::Mismatch => "signature doesn't match",
+        })
+    }
+}
+
+/// Checks Slack's `v0=` HMAC-SHA256 signature over `v0:<timestamp>:<raw body>`, and that the
+/// timestamp is within a few minutes of `now` (Unix seconds).
+pub fn verify_slack_signature(signing_secret: &str, event: &Value, now: i64) -> Result<(), SignatureError> {
+    let (timestamp, signature, body) = match (
+        http::header(event, SLACK_TIMESTAMP),
+        http::header(event, SLACK_SIGNATURE),
+        http::body(event).ok(),
+    ) {
+        (Some(timestamp), Some(signature), Some(body)) => (timestamp, signature, body),
+        _ => return Err(SignatureError::Missing),
+    };
+
+    // The header is the sender's to choose, so the age is computed without overflowing.
+    let age = timestamp.parse::<i64>().ok().and_then(|sent| now.checked_sub(sent)).and_then(i64::checked_abs);
+    if !age.map_or(false, |age| age <= MAX_SLACK_REQUEST_AGE_SECS) {
+        return Err(SignatureError::Stale);
+    }
+    let expected = match signature.strip_prefix("v0=").and_then(|hex| hex::decode(hex).ok()) {
+        Some(expected) => expected,
+        None => return Err(SignatureError::Mismatch),
+    };
+
+    match slack_mac(signing_secret, &timestamp, &body) {
+        Some(mac) => mac.verify(&expected).map_err(|_| SignatureError::Mismatch),
+        None => Err(SignatureError::Mismatch),
+    }
+}
+
+/// The `X-Slack-Signature` Slack would send with `body`, for requests the bot makes to its own
+/// command functions.
+pub fn sign_slack_request(signing_secret: &str, timestamp: &str, body: &str) -> Result<String, Error> {
+    match slack_mac(signing_secret, timestamp, body) {
+        Some(mac) => Ok(format!("v0={}", hex::encode(mac.finalize().into_bytes()))),
+        None => bail!("Slack signing secret can't be used as an HMAC key"),
+    }
+}
+
+fn slack_mac(signing_secret: &str, timestamp: &str, body: &str) -> Option<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_varkey(signing_secret.as_bytes()).ok()?;
+    mac.update(b"v0:");
+    mac.update(timestamp.as_bytes());
+    mac.update(b":");
+    mac.update(body.as_bytes());
+    Some(mac)
+}
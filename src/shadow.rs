@@ -0,0 +1,205 @@
+//! Canary comparisons while `/tuser` moves between Twitch clients. `HELIX_SHADOW_PERCENT`
+//! (default 0, off) of lookups also fetch users and live status the old way, on the blocking
+//! client in a blocking task, and compare the two answers: a difference is logged with the IDs it
+//! concerns, and every comparison is counted in the `HelixShadowComparison` metric (dimensions
+//! `Call`, and `Outcome`: `match`, `diverged`, or `late` when it wasn't made). Replies only ever
+//! use the new path's answer.
+//!
+//! A sampled lookup never waits for the old path: the two start together, and if the old one
+//! hasn't finished by the time the new one has, that call goes uncompared. Follower counts and
+//! channel search have only ever had the async path and aren't compared.
+
+use crate::metrics;
+use crate::planner;
+use crate::secrets::Secrets;
+use crate::twitch::{self, ChannelMatch, Helix, LookupError, TimeoutConfig, TwitchApi, TwitchStream, TwitchUser};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use log::{info, warn};
+use rand::Rng;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use tokio::task::JoinHandle;
+
+/// Fields that can change between two calls a moment apart, left out of comparisons.
+const VOLATILE_FIELDS: &[&str] = &["viewer_count"];
+
+/// The [`TwitchApi`] for one lookup in `team_id`: [`Helix`], shadowed by the old path if this
+/// lookup is sampled.
+pub fn api(secrets: Secrets, team_id: &str) -> Box<dyn TwitchApi> {
+    let percent = sample_percent();
+    if percent > 0 && rand::thread_rng().gen_range(0, 100) < percent {
+        Box::new(Shadowed {
+            helix: Helix::new(secrets.clone()).for_team(team_id),
+            secrets,
+        })
+    } else {
+        Box::new(Helix::new(secrets).for_team(team_id))
+    }
+}
+
+/// [`Helix`], with users and streams also fetched on the blocking client and compared.
+pub struct Shadowed {
+    helix: Helix,
+    secrets: Secrets,
+}
+
+impl Shadowed {
+    pub fn new(secrets: Secrets) -> Shadowed {
+        Shadowed {
+            helix: Helix::new(secrets.clone()),
+            secrets,
+        }
+    }
+}
+
+impl TwitchApi for Shadowed {
+    fn users<'a>(
+        &'a self,
+        ids: &'a [String],
+        logins: &'a [String],
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<TwitchUser>, LookupError>> {
+        let (ids_owned, logins_owned, secrets) = (ids.to_vec(), logins.to_vec(), self.secrets.clone());
+        let old = spawn_old(move || {
+            let client = twitch::client(&timeouts)?;
+            let mut users = vec![];
+            for url in planner::user_batches(&ids_owned, &logins_owned) {
+                users.extend(twitch::get_users(&client, &url, &secrets)?);
+            }
+            Ok(users)
+        });
+        Box::pin(async move {
+            let new = self.helix.users(ids, logins, timeouts).await;
+            compare_if_done("users", &new, old, |user| &user.id);
+            new
+        })
+    }
+
+    fn streams<'a>(
+        &'a self,
+        user_ids: &'a [String],
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<TwitchStream>, LookupError>> {
+        let (user_ids_owned, secrets) = (user_ids.to_vec(), self.secrets.clone());
+        let old = spawn_old(move || twitch::get_streams(&twitch::client(&timeouts)?, &user_ids_owned, &secrets));
+        Box::pin(async move {
+            let new = self.helix.streams(user_ids, timeouts).await;
+            compare_if_done("streams", &new, old, |stream| &stream.user_id);
+            new
+        })
+    }
+
+    fn follower_counts<'a>(
+        &'a self,
+        users: &'a [TwitchUser],
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<HashMap<String, u64>, LookupError>> {
+        self.helix.follower_counts(users, timeouts)
+    }
+
+    fn search_channels<'a>(
+        &'a self,
+        query: &'a str,
+        count: usize,
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<ChannelMatch>, LookupError>> {
+        self.helix.search_channels(query, count, timeouts)
+    }
+}
+
+/// Starts the old path's call in a blocking task, running alongside the new path.
+fn spawn_old<T, F>(call: F) -> JoinHandle<Result<T, LookupError>>
+where
+    F: FnOnce() -> Result<T, LookupError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(call)
+}
+
+/// [`compare`]s with the old path's answer if it's in; if it's still running it's left to finish
+/// on its own and the call is counted as `late`.
+fn compare_if_done<T: Serialize>(
+    call: &str,
+    new: &Result<Vec<T>, LookupError>,
+    old: JoinHandle<Result<Vec<T>, LookupError>>,
+    key: fn(&T) -> &String,
+) {
+    match old.now_or_never() {
+        Some(old) => compare(call, new, &old.unwrap_or(Err(LookupError::Request)), key),
+        None => {
+            info!("Shadow {} lookup not compared: the old path was still running", call);
+            metrics::count("HelixShadowComparison", 1, &[("Call", call), ("Outcome", "late")]);
+        }
+    }
+}
+
+/// Logs how the old path's answer differs from the new one's, if at all, and counts the outcome.
+fn compare<T: Serialize>(
+    call: &str,
+    new: &Result<Vec<T>, LookupError>,
+    old: &Result<Vec<T>, LookupError>,
+    key: fn(&T) -> &String,
+) {
+    let differences = differences(new, old, key);
+    let outcome = if differences.is_empty() { "match" } else { "diverged" };
+    if differences.is_empty() {
+        info!("Shadow {} lookup matched", call);
+    } else {
+        warn!("Shadow {} lookup diverged: {}", call, differences.join("; "));
+    }
+    metrics::count("HelixShadowComparison", 1, &[("Call", call), ("Outcome", outcome)]);
+}
+
+/// What tells the two answers apart: differing errors, or the keys of items that only one has
+/// or that the two disagree on.
+pub fn differences<T: Serialize>(
+    new: &Result<Vec<T>, LookupError>,
+    old: &Result<Vec<T>, LookupError>,
+    key: fn(&T) -> &String,
+) -> Vec<String> {
+    let (new, old) = match (new, old) {
+        (Ok(new), Ok(old)) => (comparable(new, key), comparable(old, key)),
+        (Err(new), Err(old)) if new == old => return vec![],
+        (new, old) => {
+            let describe = |result: &Result<Vec<T>, LookupError>| match result {
+                Ok(items) => format!("{} items", items.len()),
+                Err(e) => format!("{:?}", e),
+            };
+            return vec![format!("new path got {}, old path {}", describe(new), describe(old))];
+        }
+    };
+
+    let mut differences = vec![];
+    for (key, item) in &new {
+        match old.get(key) {
+            None => differences.push(format!("only the new path has {}", key)),
+            Some(other) if other != item => differences.push(format!("the paths disagree on {}", key)),
+            Some(_) => {}
+        }
+    }
+    for key in old.keys().filter(|key| !new.contains_key(*key)) {
+        differences.push(format!("only the old path has {}", key));
+    }
+    differences
+}
+
+fn comparable<T: Serialize>(items: &[T], key: fn(&T) -> &String) -> BTreeMap<String, Value> {
+    items
+        .iter()
+        .map(|item| {
+            let mut value = serde_json::to_value(item).unwrap_or(Value::Null);
+            if let Some(fields) = value.as_object_mut() {
+                for field in VOLATILE_FIELDS {
+                    fields.remove(*field);
+                }
+            }
+            (key(item).clone(), value)
+        })
+        .collect()
+}
+
+fn sample_percent() -> u32 {
+    std::env::var("HELIX_SHADOW_PERCENT").ok().and_then(|percent| percent.parse().ok()).unwrap_or(0)
+}
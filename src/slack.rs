@@ -0,0 +1,716 @@
+use crate::error::BotError;
+use crate::http::{self, USER_AGENT};
+use crate::{config, stage, Error};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::bail;
+use std::borrow::Cow;
+
+pub mod blocks;
+
+/// The Web API base URL, `SLACK_API_BASE` (see `config`).
+pub fn api_base() -> &'static str {
+    &config::get().slack_api_base
+}
+
+/// Slack refuses messages with more attachments than this.
+pub const MAX_ATTACHMENTS: usize = 100;
+
+/// Longest fallback `text` generated from a message's blocks; it's for previews, not reading.
+const MAX_FALLBACK_CHARS: usize = 300;
+
+/// Slack refuses messages with more blocks than this.
+pub const MAX_BLOCKS: usize = 50;
+/// Serialized size we keep one message under. Slack's limits are loosely documented and vary by
+/// endpoint; bigger messages are refused or silently cut off.
+pub const MAX_PAYLOAD_BYTES: usize = 40_000;
+/// Slack truncates `text` past this many characters.
+const MAX_TEXT_CHARS: usize = 40_000;
+/// A slash command's `response_url` accepts this many messages, within 30 minutes of the
+/// command. The direct answer to the command's request doesn't count against it.
+pub const MAX_RESPONSES: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseType {
+    InChannel,
+    Ephemeral,
+}
+
+impl ResponseType {
+    /// Where a command's results go: the channel, unless the invoker asked for them
+    /// [quietly](is_quiet_word). Help and failures are always ephemeral.
+    pub fn for_results(quiet: bool) -> ResponseType {
+        if quiet {
+            ResponseType::Ephemeral
+        } else {
+            ResponseType::InChannel
+        }
+    }
+}
+
+/// Whether `word` in a command's text asks to keep its results to the invoker: `quiet` or
+/// `private`, bare or as a `--flag`.
+pub fn is_quiet_word(word: &str) -> bool {
+    let word = word.trim_start_matches('-');
+    word.eq_ignore_ascii_case("quiet") || word.eq_ignore_ascii_case("private")
+}
+
+impl Default for ResponseType {
+    fn default() -> ResponseType {
+        ResponseType::Ephemeral
+    }
+}
+
+/// An attachment color, always a valid `#rgb` or `#rrggbb` hex string.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct Color(Cow<'static, str>);
+
+impl Color {
+    pub const TWITCH_PURPLE: Color = Color(Cow::Borrowed("#9146ff"));
+    /// Twitch's live indicator red.
+    pub const LIVE: Color = Color(Cow::Borrowed("#eb0400"));
+    pub const OFFLINE: Color = Color(Cow::Borrowed("#adadb8"));
+
+    pub fn new(hex: &str) -> Result<Color, Error> {
+        let digits = match hex.strip_prefix('#') {
+            Some(digits) => digits,
+            None => bail!("Color {} must start with #", hex),
+        };
+        if !(digits.len() == 3 || digits.len() == 6) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("Color {} is not a 3 or 6 digit hex value", hex);
+        }
+
+        Ok(Color(Cow::Owned(hex.to_ascii_lowercase())))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlackMessage {
+    response_type: ResponseType,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    blocks: Vec<Block>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    attachments: Vec<SlackAttachment>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlackAttachment {
+    /// Plain-text summary for notifications and clients that can't show the attachment.
+    pub fallback: String,
+    pub color: Color,
+    pub author_name: String,
+    pub author_icon: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Section {
+        text: Text,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        accessory: Option<Element>,
+    },
+    Actions {
+        elements: Vec<Element>,
+    },
+    Context {
+        elements: Vec<Text>,
+    },
+    Divider,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Text {
+    Mrkdwn { text: String },
+    PlainText { text: String },
+}
+
+impl Text {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Text::Mrkdwn { text } | Text::PlainText { text } => text,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Element {
+    Overflow {
+        action_id: String,
+        options: Vec<MenuOption>,
+    },
+    /// `alt_text` must describe the image; `build` refuses messages where it's blank.
+    Image {
+        image_url: String,
+        alt_text: String,
+    },
+    Button {
+        action_id: String,
+        text: Text,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        /// `"primary"` or `"danger"`; Slack's default styling when unset.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        style: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        confirm: Option<ConfirmDialog>,
+    },
+}
+
+/// The "Are you sure?" dialog Slack shows before sending a button's action.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfirmDialog {
+    pub title: Text,
+    pub text: Text,
+    pub confirm: Text,
+    pub deny: Text,
+}
+
+impl ConfirmDialog {
+    pub fn new(title: &str, text: String, confirm: &str) -> ConfirmDialog {
+        ConfirmDialog {
+            title: Text::PlainText { text: title.to_string() },
+            text: Text::Mrkdwn { text },
+            confirm: Text::PlainText { text: confirm.to_string() },
+            deny: Text::PlainText {
+                text: "Cancel".to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MenuOption {
+    pub text: Text,
+    pub value: String,
+}
+
+impl MenuOption {
+    pub fn new(label: &str, value: String) -> MenuOption {
+        MenuOption {
+            text: Text::PlainText { text: label.to_string() },
+            value,
+        }
+    }
+}
+
+impl SlackMessage {
+    pub fn builder() -> SlackMessageBuilder {
+        SlackMessageBuilder::default()
+    }
+
+    pub fn is_in_channel(&self) -> bool {
+        self.response_type == ResponseType::InChannel
+    }
+
+    /// The same message, shown only to whoever ran the command.
+    pub fn only_for_invoker(self) -> SlackMessage {
+        SlackMessage {
+            response_type: ResponseType::Ephemeral,
+            ..self
+        }
+    }
+
+    /// The same content as an ephemeral message led by `note`, for showing someone a reply that
+    /// was already posted.
+    pub fn repeated(self, note: &str) -> SlackMessage {
+        SlackMessage {
+            response_type: ResponseType::Ephemeral,
+            text: truncate(&format!("{}\n{}", note, self.text), MAX_TEXT_CHARS),
+            ..self
+        }
+    }
+
+    /// The size of the message as sent to Slack, in bytes.
+    pub fn payload_size(&self) -> usize {
+        serde_json::to_string(self).map_or(usize::MAX, |json| json.len())
+    }
+
+    fn fits(&self) -> bool {
+        self.blocks.len() <= MAX_BLOCKS
+            && self.attachments.len() <= MAX_ATTACHMENTS
+            && self.payload_size() <= MAX_PAYLOAD_BYTES
+    }
+
+    /// Splits a message Slack would refuse as too big into several it won't, each with as many
+    /// whole blocks (then attachments) as fit, in order. The first part keeps the text; the rest
+    /// say they continue it. A single block or attachment too big on its own still gets a
+    /// message to itself.
+    pub fn split(self) -> Vec<SlackMessage> {
+        if self.fits() {
+            return vec![self];
+        }
+
+        let response_type = self.response_type;
+        let continued = || SlackMessage {
+            response_type,
+            text: "_(continued)_".to_string(),
+            blocks: vec![],
+            attachments: vec![],
+        };
+        let mut parts = vec![];
+        let mut current = SlackMessage {
+            text: truncate(&self.text, MAX_TEXT_CHARS),
+            ..continued()
+        };
+        let mut empty = true;
+
+        for block in self.blocks {
+            current.blocks.push(block);
+            if !empty && !current.fits() {
+                let block = current.blocks.pop().expect("just pushed");
+                parts.push(std::mem::replace(&mut current, continued()));
+                current.blocks.push(block);
+            }
+            empty = false;
+        }
+        for attachment in self.attachments {
+            current.attachments.push(attachment);
+            if !empty && !current.fits() {
+                let attachment = current.attachments.pop().expect("just pushed");
+                parts.push(std::mem::replace(&mut current, continued()));
+                current.attachments.push(attachment);
+            }
+            empty = false;
+        }
+        parts.push(current);
+        parts
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Builds a `SlackMessage`, checking on `build` that it is something Slack will accept. Messages
+/// are ephemeral unless `in_channel` is called, matching Slack's own default.
+#[derive(Default)]
+pub struct SlackMessageBuilder {
+    response_type: ResponseType,
+    text: String,
+    blocks: Vec<Block>,
+    attachments: Vec<SlackAttachment>,
+}
+
+impl SlackMessageBuilder {
+    pub fn response_type(mut self, response_type: ResponseType) -> Self {
+        self.response_type = response_type;
+        self
+    }
+
+    pub fn in_channel(self) -> Self {
+        self.response_type(ResponseType::InChannel)
+    }
+
+    pub fn ephemeral(self) -> Self {
+        self.response_type(ResponseType::Ephemeral)
+    }
+
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = text.into();
+        self
+    }
+
+    pub fn block(mut self, block: Block) -> Self {
+        self.blocks.push(block);
+        self
+    }
+
+    pub fn blocks(mut self, blocks: impl IntoIterator<Item = Block>) -> Self {
+        self.blocks.extend(blocks);
+        self
+    }
+
+    pub fn attachment(mut self, attachment: SlackAttachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    pub fn attachments(mut self, attachments: impl IntoIterator<Item = SlackAttachment>) -> Self {
+        self.attachments.extend(attachments);
+        self
+    }
+
+    /// Also makes sure every image has alt text, and fills in a plain-text `text` from the blocks
+    /// and attachments when none was given, so screen readers and notification previews always
+    /// have something to say.
+    pub fn build(self) -> Result<SlackMessage, Error> {
+        if self.text.is_empty() && self.blocks.is_empty() && self.attachments.is_empty() {
+            bail!("Slack message needs text, blocks or attachments");
+        }
+        if self.attachments.len() > MAX_ATTACHMENTS {
+            bail!("Slack message has {} attachments, max is {}", self.attachments.len(), MAX_ATTACHMENTS);
+        }
+        let all_blocks = self.blocks.iter().chain(self.attachments.iter().flat_map(|a| a.blocks.iter()));
+        for block in all_blocks {
+            if let Block::Section {
+                accessory: Some(Element::Image { image_url, alt_text }),
+                ..
+            } = block
+            {
+                if alt_text.trim().is_empty() {
+                    bail!("Image {} has no alt text", image_url);
+                }
+            }
+        }
+
+        let text = if self.text.is_empty() {
+            fallback_text(&self.blocks, &self.attachments)
+        } else {
+            self.text
+        };
+
+        Ok(SlackMessage {
+            response_type: self.response_type,
+            text,
+            blocks: self.blocks,
+            attachments: self.attachments,
+        })
+    }
+}
+
+/// A preview-sized plain-text summary of a message's content.
+fn fallback_text(blocks: &[Block], attachments: &[SlackAttachment]) -> String {
+    let mut parts: Vec<&str> = vec![];
+    for block in blocks {
+        match block {
+            Block::Section { text, .. } => parts.push(text.as_str()),
+            Block::Context { elements } => parts.extend(elements.iter().map(Text::as_str)),
+            Block::Actions { .. } | Block::Divider => {}
+        }
+    }
+    parts.extend(attachments.iter().map(|a| a.fallback.as_str()));
+
+    let text = parts.into_iter().filter(|part| !part.is_empty()).collect::<Vec<_>>().join("\n");
+    truncate(&text, MAX_FALLBACK_CHARS)
+}
+
+/// The fields of a slash command invocation the handlers use.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SlashCommand {
+    pub token: String,
+    pub text: String,
+    /// The slash command itself, e.g. `/tuser`.
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub team_id: String,
+    #[serde(default)]
+    pub user_id: String,
+    #[serde(default)]
+    pub channel_id: String,
+    #[serde(default)]
+    pub response_url: String,
+    #[serde(default)]
+    pub trigger_id: String,
+}
+
+impl SlashCommand {
+    /// Reads the command out of an API Gateway event's body: the form-encoded fields Slack
+    /// posts, or a JSON object with the same fields.
+    pub fn from_event(event: &Value) -> Result<SlashCommand, Error> {
+        // `sls invoke local -d "$(cat tests/payload.json)"` passes the fields as the event itself.
+        let decode = |e: &dyn std::fmt::Display| BotError::Decode(format!("slash command: {}", e));
+        if event.get("body").is_none() && event.get("token").is_some() {
+            return Ok(serde_json::from_value(event.clone()).map_err(|e| decode(&e))?);
+        }
+        let body = http::body(event)?;
+        if body.trim_start().starts_with('{') {
+            return Ok(serde_json::from_str(&body).map_err(|e| decode(&e))?);
+        }
+        Ok(serde_urlencoded::from_str(&body).map_err(|e| decode(&e))?)
+    }
+}
+
+/// Reply for requests that couldn't be decoded at all.
+pub fn malformed_request() -> Result<SlackMessage, Error> {
+    SlackMessage::builder()
+        .ephemeral()
+        .text("Sorry, that request couldn't be understood. Please try the command again.")
+        .build()
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SlackFile {
+    pub id: String,
+    pub name: String,
+    pub filetype: String,
+    #[serde(default)]
+    pub size: u64,
+    pub url_private_download: String,
+}
+
+#[derive(Deserialize)]
+struct FileInfoResponse {
+    file: SlackFile,
+}
+
+/// Web API methods answer 200 with `"ok": false` on failure, so the body has to be checked too.
+fn check_response(method: &str, resp: reqwest::blocking::Response) -> Result<Value, Error> {
+    let status = resp.status();
+    if status != 200 {
+        bail!("Slack {} returned HTTP {} ({})", method, status, USER_AGENT);
+    }
+
+    let body: Value = resp.json()?;
+    if body.get("ok").and_then(Value::as_bool) != Some(true) {
+        bail!(
+            "Slack {} failed: {} ({})",
+            method,
+            body.get("error").and_then(Value::as_str).unwrap_or("unknown error"),
+            USER_AGENT
+        );
+    }
+    Ok(body)
+}
+
+/// Calls a JSON-bodied Web API method and returns the (successful) response body.
+pub fn call(client: &reqwest::blocking::Client, token: &str, method: &str, body: &Value) -> Result<Value, Error> {
+    let resp = client
+        .post(&format!("{}/{}", api_base(), method))
+        .bearer_auth(token)
+        .json(body)
+        .send()?;
+    check_response(method, resp)
+}
+
+/// Posts to a channel, or to a user's DM with the bot when given a user ID.
+pub fn post_message(
+    client: &reqwest::blocking::Client,
+    token: &str,
+    channel: &str,
+    message: &SlackMessage,
+) -> Result<(), Error> {
+    post(client, token, channel, message).map(|_| ())
+}
+
+/// Posts a message and returns its `ts`, for messages that are updated or pinned later.
+pub fn post(
+    client: &reqwest::blocking::Client,
+    token: &str,
+    channel: &str,
+    message: &SlackMessage,
+) -> Result<String, Error> {
+    let mut body = serde_json::to_value(message)?;
+    body["channel"] = Value::String(stage::slack_channel(channel));
+
+    let resp = call(client, token, "chat.postMessage", &body)?;
+    match resp.get("ts").and_then(Value::as_str) {
+        Some(ts) => Ok(ts.to_string()),
+        None => bail!("Slack chat.postMessage returned no ts"),
+    }
+}
+
+pub fn update_message(
+    client: &reqwest::blocking::Client,
+    token: &str,
+    channel: &str,
+    ts: &str,
+    message: &SlackMessage,
+) -> Result<(), Error> {
+    let mut body = serde_json::to_value(message)?;
+    body["channel"] = Value::String(stage::slack_channel(channel));
+    body["ts"] = Value::String(ts.to_string());
+
+    call(client, token, "chat.update", &body)?;
+    Ok(())
+}
+
+/// Pins a message to its channel. Needs the `pins:write` scope.
+pub fn pin(client: &reqwest::blocking::Client, token: &str, channel: &str, ts: &str) -> Result<(), Error> {
+    let channel = stage::slack_channel(channel);
+    call(client, token, "pins.add", &serde_json::json!({ "channel": channel, "timestamp": ts }))?;
+    Ok(())
+}
+
+pub fn open_view(client: &reqwest::blocking::Client, token: &str, trigger_id: &str, view: &Value) -> Result<(), Error> {
+    call(
+        client,
+        token,
+        "views.open",
+        &serde_json::json!({ "trigger_id": trigger_id, "view": view }),
+    )?;
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OAuthAccess {
+    pub access_token: String,
+    pub team: OAuthTeam,
+    pub authed_user: OAuthUser,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OAuthTeam {
+    pub id: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OAuthUser {
+    pub id: String,
+}
+
+/// Exchanges the `code` from an install redirect for the workspace's bot token.
+pub fn oauth_access(
+    client: &reqwest::blocking::Client,
+    client_id: &str,
+    client_secret: &str,
+    code: &str,
+) -> Result<OAuthAccess, Error> {
+    let resp = client
+        .post(&format!("{}/oauth.v2.access", api_base()))
+        .form(&[("client_id", client_id), ("client_secret", client_secret), ("code", code)])
+        .send()?;
+    let body = check_response("oauth.v2.access", resp)?;
+
+    Ok(serde_json::from_value(body)?)
+}
+
+#[derive(Deserialize)]
+struct UserInfoResponse {
+    user: UserInfo,
+}
+
+#[derive(Deserialize)]
+struct UserInfo {
+    #[serde(default)]
+    is_admin: bool,
+    #[serde(default)]
+    is_owner: bool,
+}
+
+/// Whether a Slack user is an admin or owner of the workspace. Needs the `users:read` scope.
+pub fn is_workspace_admin(client: &reqwest::blocking::Client, token: &str, user_id: &str) -> Result<bool, Error> {
+    let resp = client
+        .get(&format!("{}/users.info", api_base()))
+        .bearer_auth(token)
+        .query(&[("user", user_id)])
+        .send()?;
+    let user = serde_json::from_value::<UserInfoResponse>(check_response("users.info", resp)?)?.user;
+
+    Ok(user.is_admin || user.is_owner)
+}
+
+/// Sends a follow-up to a command or interaction's `response_url` as a new message.
+pub fn respond(client: &reqwest::blocking::Client, response_url: &str, message: &SlackMessage) -> Result<(), Error> {
+    send_response(client, response_url, message, false)
+}
+
+/// Replaces the message an interaction came from, e.g. to turn a page.
+pub fn replace(client: &reqwest::blocking::Client, response_url: &str, message: &SlackMessage) -> Result<(), Error> {
+    send_response(client, response_url, message, true)
+}
+
+/// Replaces the message an interaction came from with `message` as Slack sent it in the
+/// payload, after changing it with e.g. [`with_block_after`].
+pub fn update(client: &reqwest::blocking::Client, response_url: &str, message: &Value) -> Result<(), Error> {
+    send_response(client, response_url, message, true)
+}
+
+/// `original`, a message as an interaction payload carries it, with `block` shown under the
+/// block whose ID is `anchor`, in the message or one of its attachments. The new block's ID is
+/// the anchor's with `:expanded` after it, so another call for the same anchor replaces it
+/// rather than stacking up. Fails if there's no such block.
+pub fn with_block_after(original: &Value, anchor: &str, block: &Block) -> Result<Value, Error> {
+    let expanded_id = format!("{}:expanded", anchor);
+    let mut inserted = serde_json::to_value(block)?;
+    inserted["block_id"] = Value::String(expanded_id.clone());
+
+    let mut message = serde_json::Map::new();
+    for field in &["text", "blocks", "attachments"] {
+        if let Some(value) = original.get(*field) {
+            message.insert(field.to_string(), value.clone());
+        }
+    }
+
+    let mut lists: Vec<&mut Vec<Value>> = vec![];
+    for (field, value) in message.iter_mut() {
+        match (field.as_str(), value) {
+            ("blocks", Value::Array(blocks)) => lists.push(blocks),
+            ("attachments", Value::Array(attachments)) => {
+                lists.extend(attachments.iter_mut().filter_map(|attachment| match attachment.get_mut("blocks") {
+                    Some(Value::Array(blocks)) => Some(blocks),
+                    _ => None,
+                }))
+            }
+            _ => {}
+        }
+    }
+    let mut found = false;
+    for blocks in lists {
+        blocks.retain(|block| block["block_id"] != expanded_id.as_str());
+        if let Some(i) = blocks.iter().position(|block| block["block_id"] == anchor) {
+            blocks.insert(i + 1, inserted);
+            found = true;
+            break;
+        }
+    }
+    if !found {
+        bail!("The message has no block {}", anchor);
+    }
+    Ok(Value::Object(message))
+}
+
+fn send_response(
+    client: &reqwest::blocking::Client,
+    response_url: &str,
+    message: &impl serde::Serialize,
+    replace_original: bool,
+) -> Result<(), Error> {
+    let mut body = serde_json::to_value(message)?;
+    body["replace_original"] = Value::Bool(replace_original);
+
+    let resp = client.post(response_url).json(&body).send()?;
+    if resp.status() != 200 {
+        bail!("Slack response_url returned HTTP {}", resp.status());
+    }
+    Ok(())
+}
+
+pub fn file_info(client: &reqwest::blocking::Client, token: &str, file_id: &str) -> Result<SlackFile, Error> {
+    let resp = client
+        .get(&format!("{}/files.info", api_base()))
+        .bearer_auth(token)
+        .query(&[("file", file_id)])
+        .send()?;
+    let body = check_response("files.info", resp)?;
+
+    Ok(serde_json::from_value::<FileInfoResponse>(body)?.file)
+}
+
+pub fn download_file(client: &reqwest::blocking::Client, token: &str, file: &SlackFile) -> Result<String, Error> {
+    let resp = client.get(&file.url_private_download).bearer_auth(token).send()?;
+    if resp.status() != 200 {
+        bail!("Downloading Slack file {} returned HTTP {}", file.id, resp.status());
+    }
+    Ok(resp.text()?)
+}
+
+pub fn upload_file(
+    client: &reqwest::blocking::Client,
+    token: &str,
+    channel: &str,
+    filename: &str,
+    content: &str,
+) -> Result<(), Error> {
+    let resp = client
+        .post(&format!("{}/files.upload", api_base()))
+        .bearer_auth(token)
+        .form(&[("channels", stage::slack_channel(channel).as_str()), ("filename", filename), ("content", content)])
+        .send()?;
+    check_response("files.upload", resp)?;
+    Ok(())
+}
@@ -0,0 +1,99 @@
+//! Builds Block Kit layouts: messages whose content is top-level blocks rather than legacy
+//! attachments, which can't mix images, buttons and text this freely.
+//!
+//! ```ignore
+//! let blocks = Blocks::new()
+//!     .section_with_image("*pokimane*", &user.profile_image_url, "pokimane's profile picture")
+//!     .context(vec!["ID 44445592".to_string()])
+//!     .actions(vec![blocks::link_button("open_channel", "Open channel", url)])
+//!     .into_blocks();
+//! ```
+
+use crate::slack::{Block, Element, Text};
+
+#[derive(Debug, Default)]
+pub struct Blocks {
+    blocks: Vec<Block>,
+}
+
+impl Blocks {
+    pub fn new() -> Blocks {
+        Blocks::default()
+    }
+
+    pub fn section(mut self, text: impl Into<String>) -> Self {
+        self.blocks.push(Block::Section {
+            text: mrkdwn(text),
+            accessory: None,
+        });
+        self
+    }
+
+    /// A section with a thumbnail on its right. `alt_text` is required, see [`Element::Image`].
+    pub fn section_with_image(mut self, text: impl Into<String>, image_url: &str, alt_text: impl Into<String>) -> Self {
+        let accessory = if image_url.is_empty() {
+            None
+        } else {
+            Some(Element::Image {
+                image_url: image_url.to_string(),
+                alt_text: alt_text.into(),
+            })
+        };
+        self.blocks.push(Block::Section {
+            text: mrkdwn(text),
+            accessory,
+        });
+        self
+    }
+
+    /// A line of small print; left out when `items` is empty.
+    pub fn context(mut self, items: Vec<String>) -> Self {
+        if !items.is_empty() {
+            self.blocks.push(Block::Context {
+                elements: items.into_iter().map(mrkdwn).collect(),
+            });
+        }
+        self
+    }
+
+    /// Left out when `elements` is empty.
+    pub fn actions(mut self, elements: Vec<Element>) -> Self {
+        if !elements.is_empty() {
+            self.blocks.push(Block::Actions { elements });
+        }
+        self
+    }
+
+    pub fn divider(mut self) -> Self {
+        self.blocks.push(Block::Divider);
+        self
+    }
+
+    /// Adds another layout's blocks after these.
+    pub fn extend(mut self, other: Blocks) -> Self {
+        self.blocks.extend(other.blocks);
+        self
+    }
+
+    pub fn into_blocks(self) -> Vec<Block> {
+        self.blocks
+    }
+}
+
+pub fn mrkdwn(text: impl Into<String>) -> Text {
+    Text::Mrkdwn { text: text.into() }
+}
+
+/// A button that opens `url` in the browser.
+pub fn link_button(action_id: &str, label: &str, url: String) -> Element {
+    Element::Button {
+        action_id: action_id.to_string(),
+        text: Text::PlainText {
+            text: label.to_string(),
+        },
+        value: None,
+        url: Some(url),
+        style: None,
+        confirm: None,
+    }
+}
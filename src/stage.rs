@@ -0,0 +1,84 @@
+//! Which deployment this is. `STAGE` (`dev`, `staging` or `prod`, default `prod` when unset so
+//! existing deployments keep their names) picks the secret names, table and bucket names and the
+//! default log level, and outside prod can steer channel posts to a test channel. Any other value
+//! stops the function when it starts (see `local::serve`) rather than quietly running against
+//! prod's resources.
+
+use crate::Error;
+use log::LevelFilter;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Dev,
+    Staging,
+    Prod,
+}
+
+static CURRENT: OnceLock<Stage> = OnceLock::new();
+
+impl Stage {
+    /// The stage `STAGE` names, or an error if it's set to anything but `dev`, `staging` or
+    /// `prod`.
+    pub fn from_env() -> Result<Stage, Error> {
+        let stage = std::env::var("STAGE").unwrap_or_default();
+        match stage.to_ascii_lowercase().as_str() {
+            "dev" => Ok(Stage::Dev),
+            "staging" => Ok(Stage::Staging),
+            "prod" | "" => Ok(Stage::Prod),
+            _ => Err(format!("Unknown STAGE {:?}: expected dev, staging or prod", stage).into()),
+        }
+    }
+
+    /// The stage `STAGE` names, read once. A function checks it with [`Stage::from_env`] before
+    /// its first event, so an unknown value only gets this far outside one (tests, tools), where
+    /// it counts as prod.
+    pub fn current() -> Stage {
+        *CURRENT.get_or_init(|| Stage::from_env().unwrap_or(Stage::Prod))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Dev => "dev",
+            Stage::Staging => "staging",
+            Stage::Prod => "prod",
+        }
+    }
+
+    /// The stage's copy of a secret: `prod/tuser` becomes `dev/tuser` in dev.
+    pub fn secret_id(self, secret_id: &str) -> String {
+        match secret_id.strip_prefix("prod/") {
+            Some(rest) => format!("{}/{}", self.name(), rest),
+            None => secret_id.to_string(),
+        }
+    }
+
+    /// The stage's copy of a table or bucket: `tuser-cache` in prod, `tuser-cache-dev` in dev.
+    pub fn resource(self, name: &str) -> String {
+        match self {
+            Stage::Prod => name.to_string(),
+            stage => format!("{}-{}", name, stage.name()),
+        }
+    }
+
+    /// Used when `RUST_LOG` doesn't set a level.
+    pub fn default_log_level(self) -> LevelFilter {
+        match self {
+            Stage::Dev => LevelFilter::Debug,
+            Stage::Staging | Stage::Prod => LevelFilter::Info,
+        }
+    }
+}
+
+/// Where a write to `channel` should go. Outside prod, `SLACK_TEST_CHANNEL` takes every channel
+/// post, update, pin and upload, so a staging deploy installed in a real workspace can't touch its
+/// channels. DMs (user IDs, or `D` conversation IDs) go through unchanged.
+pub fn slack_channel(channel: &str) -> String {
+    if Stage::current() == Stage::Prod || channel.starts_with(|c| c == 'U' || c == 'W' || c == 'D') {
+        return channel.to_string();
+    }
+    match std::env::var("SLACK_TEST_CHANNEL") {
+        Ok(test_channel) if !test_channel.is_empty() => test_channel,
+        _ => channel.to_string(),
+    }
+}
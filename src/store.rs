@@ -0,0 +1,775 @@
+//! Key-value persistence behind a [`Store`] trait, so stores built on it can run against
+//! DynamoDB in AWS or a local SQLite file. One `Store` is one table: items are found by a single
+//! string key and hold named string or number attributes. Tables keyed by a partition and a sort
+//! key (the audit log, title history) go through [`SortedStore`] instead.
+//!
+//! [`open`] picks the backend: DynamoDB, unless `STORE_BACKEND=sqlite`, which keeps every table
+//! in the file at `SQLITE_PATH` (default `twitch-info-bot.db`). SQLite needs the crate's
+//! `sqlite` feature; a table that can't be opened there fails every call on it with the reason,
+//! so the handler reports it like any other storage error.
+
+use crate::config;
+use crate::Error;
+use chrono::Utc;
+use futures::future::BoxFuture;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    AttributeValue, DeleteItemError, DeleteItemInput, DynamoDb, DynamoDbClient, GetItemInput, PutItemError,
+    PutItemInput, QueryInput, ScanInput, UpdateItemInput,
+};
+use rusoto_signature::region::Region;
+use std::collections::HashMap;
+
+/// A stored attribute. Numbers stay numbers so DynamoDB's TTL can read `expires_at`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Attr {
+    S(String),
+    N(i64),
+}
+
+impl Attr {
+    pub fn into_string(self) -> Option<String> {
+        match self {
+            Attr::S(s) => Some(s),
+            Attr::N(_) => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<i64> {
+        match self {
+            Attr::N(n) => Some(*n),
+            Attr::S(_) => None,
+        }
+    }
+}
+
+/// An item's attributes, without its key.
+pub type Attrs = HashMap<String, Attr>;
+
+/// What a conditional write needs to find under its key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// No item, or one whose `expires_at` has passed.
+    Absent,
+    /// An item whose attribute `name` holds this value.
+    Equals(&'static str, Attr),
+}
+
+pub trait Store: Send + Sync {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Attrs>, Error>>;
+
+    /// Replaces the whole item.
+    fn put<'a>(&'a self, key: &'a str, attrs: Attrs) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Replaces the whole item if `condition` holds, returning whether it did.
+    fn put_if<'a>(&'a self, key: &'a str, attrs: Attrs, condition: Condition) -> BoxFuture<'a, Result<bool, Error>>;
+
+    /// Sets one attribute, creating the item if it doesn't exist and leaving its others alone.
+    fn set<'a>(&'a self, key: &'a str, name: &'a str, value: Attr) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Adds `by` to the number attribute `name` (0 if it's missing) and sets the attributes in
+    /// `also`, in one write, creating the item if it doesn't exist.
+    fn add<'a>(&'a self, key: &'a str, name: &'a str, by: i64, also: Attrs) -> BoxFuture<'a, Result<(), Error>>;
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Deletes the item if `condition` holds, returning whether it did.
+    fn delete_if<'a>(&'a self, key: &'a str, condition: Condition) -> BoxFuture<'a, Result<bool, Error>>;
+
+    /// Every item, for jobs that run across all of them, with only the `attributes` named (all
+    /// of them when empty).
+    fn scan<'a>(&'a self, attributes: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<(String, Attrs)>, Error>>;
+}
+
+/// A table of items under a partition key, ordered within the partition by a string sort key.
+pub trait SortedStore: Send + Sync {
+    /// Adds an item, replacing any with the same partition and sort key.
+    fn put<'a>(&'a self, partition: &'a str, sort: &'a str, attrs: Attrs) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// The partition's items with their sort keys, greatest first, stopping after `limit`.
+    fn newest<'a>(&'a self, partition: &'a str, limit: usize) -> BoxFuture<'a, Result<Vec<(String, Attrs)>, Error>>;
+}
+
+fn sqlite_backend() -> bool {
+    std::env::var("STORE_BACKEND").map_or(false, |backend| backend == "sqlite")
+}
+
+/// The configured backend for `table`, whose items are keyed by the `key` attribute.
+pub fn open(table: String, key: &'static str) -> Box<dyn Store> {
+    if sqlite_backend() {
+        return sqlite(table);
+    }
+    Box::new(DynamoStore::new(config::get().region.clone(), table, key))
+}
+
+/// The configured backend for `table`, keyed by the `partition` and `sort` attributes.
+pub fn open_sorted(table: String, partition: &'static str, sort: &'static str) -> Box<dyn SortedStore> {
+    if sqlite_backend() {
+        return sqlite_sorted(table);
+    }
+    Box::new(DynamoSortedStore::new(config::get().region.clone(), table, partition, sort))
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_path() -> String {
+    std::env::var("SQLITE_PATH").unwrap_or_else(|_| "twitch-info-bot.db".to_string())
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite(table: String) -> Box<dyn Store> {
+    let path = sqlite_path();
+    match SqliteStore::open(&path, &table) {
+        Ok(store) => Box::new(store),
+        Err(e) => Box::new(Unavailable(format!("Could not open {} in SQLite file {}: {}", table, path, e))),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_sorted(table: String) -> Box<dyn SortedStore> {
+    let path = sqlite_path();
+    match SqliteSortedStore::open(&path, &table) {
+        Ok(store) => Box::new(store),
+        Err(e) => Box::new(Unavailable(format!("Could not open {} in SQLite file {}: {}", table, path, e))),
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite(table: String) -> Box<dyn Store> {
+    Box::new(Unavailable(format!("STORE_BACKEND=sqlite needs the bot built with the `sqlite` feature ({})", table)))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_sorted(table: String) -> Box<dyn SortedStore> {
+    Box::new(Unavailable(format!("STORE_BACKEND=sqlite needs the bot built with the `sqlite` feature ({})", table)))
+}
+
+/// A table the configured backend couldn't open; every call fails with why.
+struct Unavailable(String);
+
+impl Unavailable {
+    fn fail<'a, T: Send + 'a>(&'a self) -> BoxFuture<'a, Result<T, Error>> {
+        Box::pin(async move { Err(self.0.clone().into()) })
+    }
+}
+
+impl Store for Unavailable {
+    fn get<'a>(&'a self, _key: &'a str) -> BoxFuture<'a, Result<Option<Attrs>, Error>> {
+        self.fail()
+    }
+
+    fn put<'a>(&'a self, _key: &'a str, _attrs: Attrs) -> BoxFuture<'a, Result<(), Error>> {
+        self.fail()
+    }
+
+    fn put_if<'a>(&'a self, _key: &'a str, _attrs: Attrs, _condition: Condition) -> BoxFuture<'a, Result<bool, Error>> {
+        self.fail()
+    }
+
+    fn set<'a>(&'a self, _key: &'a str, _name: &'a str, _value: Attr) -> BoxFuture<'a, Result<(), Error>> {
+        self.fail()
+    }
+
+    fn add<'a>(&'a self, _key: &'a str, _name: &'a str, _by: i64, _also: Attrs) -> BoxFuture<'a, Result<(), Error>> {
+        self.fail()
+    }
+
+    fn delete<'a>(&'a self, _key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        self.fail()
+    }
+
+    fn delete_if<'a>(&'a self, _key: &'a str, _condition: Condition) -> BoxFuture<'a, Result<bool, Error>> {
+        self.fail()
+    }
+
+    fn scan<'a>(&'a self, _attributes: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<(String, Attrs)>, Error>> {
+        self.fail()
+    }
+}
+
+impl SortedStore for Unavailable {
+    fn put<'a>(&'a self, _partition: &'a str, _sort: &'a str, _attrs: Attrs) -> BoxFuture<'a, Result<(), Error>> {
+        self.fail()
+    }
+
+    fn newest<'a>(&'a self, _partition: &'a str, _limit: usize) -> BoxFuture<'a, Result<Vec<(String, Attrs)>, Error>> {
+        self.fail()
+    }
+}
+
+pub struct DynamoStore {
+    client: DynamoDbClient,
+    table: String,
+    key: &'static str,
+}
+
+impl DynamoStore {
+    pub fn new(region: Region, table: String, key: &'static str) -> DynamoStore {
+        DynamoStore {
+            client: DynamoDbClient::new(region),
+            table,
+            key,
+        }
+    }
+
+    fn key(&self, key: &str) -> HashMap<String, AttributeValue> {
+        let mut item = HashMap::new();
+        item.insert(self.key.to_string(), to_dynamo(Attr::S(key.to_string())));
+        item
+    }
+}
+
+impl Store for DynamoStore {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Attrs>, Error>> {
+        Box::pin(async move {
+            let resp = self
+                .client
+                .get_item(GetItemInput {
+                    table_name: self.table.clone(),
+                    key: self.key(key),
+                    consistent_read: Some(true),
+                    ..Default::default()
+                })
+                .await?;
+            Ok(resp.item.map(|item| from_dynamo(self.key, item).1))
+        })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, attrs: Attrs) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut item = self.key(key);
+            item.extend(attrs.into_iter().map(|(name, value)| (name, to_dynamo(value))));
+            self.client
+                .put_item(PutItemInput {
+                    table_name: self.table.clone(),
+                    item,
+                    ..Default::default()
+                })
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn put_if<'a>(&'a self, key: &'a str, attrs: Attrs, condition: Condition) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move {
+            let mut item = self.key(key);
+            item.extend(attrs.into_iter().map(|(name, value)| (name, to_dynamo(value))));
+            let (expression, names, values) = condition_expression(self.key, condition);
+            let put = self
+                .client
+                .put_item(PutItemInput {
+                    table_name: self.table.clone(),
+                    item,
+                    condition_expression: Some(expression),
+                    expression_attribute_names: Some(names),
+                    expression_attribute_values: Some(values),
+                    ..Default::default()
+                })
+                .await;
+            match put {
+                Ok(_) => Ok(true),
+                Err(RusotoError::Service(PutItemError::ConditionalCheckFailed(_))) => Ok(false),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn set<'a>(&'a self, key: &'a str, name: &'a str, value: Attr) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut names = HashMap::new();
+            names.insert("#attr".to_string(), name.to_string());
+            let mut values = HashMap::new();
+            values.insert(":value".to_string(), to_dynamo(value));
+
+            self.client
+                .update_item(UpdateItemInput {
+                    table_name: self.table.clone(),
+                    key: self.key(key),
+                    update_expression: Some("SET #attr = :value".to_string()),
+                    expression_attribute_names: Some(names),
+                    expression_attribute_values: Some(values),
+                    ..Default::default()
+                })
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn add<'a>(&'a self, key: &'a str, name: &'a str, by: i64, also: Attrs) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut names = HashMap::new();
+            names.insert("#counter".to_string(), name.to_string());
+            let mut values = HashMap::new();
+            values.insert(":by".to_string(), to_dynamo(Attr::N(by)));
+            let mut sets = vec![];
+            for (n, (name, value)) in also.into_iter().enumerate() {
+                names.insert(format!("#also{}", n), name);
+                values.insert(format!(":also{}", n), to_dynamo(value));
+                sets.push(format!("#also{} = :also{}", n, n));
+            }
+            let mut expression = "ADD #counter :by".to_string();
+            if !sets.is_empty() {
+                expression.push_str(&format!(" SET {}", sets.join(", ")));
+            }
+
+            self.client
+                .update_item(UpdateItemInput {
+                    table_name: self.table.clone(),
+                    key: self.key(key),
+                    update_expression: Some(expression),
+                    expression_attribute_names: Some(names),
+                    expression_attribute_values: Some(values),
+                    ..Default::default()
+                })
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.client
+                .delete_item(DeleteItemInput {
+                    table_name: self.table.clone(),
+                    key: self.key(key),
+                    ..Default::default()
+                })
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn delete_if<'a>(&'a self, key: &'a str, condition: Condition) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move {
+            let (expression, names, values) = condition_expression(self.key, condition);
+            let delete = self
+                .client
+                .delete_item(DeleteItemInput {
+                    table_name: self.table.clone(),
+                    key: self.key(key),
+                    condition_expression: Some(expression),
+                    expression_attribute_names: Some(names),
+                    expression_attribute_values: Some(values),
+                    ..Default::default()
+                })
+                .await;
+            match delete {
+                Ok(_) => Ok(true),
+                Err(RusotoError::Service(DeleteItemError::ConditionalCheckFailed(_))) => Ok(false),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn scan<'a>(&'a self, attributes: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<(String, Attrs)>, Error>> {
+        Box::pin(async move {
+            // Attribute names like `record` are reserved words in expressions, so they go by `#n`.
+            let names: HashMap<String, String> = std::iter::once(self.key)
+                .chain(attributes.iter().copied())
+                .enumerate()
+                .map(|(n, name)| (format!("#a{}", n), name.to_string()))
+                .collect();
+            let projection = (0..names.len()).map(|n| format!("#a{}", n)).collect::<Vec<_>>().join(", ");
+            let (projection, names) = match attributes {
+                [] => (None, None),
+                _ => (Some(projection), Some(names)),
+            };
+
+            let mut items = vec![];
+            let mut start_key = None;
+            loop {
+                let resp = self
+                    .client
+                    .scan(ScanInput {
+                        table_name: self.table.clone(),
+                        projection_expression: projection.clone(),
+                        expression_attribute_names: names.clone(),
+                        exclusive_start_key: start_key,
+                        ..Default::default()
+                    })
+                    .await?;
+                items.extend(resp.items.unwrap_or_default().into_iter().map(|item| from_dynamo(self.key, item)));
+
+                start_key = resp.last_evaluated_key;
+                if start_key.is_none() {
+                    return Ok(items);
+                }
+            }
+        })
+    }
+}
+
+/// The `condition_expression` for `condition` on a table keyed by `key`, with the names and
+/// values it refers to.
+fn condition_expression(
+    key: &str,
+    condition: Condition,
+) -> (String, HashMap<String, String>, HashMap<String, AttributeValue>) {
+    let mut names = HashMap::new();
+    let mut values = HashMap::new();
+    let expression = match condition {
+        Condition::Absent => {
+            names.insert("#key".to_string(), key.to_string());
+            values.insert(":now".to_string(), to_dynamo(Attr::N(Utc::now().timestamp())));
+            "attribute_not_exists(#key) OR expires_at < :now"
+        }
+        Condition::Equals(name, value) => {
+            names.insert("#attr".to_string(), name.to_string());
+            values.insert(":expected".to_string(), to_dynamo(value));
+            "#attr = :expected"
+        }
+    };
+    (expression.to_string(), names, values)
+}
+
+pub struct DynamoSortedStore {
+    client: DynamoDbClient,
+    table: String,
+    partition: &'static str,
+    sort: &'static str,
+}
+
+impl DynamoSortedStore {
+    pub fn new(region: Region, table: String, partition: &'static str, sort: &'static str) -> DynamoSortedStore {
+        DynamoSortedStore {
+            client: DynamoDbClient::new(region),
+            table,
+            partition,
+            sort,
+        }
+    }
+}
+
+impl SortedStore for DynamoSortedStore {
+    fn put<'a>(&'a self, partition: &'a str, sort: &'a str, attrs: Attrs) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut item: HashMap<String, AttributeValue> =
+                attrs.into_iter().map(|(name, value)| (name, to_dynamo(value))).collect();
+            item.insert(self.partition.to_string(), to_dynamo(Attr::S(partition.to_string())));
+            item.insert(self.sort.to_string(), to_dynamo(Attr::S(sort.to_string())));
+            self.client
+                .put_item(PutItemInput {
+                    table_name: self.table.clone(),
+                    item,
+                    ..Default::default()
+                })
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn newest<'a>(&'a self, partition: &'a str, limit: usize) -> BoxFuture<'a, Result<Vec<(String, Attrs)>, Error>> {
+        Box::pin(async move {
+            if limit == 0 {
+                return Ok(vec![]);
+            }
+            let mut names = HashMap::new();
+            names.insert("#partition".to_string(), self.partition.to_string());
+            let mut values = HashMap::new();
+            values.insert(":partition".to_string(), to_dynamo(Attr::S(partition.to_string())));
+
+            let mut items = vec![];
+            let mut start_key = None;
+            loop {
+                let resp = self
+                    .client
+                    .query(QueryInput {
+                        table_name: self.table.clone(),
+                        key_condition_expression: Some("#partition = :partition".to_string()),
+                        expression_attribute_names: Some(names.clone()),
+                        expression_attribute_values: Some(values.clone()),
+                        scan_index_forward: Some(false),
+                        limit: Some((limit - items.len()) as i64),
+                        exclusive_start_key: start_key,
+                        ..Default::default()
+                    })
+                    .await?;
+                for item in resp.items.unwrap_or_default() {
+                    let (sort, mut attrs) = from_dynamo(self.sort, item);
+                    attrs.remove(self.partition);
+                    items.push((sort, attrs));
+                }
+
+                start_key = resp.last_evaluated_key;
+                if start_key.is_none() || items.len() >= limit {
+                    items.truncate(limit);
+                    return Ok(items);
+                }
+            }
+        })
+    }
+}
+
+fn to_dynamo(value: Attr) -> AttributeValue {
+    match value {
+        Attr::S(s) => AttributeValue {
+            s: Some(s),
+            ..Default::default()
+        },
+        Attr::N(n) => AttributeValue {
+            n: Some(n.to_string()),
+            ..Default::default()
+        },
+    }
+}
+
+/// The item's key and its other string and number attributes; anything else is dropped.
+fn from_dynamo(key: &str, item: HashMap<String, AttributeValue>) -> (String, Attrs) {
+    let mut id = String::new();
+    let mut attrs = Attrs::new();
+    for (name, value) in item {
+        let value = match (value.s, value.n.and_then(|n| n.parse().ok())) {
+            (Some(s), _) => Attr::S(s),
+            (None, Some(n)) => Attr::N(n),
+            _ => continue,
+        };
+        match value {
+            Attr::S(s) if name == key => id = s,
+            value => {
+                attrs.insert(name, value);
+            }
+        }
+    }
+    (id, attrs)
+}
+
+/// Tables as rows of `(key, attrs)` in a local SQLite file, the attributes as a JSON object.
+/// Items whose `expires_at` has passed read as missing, standing in for DynamoDB's TTL.
+#[cfg(feature = "sqlite")]
+pub struct SqliteStore {
+    conn: std::sync::Mutex<rusqlite::Connection>,
+    table: String,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    pub fn open(path: &str, table: &str) -> Result<SqliteStore, Error> {
+        let conn = rusqlite::Connection::open(path)?;
+        let table = quote(table);
+        conn.execute(
+            &format!("CREATE TABLE IF NOT EXISTS {} (key TEXT PRIMARY KEY, attrs TEXT NOT NULL)", table),
+            [],
+        )?;
+        Ok(SqliteStore {
+            conn: std::sync::Mutex::new(conn),
+            table,
+        })
+    }
+
+    // A local file answers in microseconds, so these run inline rather than on a blocking thread.
+    // Each call holds the connection throughout, so a read and the write after it can't interleave
+    // with another caller's.
+    fn lock(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn read(&self, conn: &rusqlite::Connection, key: &str) -> Result<Option<Attrs>, Error> {
+        use rusqlite::OptionalExtension;
+        let json: Option<String> = conn
+            .query_row(&format!("SELECT attrs FROM {} WHERE key = ?1", self.table), [key], |row| row.get(0))
+            .optional()?;
+        match json {
+            Some(json) => Ok(unexpired(decode(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn holds(&self, conn: &rusqlite::Connection, key: &str, condition: Condition) -> Result<bool, Error> {
+        Ok(match (self.read(conn, key)?, condition) {
+            (None, Condition::Absent) => true,
+            (Some(current), Condition::Equals(name, value)) => current.get(name) == Some(&value),
+            _ => false,
+        })
+    }
+
+    fn write(&self, conn: &rusqlite::Connection, key: &str, attrs: &Attrs) -> Result<(), Error> {
+        conn.execute(
+            &format!("INSERT OR REPLACE INTO {} (key, attrs) VALUES (?1, ?2)", self.table),
+            [key, &encode(attrs)],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Store for SqliteStore {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Attrs>, Error>> {
+        Box::pin(async move { self.read(&self.lock(), key) })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, attrs: Attrs) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move { self.write(&self.lock(), key, &attrs) })
+    }
+
+    fn put_if<'a>(&'a self, key: &'a str, attrs: Attrs, condition: Condition) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move {
+            let conn = self.lock();
+            let holds = self.holds(&conn, key, condition)?;
+            if holds {
+                self.write(&conn, key, &attrs)?;
+            }
+            Ok(holds)
+        })
+    }
+
+    fn set<'a>(&'a self, key: &'a str, name: &'a str, value: Attr) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let conn = self.lock();
+            let mut attrs = self.read(&conn, key)?.unwrap_or_default();
+            attrs.insert(name.to_string(), value);
+            self.write(&conn, key, &attrs)
+        })
+    }
+
+    fn add<'a>(&'a self, key: &'a str, name: &'a str, by: i64, also: Attrs) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let conn = self.lock();
+            let mut attrs = self.read(&conn, key)?.unwrap_or_default();
+            let count = attrs.get(name).and_then(Attr::as_number).unwrap_or_default() + by;
+            attrs.insert(name.to_string(), Attr::N(count));
+            attrs.extend(also);
+            self.write(&conn, key, &attrs)
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.lock().execute(&format!("DELETE FROM {} WHERE key = ?1", self.table), [key])?;
+            Ok(())
+        })
+    }
+
+    fn delete_if<'a>(&'a self, key: &'a str, condition: Condition) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move {
+            let conn = self.lock();
+            let holds = self.holds(&conn, key, condition)?;
+            if holds {
+                conn.execute(&format!("DELETE FROM {} WHERE key = ?1", self.table), [key])?;
+            }
+            Ok(holds)
+        })
+    }
+
+    fn scan<'a>(&'a self, attributes: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<(String, Attrs)>, Error>> {
+        Box::pin(async move {
+            let conn = self.lock();
+            let mut statement = conn.prepare(&format!("SELECT key, attrs FROM {}", self.table))?;
+            let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+            let mut items = vec![];
+            for row in rows {
+                let (key, json) = row?;
+                if let Some(mut attrs) = unexpired(decode(&json)?) {
+                    if !attributes.is_empty() {
+                        attrs.retain(|name, _| attributes.contains(&name.as_str()));
+                    }
+                    items.push((key, attrs));
+                }
+            }
+            Ok(items)
+        })
+    }
+}
+
+/// [`SortedStore`] tables as rows of `(partition, sort, attrs)`, expiring like [`SqliteStore`].
+#[cfg(feature = "sqlite")]
+pub struct SqliteSortedStore {
+    conn: std::sync::Mutex<rusqlite::Connection>,
+    table: String,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteSortedStore {
+    pub fn open(path: &str, table: &str) -> Result<SqliteSortedStore, Error> {
+        let conn = rusqlite::Connection::open(path)?;
+        let table = quote(table);
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (partition TEXT NOT NULL, sort TEXT NOT NULL, attrs TEXT NOT NULL, \
+                 PRIMARY KEY (partition, sort))",
+                table
+            ),
+            [],
+        )?;
+        Ok(SqliteSortedStore {
+            conn: std::sync::Mutex::new(conn),
+            table,
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl SortedStore for SqliteSortedStore {
+    fn put<'a>(&'a self, partition: &'a str, sort: &'a str, attrs: Attrs) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+            conn.execute(
+                &format!("INSERT OR REPLACE INTO {} (partition, sort, attrs) VALUES (?1, ?2, ?3)", self.table),
+                [partition, sort, &encode(&attrs)],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn newest<'a>(&'a self, partition: &'a str, limit: usize) -> BoxFuture<'a, Result<Vec<(String, Attrs)>, Error>> {
+        Box::pin(async move {
+            let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+            let query = format!("SELECT sort, attrs FROM {} WHERE partition = ?1 ORDER BY sort DESC", self.table);
+            let mut statement = conn.prepare(&query)?;
+            let rows = statement.query_map([partition], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            let mut items = vec![];
+            for row in rows {
+                let (sort, json) = row?;
+                if let Some(attrs) = unexpired(decode(&json)?) {
+                    items.push((sort, attrs));
+                }
+                if items.len() >= limit {
+                    break;
+                }
+            }
+            Ok(items)
+        })
+    }
+}
+
+/// Table names are our own resource names, but quote them since they carry dashes.
+#[cfg(feature = "sqlite")]
+fn quote(table: &str) -> String {
+    format!("\"{}\"", table.replace('"', ""))
+}
+
+#[cfg(feature = "sqlite")]
+fn unexpired(attrs: Attrs) -> Option<Attrs> {
+    match attrs.get("expires_at").and_then(Attr::as_number) {
+        Some(expires_at) if expires_at <= Utc::now().timestamp() => None,
+        _ => Some(attrs),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn encode(attrs: &Attrs) -> String {
+    let object: serde_json::Map<String, serde_json::Value> = attrs
+        .iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Attr::S(s) => serde_json::Value::String(s.clone()),
+                Attr::N(n) => serde_json::Value::from(*n),
+            };
+            (name.clone(), value)
+        })
+        .collect();
+    serde_json::Value::Object(object).to_string()
+}
+
+#[cfg(feature = "sqlite")]
+fn decode(json: &str) -> Result<Attrs, Error> {
+    let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(json)?;
+    Ok(object
+        .into_iter()
+        .filter_map(|(name, value)| match value {
+            serde_json::Value::String(s) => Some((name, Attr::S(s))),
+            serde_json::Value::Number(n) => n.as_i64().map(|n| (name, Attr::N(n))),
+            _ => None,
+        })
+        .collect())
+}
@@ -0,0 +1,74 @@
+//! History of watched channels' title and category changes, from `channel.update` EventSub
+//! notifications, for `/ttitles`. Keyed by broadcaster id so it survives a rename, and sorted by
+//! when the change was sent.
+
+use crate::stage::Stage;
+use crate::store::{self, Attr, Attrs, SortedStore};
+use crate::Error;
+use chrono::Utc;
+use serde_json::Value;
+
+const DEFAULT_TABLE: &str = "tuser-titles";
+/// Long enough to check the title a sponsored stream ran with a few months later.
+const RETENTION_SECS: i64 = 180 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TitleChange {
+    /// RFC 3339, when Twitch sent the change.
+    pub changed_at: String,
+    pub title: String,
+    pub category_name: String,
+}
+
+impl TitleChange {
+    /// The change described by a `channel.update` notification's event, sent at `changed_at`.
+    pub fn from_event(event: &Value, changed_at: &str) -> TitleChange {
+        let field = |name: &str| event.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+        TitleChange {
+            changed_at: changed_at.to_string(),
+            title: field("title"),
+            category_name: field("category_name"),
+        }
+    }
+}
+
+pub struct TitleStore {
+    store: Box<dyn SortedStore>,
+}
+
+impl TitleStore {
+    pub fn new(store: Box<dyn SortedStore>) -> TitleStore {
+        TitleStore { store }
+    }
+
+    pub fn from_env() -> TitleStore {
+        let table = std::env::var("TITLES_TABLE").unwrap_or_else(|_| Stage::current().resource(DEFAULT_TABLE));
+        TitleStore::new(store::open_sorted(table, "broadcaster_id", "changed_at"))
+    }
+
+    pub async fn record(&self, broadcaster_id: &str, change: &TitleChange) -> Result<(), Error> {
+        let mut item = Attrs::new();
+        item.insert("title".to_string(), Attr::S(change.title.clone()));
+        item.insert("category_name".to_string(), Attr::S(change.category_name.clone()));
+        item.insert("expires_at".to_string(), Attr::N(Utc::now().timestamp() + RETENTION_SECS));
+        self.store.put(broadcaster_id, &change.changed_at, item).await
+    }
+
+    /// The channel's most recent changes, newest first.
+    pub async fn recent(&self, broadcaster_id: &str, limit: usize) -> Result<Vec<TitleChange>, Error> {
+        Ok(self
+            .store
+            .newest(broadcaster_id, limit)
+            .await?
+            .into_iter()
+            .map(|(changed_at, mut item)| {
+                let mut take = |name: &str| item.remove(name).and_then(Attr::into_string).unwrap_or_default();
+                TitleChange {
+                    changed_at,
+                    title: take("title"),
+                    category_name: take("category_name"),
+                }
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,56 @@
+//! English translations of channel descriptions through AWS Translate, for workspaces that turn
+//! on `translate_descriptions`. The source language is detected, and text that's already English
+//! isn't translated.
+
+use crate::config;
+use crate::Error;
+use rusoto_signature::region::Region;
+use rusoto_translate::{Translate, TranslateClient, TranslateTextRequest};
+
+const TARGET_LANGUAGE: &str = "en";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Translation {
+    /// The detected language's code, e.g. `es`.
+    pub source_language: String,
+    pub text: String,
+}
+
+pub struct Translator {
+    client: TranslateClient,
+}
+
+impl Translator {
+    pub fn new(region: Region) -> Translator {
+        Translator {
+            client: TranslateClient::new(region),
+        }
+    }
+
+    pub fn from_env() -> Translator {
+        Translator::new(config::get().region.clone())
+    }
+
+    /// `text` in English, or `None` when it's empty or already English.
+    pub async fn to_english(&self, text: &str) -> Result<Option<Translation>, Error> {
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+        let resp = self
+            .client
+            .translate_text(TranslateTextRequest {
+                text: text.to_string(),
+                source_language_code: "auto".to_string(),
+                target_language_code: TARGET_LANGUAGE.to_string(),
+                ..Default::default()
+            })
+            .await?;
+        if resp.source_language_code == TARGET_LANGUAGE {
+            return Ok(None);
+        }
+        Ok(Some(Translation {
+            source_language: resp.source_language_code,
+            text: resp.translated_text,
+        }))
+    }
+}
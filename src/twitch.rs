@@ -0,0 +1,1653 @@
+use crate::apptoken;
+use crate::config;
+use crate::drift::{self, Tracked};
+use crate::http::USER_AGENT;
+use crate::links::{self, AccountLink, LinkStore};
+use crate::maintenance;
+use crate::mocktwitch;
+use crate::planner;
+use crate::ratelimit;
+use crate::secrets::Secrets;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::future::{join_all, BoxFuture};
+use futures::stream::{self, StreamExt};
+use log::{error, info};
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub const INGEST_URL: &str = "https://ingest.twitch.tv/ingests";
+
+/// The Helix base URL, `TWITCH_API_BASE` (see `config`).
+pub fn helix_base() -> &'static str {
+    &config::get().twitch_api_base
+}
+
+/// Helix accepts at most this many `id`/`login` parameters per request.
+pub const MAX_IDS_PER_REQUEST: usize = 100;
+
+/// Timeouts for outbound Twitch calls. `command_budget` bounds the whole slash command so a hanging
+/// endpoint surfaces as a failed lookup instead of running into the Lambda timeout. Optional
+/// enrichment steps (live status, say) get the shorter `enrichment` timeout each, so a slow one
+/// just leaves its field off the card.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeoutConfig {
+    pub connect: Duration,
+    pub request: Duration,
+    pub command_budget: Duration,
+    pub enrichment: Duration,
+}
+
+impl TimeoutConfig {
+    pub fn from_env() -> TimeoutConfig {
+        TimeoutConfig {
+            connect: env_millis("TWITCH_CONNECT_TIMEOUT_MS", 1000),
+            request: env_millis("TWITCH_REQUEST_TIMEOUT_MS", 2000),
+            command_budget: env_millis("COMMAND_TIMEOUT_MS", 2500),
+            enrichment: env_millis("TWITCH_ENRICHMENT_TIMEOUT_MS", 700),
+        }
+    }
+
+    /// The same timeouts with each request capped at `budget`, for one step of a larger lookup.
+    pub fn for_step(self, budget: Duration) -> TimeoutConfig {
+        TimeoutConfig {
+            request: self.request.min(budget),
+            ..self
+        }
+    }
+}
+
+/// Retries for Helix failures that are likely to pass: 429s, 5xx and requests that never got an
+/// answer. Each retry waits a random time up to `base_delay` doubled per attempt, capped at
+/// `max_delay` ("full jitter", so a burst of failed calls doesn't retry in step). A 429 waits for
+/// the bucket's `Ratelimit-Reset` instead, and isn't retried if that's further off than
+/// `max_delay`. Timeouts aren't retried, since the time they took is already spent.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Attempts in all, including the first; 1 turns retries off.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: std::env::var("TWITCH_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(3)
+                .max(1),
+            base_delay: env_millis("TWITCH_RETRY_BASE_MS", 100),
+            max_delay: env_millis("TWITCH_RETRY_MAX_MS", 1000),
+        }
+    }
+
+    /// How long to wait before trying again after `attempt` (counting from 1) failed with
+    /// `error`, or `None` to give up. Only 429s are retried for requests that change something:
+    /// a 5xx or dropped connection might have come after Twitch acted on it.
+    pub fn backoff(&self, attempt: u32, error: &LookupError, idempotent: bool) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        match error {
+            LookupError::RateLimited(reset) => {
+                // Resets are whole seconds, so wait into the second after.
+                let wait = Duration::from_secs(reset.saturating_sub(ratelimit::now_secs())) + Duration::from_millis(50);
+                if wait <= self.max_delay {
+                    Some(wait)
+                } else {
+                    None
+                }
+            }
+            LookupError::Outage(_) | LookupError::Request if idempotent => {
+                let cap = self.base_delay.saturating_mul(1 << (attempt - 1).min(16)).min(self.max_delay);
+                Some(Duration::from_millis(rand::thread_rng().gen_range(0, cap.as_millis() as u64 + 1)))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn env_millis(name: &str, default: u64) -> Duration {
+    let millis = std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+    Duration::from_millis(millis)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TwitchUser {
+    #[serde(rename = "type")]
+    pub user_type: String,
+
+    pub id: String,
+    pub login: String,
+    pub display_name: String,
+    pub broadcaster_type: String,
+    pub description: String,
+    pub profile_image_url: String,
+    pub offline_image_url: String,
+    /// When the account was made, as RFC 3339.
+    #[serde(default)]
+    pub created_at: String,
+    /// Deprecated by Twitch and no longer updated, but still reported.
+    #[serde(default)]
+    pub view_count: u64,
+    /// Fields this struct has no place for; see [`drift`].
+    #[serde(flatten, default)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl Tracked for TwitchUser {
+    const ENDPOINT: &'static str = "users";
+    const FIELDS: &'static [&'static str] = &[
+        "id",
+        "login",
+        "display_name",
+        "type",
+        "broadcaster_type",
+        "description",
+        "profile_image_url",
+        "offline_image_url",
+        "view_count",
+        "created_at",
+    ];
+
+    fn extra(&self) -> &HashMap<String, Value> {
+        &self.extra
+    }
+}
+
+impl TwitchUser {
+    pub fn created(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.created_at).ok().map(|created| created.with_timezone(&Utc))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TwitchStream {
+    pub id: String,
+    pub user_id: String,
+    pub user_login: String,
+    pub user_name: String,
+    pub game_id: String,
+    pub game_name: String,
+    pub title: String,
+    pub viewer_count: u64,
+    pub started_at: String,
+    #[serde(rename = "type", default)]
+    pub stream_type: String,
+    #[serde(default)]
+    pub language: String,
+    #[serde(default)]
+    pub is_mature: bool,
+    /// A preview image URL with `{width}` and `{height}` placeholders.
+    #[serde(default)]
+    pub thumbnail_url: String,
+    #[serde(flatten, default)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl Tracked for TwitchStream {
+    const ENDPOINT: &'static str = "streams";
+    const FIELDS: &'static [&'static str] = &[
+        "id",
+        "user_id",
+        "user_login",
+        "user_name",
+        "game_id",
+        "game_name",
+        "type",
+        "title",
+        "tags",
+        "viewer_count",
+        "started_at",
+        "language",
+        "thumbnail_url",
+        "tag_ids",
+        "is_mature",
+    ];
+
+    fn extra(&self) -> &HashMap<String, Value> {
+        &self.extra
+    }
+}
+
+/// A channel from Search Channels, which matches logins and names loosely.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChannelMatch {
+    pub id: String,
+    pub broadcaster_login: String,
+    pub display_name: String,
+    #[serde(default)]
+    pub is_live: bool,
+    /// The channel's current or last category.
+    #[serde(default)]
+    pub game_name: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(flatten, default)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl Tracked for ChannelMatch {
+    const ENDPOINT: &'static str = "search/channels";
+    const FIELDS: &'static [&'static str] = &[
+        "broadcaster_language",
+        "broadcaster_login",
+        "display_name",
+        "game_id",
+        "game_name",
+        "id",
+        "is_live",
+        "tag_ids",
+        "tags",
+        "thumbnail_url",
+        "title",
+        "started_at",
+    ];
+
+    fn extra(&self) -> &HashMap<String, Value> {
+        &self.extra
+    }
+}
+
+/// A Helix game (category).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TwitchGame {
+    pub id: String,
+    pub name: String,
+    /// Has `{width}` and `{height}` placeholders; see [`TwitchGame::box_art`].
+    pub box_art_url: String,
+    /// Empty for categories IGDB doesn't know, like "Just Chatting".
+    #[serde(default)]
+    pub igdb_id: String,
+}
+
+impl TwitchGame {
+    pub fn box_art(&self, width: u32, height: u32) -> String {
+        self.box_art_url
+            .replace("{width}", &width.to_string())
+            .replace("{height}", &height.to_string())
+    }
+
+    /// IGDB's short link for the game, which redirects to its page. Short links put the numeric
+    /// ID in base 36.
+    pub fn igdb_url(&self) -> Option<String> {
+        let mut id: u64 = self.igdb_id.parse().ok()?;
+        let mut digits = vec![];
+        loop {
+            digits.push(std::char::from_digit((id % 36) as u32, 36)?);
+            id /= 36;
+            if id == 0 {
+                break;
+            }
+        }
+        Some(format!("https://www.igdb.com/g/{}", digits.iter().rev().collect::<String>()))
+    }
+}
+
+/// Which games to look up; Helix takes any one of these keys.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameQuery {
+    Id(String),
+    Name(String),
+    IgdbId(String),
+}
+
+impl TwitchStream {
+    pub fn uptime(&self, now: DateTime<Utc>) -> Option<chrono::Duration> {
+        DateTime::parse_from_rfc3339(&self.started_at)
+            .ok()
+            .map(|started| now.signed_duration_since(started.with_timezone(&Utc)))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HelixList<T> {
+    pub data: Vec<T>,
+}
+
+/// A page of a cursor-paginated Helix endpoint.
+#[derive(Deserialize, Debug)]
+pub struct HelixPage<T> {
+    pub data: Vec<T>,
+    #[serde(default)]
+    pub pagination: Pagination,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Pagination {
+    pub cursor: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TwitchClip {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    #[serde(default)]
+    pub creator_id: String,
+    pub creator_name: String,
+    pub view_count: u64,
+    pub created_at: String,
+    #[serde(default)]
+    pub broadcaster_name: String,
+    #[serde(default)]
+    pub thumbnail_url: String,
+}
+
+/// Someone connected to a channel's chat.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Chatter {
+    pub user_id: String,
+    pub user_login: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TwitchVideo {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    pub view_count: u64,
+    pub created_at: String,
+    pub duration: String,
+}
+
+impl TwitchVideo {
+    /// When the video's broadcast ended: `created_at` plus `duration` (`3h2m1s`).
+    pub fn ended_at(&self) -> Option<DateTime<Utc>> {
+        let start = DateTime::parse_from_rfc3339(&self.created_at).ok()?.with_timezone(&Utc);
+        let (mut secs, mut number) = (0, 0);
+        for c in self.duration.chars() {
+            match c {
+                '0'..='9' => number = number * 10 + i64::from(c.to_digit(10)?),
+                'h' => secs += std::mem::take(&mut number) * 3600,
+                'm' => secs += std::mem::take(&mut number) * 60,
+                's' => secs += std::mem::take(&mut number),
+                _ => return None,
+            }
+        }
+        Some(start + chrono::Duration::seconds(secs))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TwitchScheduleResponse {
+    pub data: TwitchSchedule,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TwitchSchedule {
+    pub broadcaster_name: String,
+    #[serde(default)]
+    pub segments: Option<Vec<TwitchScheduleSegment>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TwitchScheduleSegment {
+    #[serde(default)]
+    pub id: String,
+    pub start_time: String,
+    /// Set when the broadcaster cancelled this occurrence of a recurring segment.
+    #[serde(default)]
+    pub canceled_until: Option<String>,
+    pub title: String,
+    pub category: Option<TwitchCategory>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TwitchCategory {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CustomReward {
+    pub id: String,
+    pub title: String,
+    pub cost: u64,
+    pub is_enabled: bool,
+    pub is_paused: bool,
+    #[serde(default)]
+    pub redemptions_redeemed_current_stream: Option<u64>,
+    #[serde(default)]
+    pub should_redemptions_skip_request_queue: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Redemption {
+    pub id: String,
+    pub user_name: String,
+    #[serde(default)]
+    pub user_input: String,
+    pub redeemed_at: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FollowedChannel {
+    pub broadcaster_id: String,
+    pub broadcaster_login: String,
+    pub broadcaster_name: String,
+    pub followed_at: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct BlockedUser {
+    pub user_id: String,
+    pub user_login: String,
+    pub display_name: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ExtensionTransaction {
+    pub id: String,
+    pub timestamp: String,
+    pub broadcaster_login: String,
+    pub user_login: String,
+    pub product_type: String,
+    pub product_data: ProductData,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProductData {
+    pub sku: String,
+    #[serde(rename = "displayName", default)]
+    pub display_name: String,
+    pub cost: ProductCost,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProductCost {
+    pub amount: u64,
+    #[serde(rename = "type")]
+    pub cost_type: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DropEntitlement {
+    pub id: String,
+    pub benefit_id: String,
+    pub timestamp: String,
+    pub user_id: String,
+    pub game_id: String,
+    pub fulfillment_status: String,
+    pub last_updated: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AutomodSettings {
+    pub overall_level: Option<u8>,
+    pub disability: u8,
+    pub aggression: u8,
+    pub sexuality_sex_or_gender: u8,
+    pub misogyny: u8,
+    pub bullying: u8,
+    pub swearing: u8,
+    pub race_ethnicity_or_religion: u8,
+    pub sex_based_terms: u8,
+}
+
+#[derive(Deserialize, Debug)]
+struct AutomodStatus {
+    is_permitted: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IngestResponse {
+    pub ingests: Vec<Ingest>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Ingest {
+    pub name: String,
+    pub availability: f64,
+    #[serde(default)]
+    pub default: bool,
+}
+
+/// Why a Twitch lookup failed, kept separate so each case gets its own message in Slack.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LookupError {
+    Timeout,
+    Unauthorized(u16),
+    Rejected(u16),
+    Outage(u16),
+    /// The Helix bucket is empty until this Unix timestamp.
+    RateLimited(u64),
+    /// Twitch is under maintenance, expected to last until this Unix timestamp.
+    Maintenance(u64),
+    Request,
+    Decode,
+}
+
+impl LookupError {
+    pub fn user_message(&self, text: &str) -> String {
+        match self {
+            LookupError::Timeout => format!("Twitch didn't respond in time looking up {}. Try again in a moment.", text),
+            LookupError::Unauthorized(status) => format!(
+                "Twitch rejected the bot's credentials (HTTP {}). Ask an admin to check the Twitch app token.",
+                status
+            ),
+            LookupError::Rejected(status) => format!(
+                "Twitch rejected the lookup for {} (HTTP {}). Check the usernames and IDs.",
+                text, status
+            ),
+            LookupError::Outage(status) => format!(
+                "Twitch is having problems right now (HTTP {}). Try again later.",
+                status
+            ),
+            LookupError::RateLimited(reset) => format!(
+                "Twitch is rate limiting us, try again in {}s.",
+                reset.saturating_sub(ratelimit::now_secs()).max(1)
+            ),
+            LookupError::Maintenance(_) => {
+                "Twitch is down for scheduled maintenance. Try again once it's back.".to_string()
+            }
+            LookupError::Request => format!("Couldn't reach Twitch to look up {}. Try again in a moment.", text),
+            LookupError::Decode => format!("Twitch sent back an answer the bot couldn't read looking up {}.", text),
+        }
+    }
+}
+
+/// One Get Users URL for all of `ids` and `logins`, however many; Helix rejects more than
+/// [`MAX_IDS_PER_REQUEST`], so requests go through [`planner::user_batches`].
+pub fn users_url(ids: &[String], logins: &[String]) -> String {
+    let params: Vec<String> = ids
+        .iter()
+        .map(|id| format!("id={}", id))
+        .chain(logins.iter().map(|login| format!("login={}", login)))
+        .collect();
+
+    format!("{}/users?{}", helix_base(), params.join("&"))
+}
+
+/// The async twin of [`client`], with the same timeouts.
+pub fn async_client(timeouts: &TimeoutConfig) -> Result<reqwest::Client, LookupError> {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(timeouts.connect)
+        .timeout(timeouts.request)
+        .build()
+        .map_err(|e| {
+            error!("Could not build Twitch client: {}", e);
+            LookupError::Request
+        })
+}
+
+pub fn client(timeouts: &TimeoutConfig) -> Result<reqwest::blocking::Client, LookupError> {
+    reqwest::blocking::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(timeouts.connect)
+        .timeout(timeouts.request)
+        .build()
+        .map_err(|e| {
+            error!("Could not build Twitch client: {}", e);
+            LookupError::Request
+        })
+}
+
+/// Who a Helix call is made as: the app, or a linked user for endpoints that act on their behalf.
+#[derive(Clone, Copy)]
+pub struct Credentials<'a> {
+    client_id: &'a str,
+    token: &'a str,
+    /// Set for app calls, whose token comes from [`apptoken`] and can be refreshed.
+    app: Option<&'a Secrets>,
+}
+
+impl<'a> Credentials<'a> {
+    pub fn app(secrets: &'a Secrets) -> Credentials<'a> {
+        Credentials {
+            client_id: &secrets.twitch_client_id,
+            token: &secrets.twitch_app_token,
+            app: Some(secrets),
+        }
+    }
+
+    pub fn user(secrets: &'a Secrets, link: &'a AccountLink) -> Credentials<'a> {
+        Credentials {
+            client_id: &secrets.twitch_client_id,
+            token: &link.access_token,
+            app: None,
+        }
+    }
+}
+
+/// GETs a Helix endpoint with the app token and decodes the response body as `T`.
+pub fn helix_get<T: DeserializeOwned>(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    secrets: &Secrets,
+) -> Result<T, LookupError> {
+    helix_call(client, Method::GET, url, Credentials::app(secrets), None)
+}
+
+/// Sends a Helix request, decoding the response as `T`. Empty (204) responses decode as JSON
+/// `null`, so use `()` or `Value` for endpoints that return nothing. An app call that Helix
+/// answers with a 401 is retried once with a refreshed app token, and transient failures are
+/// retried as [`RetryPolicy`] allows.
+pub fn helix_call<T: DeserializeOwned>(
+    client: &reqwest::blocking::Client,
+    method: Method,
+    url: &str,
+    credentials: Credentials,
+    body: Option<&Value>,
+) -> Result<T, LookupError> {
+    if mocktwitch::enabled() {
+        return mocktwitch::respond(&method, url);
+    }
+    let policy = RetryPolicy::from_env();
+    let idempotent = method == Method::GET || method == Method::PUT || method == Method::DELETE;
+    let mut attempt = 1;
+    loop {
+        let result = helix_call_once(client, method.clone(), url, credentials, body);
+        match result.as_ref().err().and_then(|e| policy.backoff(attempt, e, idempotent)) {
+            Some(delay) => {
+                info!("Retrying {} {} in {:?} (attempt {} failed)", method, url, delay, attempt);
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+            None => return result,
+        }
+    }
+}
+
+fn helix_call_once<T: DeserializeOwned>(
+    client: &reqwest::blocking::Client,
+    method: Method,
+    url: &str,
+    credentials: Credentials,
+    body: Option<&Value>,
+) -> Result<T, LookupError> {
+    let result = helix_attempt(client, method.clone(), url, credentials, body);
+    match (credentials.app, &result) {
+        (Some(secrets), Err(LookupError::Unauthorized(401))) => match apptoken::refresh(client, secrets) {
+            Ok(_) => helix_attempt(client, method, url, credentials, body),
+            Err(e) => {
+                error!("Could not refresh the Twitch app token after a 401: {}", e);
+                result
+            }
+        },
+        _ => result,
+    }
+}
+
+fn helix_attempt<T: DeserializeOwned>(
+    client: &reqwest::blocking::Client,
+    method: Method,
+    url: &str,
+    credentials: Credentials,
+    body: Option<&Value>,
+) -> Result<T, LookupError> {
+    let is_app = credentials.app.is_some();
+    refuse_early(is_app)?;
+
+    let token = match credentials.app {
+        Some(secrets) => apptoken::token(client, secrets),
+        None => credentials.token.to_string(),
+    };
+    let mut request = client
+        .request(method, url)
+        .header("Client-ID", credentials.client_id)
+        .header("Authorization", format!("Bearer {}", token));
+    if let Some(body) = body {
+        request = request.json(body);
+    }
+
+    match request.send() {
+        Ok(data) => {
+            if is_app {
+                ratelimit::record(data.headers());
+            }
+            let status = data.status();
+            if !status.is_success() {
+                let headers = data.headers().clone();
+                let body = data.text().unwrap_or_default();
+                return Err(failure(status, &headers, &body, is_app));
+            }
+
+            maintenance::end();
+
+            if status == StatusCode::NO_CONTENT {
+                return decode_empty();
+            }
+
+            data.json::<T>().map_err(|e| {
+                error!("Could not decode Twitch response: {}", e);
+                LookupError::Decode
+            })
+        }
+        Err(e) => Err(request_failure(&e)),
+    }
+}
+
+/// App GETs with the async client, for lookups that fan out into concurrent requests. Behaves
+/// like [`helix_get`], including the retry with a refreshed token after a 401 and the
+/// [`RetryPolicy`].
+pub async fn helix_get_async<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+    secrets: &Secrets,
+) -> Result<T, LookupError> {
+    helix_get_async_as(client, url, Credentials::app(secrets)).await
+}
+
+/// [`helix_get_async`] as `credentials`; only an app call is retried with a refreshed token.
+pub async fn helix_get_async_as<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+    credentials: Credentials<'_>,
+) -> Result<T, LookupError> {
+    if mocktwitch::enabled() {
+        return mocktwitch::respond(&Method::GET, url);
+    }
+    let policy = RetryPolicy::from_env();
+    let mut attempt = 1;
+    loop {
+        let result = helix_get_once(client, url, credentials).await;
+        match result.as_ref().err().and_then(|e| policy.backoff(attempt, e, true)) {
+            Some(delay) => {
+                info!("Retrying GET {} in {:?} (attempt {} failed)", url, delay, attempt);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            None => return result,
+        }
+    }
+}
+
+async fn helix_get_once<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+    credentials: Credentials<'_>,
+) -> Result<T, LookupError> {
+    let result = helix_get_attempt(client, url, credentials).await;
+    if let (Some(secrets), Err(LookupError::Unauthorized(401))) = (credentials.app, &result) {
+        match apptoken::refresh_async(client, secrets).await {
+            Ok(_) => return helix_get_attempt(client, url, credentials).await,
+            Err(e) => error!("Could not refresh the Twitch app token after a 401: {}", e),
+        }
+    }
+    result
+}
+
+async fn helix_get_attempt<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+    credentials: Credentials<'_>,
+) -> Result<T, LookupError> {
+    let is_app = credentials.app.is_some();
+    refuse_early(is_app)?;
+
+    let token = match credentials.app {
+        Some(secrets) => apptoken::token_async(client, secrets).await,
+        None => credentials.token.to_string(),
+    };
+    let sent = client
+        .get(url)
+        .header("Client-ID", credentials.client_id)
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await;
+
+    match sent {
+        Ok(data) => {
+            if is_app {
+                ratelimit::record(data.headers());
+            }
+            let status = data.status();
+            if !status.is_success() {
+                let headers = data.headers().clone();
+                let body = data.text().await.unwrap_or_default();
+                return Err(failure(status, &headers, &body, is_app));
+            }
+
+            maintenance::end();
+
+            if status == StatusCode::NO_CONTENT {
+                return decode_empty();
+            }
+
+            data.json::<T>().await.map_err(|e| {
+                error!("Could not decode Twitch response: {}", e);
+                LookupError::Decode
+            })
+        }
+        Err(e) => Err(request_failure(&e)),
+    }
+}
+
+/// Fails a request before it's sent when the app's rate-limit bucket is empty or Twitch is known
+/// to be under maintenance. The shared bucket only tracks the app token; user tokens have buckets
+/// of their own.
+fn refuse_early(is_app: bool) -> Result<(), LookupError> {
+    if is_app {
+        if let Some(reset) = ratelimit::exhausted_until() {
+            return Err(LookupError::RateLimited(reset));
+        }
+    }
+    if let Some(until) = maintenance::refuse() {
+        return Err(LookupError::Maintenance(until));
+    }
+    Ok(())
+}
+
+/// The error for an unsuccessful Helix response, noting maintenance and rate limiting on the way.
+fn failure(status: StatusCode, headers: &HeaderMap, body: &str, is_app: bool) -> LookupError {
+    error!("Twitch response error ({}): {} {:?}: {}", USER_AGENT, status, headers, body);
+    if let Some(until) = maintenance::detect(status.as_u16(), headers, body) {
+        maintenance::begin(until);
+        return LookupError::Maintenance(until);
+    }
+    match status.as_u16() {
+        429 if is_app => {
+            let reset = ratelimit::exhausted_until().unwrap_or_else(|| ratelimit::now_secs() + 60);
+            ratelimit::exhaust(reset);
+            LookupError::RateLimited(reset)
+        }
+        // User tokens have buckets of their own, which aren't tracked, but say when they reset.
+        429 => LookupError::RateLimited(
+            headers
+                .get("ratelimit-reset")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_else(|| ratelimit::now_secs() + 60),
+        ),
+        401 | 403 => LookupError::Unauthorized(status.as_u16()),
+        code if status.is_server_error() => LookupError::Outage(code),
+        code => LookupError::Rejected(code),
+    }
+}
+
+fn request_failure(e: &reqwest::Error) -> LookupError {
+    if e.is_timeout() {
+        error!("Request to Twitch timed out ({}): {}", USER_AGENT, e);
+        LookupError::Timeout
+    } else {
+        error!("Request to Twitch failed ({}): {}", USER_AGENT, e);
+        LookupError::Request
+    }
+}
+
+/// Empty (204) responses decode as JSON `null`.
+fn decode_empty<T: DeserializeOwned>() -> Result<T, LookupError> {
+    serde_json::from_str("null").map_err(|e| {
+        error!("Endpoint returned no content but {} was expected: {}", std::any::type_name::<T>(), e);
+        LookupError::Decode
+    })
+}
+
+pub fn get_users(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    secrets: &Secrets,
+) -> Result<Vec<TwitchUser>, LookupError> {
+    drift::decode(helix_get::<HelixList<Value>>(client, url, secrets)?.data)
+}
+
+/// Live streams among `user_ids`; offline users are simply absent from the result.
+pub fn get_streams(
+    client: &reqwest::blocking::Client,
+    user_ids: &[String],
+    secrets: &Secrets,
+) -> Result<Vec<TwitchStream>, LookupError> {
+    let mut streams = vec![];
+    for chunk in user_ids.chunks(MAX_IDS_PER_REQUEST) {
+        let params: Vec<String> = chunk.iter().map(|id| format!("user_id={}", id)).collect();
+        let url = format!("{}/streams?first={}&{}", helix_base(), MAX_IDS_PER_REQUEST, params.join("&"));
+        streams.extend(drift::decode::<TwitchStream>(helix_get::<HelixList<Value>>(client, &url, secrets)?.data)?);
+    }
+    Ok(streams)
+}
+
+/// Looks up users by ID and login with the async client. Helix takes up to
+/// `MAX_IDS_PER_REQUEST` of them per request, so larger queries are split and the chunks fetched
+/// concurrently; results keep the chunks' order, and any failed chunk fails the lookup.
+pub async fn get_users_async(
+    client: &reqwest::Client,
+    ids: &[String],
+    logins: &[String],
+    secrets: &Secrets,
+) -> Result<Vec<TwitchUser>, LookupError> {
+    let urls = planner::user_batches(ids, logins);
+    let chunks = join_all(urls.iter().map(|url| async move {
+        drift::decode::<TwitchUser>(helix_get_async::<HelixList<Value>>(client, url, secrets).await?.data)
+    }))
+    .await;
+    chunks.into_iter().collect::<Result<Vec<_>, LookupError>>().map(|chunks| chunks.concat())
+}
+
+/// [`get_streams`] with the async client, fetching the chunks concurrently.
+pub async fn get_streams_async(
+    client: &reqwest::Client,
+    user_ids: &[String],
+    secrets: &Secrets,
+) -> Result<Vec<TwitchStream>, LookupError> {
+    let chunks = join_all(user_ids.chunks(MAX_IDS_PER_REQUEST).map(|chunk| async move {
+        let params: Vec<String> = chunk.iter().map(|id| format!("user_id={}", id)).collect();
+        let url = format!("{}/streams?first={}&{}", helix_base(), MAX_IDS_PER_REQUEST, params.join("&"));
+        drift::decode::<TwitchStream>(helix_get_async::<HelixList<Value>>(client, &url, secrets).await?.data)
+    }))
+    .await;
+    chunks.into_iter().collect::<Result<Vec<_>, LookupError>>().map(|chunks| chunks.concat())
+}
+
+#[derive(Deserialize, Debug)]
+struct FollowerTotal {
+    total: u64,
+}
+
+/// How many followers the broadcaster has. Helix only answers this with a user token; the
+/// broadcaster's own (see [`links::fresh_channel_link`]) is the one the bot uses.
+pub fn get_follower_count(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+) -> Result<u64, LookupError> {
+    let url = format!("{}/channels/followers?broadcaster_id={}&first=1", helix_base(), broadcaster_id);
+    Ok(helix_call::<FollowerTotal>(client, Method::GET, &url, credentials, None)?.total)
+}
+
+/// How many follower counts are fetched at once.
+const FOLLOWER_FAN_OUT: usize = 10;
+
+/// Follower counts by broadcaster ID for the broadcasters in `links`, each fetched with their
+/// own link, at most [`FOLLOWER_FAN_OUT`] at a time. Any failed request fails the lookup.
+pub async fn get_follower_counts_async(
+    client: &reqwest::Client,
+    links: &HashMap<String, AccountLink>,
+    secrets: &Secrets,
+) -> Result<HashMap<String, u64>, LookupError> {
+    let counts = stream::iter(links.iter().map(|(id, link)| async move {
+        let url = format!("{}/channels/followers?broadcaster_id={}&first=1", helix_base(), id);
+        let credentials = Credentials::user(secrets, link);
+        Ok((id.clone(), helix_get_async_as::<FollowerTotal>(client, &url, credentials).await?.total))
+    }))
+    .buffer_unordered(FOLLOWER_FAN_OUT)
+    .collect::<Vec<_>>()
+    .await;
+    counts.into_iter().collect()
+}
+
+/// The lookups behind `/tuser`, so its logic can run against Helix or a stand-in such as
+/// [`mocktwitch::MockTwitch`]. Each call gets its own `timeouts`; stand-ins ignore them.
+pub trait TwitchApi: Send + Sync {
+    fn users<'a>(
+        &'a self,
+        ids: &'a [String],
+        logins: &'a [String],
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<TwitchUser>, LookupError>>;
+
+    /// Live streams among `user_ids`; offline users are simply absent.
+    fn streams<'a>(
+        &'a self,
+        user_ids: &'a [String],
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<TwitchStream>, LookupError>>;
+
+    /// Follower counts by user ID, for the `users` whose counts can be read; the others are left
+    /// out.
+    fn follower_counts<'a>(
+        &'a self,
+        users: &'a [TwitchUser],
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<HashMap<String, u64>, LookupError>>;
+
+    /// Up to `count` channels loosely matching `query`, best match first.
+    fn search_channels<'a>(
+        &'a self,
+        query: &'a str,
+        count: usize,
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<ChannelMatch>, LookupError>>;
+}
+
+/// [`TwitchApi`] on Helix, with the app token in `secrets`. Follower counts need the
+/// broadcaster's own link, so they're only fetched [`for_team`](Helix::for_team), for the
+/// channels linked in that workspace.
+pub struct Helix {
+    secrets: Secrets,
+    team_id: Option<String>,
+}
+
+impl Helix {
+    pub fn new(secrets: Secrets) -> Helix {
+        Helix { secrets, team_id: None }
+    }
+
+    pub fn for_team(self, team_id: &str) -> Helix {
+        Helix {
+            team_id: Some(team_id.to_string()),
+            ..self
+        }
+    }
+}
+
+/// The links of the `users` who linked their channel in `team_id`, by user ID. A link that
+/// can't be loaded is logged and left out.
+async fn broadcaster_links(team_id: &str, users: &[TwitchUser], secrets: &Secrets) -> HashMap<String, AccountLink> {
+    let store = LinkStore::from_env();
+    let store = &store;
+    stream::iter(users.iter().map(|user| async move {
+        match links::fresh_channel_link(store, secrets, team_id, &user.login).await {
+            Ok(link) => link.filter(|link| link.twitch_user_id == user.id).map(|link| (user.id.clone(), link)),
+            Err(e) => {
+                error!("Could not load {}'s link for their follower count: {}", user.login, e);
+                None
+            }
+        }
+    }))
+    .buffer_unordered(FOLLOWER_FAN_OUT)
+    .filter_map(|link| async move { link })
+    .collect()
+    .await
+}
+
+impl TwitchApi for Helix {
+    fn users<'a>(
+        &'a self,
+        ids: &'a [String],
+        logins: &'a [String],
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<TwitchUser>, LookupError>> {
+        Box::pin(async move { get_users_async(&async_client(&timeouts)?, ids, logins, &self.secrets).await })
+    }
+
+    fn streams<'a>(
+        &'a self,
+        user_ids: &'a [String],
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<TwitchStream>, LookupError>> {
+        Box::pin(async move { get_streams_async(&async_client(&timeouts)?, user_ids, &self.secrets).await })
+    }
+
+    fn follower_counts<'a>(
+        &'a self,
+        users: &'a [TwitchUser],
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<HashMap<String, u64>, LookupError>> {
+        Box::pin(async move {
+            let links = match &self.team_id {
+                Some(team_id) => broadcaster_links(team_id, users, &self.secrets).await,
+                None => HashMap::new(),
+            };
+            if links.is_empty() {
+                return Ok(HashMap::new());
+            }
+            get_follower_counts_async(&async_client(&timeouts)?, &links, &self.secrets).await
+        })
+    }
+
+    fn search_channels<'a>(
+        &'a self,
+        query: &'a str,
+        count: usize,
+        timeouts: TimeoutConfig,
+    ) -> BoxFuture<'a, Result<Vec<ChannelMatch>, LookupError>> {
+        Box::pin(async move { search_channels_async(&async_client(&timeouts)?, query, count, &self.secrets).await })
+    }
+}
+
+/// At most this many panel links are shown for a channel.
+pub const MAX_PANEL_LINKS: usize = 10;
+
+const CHANNEL_EXTRAS_QUERY: &str = "query($login: String!) { user(login: $login) { \
+    channel { trailer { video { id } } } panels { ... on DefaultPanel { title linkURL description } } } }";
+
+/// What Helix doesn't say about a channel, from Twitch's own GraphQL API.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChannelExtras {
+    /// The trailer VOD's URL.
+    pub trailer: Option<String>,
+    /// The channel's panels that point somewhere, in the order they're shown, up to
+    /// [`MAX_PANEL_LINKS`].
+    pub panels: Vec<PanelLink>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PanelLink {
+    pub title: String,
+    pub url: String,
+}
+
+/// The channel's trailer and panel links. The GraphQL API isn't a documented one, so this is
+/// `None` when no `TWITCH_GQL_CLIENT_ID` is configured (see `config`) or the user isn't found.
+pub fn get_channel_extras(
+    client: &reqwest::blocking::Client,
+    login: &str,
+) -> Result<Option<ChannelExtras>, LookupError> {
+    let config = config::get();
+    let client_id = match &config.twitch_gql_client_id {
+        Some(client_id) => client_id,
+        None => return Ok(None),
+    };
+    let body = serde_json::json!({ "query": CHANNEL_EXTRAS_QUERY, "variables": { "login": login } });
+    let resp = client
+        .post(&config.twitch_gql_base)
+        .header("Client-Id", client_id)
+        .json(&body)
+        .send()
+        .map_err(|e| request_failure(&e))?;
+    if !resp.status().is_success() {
+        return Err(LookupError::Outage(resp.status().as_u16()));
+    }
+    let body: Value = resp.json().map_err(|e| {
+        error!("Could not decode Twitch GraphQL response: {}", e);
+        LookupError::Decode
+    })?;
+
+    let user = match body.pointer("/data/user") {
+        Some(user) if user.is_object() => user,
+        _ => return Ok(None),
+    };
+    let trailer = user
+        .pointer("/channel/trailer/video/id")
+        .and_then(Value::as_str)
+        .map(|id| format!("https://www.twitch.tv/videos/{}", id));
+    let panels = user
+        .get("panels")
+        .and_then(Value::as_array)
+        .map(|panels| panels.iter().filter_map(panel_link).take(MAX_PANEL_LINKS).collect())
+        .unwrap_or_default();
+    Ok(Some(ChannelExtras { trailer, panels }))
+}
+
+/// Where a panel points: its image link, or else the first web link in its description. Titleless
+/// panels are named after the link's host.
+fn panel_link(panel: &Value) -> Option<PanelLink> {
+    let field = |name: &str| panel.get(name).and_then(Value::as_str).map(str::trim).filter(|value| !value.is_empty());
+    let url = field("linkURL")
+        .filter(|url| is_web_link(url))
+        .map(str::to_string)
+        .or_else(|| field("description").and_then(first_web_link))?;
+    let title = match field("title") {
+        Some(title) => title.to_string(),
+        None => url.split('/').nth(2).unwrap_or(&url).to_string(),
+    };
+    Some(PanelLink { title, url })
+}
+
+fn is_web_link(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+/// The first `http(s)://` link in panel markdown, bare or as `[text](url)`.
+fn first_web_link(text: &str) -> Option<String> {
+    let start = text.find("https://").into_iter().chain(text.find("http://")).min()?;
+    let link: String = text[start..]
+        .chars()
+        .take_while(|c| !c.is_whitespace() && !matches!(c, ')' | ']' | '<' | '>' | '|' | '"'))
+        .collect();
+    Some(link.trim_end_matches(|c| matches!(c, '.' | ',' | '!' | '?')).to_string())
+}
+
+/// Twitch's ingest servers. The endpoint is public and not part of Helix, so no credentials.
+pub fn get_ingests(client: &reqwest::blocking::Client) -> Result<Vec<Ingest>, LookupError> {
+    let resp = client.get(INGEST_URL).send().map_err(|e| {
+        error!("Request to Twitch ingests failed: {}", e);
+        if e.is_timeout() {
+            LookupError::Timeout
+        } else {
+            LookupError::Request
+        }
+    })?;
+    if !resp.status().is_success() {
+        return Err(LookupError::Outage(resp.status().as_u16()));
+    }
+
+    resp.json::<IngestResponse>().map(|r| r.ingests).map_err(|e| {
+        error!("Could not decode Twitch ingests: {}", e);
+        LookupError::Decode
+    })
+}
+
+pub fn get_custom_rewards(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+) -> Result<Vec<CustomReward>, LookupError> {
+    let url = format!("{}/channel_points/custom_rewards?broadcaster_id={}", helix_base(), broadcaster_id);
+    Ok(helix_call::<HelixList<CustomReward>>(client, Method::GET, &url, credentials, None)?.data)
+}
+
+/// Twitch only lets the client that created a reward update it; others get a 403.
+pub fn set_reward_paused(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+    reward_id: &str,
+    paused: bool,
+) -> Result<CustomReward, LookupError> {
+    let url = format!(
+        "{}/channel_points/custom_rewards?broadcaster_id={}&id={}",
+        helix_base(), broadcaster_id, reward_id
+    );
+    let body = serde_json::json!({ "is_paused": paused });
+    let mut updated = helix_call::<HelixList<CustomReward>>(client, Method::PATCH, &url, credentials, Some(&body))?;
+    updated.data.pop().ok_or(LookupError::Decode)
+}
+
+/// Follows `pagination.cursor` from `first_url` (which must already have a query string) until
+/// Helix runs out of pages or `limit` items have been read.
+pub fn get_all_pages<T: DeserializeOwned>(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    first_url: &str,
+    limit: usize,
+) -> Result<Vec<T>, LookupError> {
+    let mut items = vec![];
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let url = match &cursor {
+            Some(cursor) => format!("{}&after={}", first_url, cursor),
+            None => first_url.to_string(),
+        };
+
+        let page = helix_call::<HelixPage<T>>(client, Method::GET, &url, credentials, None)?;
+        items.extend(page.data);
+        cursor = page.pagination.cursor.filter(|c| !c.is_empty());
+        if cursor.is_none() || items.len() >= limit {
+            break;
+        }
+    }
+
+    items.truncate(limit);
+    Ok(items)
+}
+
+/// Unfulfilled redemptions of one reward, oldest first, stopping after `limit`.
+pub fn get_unfulfilled_redemptions(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+    reward_id: &str,
+    limit: usize,
+) -> Result<Vec<Redemption>, LookupError> {
+    let url = format!(
+        "{}/channel_points/custom_rewards/redemptions?broadcaster_id={}&reward_id={}\
+         &status=UNFULFILLED&sort=OLDEST&first=50",
+        helix_base(), broadcaster_id, reward_id
+    );
+    get_all_pages(client, credentials, &url, limit)
+}
+
+/// Channels `user_id` follows, most recent first, stopping after `limit`. Needs the user's own token.
+pub fn get_followed_channels(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    user_id: &str,
+    limit: usize,
+) -> Result<Vec<FollowedChannel>, LookupError> {
+    let url = format!("{}/channels/followed?user_id={}&first=100", helix_base(), user_id);
+    get_all_pages(client, credentials, &url, limit)
+}
+
+/// Live streams of channels `user_id` follows, most viewers first, stopping after `limit`. Needs
+/// the user's own token.
+pub fn get_followed_streams(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    user_id: &str,
+    limit: usize,
+) -> Result<Vec<TwitchStream>, LookupError> {
+    let url = format!("{}/streams/followed?user_id={}&first=100", helix_base(), user_id);
+    get_all_pages(client, credentials, &url, limit)
+}
+
+/// Everyone `broadcaster_id` has blocked, stopping after `limit`. Needs the user's own token.
+pub fn get_blocked_users(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+    limit: usize,
+) -> Result<Vec<BlockedUser>, LookupError> {
+    let url = format!("{}/users/blocks?broadcaster_id={}&first=100", helix_base(), broadcaster_id);
+    get_all_pages(client, credentials, &url, limit)
+}
+
+/// Blocks or unblocks `target_user_id` for the user whose token `credentials` holds.
+pub fn set_blocked(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    target_user_id: &str,
+    blocked: bool,
+) -> Result<(), LookupError> {
+    let url = format!("{}/users/blocks?target_user_id={}", helix_base(), target_user_id);
+    let method = if blocked { Method::PUT } else { Method::DELETE };
+    helix_call::<Value>(client, method, &url, credentials, None)?;
+    Ok(())
+}
+
+/// Bits transactions for an extension owned by the bot's Twitch app, newest first.
+pub fn get_extension_transactions(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    extension_id: &str,
+    limit: usize,
+) -> Result<Vec<ExtensionTransaction>, LookupError> {
+    let params = serde_urlencoded::to_string(&[("extension_id", extension_id)]).map_err(|_| LookupError::Request)?;
+    let url = format!("{}/extensions/transactions?{}&first=100", helix_base(), params);
+    get_all_pages(client, Credentials::app(secrets), &url, limit)
+}
+
+/// Drops entitlements granted for one of the organization's games.
+pub fn get_drop_entitlements(
+    client: &reqwest::blocking::Client,
+    secrets: &Secrets,
+    game_id: &str,
+    limit: usize,
+) -> Result<Vec<DropEntitlement>, LookupError> {
+    let params = serde_urlencoded::to_string(&[("game_id", game_id)]).map_err(|_| LookupError::Request)?;
+    let url = format!("{}/entitlements/drops?{}&first=1000", helix_base(), params);
+    get_all_pages(client, Credentials::app(secrets), &url, limit)
+}
+
+#[derive(Deserialize)]
+struct StreamKey {
+    stream_key: String,
+}
+
+/// The broadcaster's stream key. Only their own token can read it.
+pub fn get_stream_key(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+) -> Result<String, LookupError> {
+    let url = format!("{}/streams/key?broadcaster_id={}", helix_base(), broadcaster_id);
+    let mut keys = helix_call::<HelixList<StreamKey>>(client, Method::GET, &url, credentials, None)?;
+    keys.data.pop().map(|key| key.stream_key).ok_or(LookupError::Decode)
+}
+
+pub fn start_raid(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    from_broadcaster_id: &str,
+    to_broadcaster_id: &str,
+) -> Result<(), LookupError> {
+    let url = format!(
+        "{}/raids?from_broadcaster_id={}&to_broadcaster_id={}",
+        helix_base(), from_broadcaster_id, to_broadcaster_id
+    );
+    helix_call::<Value>(client, Method::POST, &url, credentials, None)?;
+    Ok(())
+}
+
+pub fn cancel_raid(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+) -> Result<(), LookupError> {
+    let url = format!("{}/raids?broadcaster_id={}", helix_base(), broadcaster_id);
+    helix_call::<Value>(client, Method::DELETE, &url, credentials, None)?;
+    Ok(())
+}
+
+/// The channel's AutoMod levels, read as `moderator_id` (the broadcaster counts as a moderator).
+pub fn get_automod_settings(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+    moderator_id: &str,
+) -> Result<AutomodSettings, LookupError> {
+    let url = format!(
+        "{}/moderation/automod/settings?broadcaster_id={}&moderator_id={}",
+        helix_base(), broadcaster_id, moderator_id
+    );
+    let mut settings = helix_call::<HelixList<AutomodSettings>>(client, Method::GET, &url, credentials, None)?;
+    settings.data.pop().ok_or(LookupError::Decode)
+}
+
+/// Whether AutoMod would let `message` through in the channel. Needs the broadcaster's token.
+pub fn check_automod(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+    message: &str,
+) -> Result<bool, LookupError> {
+    let url = format!("{}/moderation/enforcements/status?broadcaster_id={}", helix_base(), broadcaster_id);
+    let body = serde_json::json!({ "data": [{ "msg_id": "slack-check", "msg_text": message }] });
+    let mut status = helix_call::<HelixList<AutomodStatus>>(client, Method::POST, &url, credentials, Some(&body))?;
+    status.data.pop().map(|s| s.is_permitted).ok_or(LookupError::Decode)
+}
+
+/// Bans `user_id` from the broadcaster's chat, or times them out for `duration` seconds.
+pub fn ban_user(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+    moderator_id: &str,
+    user_id: &str,
+    duration: Option<u32>,
+    reason: &str,
+) -> Result<(), LookupError> {
+    let url = format!(
+        "{}/moderation/bans?broadcaster_id={}&moderator_id={}",
+        helix_base(), broadcaster_id, moderator_id
+    );
+    let mut data = serde_json::json!({ "user_id": user_id, "reason": reason });
+    if let Some(duration) = duration {
+        data["duration"] = duration.into();
+    }
+    helix_call::<Value>(client, Method::POST, &url, credentials, Some(&serde_json::json!({ "data": data })))?;
+    Ok(())
+}
+
+/// Approves or denies a pending unban request as `moderator_id`.
+pub fn resolve_unban_request(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+    moderator_id: &str,
+    request_id: &str,
+    approve: bool,
+) -> Result<(), LookupError> {
+    let url = format!(
+        "{}/moderation/unban_requests?broadcaster_id={}&moderator_id={}&unban_request_id={}&status={}",
+        helix_base(),
+        broadcaster_id,
+        moderator_id,
+        request_id,
+        if approve { "approved" } else { "denied" }
+    );
+    helix_call::<Value>(client, Method::PATCH, &url, credentials, None)?;
+    Ok(())
+}
+
+/// Issues an official warning; the user can't chat again until they acknowledge it.
+pub fn warn_user(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+    moderator_id: &str,
+    user_id: &str,
+    reason: &str,
+) -> Result<(), LookupError> {
+    let url = format!(
+        "{}/moderation/warnings?broadcaster_id={}&moderator_id={}",
+        helix_base(), broadcaster_id, moderator_id
+    );
+    let body = serde_json::json!({ "data": { "user_id": user_id, "reason": reason } });
+    helix_call::<Value>(client, Method::POST, &url, credentials, Some(&body))?;
+    Ok(())
+}
+
+/// Whether `user_id` moderates `broadcaster_id`'s channel. Needs the broadcaster's token with
+/// `moderation:read`.
+pub fn is_moderator(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+    user_id: &str,
+) -> Result<bool, LookupError> {
+    let url = format!("{}/moderation/moderators?broadcaster_id={}&user_id={}", helix_base(), broadcaster_id, user_id);
+    let moderators = helix_call::<HelixList<Value>>(client, Method::GET, &url, credentials, None)?;
+    Ok(!moderators.data.is_empty())
+}
+
+pub fn get_clips(
+    client: &reqwest::blocking::Client,
+    broadcaster_id: &str,
+    count: usize,
+    secrets: &Secrets,
+) -> Result<Vec<TwitchClip>, LookupError> {
+    let url = format!("{}/clips?broadcaster_id={}&first={}", helix_base(), broadcaster_id, planner::page_size(count));
+    get_all_pages(client, Credentials::app(secrets), &url, count)
+}
+
+/// The broadcaster's most viewed clips created since `since`, or of all time without it.
+pub fn get_top_clips(
+    client: &reqwest::blocking::Client,
+    broadcaster_id: &str,
+    count: usize,
+    since: Option<DateTime<Utc>>,
+    secrets: &Secrets,
+) -> Result<Vec<TwitchClip>, LookupError> {
+    let first = planner::page_size(count);
+    let mut url = format!("{}/clips?broadcaster_id={}&first={}", helix_base(), broadcaster_id, first);
+    if let Some(since) = since {
+        let range = [
+            ("started_at", since.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            ("ended_at", Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)),
+        ];
+        url.push('&');
+        url.push_str(&serde_urlencoded::to_string(&range).map_err(|_| LookupError::Request)?);
+    }
+    get_all_pages(client, Credentials::app(secrets), &url, count)
+}
+
+/// The clip with this slug (the ID at the end of its URL).
+pub fn get_clip(
+    client: &reqwest::blocking::Client,
+    slug: &str,
+    secrets: &Secrets,
+) -> Result<Option<TwitchClip>, LookupError> {
+    let params = serde_urlencoded::to_string(&[("id", slug)]).map_err(|_| LookupError::Request)?;
+    let url = format!("{}/clips?{}", helix_base(), params);
+    Ok(helix_get::<HelixList<TwitchClip>>(client, &url, secrets)?.data.pop())
+}
+
+/// Who's in `broadcaster_id`'s chat, stopping after `limit`. Needs the token of the broadcaster
+/// or one of their moderators (`moderator_id`), with `moderator:read:chatters`.
+pub fn get_chatters(
+    client: &reqwest::blocking::Client,
+    credentials: Credentials,
+    broadcaster_id: &str,
+    moderator_id: &str,
+    limit: usize,
+) -> Result<Vec<Chatter>, LookupError> {
+    let url = format!(
+        "{}/chat/chatters?broadcaster_id={}&moderator_id={}&first=1000",
+        helix_base(), broadcaster_id, moderator_id
+    );
+    get_all_pages(client, credentials, &url, limit)
+}
+
+pub fn get_videos(
+    client: &reqwest::blocking::Client,
+    user_id: &str,
+    count: usize,
+    secrets: &Secrets,
+) -> Result<Vec<TwitchVideo>, LookupError> {
+    let url = format!("{}/videos?user_id={}&first={}", helix_base(), user_id, planner::page_size(count));
+    get_all_pages(client, Credentials::app(secrets), &url, count)
+}
+
+/// The channel's most recent past broadcast (archive VOD), where its VODs are kept.
+pub fn get_last_broadcast(
+    client: &reqwest::blocking::Client,
+    user_id: &str,
+    secrets: &Secrets,
+) -> Result<Option<TwitchVideo>, LookupError> {
+    let url = format!("{}/videos?user_id={}&type=archive&first=1", helix_base(), user_id);
+    Ok(helix_get::<HelixList<TwitchVideo>>(client, &url, secrets)?.data.pop())
+}
+
+/// Twitch answers 404 for channels that never set up a schedule, which is reported as `None`.
+pub fn get_schedule(
+    client: &reqwest::blocking::Client,
+    broadcaster_id: &str,
+    count: usize,
+    secrets: &Secrets,
+) -> Result<Option<TwitchSchedule>, LookupError> {
+    let url = format!("{}/schedule?broadcaster_id={}&first={}", helix_base(), broadcaster_id, count);
+    match helix_get::<TwitchScheduleResponse>(client, &url, secrets) {
+        Ok(resp) => Ok(Some(resp.data)),
+        Err(LookupError::Rejected(404)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn get_games(
+    client: &reqwest::blocking::Client,
+    query: &GameQuery,
+    secrets: &Secrets,
+) -> Result<Vec<TwitchGame>, LookupError> {
+    let (key, value) = match query {
+        GameQuery::Id(id) => ("id", id),
+        GameQuery::Name(name) => ("name", name),
+        GameQuery::IgdbId(id) => ("igdb_id", id),
+    };
+    let params = serde_urlencoded::to_string(&[(key, value)]).map_err(|_| LookupError::Request)?;
+    let url = format!("{}/games?{}", helix_base(), params);
+    Ok(helix_get::<HelixList<TwitchGame>>(client, &url, secrets)?.data)
+}
+
+/// Categories whose names match `query` loosely, best match first. They carry no IGDB ID.
+pub fn search_categories(
+    client: &reqwest::blocking::Client,
+    query: &str,
+    count: usize,
+    secrets: &Secrets,
+) -> Result<Vec<TwitchGame>, LookupError> {
+    let params = serde_urlencoded::to_string(&[("query", query)]).map_err(|_| LookupError::Request)?;
+    let url = format!("{}/search/categories?{}&first={}", helix_base(), params, count);
+    let mut games = helix_get::<HelixList<TwitchGame>>(client, &url, secrets)?.data;
+    // Search answers with 52x72 box art rather than the template Get Games gives.
+    for game in &mut games {
+        game.box_art_url = game.box_art_url.replace("-52x72.", "-{width}x{height}.");
+    }
+    Ok(games)
+}
+
+/// Channels whose login or name match `query` loosely, best match first.
+pub async fn search_channels_async(
+    client: &reqwest::Client,
+    query: &str,
+    count: usize,
+    secrets: &Secrets,
+) -> Result<Vec<ChannelMatch>, LookupError> {
+    let params = serde_urlencoded::to_string(&[("query", query)]).map_err(|_| LookupError::Request)?;
+    let url = format!("{}/search/channels?{}&first={}", helix_base(), params, count);
+    drift::decode(helix_get_async::<HelixList<Value>>(client, &url, secrets).await?.data)
+}
+
+/// The category's most watched live streams.
+pub fn get_top_streams(
+    client: &reqwest::blocking::Client,
+    game_id: &str,
+    count: usize,
+    secrets: &Secrets,
+) -> Result<Vec<TwitchStream>, LookupError> {
+    let url = format!("{}/streams?game_id={}&first={}", helix_base(), game_id, planner::page_size(count));
+    drift::decode(get_all_pages(client, Credentials::app(secrets), &url, count)?)
+}
+
+/// Looks up any number of logins, `MAX_IDS_PER_REQUEST` at a time.
+pub fn get_users_by_login(
+    client: &reqwest::blocking::Client,
+    logins: &[String],
+    secrets: &Secrets,
+) -> Result<Vec<TwitchUser>, LookupError> {
+    let mut users = Vec::with_capacity(logins.len());
+    for url in planner::user_batches(&[], logins) {
+        users.extend(get_users(client, &url, secrets)?);
+    }
+    Ok(users)
+}
@@ -0,0 +1,145 @@
+//! Per-workspace monthly usage counters (command invocations, lookup cache hits and misses) and
+//! the last Twitch rate-limit bucket any container saw, for `/tquota`.
+//!
+//! The limiter's bucket lives in each container's memory, so whoever records usage also copies
+//! the bucket it last saw into the table; `/tquota` reads it back from there.
+
+use crate::ratelimit::{self, Bucket};
+use crate::stage::Stage;
+use crate::store::{self, Attr, Attrs, Store};
+use crate::Error;
+use chrono::{DateTime, Utc};
+
+const DEFAULT_TABLE: &str = "tuser-usage";
+/// Monthly command invocations a workspace gets when `MONTHLY_INVOCATION_QUOTA` isn't set.
+pub const DEFAULT_MONTHLY_QUOTA: u64 = 10_000;
+/// Keeps counters around for a year so last month's numbers can still be compared.
+const RETENTION_SECS: i64 = 366 * 24 * 60 * 60;
+const BUCKET_KEY: &str = "ratelimit";
+
+pub fn monthly_quota() -> u64 {
+    std::env::var("MONTHLY_INVOCATION_QUOTA")
+        .ok()
+        .and_then(|quota| quota.parse().ok())
+        .unwrap_or(DEFAULT_MONTHLY_QUOTA)
+}
+
+/// `2021-03`, the month counters are kept under.
+pub fn month_of(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m").to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub invocations: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+}
+
+impl Usage {
+    /// The share of lookups answered from the cache, or `None` before any lookups.
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let lookups = self.cache_hits + self.cache_misses;
+        if lookups == 0 {
+            return None;
+        }
+        Some(self.cache_hits as f64 / lookups as f64)
+    }
+}
+
+/// A rate-limit bucket and when it was reported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketSnapshot {
+    pub bucket: Bucket,
+    /// Unix seconds.
+    pub recorded_at: i64,
+}
+
+pub struct UsageStore {
+    store: Box<dyn Store>,
+}
+
+impl UsageStore {
+    pub fn new(store: Box<dyn Store>) -> UsageStore {
+        UsageStore { store }
+    }
+
+    pub fn from_env() -> UsageStore {
+        let table = std::env::var("USAGE_TABLE").unwrap_or_else(|_| Stage::current().resource(DEFAULT_TABLE));
+        UsageStore::new(store::open(table, "usage_key"))
+    }
+
+    /// Counts a command invocation for the workspace this month, and saves the container's
+    /// rate-limit bucket.
+    pub async fn record_invocation(&self, team_id: &str) -> Result<(), Error> {
+        self.add(team_id, "invocations").await?;
+        self.save_bucket().await
+    }
+
+    pub async fn record_cache(&self, team_id: &str, hit: bool) -> Result<(), Error> {
+        self.add(team_id, if hit { "cache_hits" } else { "cache_misses" }).await
+    }
+
+    pub async fn month(&self, team_id: &str, month: &str) -> Result<Usage, Error> {
+        let item = match self.store.get(&usage_key(team_id, month)).await? {
+            Some(item) => item,
+            None => return Ok(Usage::default()),
+        };
+        let count = |name: &str| item.get(name).and_then(Attr::as_number).unwrap_or_default() as u64;
+        Ok(Usage {
+            invocations: count("invocations"),
+            cache_hits: count("cache_hits"),
+            cache_misses: count("cache_misses"),
+        })
+    }
+
+    /// The most recently saved rate-limit bucket. It's the app token's, so every workspace shares it.
+    pub async fn last_bucket(&self) -> Result<Option<BucketSnapshot>, Error> {
+        let item = match self.store.get(BUCKET_KEY).await? {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        let number = |name: &str| item.get(name).and_then(Attr::as_number);
+        let (limit, remaining, reset, recorded_at) = match (
+            number("limit"),
+            number("remaining"),
+            number("reset"),
+            number("recorded_at"),
+        ) {
+            (Some(limit), Some(remaining), Some(reset), Some(recorded_at)) => (limit, remaining, reset, recorded_at),
+            _ => return Ok(None),
+        };
+        Ok(Some(BucketSnapshot {
+            bucket: Bucket {
+                limit: limit as u32,
+                remaining: remaining as u32,
+                reset: reset as u64,
+            },
+            recorded_at,
+        }))
+    }
+
+    async fn save_bucket(&self) -> Result<(), Error> {
+        let bucket = match ratelimit::bucket() {
+            Some(bucket) => bucket,
+            None => return Ok(()),
+        };
+        let mut item = Attrs::new();
+        item.insert("limit".to_string(), Attr::N(i64::from(bucket.limit)));
+        item.insert("remaining".to_string(), Attr::N(i64::from(bucket.remaining)));
+        item.insert("reset".to_string(), Attr::N(bucket.reset as i64));
+        item.insert("recorded_at".to_string(), Attr::N(Utc::now().timestamp()));
+        self.store.put(BUCKET_KEY, item).await
+    }
+
+    async fn add(&self, team_id: &str, counter: &str) -> Result<(), Error> {
+        let now = Utc::now();
+        let mut also = Attrs::new();
+        also.insert("expires_at".to_string(), Attr::N(now.timestamp() + RETENTION_SECS));
+        self.store.add(&usage_key(team_id, &month_of(now)), counter, 1, also).await
+    }
+}
+
+fn usage_key(team_id: &str, month: &str) -> String {
+    format!("{}:{}", team_id, month)
+}
@@ -0,0 +1,202 @@
+//! Per-workspace settings, stored as a JSON document keyed by Slack `team_id`. The bot token
+//! from the workspace's OAuth install lives alongside it in its own attribute.
+//!
+//! An install starts at `/oauth`, which stores a one-time `state` nonce here and sends the
+//! installer to Slack; the redirect back has to return an unexpired one before its code is used.
+
+use crate::audit::{AuditEntry, AuditLog};
+use crate::notify::SinkConfig;
+use crate::plans::Plan;
+use crate::ratelimit::now_secs;
+use crate::stage::Stage;
+use crate::store::{self, Attr, Attrs, Condition, Store};
+use crate::Error;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+const DEFAULT_TABLE: &str = "tuser-workspaces";
+/// How long an installer has to approve the app in Slack before the install's `state` expires.
+const INSTALL_STATE_TTL_SECS: u64 = 10 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    /// Render lookups as one line per channel instead of cards.
+    pub compact_output: bool,
+    pub digest_channel: Option<String>,
+    pub alert_channel: Option<String>,
+    /// Where go-live alerts go besides Slack, set by the operator (see `notify`).
+    pub notification_sinks: Vec<SinkConfig>,
+    /// Go-live alerts within this many minutes of each other share one message; 0 posts each
+    /// separately. Unset means `alerts::DEFAULT_COALESCE_MINUTES`.
+    pub alert_coalesce_minutes: Option<u32>,
+    /// Where moderation notifications (unban requests) are posted.
+    pub moderation_channel: Option<String>,
+    /// IANA zone name used for schedules and digests.
+    pub timezone: Option<String>,
+    /// BCP 47 tag (`de-DE`) deciding how counts and dates are written.
+    pub locale: Option<String>,
+    /// Logins of channels the workspace follows.
+    pub watchlist: Vec<String>,
+    /// Post a reminder to the alert channel this many minutes before a watched channel's
+    /// scheduled stream; `None` turns reminders off.
+    pub reminder_minutes: Option<u32>,
+    /// Watched channels the workspace doesn't want reminders for.
+    pub reminder_mutes: Vec<String>,
+    /// The pinned `/tboard` message kept up to date with the watchlist's live status.
+    pub board: Option<BoardLocation>,
+    /// Add a footer to go-live alerts showing how long the alert took to arrive.
+    pub debug_footer: bool,
+    /// Slack users a workspace admin has allowed to retrieve their stream key.
+    pub stream_key_grants: Vec<String>,
+    /// Add English translations of non-English channel descriptions to lookup cards.
+    pub translate_descriptions: bool,
+    /// Render lookup cards as classic attachments instead of Block Kit sections.
+    pub legacy_cards: bool,
+    /// Words masked in Twitch titles and descriptions posted to channels (`scrub`).
+    pub banned_words: Vec<String>,
+    /// Set by the operator; `None` means `plans::Plan::default_plan()`.
+    pub plan: Option<Plan>,
+}
+
+/// Where a workspace's pinned status board message lives.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BoardLocation {
+    pub channel: String,
+    pub ts: String,
+}
+
+pub struct WorkspaceStore {
+    store: Box<dyn Store>,
+}
+
+impl WorkspaceStore {
+    pub fn new(store: Box<dyn Store>) -> WorkspaceStore {
+        WorkspaceStore { store }
+    }
+
+    pub fn from_env() -> WorkspaceStore {
+        let table = std::env::var("WORKSPACE_TABLE").unwrap_or_else(|_| Stage::current().resource(DEFAULT_TABLE));
+        WorkspaceStore::new(store::open(table, "team_id"))
+    }
+
+    /// Returns the workspace's config, or the defaults for a workspace that never saved one.
+    pub async fn load(&self, team_id: &str) -> Result<WorkspaceConfig, Error> {
+        match self.attribute(team_id, "config").await? {
+            Some(config) => Ok(serde_json::from_str(&config)?),
+            None => Ok(WorkspaceConfig::default()),
+        }
+    }
+
+    /// Every workspace that has saved a config, for scheduled jobs that run across all of them.
+    pub async fn all(&self) -> Result<Vec<(String, WorkspaceConfig)>, Error> {
+        let mut workspaces = vec![];
+        for (team_id, mut item) in self.store.scan(&["config"]).await? {
+            if let Some(config) = item.remove("config").and_then(Attr::into_string) {
+                workspaces.push((team_id, serde_json::from_str(&config)?));
+            }
+        }
+        Ok(workspaces)
+    }
+
+    pub async fn save(&self, team_id: &str, config: &WorkspaceConfig) -> Result<(), Error> {
+        self.store.set(team_id, "config", Attr::S(serde_json::to_string(config)?)).await
+    }
+
+    /// Saves a change made from Slack, recording it in the audit log first with the previous
+    /// values of the fields it changes so it can be undone.
+    pub async fn save_change(
+        &self,
+        audit: &AuditLog,
+        entry: &AuditEntry,
+        previous: &WorkspaceConfig,
+        config: &WorkspaceConfig,
+    ) -> Result<(), Error> {
+        let changed = changed_fields(previous, config)?;
+        if !changed.is_empty() {
+            audit.record_change(entry, &changed, &applied_fields(config, &changed)?, None).await?;
+        }
+        self.save(&entry.team_id, config).await
+    }
+
+    pub async fn bot_token(&self, team_id: &str) -> Result<Option<String>, Error> {
+        self.attribute(team_id, "bot_token").await
+    }
+
+    pub async fn save_bot_token(&self, team_id: &str, token: &str) -> Result<(), Error> {
+        self.store.set(team_id, "bot_token", Attr::S(token.to_string())).await
+    }
+
+    /// Starts an install, returning the `state` to send through Slack's OAuth flow.
+    pub async fn begin_install(&self) -> Result<String, Error> {
+        let state = format!("{:032x}", rand::random::<u128>());
+
+        let mut item = Attrs::new();
+        item.insert("expires_at".to_string(), Attr::N((now_secs() + INSTALL_STATE_TTL_SECS) as i64));
+        self.store.put(&format!("install:{}", state), item).await?;
+
+        Ok(state)
+    }
+
+    /// Consumes a `state` from the install redirect. `false` for a state that's unknown, already
+    /// used, or more than ten minutes old, which the table's TTL may not have swept yet.
+    pub async fn finish_install(&self, state: &str) -> Result<bool, Error> {
+        let state_key = format!("install:{}", state);
+        let expires_at = match self.store.get(&state_key).await?.and_then(|item| item.get("expires_at")?.as_number()) {
+            Some(expires_at) if expires_at > now_secs() as i64 => expires_at,
+            _ => return Ok(false),
+        };
+        // Of two redirects carrying the same state, only the one whose delete finds it goes on.
+        self.store.delete_if(&state_key, Condition::Equals("expires_at", Attr::N(expires_at))).await
+    }
+
+    async fn attribute(&self, team_id: &str, name: &str) -> Result<Option<String>, Error> {
+        Ok(self.store.get(team_id).await?.and_then(|mut item| item.remove(name)).and_then(Attr::into_string))
+    }
+}
+
+/// The fields that differ between two configs, with their values in `previous`.
+pub fn changed_fields(previous: &WorkspaceConfig, config: &WorkspaceConfig) -> Result<Map<String, Value>, Error> {
+    let (previous, config) = match (serde_json::to_value(previous)?, serde_json::to_value(config)?) {
+        (Value::Object(previous), Value::Object(config)) => (previous, config),
+        _ => return Ok(Map::new()),
+    };
+    Ok(previous
+        .into_iter()
+        .filter(|(field, value)| config.get(field) != Some(value))
+        .collect())
+}
+
+/// `config`'s values for the `changed` fields, i.e. what a change left them at.
+pub fn applied_fields(config: &WorkspaceConfig, changed: &Map<String, Value>) -> Result<Map<String, Value>, Error> {
+    let config = match serde_json::to_value(config)? {
+        Value::Object(config) => config,
+        _ => return Ok(Map::new()),
+    };
+    Ok(changed
+        .keys()
+        .map(|field| (field.clone(), config.get(field).cloned().unwrap_or(Value::Null)))
+        .collect())
+}
+
+/// The first of the `applied` fields that `config` no longer has at its recorded value, i.e. one
+/// changed again since.
+pub fn conflicting_field(config: &WorkspaceConfig, applied: &Map<String, Value>) -> Result<Option<String>, Error> {
+    let current = applied_fields(config, applied)?;
+    Ok(applied
+        .iter()
+        .find(|(field, value)| current.get(field.as_str()) != Some(*value))
+        .map(|(field, _)| field.clone()))
+}
+
+/// `config` with the given fields put back to their recorded values. Other fields are untouched,
+/// so later changes to them survive the undo.
+pub fn restore_fields(config: &WorkspaceConfig, previous: &Map<String, Value>) -> Result<WorkspaceConfig, Error> {
+    let mut value = serde_json::to_value(config)?;
+    if let Value::Object(fields) = &mut value {
+        for (field, old) in previous {
+            fields.insert(field.clone(), old.clone());
+        }
+    }
+    Ok(serde_json::from_value(value)?)
+}
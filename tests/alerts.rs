@@ -0,0 +1,256 @@
+//! Coalescing go-live alerts into one message per window, against the SQLite store and a mock
+//! Slack.
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{block_on, slack};
+use futures::future::BoxFuture;
+use httpmock::prelude::*;
+use httpmock::Mock;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use twitch_info_bot::alerts::{AlertWindow, AlertWindowStore};
+use twitch_info_bot::store::{Attr, Attrs, Condition, SqliteStore, Store};
+use twitch_info_bot::Error;
+
+const TEAM: &str = "T1";
+
+fn windows() -> AlertWindowStore {
+    AlertWindowStore::new(Box::new(sqlite()))
+}
+
+fn sqlite() -> SqliteStore {
+    SqliteStore::open(":memory:", "alert_windows").expect("in-memory store")
+}
+
+fn deliver(windows: &AlertWindowStore, channel: &str, line: &str, minutes: u32) -> Result<(), Error> {
+    let delivered = windows.deliver(TEAM, channel, line.to_string(), " · footer", minutes, "xoxb-test".to_string());
+    block_on(delivered).map(|_| ())
+}
+
+fn window(windows: &AlertWindowStore, channel: &str) -> Option<AlertWindow> {
+    block_on(windows.get(TEAM, channel)).unwrap()
+}
+
+/// `chat.postMessage` to `channel`, answering with `ts`.
+fn post(channel: &str, ts: &str) -> Mock<'static> {
+    slack().mock(|when, then| {
+        when.method(POST).path("/api/chat.postMessage").json_body_partial(json!({ "channel": channel }).to_string());
+        then.status(200).json_body(json!({ "ok": true, "channel": channel, "ts": ts }));
+    })
+}
+
+/// `chat.update` of any message in `channel`.
+fn update(channel: &str) -> Mock<'static> {
+    slack().mock(|when, then| {
+        when.method(POST).path("/api/chat.update").json_body_partial(json!({ "channel": channel }).to_string());
+        then.status(200).json_body(json!({ "ok": true, "channel": channel }));
+    })
+}
+
+#[test]
+fn the_first_alert_posts_and_opens_a_window() {
+    let windows = windows();
+    let posted = post("C-open", "1700000000.000100");
+
+    deliver(&windows, "C-open", "ninja is live", 5).unwrap();
+
+    posted.assert_hits(1);
+    let window = window(&windows, "C-open").expect("an open window");
+    assert_eq!(window.ts, "1700000000.000100");
+    assert_eq!(window.lines, vec!["ninja is live"]);
+    assert_eq!(window.version, 1);
+}
+
+#[test]
+fn alerts_while_the_window_is_open_update_its_message() {
+    let windows = windows();
+    let posted = post("C-coalesce", "1700000000.000200");
+    let updated = slack().mock(|when, then| {
+        when.method(POST).path("/api/chat.update").json_body_partial(
+            json!({
+                "channel": "C-coalesce",
+                "ts": "1700000000.000200",
+                "text": "🔴 2 watched channels went live:\n• ninja is live\n• pokimane is live",
+            })
+            .to_string(),
+        );
+        then.status(200).json_body(json!({ "ok": true }));
+    });
+
+    deliver(&windows, "C-coalesce", "ninja is live", 5).unwrap();
+    deliver(&windows, "C-coalesce", "pokimane is live", 5).unwrap();
+
+    posted.assert_hits(1);
+    updated.assert_hits(1);
+    let window = window(&windows, "C-coalesce").unwrap();
+    assert_eq!(window.lines, vec!["ninja is live", "pokimane is live"]);
+    assert_eq!(window.version, 2);
+}
+
+#[test]
+fn an_alert_after_the_window_closed_opens_a_new_one() {
+    let windows = windows();
+    let posted = post("C-closed", "1700000000.000300");
+    let updated = update("C-closed");
+    let closed = AlertWindow {
+        ts: "1600000000.000100".to_string(),
+        opened_at: chrono::Utc::now().timestamp() - 10 * 60,
+        lines: vec!["ninja is live".to_string()],
+        version: 0,
+    };
+    assert!(block_on(windows.put(TEAM, "C-closed", &closed, None)).unwrap());
+
+    deliver(&windows, "C-closed", "pokimane is live", 5).unwrap();
+
+    posted.assert_hits(1);
+    updated.assert_hits(0);
+    let window = window(&windows, "C-closed").unwrap();
+    assert_eq!(window.ts, "1700000000.000300");
+    assert_eq!(window.lines, vec!["pokimane is live"]);
+}
+
+#[test]
+fn without_coalescing_every_alert_is_its_own_post() {
+    let windows = windows();
+    let posted = post("C-off", "1700000000.000400");
+    let updated = update("C-off");
+
+    deliver(&windows, "C-off", "ninja is live", 0).unwrap();
+    deliver(&windows, "C-off", "pokimane is live", 0).unwrap();
+
+    posted.assert_hits(2);
+    updated.assert_hits(0);
+    assert!(window(&windows, "C-off").is_none());
+}
+
+#[test]
+fn a_save_only_succeeds_against_the_version_it_read() {
+    let windows = windows();
+    let opened = AlertWindow {
+        ts: "1700000000.000500".to_string(),
+        opened_at: chrono::Utc::now().timestamp(),
+        lines: vec!["ninja is live".to_string()],
+        version: 0,
+    };
+
+    assert!(block_on(windows.put(TEAM, "C-versions", &opened, None)).unwrap());
+    assert!(!block_on(windows.put(TEAM, "C-versions", &opened, None)).unwrap());
+    assert!(!block_on(windows.put(TEAM, "C-versions", &opened, Some(0))).unwrap());
+    assert!(block_on(windows.put(TEAM, "C-versions", &opened, Some(1))).unwrap());
+    assert_eq!(window(&windows, "C-versions").unwrap().version, 2);
+}
+
+/// A store where another delivery adds its own line to the window just before each of the first
+/// `conflicts` conditional writes.
+struct Contended {
+    inner: SqliteStore,
+    conflicts: Arc<AtomicUsize>,
+}
+
+impl Contended {
+    async fn rival_line(&self, key: &str) -> Result<(), Error> {
+        let mut item = match self.inner.get(key).await? {
+            Some(item) => item,
+            None => return Ok(()),
+        };
+        let mut window: Value = match item.remove("window").and_then(Attr::into_string) {
+            Some(window) => serde_json::from_str(&window)?,
+            None => return Ok(()),
+        };
+        let version = window["version"].as_i64().unwrap_or(0) + 1;
+        window["version"] = json!(version);
+        window["lines"].as_array_mut().unwrap().push(json!("rival is live"));
+        item.insert("window".to_string(), Attr::S(window.to_string()));
+        item.insert("version".to_string(), Attr::N(version));
+        self.inner.put(key, item).await
+    }
+}
+
+impl Store for Contended {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Attrs>, Error>> {
+        self.inner.get(key)
+    }
+
+    fn put<'a>(&'a self, key: &'a str, attrs: Attrs) -> BoxFuture<'a, Result<(), Error>> {
+        self.inner.put(key, attrs)
+    }
+
+    fn put_if<'a>(&'a self, key: &'a str, attrs: Attrs, condition: Condition) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move {
+            let contended = self.conflicts.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+            if contended.is_ok() {
+                self.rival_line(key).await?;
+            }
+            self.inner.put_if(key, attrs, condition).await
+        })
+    }
+
+    fn set<'a>(&'a self, key: &'a str, name: &'a str, value: Attr) -> BoxFuture<'a, Result<(), Error>> {
+        self.inner.set(key, name, value)
+    }
+
+    fn add<'a>(&'a self, key: &'a str, name: &'a str, by: i64, also: Attrs) -> BoxFuture<'a, Result<(), Error>> {
+        self.inner.add(key, name, by, also)
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        self.inner.delete(key)
+    }
+
+    fn delete_if<'a>(&'a self, key: &'a str, condition: Condition) -> BoxFuture<'a, Result<bool, Error>> {
+        self.inner.delete_if(key, condition)
+    }
+
+    fn scan<'a>(&'a self, attributes: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<(String, Attrs)>, Error>> {
+        self.inner.scan(attributes)
+    }
+}
+
+/// An open window in `channel`, holding one alert, in a store whose next `races` writes race.
+fn contended(channel: &str, races: usize) -> AlertWindowStore {
+    let conflicts = Arc::new(AtomicUsize::new(0));
+    let windows = AlertWindowStore::new(Box::new(Contended {
+        inner: sqlite(),
+        conflicts: conflicts.clone(),
+    }));
+    let opened = AlertWindow {
+        ts: "1700000000.000600".to_string(),
+        opened_at: chrono::Utc::now().timestamp(),
+        lines: vec!["ninja is live".to_string()],
+        version: 0,
+    };
+    assert!(block_on(windows.put(TEAM, channel, &opened, None)).unwrap());
+    conflicts.store(races, Ordering::SeqCst);
+    windows
+}
+
+#[test]
+fn a_conflicting_write_is_retried_against_the_new_window() {
+    let windows = contended("C-retry", 2);
+    let posted = post("C-retry", "1700000000.000700");
+    let updated = update("C-retry");
+
+    deliver(&windows, "C-retry", "pokimane is live", 5).unwrap();
+
+    posted.assert_hits(0);
+    updated.assert_hits(1);
+    let window = window(&windows, "C-retry").unwrap();
+    assert_eq!(window.lines, vec!["ninja is live", "rival is live", "rival is live", "pokimane is live"]);
+}
+
+#[test]
+fn delivery_gives_up_when_every_attempt_conflicts() {
+    let windows = contended("C-give-up", 3);
+    let posted = post("C-give-up", "1700000000.000800");
+    let updated = update("C-give-up");
+
+    let error = deliver(&windows, "C-give-up", "pokimane is live", 5).unwrap_err();
+
+    assert!(error.to_string().contains("after 3 attempts"), "{}", error);
+    posted.assert_hits(0);
+    updated.assert_hits(0);
+    assert!(!window(&windows, "C-give-up").unwrap().lines.contains(&"pokimane is live".to_string()));
+}
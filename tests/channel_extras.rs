@@ -0,0 +1,87 @@
+//! Channel trailers and panel links, which come from Twitch's GraphQL API rather than Helix.
+
+mod common;
+
+use common::twitch;
+use httpmock::prelude::*;
+use serde_json::{json, Value};
+use twitch_info_bot::twitch::{self, ChannelExtras, PanelLink, TimeoutConfig, MAX_PANEL_LINKS};
+
+/// Answers the GraphQL query for `login` with `user`.
+fn gql_user(login: &str, user: Value) {
+    twitch().mock(|when, then| {
+        when.method(POST)
+            .path("/gql")
+            .header("Client-Id", "test-gql-client")
+            .json_body_partial(json!({ "variables": { "login": login } }).to_string());
+        then.status(200).json_body(json!({ "data": { "user": user } }));
+    });
+}
+
+fn extras(login: &str) -> Option<ChannelExtras> {
+    let client = twitch::client(&TimeoutConfig::from_env()).unwrap();
+    twitch::get_channel_extras(&client, login).unwrap()
+}
+
+fn panel(title: &str, url: &str) -> PanelLink {
+    PanelLink {
+        title: title.to_string(),
+        url: url.to_string(),
+    }
+}
+
+#[test]
+fn the_trailer_and_linked_panels_are_returned() {
+    gql_user(
+        "gqltrailer",
+        json!({
+            "channel": { "trailer": { "video": { "id": "1234567890" } } },
+            "panels": [
+                { "title": "Discord", "linkURL": "https://discord.gg/example", "description": "" },
+                { "title": "Rules", "linkURL": null, "description": "Be nice. No links." },
+                { "title": "Merch", "linkURL": "", "description": "Get it [here](https://shop.example.com/merch)!" },
+                { "title": "", "linkURL": "https://example.org/about", "description": null },
+                { "title": "Sneaky", "linkURL": "javascript:alert(1)", "description": "" },
+                {},
+            ],
+        }),
+    );
+
+    let extras = extras("gqltrailer").expect("the user's extras");
+    assert_eq!(extras.trailer.as_deref(), Some("https://www.twitch.tv/videos/1234567890"));
+    assert_eq!(
+        extras.panels,
+        vec![
+            panel("Discord", "https://discord.gg/example"),
+            panel("Merch", "https://shop.example.com/merch"),
+            panel("example.org", "https://example.org/about"),
+        ]
+    );
+}
+
+#[test]
+fn a_channel_without_a_trailer_or_panels_has_neither() {
+    gql_user("gqlbare", json!({ "channel": { "trailer": { "video": null } }, "panels": [] }));
+
+    assert_eq!(extras("gqlbare"), Some(ChannelExtras::default()));
+}
+
+#[test]
+fn only_the_first_panel_links_are_kept() {
+    let panels: Vec<Value> = (0..MAX_PANEL_LINKS + 5)
+        .map(|n| json!({ "title": format!("Panel {}", n), "linkURL": format!("https://example.com/{}", n) }))
+        .collect();
+    gql_user("gqlpanels", json!({ "channel": null, "panels": panels }));
+
+    let extras = extras("gqlpanels").unwrap();
+    assert_eq!(extras.trailer, None);
+    assert_eq!(extras.panels.len(), MAX_PANEL_LINKS);
+    assert_eq!(extras.panels[0], panel("Panel 0", "https://example.com/0"));
+}
+
+#[test]
+fn an_unknown_user_has_no_extras() {
+    gql_user("gqlnobody", Value::Null);
+
+    assert_eq!(extras("gqlnobody"), None);
+}
@@ -0,0 +1,107 @@
+// Each test binary uses its own subset of these.
+#![allow(dead_code)]
+
+use httpmock::prelude::*;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use twitch_info_bot::query::{self, LookupResult};
+use twitch_info_bot::secrets::Secrets;
+use twitch_info_bot::twitch::{Helix, LookupError, TimeoutConfig, TwitchApi, TwitchStream, TwitchUser};
+
+/// The mock Twitch for this test binary. The bot reads its Twitch base URLs once per process
+/// (see `config`), so the tests in a binary share one server and keep their mocks apart by the
+/// users they look up.
+pub fn twitch() -> &'static MockServer {
+    static SERVER: OnceLock<MockServer> = OnceLock::new();
+    SERVER.get_or_init(|| {
+        let server = MockServer::start();
+        std::env::set_var("TWITCH_API_BASE", server.url("/helix"));
+        std::env::set_var("TWITCH_AUTH_BASE", server.url("/oauth2"));
+        std::env::set_var("TWITCH_GQL_BASE", server.url("/gql"));
+        std::env::set_var("TWITCH_GQL_CLIENT_ID", "test-gql-client");
+        std::env::set_var("TWITCH_RETRY_BASE_MS", "1");
+        std::env::set_var("TWITCH_RETRY_MAX_MS", "10");
+        server.mock(|when, then| {
+            when.method(GET).path("/oauth2/validate");
+            then.status(200)
+                .json_body(json!({ "client_id": "test-client", "scopes": [], "expires_in": 5_000_000 }));
+        });
+        server
+    })
+}
+
+/// The mock Slack Web API for this test binary, shared like [`twitch`]; tests keep their mocks
+/// apart by the channels they post to.
+pub fn slack() -> &'static MockServer {
+    static SERVER: OnceLock<MockServer> = OnceLock::new();
+    SERVER.get_or_init(|| {
+        let server = MockServer::start();
+        std::env::set_var("SLACK_API_BASE", server.url("/api"));
+        server
+    })
+}
+
+/// A Helix response body from `tests/fixtures/helix`.
+pub fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/helix/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+pub fn secrets() -> Secrets {
+    serde_json::from_value(json!({
+        "slack_token": "test-slack-token",
+        "twitch_client_id": "test-client",
+        "twitch_client_secret": "test-client-secret",
+        "twitch_app_token": "test-app-token",
+    }))
+    .expect("test secrets")
+}
+
+pub fn user(id: &str, login: &str) -> TwitchUser {
+    let user: Value = json!({
+        "type": "",
+        "id": id,
+        "login": login,
+        "display_name": login,
+        "broadcaster_type": "",
+        "description": "",
+        "profile_image_url": "",
+        "offline_image_url": "",
+    });
+    serde_json::from_value(user).expect("test user")
+}
+
+/// A live stream for the user, as `/helix/streams` reports one.
+pub fn stream(user_id: &str, login: &str) -> TwitchStream {
+    let stream: Value = json!({
+        "id": format!("stream-{}", user_id),
+        "user_id": user_id,
+        "user_login": login,
+        "user_name": login,
+        "game_id": "509658",
+        "game_name": "Just Chatting",
+        "title": "hi chat",
+        "viewer_count": 21304,
+        "started_at": "2021-03-10T15:04:21Z",
+    });
+    serde_json::from_value(stream).expect("test stream")
+}
+
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Runtime::new().expect("test runtime").block_on(future)
+}
+
+/// Runs `/tuser`'s lookup for `text` against `api`, for `compact` output (live status only) or
+/// cards (follower counts too).
+pub fn lookup(api: &dyn TwitchApi, text: &str, compact: bool) -> Result<LookupResult, LookupError> {
+    let query = query::parse_command_text(text).expect("valid lookup text");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    block_on(query::lookup(api, &query, &TimeoutConfig::from_env(), deadline, compact))
+}
+
+/// [`lookup`] against the mock server.
+pub fn lookup_on_helix(text: &str, compact: bool) -> Result<LookupResult, LookupError> {
+    twitch();
+    lookup(&Helix::new(secrets()), text, compact)
+}
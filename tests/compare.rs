@@ -0,0 +1,81 @@
+//! `/tcompare --overlap`'s audience samples and the estimate made from them.
+
+use std::collections::HashSet;
+use twitch_info_bot::overlap::{self, Estimate, Sample, MIN_SAMPLE};
+use twitch_info_bot::twitch::{Chatter, TwitchClip};
+
+fn ids(range: std::ops::Range<usize>) -> HashSet<String> {
+    range.map(|n| format!("viewer{}", n)).collect()
+}
+
+fn clip(creator_id: &str) -> TwitchClip {
+    TwitchClip {
+        id: format!("clip-by-{}", creator_id),
+        url: String::new(),
+        title: String::new(),
+        creator_id: creator_id.to_string(),
+        creator_name: creator_id.to_string(),
+        view_count: 0,
+        created_at: String::new(),
+        broadcaster_name: String::new(),
+        thumbnail_url: String::new(),
+    }
+}
+
+#[test]
+fn overlap_is_a_share_of_the_smaller_sample() {
+    let a = Sample {
+        clippers: ids(0..20),
+        chatters: Some(ids(20..40)),
+    };
+    let b = Sample {
+        clippers: ids(30..50),
+        chatters: None,
+    };
+
+    let estimate = overlap::estimate(&a, &b).unwrap();
+    assert_eq!(
+        estimate,
+        Estimate {
+            shared: 10,
+            smaller: 20,
+            percent: 50
+        }
+    );
+    assert!(overlap::describe("A", &a, "B", &b).contains("estimate"));
+}
+
+#[test]
+fn samples_too_small_give_no_estimate() {
+    let a = Sample {
+        clippers: ids(0..MIN_SAMPLE - 1),
+        chatters: None,
+    };
+    let b = Sample {
+        clippers: ids(0..100),
+        chatters: None,
+    };
+
+    assert_eq!(overlap::estimate(&a, &b), None);
+    assert!(overlap::describe("A", &a, "B", &b).contains("not enough to estimate"));
+}
+
+#[test]
+fn the_compared_broadcasters_are_not_part_of_either_audience() {
+    let clips = vec![clip("111"), clip("viewer1"), clip("222"), clip("")];
+    let chatters = vec![
+        Chatter {
+            user_id: "222".to_string(),
+            user_login: "other".to_string(),
+        },
+        Chatter {
+            user_id: "viewer2".to_string(),
+            user_login: "viewer2".to_string(),
+        },
+    ];
+
+    let sample = Sample::new(&clips, Some(&chatters), &["111", "222"]);
+    assert_eq!(sample.clippers, ids(1..2));
+    assert_eq!(sample.chatters, Some(ids(2..3)));
+    assert_eq!(sample.sources(), "1 clip creators and 1 chatters");
+}
@@ -0,0 +1,39 @@
+//! What crash reports leave out.
+
+mod common;
+
+use common::secrets;
+use twitch_info_bot::crash;
+
+#[test]
+fn reports_are_scrubbed_of_credentials_and_tokens() {
+    let text = "Helix said 401 to Bearer abc123 with test-app-token\nposting with xoxb-1-2-3 failed: oauth zzz";
+
+    let scrubbed = crash::scrub(text, &secrets(), &[]);
+
+    assert_eq!(
+        scrubbed,
+        "Helix said 401 to Bearer [redacted] with [redacted]\nposting with [redacted] failed: oauth [redacted]"
+    );
+}
+
+#[test]
+fn tokens_are_found_inside_words_and_across_any_whitespace() {
+    let text = "url=https://x?token=xoxp-99-a_b.\tkey:live_123456_AbC_9\n\tBearer\tq1w2e3 live_ a xoxz-1";
+
+    let scrubbed = crash::scrub(text, &secrets(), &[]);
+
+    assert_eq!(
+        scrubbed,
+        "url=https://x?token=[redacted].\tkey:[redacted]\n\tBearer\t[redacted] live_ a xoxz-1"
+    );
+}
+
+#[test]
+fn the_workspace_and_link_tokens_it_is_given_are_scrubbed() {
+    let tokens = vec!["user-access-token-1".to_string(), "short".to_string()];
+
+    let scrubbed = crash::scrub("refresh failed for user-access-token-1 (short)", &secrets(), &tokens);
+
+    assert_eq!(scrubbed, "refresh failed for [redacted] (short)");
+}
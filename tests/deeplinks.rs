@@ -0,0 +1,74 @@
+//! Signed links that act when opened in a browser.
+
+use chrono::Utc;
+use serde_json::Value;
+use std::collections::HashMap;
+use twitch_info_bot::deeplinks::{DeepLink, LinkAction, LinkError};
+
+const SECRET: &str = "test-deep-link-secret";
+
+fn link() -> DeepLink {
+    DeepLink::new(LinkAction::RefreshBoard, "T1", "U1", "")
+}
+
+/// The query string of `link`'s URL as `tgo` receives it, in `queryStringParameters`.
+fn params(link: &DeepLink, secret: &str) -> Value {
+    std::env::set_var("DEEP_LINK_BASE_URL", "https://bot.example.com/");
+    let url = link.url(secret).expect("links are configured");
+    let query = url.split_once('?').expect("the link has a query").1;
+    let params: HashMap<String, String> = serde_urlencoded::from_str(query).unwrap();
+    serde_json::to_value(params).unwrap()
+}
+
+#[test]
+fn a_link_verifies_as_it_was_made() {
+    let link = link();
+    let params = params(&link, SECRET);
+
+    assert!(params["s"].as_str().is_some());
+    assert_eq!(DeepLink::verify(SECRET, &params), Ok(link));
+}
+
+#[test]
+fn changing_any_signed_part_breaks_the_signature() {
+    let signed = params(&link(), SECRET);
+    let later = (Utc::now().timestamp() + 3600).to_string();
+
+    for (name, value) in [("t", "T2"), ("u", "U2"), ("v", "ninja"), ("e", later.as_str())] {
+        let mut tampered = signed.clone();
+        tampered[name] = Value::String(value.to_string());
+        assert_eq!(DeepLink::verify(SECRET, &tampered), Err(LinkError::BadSignature), "changed {}", name);
+    }
+    assert_eq!(DeepLink::verify("another-secret", &signed), Err(LinkError::BadSignature));
+}
+
+#[test]
+fn a_signature_of_the_wrong_length_is_refused() {
+    let signed = params(&link(), SECRET);
+    let full = signed["s"].as_str().unwrap().to_string();
+
+    for signature in [&full[..full.len() - 2], &format!("{}00", full)[..], ""] {
+        let mut params = signed.clone();
+        params["s"] = Value::String(signature.to_string());
+        assert_eq!(DeepLink::verify(SECRET, &params), Err(LinkError::BadSignature));
+    }
+}
+
+#[test]
+fn an_expired_link_is_refused() {
+    let link = DeepLink {
+        expires_at: Utc::now().timestamp() - 1,
+        ..link()
+    };
+
+    assert_eq!(DeepLink::verify(SECRET, &params(&link, SECRET)), Err(LinkError::Expired));
+}
+
+#[test]
+fn links_need_a_secret() {
+    std::env::set_var("DEEP_LINK_BASE_URL", "https://bot.example.com/");
+    assert_eq!(link().url(""), None);
+
+    let signed = params(&link(), SECRET);
+    assert_eq!(DeepLink::verify("", &signed), Err(LinkError::BadSignature));
+}
@@ -0,0 +1,227 @@
+//! `/tuser` lookups against a mock Helix: the requests the bot sends, and how it maps the
+//! responses and errors it gets back.
+
+mod common;
+
+use common::{fixture, lookup, lookup_on_helix, secrets, twitch};
+use httpmock::prelude::*;
+use serde_json::json;
+use std::thread;
+use std::time::{Duration, Instant};
+use twitch_info_bot::drift::{self, Change};
+use twitch_info_bot::shadow::{self, Shadowed};
+use twitch_info_bot::twitch::{LookupError, TwitchUser};
+
+#[test]
+fn lookup_asks_for_ids_and_logins_and_adds_live_status() {
+    let users = twitch().mock(|when, then| {
+        when.method(GET)
+            .path("/helix/users")
+            .query_param("id", "12826")
+            .query_param("login", "ninja")
+            .query_param("login", "nosuchuser")
+            .header("Client-ID", "test-client")
+            .header("Authorization", "Bearer test-app-token");
+        then.status(200).body_from_file(fixture("users.json"));
+    });
+    let streams = twitch().mock(|when, then| {
+        when.method(GET)
+            .path("/helix/streams")
+            .query_param("user_id", "19571641")
+            .query_param("user_id", "12826");
+        then.status(200).body_from_file(fixture("streams.json"));
+    });
+    let search = twitch().mock(|when, then| {
+        when.method(GET).path("/helix/search/channels").query_param("query", "nosuchuser");
+        then.status(200).json_body(json!({ "data": [], "pagination": {} }));
+    });
+
+    let result = lookup_on_helix("ninja 12826 nosuchuser", true).unwrap();
+
+    users.assert();
+    streams.assert();
+    search.assert();
+    let logins: Vec<&str> = result.users.iter().map(|user| user.login.as_str()).collect();
+    assert_eq!(logins, ["ninja", "twitch"]);
+    assert_eq!(result.streams.len(), 1);
+    assert_eq!(result.streams[0].user_login, "ninja");
+    assert_eq!(result.streams[0].viewer_count, 23456);
+    assert_eq!(result.not_found, ["nosuchuser"]);
+    assert!(result.missing.is_empty());
+}
+
+/// Helix only gives follower counts to a user token, so a channel nobody linked has none.
+#[test]
+fn card_lookup_adds_live_status_and_leaves_out_unlinked_follower_counts() {
+    twitch().mock(|when, then| {
+        when.method(GET).path("/helix/users").query_param("login", "twitch");
+        then.status(200).body_from_file(fixture("users_twitch.json"));
+    });
+    let streams = twitch().mock(|when, then| {
+        when.method(GET).path("/helix/streams").query_param("user_id", "12826");
+        then.status(200).json_body(json!({ "data": [], "pagination": {} }));
+    });
+    let followers = twitch().mock(|when, then| {
+        when.method(GET)
+            .path("/helix/channels/followers")
+            .query_param("broadcaster_id", "12826")
+            .query_param("first", "1");
+        then.status(200).body_from_file(fixture("channels_followers.json"));
+    });
+
+    let result = lookup_on_helix("https://twitch.tv/twitch", false).unwrap();
+
+    streams.assert();
+    followers.assert_hits(0);
+    assert!(result.followers.is_empty());
+    assert!(result.streams.is_empty());
+    assert!(result.missing.is_empty());
+    let created = result.users[0].created().unwrap();
+    assert_eq!(created.to_rfc3339(), "2007-05-22T10:39:54+00:00");
+}
+
+#[test]
+fn rejected_lookup_is_not_retried() {
+    let users = twitch().mock(|when, then| {
+        when.method(GET).path("/helix/users").query_param("login", "bad_request");
+        then.status(400).body_from_file(fixture("bad_request.json"));
+    });
+
+    assert_eq!(lookup_on_helix("bad_request", true).unwrap_err(), LookupError::Rejected(400));
+    users.assert_hits(1);
+}
+
+#[test]
+fn server_errors_are_retried_then_reported() {
+    let users = twitch().mock(|when, then| {
+        when.method(GET).path("/helix/users").query_param("login", "flaky");
+        then.status(500).body("{}");
+    });
+
+    assert_eq!(lookup_on_helix("flaky", true).unwrap_err(), LookupError::Outage(500));
+    users.assert_hits(3);
+}
+
+#[test]
+fn failed_live_status_is_left_off_instead_of_failing() {
+    twitch().mock(|when, then| {
+        when.method(GET).path("/helix/users").query_param("login", "pokimane");
+        then.status(200).body_from_file(fixture("users_pokimane.json"));
+    });
+    twitch().mock(|when, then| {
+        when.method(GET).path("/helix/streams").query_param("user_id", "44445592");
+        then.status(502).body("Bad Gateway");
+    });
+
+    let result = lookup_on_helix("pokimane", true).unwrap();
+
+    assert_eq!(result.users.len(), 1);
+    assert!(result.streams.is_empty());
+    assert_eq!(result.missing, ["live status"]);
+}
+
+#[test]
+fn unreadable_response_is_a_decode_error() {
+    twitch().mock(|when, then| {
+        when.method(GET).path("/helix/users").query_param("login", "garbled");
+        then.status(200).body(r#"{"data": "not a list"}"#);
+    });
+
+    assert_eq!(lookup_on_helix("garbled", true).unwrap_err(), LookupError::Decode);
+}
+
+#[test]
+fn unknown_login_is_searched_for_suggestions() {
+    twitch().mock(|when, then| {
+        when.method(GET).path("/helix/users").query_param("login", "pokiman");
+        then.status(200).json_body(json!({ "data": [] }));
+    });
+    let search = twitch().mock(|when, then| {
+        when.method(GET)
+            .path("/helix/search/channels")
+            .query_param("query", "pokiman")
+            .query_param("first", "5");
+        then.status(200).body_from_file(fixture("search_channels.json"));
+    });
+
+    let result = lookup_on_helix("pokiman", true).unwrap();
+
+    search.assert();
+    assert!(result.users.is_empty());
+    assert_eq!(result.not_found, ["pokiman"]);
+    let channels = &result.suggestions[0].channels;
+    assert_eq!(channels[0].broadcaster_login, "pokimane");
+    assert_eq!(channels[0].game_name, "Just Chatting");
+}
+
+#[test]
+fn fields_twitch_adds_or_drops_are_reported_as_drift() {
+    let body: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(fixture("users_twitch.json")).unwrap())
+        .unwrap();
+    let raw = body["data"][0].clone();
+    let mut drifted = raw.clone();
+    drifted.as_object_mut().unwrap().remove("view_count");
+    drifted["pronouns"] = json!("they/them");
+
+    let user: TwitchUser = serde_json::from_value(raw.clone()).unwrap();
+    assert!(drift::changes(&raw, &user).is_empty());
+
+    let decoded: Vec<TwitchUser> = drift::decode(vec![drifted.clone()]).unwrap();
+    assert_eq!(decoded[0].extra.get("pronouns"), Some(&json!("they/them")));
+    assert_eq!(
+        drift::changes(&drifted, &decoded[0]),
+        [(Change::Missing, "view_count".to_string()), (Change::Unknown, "pronouns".to_string())]
+    );
+}
+
+#[test]
+fn lookups_past_the_parameter_limit_are_split_across_requests() {
+    let logins: Vec<String> = (0..150).map(|i| format!("batch{:03}", i)).collect();
+    let first = twitch().mock(|when, then| {
+        when.method(GET).path("/helix/users").query_param("login", "batch000");
+        then.status(200).json_body(json!({ "data": [] }));
+    });
+    let second = twitch().mock(|when, then| {
+        when.method(GET).path("/helix/users").query_param("login", "batch149");
+        then.status(200).json_body(json!({ "data": [] }));
+    });
+
+    let result = lookup_on_helix(&logins.join(" "), true).unwrap();
+
+    first.assert();
+    second.assert();
+    assert_eq!(result.not_found, logins);
+}
+
+#[test]
+fn shadowed_lookups_ask_both_paths_and_answer_with_the_new_one() {
+    let user = json!({
+        "id": "555001", "login": "shadowed", "display_name": "Shadowed", "type": "", "broadcaster_type": "",
+        "description": "", "profile_image_url": "", "offline_image_url": "", "view_count": 0,
+        "created_at": "2020-01-01T00:00:00Z",
+    });
+    let users = twitch().mock(|when, then| {
+        when.method(GET).path("/helix/users").query_param("login", "shadowed");
+        then.status(200).json_body(json!({ "data": [user] }));
+    });
+    let streams = twitch().mock(|when, then| {
+        when.method(GET).path("/helix/streams").query_param("user_id", "555001");
+        then.status(200).json_body(json!({ "data": [], "pagination": {} }));
+    });
+
+    let result = lookup(&Shadowed::new(secrets()), "shadowed", true).unwrap();
+
+    // The old path isn't waited on, so it may still be finishing.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while (users.hits() < 2 || streams.hits() < 2) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    users.assert_hits(2);
+    streams.assert_hits(2);
+    assert_eq!(result.users[0].login, "shadowed");
+
+    let one = Ok(result.users.clone());
+    assert!(shadow::differences(&one, &one, |user| &user.id).is_empty());
+    let differences = shadow::differences(&one, &Ok(vec![]), |user| &user.id);
+    assert_eq!(differences, ["only the new path has 555001"]);
+}
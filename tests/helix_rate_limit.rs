@@ -0,0 +1,34 @@
+//! Helix's rate limit. The bot tracks the app's bucket process-wide, so these run in a test
+//! binary of their own.
+
+mod common;
+
+use common::{lookup_on_helix, twitch};
+use httpmock::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+use twitch_info_bot::twitch::LookupError;
+
+#[test]
+fn empty_bucket_fails_fast_until_it_resets() {
+    let reset = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 60;
+    let limited = twitch().mock(|when, then| {
+        when.method(GET).path("/helix/users").query_param("login", "ninja");
+        then.status(429)
+            .header("Ratelimit-Limit", "800")
+            .header("Ratelimit-Remaining", "0")
+            .header("Ratelimit-Reset", reset.to_string())
+            .body(r#"{"error": "Too Many Requests", "status": 429, "message": ""}"#);
+    });
+    let later = twitch().mock(|when, then| {
+        when.method(GET).path("/helix/users").query_param("login", "pokimane");
+        then.status(200).body(r#"{"data": []}"#);
+    });
+
+    // The reset is further off than the retry policy waits, so the 429 isn't retried.
+    assert_eq!(lookup_on_helix("ninja", true).unwrap_err(), LookupError::RateLimited(reset));
+    limited.assert_hits(1);
+
+    // Until then, lookups are refused without asking Twitch.
+    assert_eq!(lookup_on_helix("pokimane", true).unwrap_err(), LookupError::RateLimited(reset));
+    later.assert_hits(0);
+}
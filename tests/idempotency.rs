@@ -0,0 +1,85 @@
+//! Claiming, completing and releasing idempotency keys, against the SQLite store.
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::block_on;
+use twitch_info_bot::idempotency::{Claim, ClaimedKey, IdempotencyStore};
+use twitch_info_bot::store::SqliteStore;
+
+fn idempotency() -> IdempotencyStore {
+    IdempotencyStore::new(Box::new(SqliteStore::open(":memory:", "idempotency").expect("in-memory store")))
+}
+
+fn claimed(claim: Claim) -> ClaimedKey {
+    match claim {
+        Claim::New(key) => key,
+        Claim::Duplicate(response) => panic!("expected a new claim, found a duplicate with {:?}", response),
+    }
+}
+
+fn duplicate(claim: Claim) -> Option<String> {
+    match claim {
+        Claim::New(key) => panic!("expected a duplicate, claimed {}", key.key),
+        Claim::Duplicate(response) => response,
+    }
+}
+
+#[test]
+fn a_retry_before_the_action_finishes_is_a_duplicate_without_a_response() {
+    let idempotency = idempotency();
+    block_on(async {
+        let key = claimed(idempotency.claim("tclip", "trigger-1").await.unwrap());
+        assert_eq!(key.key, "tclip:trigger-1");
+        assert_eq!(duplicate(idempotency.claim("tclip", "trigger-1").await.unwrap()), None);
+    });
+}
+
+#[test]
+fn a_retry_after_the_action_finishes_replays_its_response() {
+    let idempotency = idempotency();
+    block_on(async {
+        let key = claimed(idempotency.claim("tclip", "trigger-1").await.unwrap());
+        idempotency.complete(&key, "Clipped!").await.unwrap();
+        let replayed = duplicate(idempotency.claim("tclip", "trigger-1").await.unwrap());
+        assert_eq!(replayed.as_deref(), Some("Clipped!"));
+    });
+}
+
+#[test]
+fn claims_are_per_action_and_trigger() {
+    let idempotency = idempotency();
+    block_on(async {
+        claimed(idempotency.claim("tclip", "trigger-1").await.unwrap());
+        claimed(idempotency.claim("tclip", "trigger-2").await.unwrap());
+        claimed(idempotency.claim("tannounce", "trigger-1").await.unwrap());
+    });
+}
+
+#[test]
+fn a_released_claim_lets_the_retry_run_again() {
+    let idempotency = idempotency();
+    block_on(async {
+        let key = claimed(idempotency.claim("tclip", "trigger-1").await.unwrap());
+        idempotency.release(key).await;
+        claimed(idempotency.claim("tclip", "trigger-1").await.unwrap());
+    });
+}
+
+#[test]
+fn releasing_a_stale_claim_leaves_the_newer_one() {
+    let idempotency = idempotency();
+    block_on(async {
+        let first = claimed(idempotency.claim("tclip", "trigger-1").await.unwrap());
+        idempotency.release(first.clone()).await;
+        let second = claimed(idempotency.claim("tclip", "trigger-1").await.unwrap());
+
+        // The first invocation releasing again must not free the retry's claim.
+        idempotency.release(first).await;
+        assert_eq!(duplicate(idempotency.claim("tclip", "trigger-1").await.unwrap()), None);
+
+        idempotency.complete(&second, "Clipped!").await.unwrap();
+        let replayed = duplicate(idempotency.claim("tclip", "trigger-1").await.unwrap());
+        assert_eq!(replayed.as_deref(), Some("Clipped!"));
+    });
+}
@@ -0,0 +1,52 @@
+//! The one-time `state` an install carries through Slack's OAuth flow, against the SQLite store.
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use chrono::Utc;
+use common::block_on;
+use std::path::PathBuf;
+use twitch_info_bot::store::{Attr, Attrs, SqliteStore, Store};
+use twitch_info_bot::workspace::WorkspaceStore;
+
+/// A database file of the test's own, so a second connection to it can plant items.
+fn database(test: &str) -> String {
+    let path: PathBuf = std::env::temp_dir().join(format!("tinstall-{}-{}.sqlite", std::process::id(), test));
+    let _ = std::fs::remove_file(&path);
+    path.to_string_lossy().into_owned()
+}
+
+fn workspaces(path: &str) -> WorkspaceStore {
+    WorkspaceStore::new(Box::new(SqliteStore::open(path, "workspaces").expect("workspace store")))
+}
+
+#[test]
+fn a_state_from_begin_install_finishes_once() {
+    let workspaces = workspaces(&database("once"));
+    block_on(async {
+        let state = workspaces.begin_install().await.unwrap();
+        assert!(workspaces.finish_install(&state).await.unwrap());
+        assert!(!workspaces.finish_install(&state).await.unwrap(), "a reused state");
+    });
+}
+
+#[test]
+fn an_unknown_state_is_rejected() {
+    let workspaces = workspaces(&database("unknown"));
+    block_on(async {
+        workspaces.begin_install().await.unwrap();
+        assert!(!workspaces.finish_install("0123456789abcdef0123456789abcdef").await.unwrap());
+    });
+}
+
+#[test]
+fn an_expired_state_is_rejected() {
+    let path = database("expired");
+    let workspaces = workspaces(&path);
+    let planted = SqliteStore::open(&path, "workspaces").unwrap();
+    let mut item = Attrs::new();
+    item.insert("expires_at".to_string(), Attr::N(Utc::now().timestamp() - 1));
+    block_on(planted.put("install:0123456789abcdef0123456789abcdef", item)).unwrap();
+
+    assert!(!block_on(workspaces.finish_install("0123456789abcdef0123456789abcdef")).unwrap());
+}
@@ -0,0 +1,45 @@
+//! Counts and dates as each locale offered in setup writes them.
+
+use chrono::{TimeZone, Utc};
+use twitch_info_bot::locale::{Locale, LOCALES};
+
+/// `(tag, 1234567, 5 March 2021)` for every entry in `LOCALES`.
+const EXPECTED: &[(&str, &str, &str)] = &[
+    ("en-US", "1,234,567", "Mar 5, 2021"),
+    ("en-GB", "1,234,567", "05/03/2021"),
+    ("de-DE", "1.234.567", "05.03.2021"),
+    ("es-ES", "1.234.567", "05.03.2021"),
+    ("fr-FR", "1\u{202f}234\u{202f}567", "05/03/2021"),
+    ("it-IT", "1.234.567", "05.03.2021"),
+    ("nl-NL", "1.234.567", "05.03.2021"),
+    ("pt-BR", "1.234.567", "05.03.2021"),
+    ("sv-SE", "1\u{a0}234\u{a0}567", "2021-03-05"),
+    ("ja-JP", "1,234,567", "2021-03-05"),
+];
+
+#[test]
+fn every_offered_locale_formats_counts_and_dates() {
+    let tags: Vec<&str> = LOCALES.iter().map(|(tag, _)| *tag).collect();
+    let expected: Vec<&str> = EXPECTED.iter().map(|(tag, _, _)| *tag).collect();
+    assert_eq!(tags, expected, "every locale in setup needs a case here");
+
+    let date = Utc.ymd(2021, 3, 5).and_hms(18, 30, 0);
+    for (tag, count, day) in EXPECTED {
+        let locale = Locale::from_tag(tag);
+        assert_eq!(locale.count(1_234_567), *count, "{} count", tag);
+        assert_eq!(locale.date(&date), *day, "{} date", tag);
+    }
+}
+
+#[test]
+fn small_counts_are_not_grouped() {
+    assert_eq!(Locale::from_tag("de-DE").count(999), "999");
+    assert_eq!(Locale::from_tag("de-DE").count(0), "0");
+}
+
+#[test]
+fn tags_are_matched_loosely_and_unknown_ones_are_us_english() {
+    assert_eq!(Locale::from_tag("pt_br"), Locale::from_tag("pt-BR"));
+    assert_eq!(Locale::from_tag("en"), Locale::default());
+    assert_eq!(Locale::from_tag("xx-YY"), Locale::default());
+}
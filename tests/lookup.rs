@@ -0,0 +1,119 @@
+//! `/tuser`'s lookup logic against [`MockTwitch`], without a server.
+
+mod common;
+
+use common::{lookup, stream, user};
+use twitch_info_bot::mocktwitch::MockTwitch;
+use twitch_info_bot::twitch::LookupError;
+
+fn twitch() -> MockTwitch {
+    let mut twitch = MockTwitch {
+        users: vec![user("19571641", "ninja"), user("44445592", "pokimane")],
+        ..MockTwitch::default()
+    };
+    twitch.followers.insert("44445592".to_string(), 9_300_000);
+    twitch
+}
+
+#[test]
+fn lookup_matches_logins_case_insensitively_and_reports_the_rest() {
+    let result = lookup(&twitch(), "NINJA 44445592 nobody 999", true).unwrap();
+
+    let logins: Vec<&str> = result.users.iter().map(|user| user.login.as_str()).collect();
+    assert_eq!(logins, ["ninja", "pokimane"]);
+    assert_eq!(result.not_found, ["nobody", "999"]);
+}
+
+#[test]
+fn failed_enrichment_is_recorded_as_missing() {
+    let mut twitch = twitch();
+    twitch.failing.insert("streams", LookupError::Timeout);
+    let result = lookup(&twitch, "ninja", true).unwrap();
+    assert_eq!(result.missing, ["live status"]);
+
+    twitch.failing.insert("followers", LookupError::Outage(503));
+    let result = lookup(&twitch, "ninja", false).unwrap();
+    assert_eq!(result.missing, ["live status", "follower counts"]);
+    assert!(result.followers.is_empty());
+}
+
+#[test]
+fn cards_get_follower_counts_and_compact_output_does_not() {
+    let mut twitch = twitch();
+    twitch.streams.push(stream("44445592", "pokimane"));
+    let cards = lookup(&twitch, "pokimane", false).unwrap();
+    assert_eq!(cards.followers.get("44445592"), Some(&9_300_000));
+    assert_eq!(cards.streams.len(), 1);
+
+    let compact = lookup(&twitch(), "pokimane", true).unwrap();
+    assert!(compact.followers.is_empty());
+}
+
+#[test]
+fn failed_user_lookup_fails_the_lookup() {
+    let mut twitch = twitch();
+    twitch.failing.insert("users", LookupError::Unauthorized(401));
+    assert_eq!(lookup(&twitch, "ninja", true).unwrap_err(), LookupError::Unauthorized(401));
+}
+
+#[test]
+fn nobody_found_skips_enrichment() {
+    let mut twitch = twitch();
+    twitch.failing.insert("streams", LookupError::Timeout);
+    let result = lookup(&twitch, "nobody", true).unwrap();
+    assert!(result.users.is_empty());
+    assert!(result.missing.is_empty());
+    assert_eq!(result.not_found, ["nobody"]);
+}
+
+#[test]
+fn unknown_logins_get_suggestions() {
+    let result = lookup(&twitch(), "ninja pokiman", true).unwrap();
+
+    assert_eq!(result.not_found, ["pokiman"]);
+    assert_eq!(result.suggestions.len(), 1);
+    assert_eq!(result.suggestions[0].term, "pokiman");
+    assert_eq!(result.suggestions[0].channels[0].broadcaster_login, "pokimane");
+    let note = result.not_found_note().unwrap();
+    assert!(note.contains("did you mean <https://twitch.tv/pokimane|pokimane>?"), "{}", note);
+}
+
+#[test]
+fn search_prefix_only_searches() {
+    let mut twitch = twitch();
+    twitch.failing.insert("users", LookupError::Outage(503));
+    twitch.streams.push(stream("19571641", "ninja"));
+
+    let result = lookup(&twitch, "search: NIN", false).unwrap();
+
+    assert!(result.users.is_empty() && result.not_found.is_empty());
+    let channels = &result.suggestions[0].channels;
+    assert_eq!(channels.len(), 1);
+    assert_eq!(channels[0].broadcaster_login, "ninja");
+    assert!(channels[0].is_live);
+}
+
+#[test]
+fn failed_search_leaves_suggestions_off() {
+    let mut twitch = twitch();
+    twitch.failing.insert("search", LookupError::Timeout);
+    let result = lookup(&twitch, "ninja pokiman", true).unwrap();
+    assert_eq!(result.users.len(), 1);
+    assert_eq!(result.missing, ["suggestions"]);
+    assert_eq!(result.not_found_note().unwrap(), "_Not found: pokiman_");
+}
+
+#[test]
+fn lookups_past_the_cap_leave_the_rest_out_with_a_note() {
+    let logins: Vec<String> = (0..205).map(|i| format!("user{}", i)).collect();
+    let text = format!("ninja NINJA {}", logins.join(" "));
+
+    let result = lookup(&twitch(), &text, true).unwrap();
+
+    assert_eq!(result.users.len(), 1);
+    // The repeated ninja doesn't use up a place.
+    assert_eq!(result.not_found.len(), 199);
+    assert_eq!(result.left_out, logins[199..]);
+    let note = result.not_found_note().unwrap();
+    assert!(note.ends_with("_Only the first 200 users were looked up; 6 more were left out._"), "{}", note);
+}
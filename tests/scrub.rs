@@ -0,0 +1,41 @@
+//! Masking what shouldn't be reposted from Twitch into shared channels.
+
+use twitch_info_bot::scrub::OutputFilter;
+
+fn scrub(text: &str) -> String {
+    OutputFilter::default().scrub(text)
+}
+
+#[test]
+fn email_addresses_are_masked() {
+    assert_eq!(scrub("business: camr@example.com."), "business: [email removed].");
+    assert_eq!(scrub("(team@mail.example.org)"), "([email removed])");
+    assert_eq!(scrub("@ninja and ninja@ are not emails"), "@ninja and ninja@ are not emails");
+}
+
+#[test]
+fn phone_numbers_are_masked() {
+    assert_eq!(scrub("call 415-555-0123. Thanks"), "call [phone removed]. Thanks");
+    assert_eq!(scrub("UK: +44 20 7946 0958"), "UK: [phone removed]");
+    assert_eq!(scrub("(415) 555.0123!"), "[phone removed]!");
+}
+
+#[test]
+fn years_counts_and_twitch_ids_are_not_phone_numbers() {
+    for text in [
+        "2021 2022 2023 finals",
+        "Twitch ID 1234567890",
+        "user 44322889 and 141981764",
+        "1 000 000 000 views",
+        "patch 1.2.3 on 2023-05-01",
+    ] {
+        assert_eq!(scrub(text), text);
+    }
+}
+
+#[test]
+fn banned_words_are_starred_out_whatever_their_case() {
+    let filter = OutputFilter::new(&["Spoiler".to_string(), " ".to_string()]);
+
+    assert_eq!(filter.scrub("big SPOILER!\nspoilers\tspoiler"), "big *******!\nspoilers\t*******");
+}
@@ -0,0 +1,73 @@
+//! Checking that a request really came from Slack.
+
+use serde_json::{json, Value};
+use twitch_info_bot::secrets::{self, SignatureError, SLACK_SIGNATURE, SLACK_TIMESTAMP};
+
+const SIGNING_SECRET: &str = "test-signing-secret";
+const NOW: i64 = 1_700_000_000;
+const BODY: &str = "command=%2Ftuser&text=ninja&team_id=T1&user_id=U1";
+
+fn event(timestamp: &str, signature: &str, body: &str) -> Value {
+    json!({
+        "headers": { SLACK_TIMESTAMP: timestamp, SLACK_SIGNATURE: signature },
+        "body": body,
+    })
+}
+
+fn signed(timestamp: i64, body: &str) -> Value {
+    let timestamp = timestamp.to_string();
+    let signature = secrets::sign_slack_request(SIGNING_SECRET, &timestamp, body).unwrap();
+    event(&timestamp, &signature, body)
+}
+
+#[test]
+fn a_request_signed_with_the_secret_passes() {
+    assert_eq!(secrets::verify_slack_signature(SIGNING_SECRET, &signed(NOW - 10, BODY), NOW), Ok(()));
+}
+
+#[test]
+fn a_body_changed_after_signing_fails() {
+    let mut event = signed(NOW, BODY);
+    event["body"] = Value::String(BODY.replace("ninja", "pokimane"));
+
+    assert_eq!(
+        secrets::verify_slack_signature(SIGNING_SECRET, &event, NOW),
+        Err(SignatureError::Mismatch)
+    );
+    assert_eq!(
+        secrets::verify_slack_signature("another-secret", &signed(NOW, BODY), NOW),
+        Err(SignatureError::Mismatch)
+    );
+}
+
+#[test]
+fn an_old_timestamp_is_refused_even_when_signed() {
+    assert_eq!(
+        secrets::verify_slack_signature(SIGNING_SECRET, &signed(NOW - 10 * 60, BODY), NOW),
+        Err(SignatureError::Stale)
+    );
+    assert_eq!(
+        secrets::verify_slack_signature(SIGNING_SECRET, &signed(NOW + 10 * 60, BODY), NOW),
+        Err(SignatureError::Stale)
+    );
+}
+
+#[test]
+fn malformed_or_missing_headers_fail() {
+    let timestamp = NOW.to_string();
+    let check = |event: &Value| secrets::verify_slack_signature(SIGNING_SECRET, event, NOW);
+
+    assert_eq!(check(&event(&timestamp, "v0=not-hex", BODY)), Err(SignatureError::Mismatch));
+    assert_eq!(check(&event(&timestamp, "deadbeef", BODY)), Err(SignatureError::Mismatch));
+    assert_eq!(check(&event("yesterday", "v0=deadbeef", BODY)), Err(SignatureError::Stale));
+    assert_eq!(check(&json!({ "headers": {}, "body": BODY })), Err(SignatureError::Missing));
+}
+
+#[test]
+fn an_extreme_timestamp_is_stale_rather_than_an_overflow() {
+    let check = |timestamp: i64| secrets::verify_slack_signature(SIGNING_SECRET, &signed(timestamp, BODY), NOW);
+
+    assert_eq!(check(i64::MIN), Err(SignatureError::Stale));
+    assert_eq!(check(i64::MAX), Err(SignatureError::Stale));
+    assert_eq!(check(-NOW), Err(SignatureError::Stale));
+}
@@ -0,0 +1,129 @@
+//! Updating a posted message in place when a card button is clicked, and splitting a message too
+//! big for Slack into parts.
+
+use serde_json::{json, Value};
+use twitch_info_bot::slack::{self, blocks, Block, Color, SlackAttachment, SlackMessage, MAX_BLOCKS};
+
+fn section(text: &str) -> Block {
+    Block::Section {
+        text: blocks::mrkdwn(text),
+        accessory: None,
+    }
+}
+
+fn block_ids(blocks: &Value) -> Vec<&str> {
+    blocks.as_array().unwrap().iter().map(|block| block["block_id"].as_str().unwrap()).collect()
+}
+
+/// A message as Slack sends it back in a `block_actions` payload: two legacy cards.
+fn posted() -> Value {
+    json!({
+        "type": "message",
+        "ts": "1700000000.000100",
+        "text": "ninja, pokimane",
+        "attachments": [
+            {
+                "fallback": "ninja",
+                "blocks": [{"type": "context", "block_id": "a1"}, {"type": "actions", "block_id": "a2"}]
+            },
+            {
+                "fallback": "pokimane",
+                "blocks": [{"type": "context", "block_id": "b1"}, {"type": "actions", "block_id": "b2"}]
+            }
+        ]
+    })
+}
+
+#[test]
+fn a_view_goes_under_the_clicked_card_and_replaces_the_last_one() {
+    let once = slack::with_block_after(&posted(), "b2", &section("Recent VODs")).unwrap();
+    let twice = slack::with_block_after(&once, "b2", &section("Clips")).unwrap();
+
+    assert_eq!(block_ids(&twice["attachments"][0]["blocks"]), ["a1", "a2"]);
+    assert_eq!(block_ids(&twice["attachments"][1]["blocks"]), ["b1", "b2", "b2:expanded"]);
+    assert_eq!(twice["attachments"][1]["blocks"][2]["text"]["text"], "Clips");
+    // Only what's needed to post the message again is kept.
+    assert_eq!(twice["ts"], Value::Null);
+    assert_eq!(twice["text"], "ninja, pokimane");
+}
+
+#[test]
+fn a_block_the_message_does_not_have_is_an_error() {
+    assert!(slack::with_block_after(&posted(), "gone", &section("Clips")).is_err());
+}
+
+fn sections(count: usize, chars: usize) -> Vec<Block> {
+    (0..count).map(|i| section(&format!("{}{}", i, "x".repeat(chars)))).collect()
+}
+
+fn as_json(part: &SlackMessage) -> Value {
+    serde_json::to_value(part).unwrap()
+}
+
+fn count(part: &Value, field: &str) -> usize {
+    part[field].as_array().map_or(0, Vec::len)
+}
+
+#[test]
+fn too_many_blocks_are_split_in_order_with_the_text_first() {
+    let message = SlackMessage::builder()
+        .in_channel()
+        .text("ninja, pokimane")
+        .blocks(sections(MAX_BLOCKS * 2 + 20, 10))
+        .build()
+        .unwrap();
+
+    let parts: Vec<Value> = message.split().iter().map(as_json).collect();
+    let sizes: Vec<usize> = parts.iter().map(|part| count(part, "blocks")).collect();
+    assert_eq!(sizes, [MAX_BLOCKS, MAX_BLOCKS, 20]);
+    assert_eq!(parts[0]["text"], "ninja, pokimane");
+    assert_eq!(parts[1]["text"], "_(continued)_");
+    assert!(parts[2]["blocks"][0]["text"]["text"].as_str().unwrap().starts_with(&(MAX_BLOCKS * 2).to_string()));
+    assert!(parts.iter().all(|part| part["response_type"] == "in_channel"));
+}
+
+#[test]
+fn text_too_long_is_cut_short() {
+    let message = SlackMessage::builder().text("y".repeat(50_000)).blocks(sections(1, 10)).build().unwrap();
+
+    let parts = message.split();
+    assert_eq!(parts.len(), 1);
+    let text = as_json(&parts[0])["text"].as_str().unwrap().to_string();
+    assert_eq!(text.chars().count(), 40_001);
+    assert!(text.ends_with('…'));
+}
+
+#[test]
+fn attachments_are_split_whole() {
+    let attachment = |name: &str| SlackAttachment {
+        fallback: name.to_string(),
+        color: Color::TWITCH_PURPLE,
+        author_name: name.to_string(),
+        author_icon: String::new(),
+        blocks: sections(25, 1000),
+    };
+    let message = SlackMessage::builder()
+        .text("three cards")
+        .attachments(vec![attachment("a"), attachment("b"), attachment("c")])
+        .build()
+        .unwrap();
+
+    let parts: Vec<Value> = message.split().iter().map(as_json).collect();
+    assert_eq!(parts.len(), 3);
+    for (part, name) in parts.iter().zip(["a", "b", "c"]) {
+        assert_eq!(count(part, "attachments"), 1);
+        assert_eq!(part["attachments"][0]["fallback"], name);
+        assert_eq!(count(&part["attachments"][0], "blocks"), 25);
+    }
+}
+
+#[test]
+fn a_single_block_too_big_on_its_own_stays_whole() {
+    let message = SlackMessage::builder().text("huge").blocks(sections(1, 50_000)).build().unwrap();
+
+    let parts = message.split();
+    assert_eq!(parts.len(), 1);
+    let part = as_json(&parts[0]);
+    assert_eq!(count(&part, "blocks"), 1);
+    assert_eq!(part["text"], "huge");
+}